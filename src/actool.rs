@@ -0,0 +1,191 @@
+//! Compiling asset catalogs (`.xcassets`) with actool from Xcode.
+
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::build_script::BuildScriptOutput;
+use crate::ibtool;
+use crate::plist::{Dictionary, Value};
+use crate::tool::{CompileError, Tool};
+use crate::xcrun;
+
+/// Options for [`compile_asset_catalog`].
+#[derive(Clone, Debug)]
+pub struct ActoolOptions {
+    /// Platform to compile for (`--platform`, e.g. `macosx`, `iphoneos`)
+    pub platform: String,
+    /// Oldest OS version the compiled catalog must support (`--minimum-deployment-target`)
+    pub minimum_deployment_target: Option<String>,
+    /// Devices to compile for (`--target-device`, e.g. `mac`, `iphone`, `ipad`)
+    pub target_devices: Vec<String>,
+    /// Name of the app icon set to generate the app icon from (`--app-icon`)
+    pub app_icon: Option<String>,
+    /// Name of the launch image set (`--launch-image`)
+    pub launch_image: Option<String>,
+    /// Name of the color set used as the app's accent color (`--accent-color`)
+    pub accent_color: Option<String>,
+    /// Report the asset catalog as an input of a build script
+    pub build_script: Option<BuildScriptOutput>,
+}
+
+impl Default for ActoolOptions {
+    fn default() -> Self {
+        ActoolOptions {
+            platform: "macosx".to_string(),
+            minimum_deployment_target: None,
+            target_devices: Vec::new(),
+            app_icon: None,
+            launch_image: None,
+            accent_color: None,
+            build_script: None,
+        }
+    }
+}
+
+impl ActoolOptions {
+    fn apply(&self, tool: &mut Tool) {
+        tool.arg("--platform").arg(&self.platform);
+        if let Some(target) = &self.minimum_deployment_target {
+            tool.arg("--minimum-deployment-target").arg(target);
+        }
+        for device in &self.target_devices {
+            tool.arg("--target-device").arg(device);
+        }
+        if let Some(icon) = &self.app_icon {
+            tool.arg("--app-icon").arg(icon);
+        }
+        if let Some(image) = &self.launch_image {
+            tool.arg("--launch-image").arg(image);
+        }
+        if let Some(color) = &self.accent_color {
+            tool.arg("--accent-color").arg(color);
+        }
+    }
+}
+
+fn partial_info_plist_path() -> PathBuf {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    std::env::temp_dir().join(format!(
+        "toolbelt-actool-{}-{}.plist",
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    ))
+}
+
+/// Compile an asset catalog into `Assets.car` and the generated icons using actool from Xcode.
+///
+/// Returns the partial Info.plist written by actool (e.g. `CFBundleIconFile`, `CFBundleIconName`
+/// when an app icon is generated). It has to be merged into the bundle's Info.plist.
+///
+/// # Arguments
+///
+/// * `source` - the `.xcassets` directory
+/// * `destination` - the bundle's resources directory
+/// * `options` - platform, deployment target and the sets to generate the app icon etc. from
+///
+/// # Example
+///
+/// ```no_run
+/// use std::path::Path;
+/// use toolbelt::actool::{compile_asset_catalog, ActoolOptions};
+/// use toolbelt::bundle::{BundleBuilder, BundleKind};
+///
+/// let options = ActoolOptions {
+///     app_icon: Some("AppIcon".to_string()),
+///     minimum_deployment_target: Some("11.0".to_string()),
+///     ..Default::default()
+/// };
+/// let partial = compile_asset_catalog(
+///     Path::new("Assets.xcassets"),
+///     Path::new("target/Resources"),
+///     &options,
+/// )
+/// .unwrap();
+///
+/// let mut builder = BundleBuilder::new(BundleKind::Bundle, "Plugin", "com.example.plugin");
+/// for (key, value) in partial {
+///     builder = builder.info(&key, value);
+/// }
+/// ```
+pub fn compile_asset_catalog(
+    source: &Path,
+    destination: &Path,
+    options: &ActoolOptions,
+) -> Result<Dictionary, CompileError> {
+    if !source.is_dir() {
+        return Err(CompileError::Io(Error::new(
+            ErrorKind::NotFound,
+            format!("Asset catalog {} not found", source.display()),
+        )));
+    }
+    if let Some(output) = &options.build_script {
+        output.rerun_if_changed(source);
+    }
+    fs::create_dir_all(destination)?;
+
+    let partial_path = partial_info_plist_path();
+    let mut actool = xcrun::tool("actool", None).map_err(|source_error| CompileError::Tool {
+        file: source.to_path_buf(),
+        source: source_error,
+    })?;
+    actool
+        .arg("--compile")
+        .arg(destination)
+        .args(["--errors", "--warnings", "--notices"])
+        .args(["--output-format", "xml1"])
+        .arg("--output-partial-info-plist")
+        .arg(&partial_path);
+    options.apply(&mut actool);
+    let result = actool.arg(source).run();
+    let partial = match &result {
+        Ok(_) if partial_path.exists() => Value::read(&partial_path),
+        _ => Ok(Value::Dictionary(Dictionary::new())),
+    };
+    let _ = fs::remove_file(&partial_path);
+
+    let output = result.map_err(|source_error| CompileError::Tool {
+        file: source.to_path_buf(),
+        source: source_error,
+    })?;
+    for (_, message) in ibtool::diagnostics(&output.stdout) {
+        warn!("{}: {}", source.display(), message);
+    }
+
+    match partial? {
+        Value::Dictionary(dictionary) => Ok(dictionary),
+        _ => Err(CompileError::Io(Error::new(
+            ErrorKind::InvalidData,
+            "actool's partial Info.plist is not a dictionary",
+        ))),
+    }
+}
+
+#[test]
+fn test_compile_asset_catalog_errors() {
+    let root = Path::new("target/compile_asset_catalog");
+    let _ = fs::remove_dir_all(root);
+    fs::create_dir_all(root.join("Assets.xcassets")).unwrap();
+
+    assert!(matches!(
+        compile_asset_catalog(
+            &root.join("Missing.xcassets"),
+            &root.join("out"),
+            &ActoolOptions::default()
+        ),
+        Err(CompileError::Io(_))
+    ));
+    // without Xcode actool can't be found, the catalog is named in the error either way
+    match compile_asset_catalog(
+        &root.join("Assets.xcassets"),
+        &root.join("out"),
+        &ActoolOptions::default(),
+    ) {
+        Err(CompileError::Tool { file, .. }) => assert_eq!(file, root.join("Assets.xcassets")),
+        Ok(_) => {}
+        Err(e) => panic!("unexpected {}", e),
+    }
+
+    fs::remove_dir_all(root).unwrap();
+}
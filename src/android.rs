@@ -0,0 +1,7 @@
+//! Android counterparts of the Apple tooling, for apps built with cargo-ndk: locating the SDK and
+//! NDK, and signing APKs and app bundles.
+
+pub mod apksigner;
+pub mod sdk;
+
+pub use apksigner::{sign_apk, ApkSignOptions};
@@ -0,0 +1,183 @@
+//! Signing Android artifacts: APKs with zipalign and apksigner from the SDK build tools, app
+//! bundles (`.aab`) with jarsigner from the JDK, as Google Play expects.
+//!
+//! Like [`crate::codesign`] on macOS and [`crate::windows::signtool`] on Windows, so a build
+//! script can sign every platform's artifact the same way.
+
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+
+use super::sdk::detect_android_sdk;
+use crate::tool::Tool;
+
+/// Options for [`sign_apk`].
+#[derive(Clone, Debug)]
+pub struct ApkSignOptions {
+    /// Password of the keystore (`--ks-pass`)
+    pub keystore_password: String,
+    /// Alias of the key in the keystore (`--ks-key-alias`), required if it holds several keys
+    /// and for app bundles
+    pub key_alias: Option<String>,
+    /// Password of the key (`--key-pass`) if it differs from the keystore password
+    pub key_password: Option<String>,
+    /// Lowest Android API level the APK supports (`--min-sdk-version`), which decides the
+    /// signature schemes apksigner uses; `None` to read it from the APK
+    pub min_sdk_version: Option<u32>,
+    /// Align the APK with zipalign before signing, which Android requires for installation
+    pub zipalign: bool,
+}
+
+impl ApkSignOptions {
+    /// Sign with the only key of a keystore, aligning APKs first.
+    pub fn new(keystore_password: &str) -> ApkSignOptions {
+        ApkSignOptions {
+            keystore_password: keystore_password.to_string(),
+            key_alias: None,
+            key_password: None,
+            min_sdk_version: None,
+            zipalign: true,
+        }
+    }
+
+    fn apply(&self, tool: &mut Tool, apk: &Path, keystore: &Path) {
+        tool.args(["sign", "--ks"]).arg(keystore);
+        tool.arg("--ks-pass")
+            .secret_arg(format!("pass:{}", self.keystore_password));
+        if let Some(alias) = &self.key_alias {
+            tool.arg("--ks-key-alias").arg(alias);
+        }
+        if let Some(password) = &self.key_password {
+            tool.arg("--key-pass")
+                .secret_arg(format!("pass:{}", password));
+        }
+        if let Some(version) = self.min_sdk_version {
+            tool.arg("--min-sdk-version").arg(version.to_string());
+        }
+        tool.arg(apk);
+    }
+
+    fn jarsigner(&self, bundle: &Path, keystore: &Path) -> Result<Tool, Error> {
+        let alias = self.key_alias.as_ref().ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                format!("Signing {} requires a key alias", bundle.display()),
+            )
+        })?;
+        let mut tool = Tool::new("jarsigner");
+        tool.args([
+            "-sigalg",
+            "SHA256withRSA",
+            "-digestalg",
+            "SHA-256",
+            "-keystore",
+        ])
+        .arg(keystore)
+        .arg("-storepass")
+        .secret_arg(&self.keystore_password);
+        if let Some(password) = &self.key_password {
+            tool.arg("-keypass").secret_arg(password);
+        }
+        tool.arg(bundle).arg(alias);
+        Ok(tool)
+    }
+}
+
+/// apksigner or zipalign from the latest build tools of the Android SDK, or from `PATH` if no SDK
+/// is found.
+fn build_tool(name: &str) -> Tool {
+    match detect_android_sdk() {
+        Ok(sdk) if name == "apksigner" && sdk.apksigner.is_file() => Tool::new(sdk.apksigner),
+        Ok(sdk) if name == "zipalign" && sdk.zipalign.is_file() => Tool::new(sdk.zipalign),
+        _ => Tool::new(name),
+    }
+}
+
+/// Align the uncompressed entries of an APK to 4 bytes in place, shared libraries to pages
+/// (`zipalign -p`).
+pub fn zipalign(apk: &Path) -> Result<(), Error> {
+    let aligned = apk.with_extension("aligned.apk");
+    build_tool("zipalign")
+        .args(["-f", "-p", "4"])
+        .arg(apk)
+        .arg(&aligned)
+        .run()
+        .map_err(Error::other)?;
+    fs::rename(&aligned, apk)
+}
+
+/// Sign an APK or app bundle in place with a key from a Java keystore (`.jks` or `.keystore`).
+///
+/// APKs are aligned with zipalign and signed with apksigner; app bundles (`.aab`) are signed
+/// with jarsigner and need [`ApkSignOptions::key_alias`].
+///
+/// # Example
+///
+/// ```no_run
+/// use std::path::Path;
+/// use toolbelt::android::{sign_apk, ApkSignOptions};
+///
+/// let mut options = ApkSignOptions::new(&std::env::var("KEYSTORE_PASSWORD").unwrap());
+/// options.key_alias = Some("upload".to_string());
+/// sign_apk(
+///     Path::new("target/release/apk/myapp.apk"),
+///     Path::new("release.jks"),
+///     &options,
+/// )
+/// .unwrap();
+/// ```
+pub fn sign_apk(apk: &Path, keystore: &Path, options: &ApkSignOptions) -> Result<(), Error> {
+    debug!("Sign {}", apk.display());
+    let mut tool = if apk.extension().is_some_and(|e| e == "aab") {
+        options.jarsigner(apk, keystore)?
+    } else {
+        if options.zipalign {
+            zipalign(apk)?;
+        }
+        let mut tool = build_tool("apksigner");
+        options.apply(&mut tool, apk, keystore);
+        tool
+    };
+    tool.run().map_err(Error::other)?;
+    Ok(())
+}
+
+/// Verify the signature of an APK with `apksigner verify`.
+pub fn verify_apk(apk: &Path) -> Result<(), Error> {
+    build_tool("apksigner")
+        .args(["verify", "--verbose"])
+        .arg(apk)
+        .run()
+        .map_err(Error::other)?;
+    Ok(())
+}
+
+#[test]
+fn test_apk_sign_options() {
+    let mut options = ApkSignOptions::new("hunter2");
+    options.min_sdk_version = Some(24);
+    let apk = Path::new("app.apk");
+    let keystore = Path::new("release.jks");
+    let mut tool = Tool::new("apksigner");
+    options.apply(&mut tool, apk, keystore);
+    assert_eq!(
+        tool.command_line(),
+        "apksigner sign --ks release.jks --ks-pass *** --min-sdk-version 24 app.apk"
+    );
+    let e = options
+        .jarsigner(Path::new("app.aab"), keystore)
+        .err()
+        .unwrap();
+    assert_eq!(e.kind(), ErrorKind::InvalidInput);
+
+    options.key_alias = Some("upload".to_string());
+    options.key_password = Some("secret".to_string());
+    assert_eq!(
+        options
+            .jarsigner(Path::new("app.aab"), keystore)
+            .unwrap()
+            .command_line(),
+        "jarsigner -sigalg SHA256withRSA -digestalg SHA-256 -keystore release.jks -storepass *** \
+         -keypass *** app.aab upload"
+    );
+}
@@ -0,0 +1,321 @@
+//! Locating the Android SDK and NDK.
+//!
+//! The SDK provides the build tools (apksigner, zipalign, aapt2) and the NDK the clang toolchain
+//! and sysroot for building native libraries. Both are found through the environment variables
+//! Android Studio and cargo-ndk use (`ANDROID_HOME`, `ANDROID_NDK_HOME`, …) or their default
+//! installation directories, preferring the latest installed version.
+
+use std::env;
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+
+use crate::xcrun::version_at_least;
+use crate::IncludeDirFormat;
+
+/// Environment variables naming the SDK, in order of precedence.
+const SDK_VARS: [&str; 2] = ["ANDROID_HOME", "ANDROID_SDK_ROOT"];
+
+/// Environment variables naming the NDK, in order of precedence.
+const NDK_VARS: [&str; 3] = ["ANDROID_NDK_HOME", "ANDROID_NDK_ROOT", "NDK_HOME"];
+
+/// An installed Android SDK and its latest build tools.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AndroidSdk {
+    pub root: PathBuf,
+    /// Version of the latest build tools, e.g. `34.0.0`
+    pub build_tools_version: String,
+    /// Directory of the build tools, e.g. `build-tools/34.0.0`
+    pub build_tools: PathBuf,
+    pub apksigner: PathBuf,
+    pub zipalign: PathBuf,
+}
+
+/// An installed Android NDK and its LLVM toolchain for the host.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AndroidNdk {
+    pub root: PathBuf,
+    /// The NDK version from `source.properties`, e.g. `26.1.10909125`
+    pub version: String,
+    /// The prebuilt toolchain, e.g. `toolchains/llvm/prebuilt/linux-x86_64`
+    pub toolchain: PathBuf,
+    /// The sysroot with the headers and libraries of the Android platform
+    pub sysroot: PathBuf,
+}
+
+/// The latest version among the subdirectories of `dir`. Only complete installations, which
+/// have a `source.properties` file, are considered.
+fn latest_version(dir: &Path) -> Option<String> {
+    fs::read_dir(dir)
+        .ok()?
+        .filter_map(Result::ok)
+        .filter(|entry| entry.path().join("source.properties").is_file())
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+        .reduce(|latest, version| {
+            if version_at_least(&latest, &version) {
+                latest
+            } else {
+                version
+            }
+        })
+}
+
+/// The NDK's target triple for a Rust architecture, e.g. `aarch64-linux-android`.
+fn ndk_triple(arch: &str) -> &str {
+    match arch {
+        "arm" | "armv7" => "arm-linux-androideabi",
+        "x86" | "i686" => "i686-linux-android",
+        "x86_64" => "x86_64-linux-android",
+        _ => "aarch64-linux-android",
+    }
+}
+
+/// The directory of the prebuilt toolchain for the host, e.g. `linux-x86_64`. macOS has a single
+/// universal toolchain named `darwin-x86_64`.
+fn host_tag() -> &'static str {
+    if cfg!(target_os = "macos") {
+        "darwin-x86_64"
+    } else if cfg!(windows) {
+        "windows-x86_64"
+    } else {
+        "linux-x86_64"
+    }
+}
+
+impl AndroidSdk {
+    /// The SDK in `root` with its latest build tools.
+    pub fn at(root: &Path) -> Result<AndroidSdk, Error> {
+        let build_tools = root.join("build-tools");
+        let version = latest_version(&build_tools).ok_or_else(|| {
+            Error::new(
+                ErrorKind::NotFound,
+                format!(
+                    "No build tools are installed in the Android SDK {}, install them with \
+                     sdkmanager \"build-tools;<version>\"",
+                    root.display()
+                ),
+            )
+        })?;
+        let build_tools = build_tools.join(&version);
+        let (apksigner, zipalign) = if cfg!(windows) {
+            ("apksigner.bat", "zipalign.exe")
+        } else {
+            ("apksigner", "zipalign")
+        };
+        Ok(AndroidSdk {
+            root: root.to_path_buf(),
+            build_tools_version: version,
+            apksigner: build_tools.join(apksigner),
+            zipalign: build_tools.join(zipalign),
+            build_tools,
+        })
+    }
+}
+
+impl AndroidNdk {
+    /// The NDK in `root`.
+    pub fn at(root: &Path) -> Result<AndroidNdk, Error> {
+        let properties = fs::read_to_string(root.join("source.properties")).map_err(|e| {
+            Error::new(
+                e.kind(),
+                format!("{} is not an Android NDK: {}", root.display(), e),
+            )
+        })?;
+        let version = properties
+            .lines()
+            .find_map(|line| {
+                let (key, value) = line.split_once('=')?;
+                (key.trim() == "Pkg.Revision").then(|| value.trim().to_string())
+            })
+            .ok_or_else(|| {
+                Error::new(
+                    ErrorKind::InvalidData,
+                    format!("{}/source.properties lacks Pkg.Revision", root.display()),
+                )
+            })?;
+        let toolchain = root.join("toolchains/llvm/prebuilt").join(host_tag());
+        if !toolchain.is_dir() {
+            return Err(Error::new(
+                ErrorKind::NotFound,
+                format!(
+                    "Android NDK {} has no toolchain for {}",
+                    version,
+                    host_tag()
+                ),
+            ));
+        }
+        Ok(AndroidNdk {
+            root: root.to_path_buf(),
+            version,
+            sysroot: toolchain.join("sysroot"),
+            toolchain,
+        })
+    }
+
+    /// The clang driver for a target architecture and minimum API level, e.g.
+    /// `bin/aarch64-linux-android24-clang`.
+    pub fn clang(&self, target_arch: &str, api_level: u32) -> PathBuf {
+        let triple = match ndk_triple(target_arch) {
+            "arm-linux-androideabi" => "armv7a-linux-androideabi",
+            triple => triple,
+        };
+        let suffix = if cfg!(windows) { ".cmd" } else { "" };
+        self.toolchain
+            .join("bin")
+            .join(format!("{}{}-clang{}", triple, api_level, suffix))
+    }
+
+    /// The sysroot's header directories for a target architecture, formatted like
+    /// [`crate::get_sdk_include_dirs`].
+    pub fn include_dirs(&self, target_arch: &str, format: IncludeDirFormat) -> Vec<String> {
+        let include = self.sysroot.join("usr/include");
+        [include.join(ndk_triple(target_arch)), include]
+            .iter()
+            .filter(|dir| dir.is_dir())
+            .map(|dir| format.format(dir))
+            .collect()
+    }
+
+    /// The sysroot's library directories for a target architecture and API level.
+    pub fn lib_dirs(&self, target_arch: &str, api_level: u32) -> Vec<PathBuf> {
+        let lib = self.sysroot.join("usr/lib").join(ndk_triple(target_arch));
+        [lib.join(api_level.to_string()), lib]
+            .into_iter()
+            .filter(|dir| dir.is_dir())
+            .collect()
+    }
+}
+
+/// The first of the environment variables that is set.
+fn var_path(vars: &[&str]) -> Option<PathBuf> {
+    vars.iter()
+        .find_map(|var| env::var_os(var).filter(|value| !value.is_empty()))
+        .map(PathBuf::from)
+}
+
+/// Where Android Studio installs the SDK by default.
+fn default_sdk_root() -> Option<PathBuf> {
+    let root = if cfg!(windows) {
+        PathBuf::from(env::var_os("LOCALAPPDATA")?).join("Android/Sdk")
+    } else if cfg!(target_os = "macos") {
+        PathBuf::from(env::var_os("HOME")?).join("Library/Android/sdk")
+    } else {
+        PathBuf::from(env::var_os("HOME")?).join("Android/Sdk")
+    };
+    root.is_dir().then_some(root)
+}
+
+fn sdk_root() -> Option<PathBuf> {
+    var_path(&SDK_VARS).or_else(default_sdk_root)
+}
+
+/// Detect the Android SDK from `ANDROID_HOME` or `ANDROID_SDK_ROOT`, or its default location,
+/// e.g. `~/Android/Sdk` on Linux.
+pub fn detect_android_sdk() -> Result<AndroidSdk, Error> {
+    let root = sdk_root().ok_or_else(|| {
+        Error::new(
+            ErrorKind::NotFound,
+            "No Android SDK found, set ANDROID_HOME or install it with Android Studio",
+        )
+    })?;
+    let sdk = AndroidSdk::at(&root)?;
+    debug!(
+        "Android SDK in {} with build tools {}",
+        sdk.root.display(),
+        sdk.build_tools_version
+    );
+    Ok(sdk)
+}
+
+/// Detect the Android NDK from `ANDROID_NDK_HOME`, `ANDROID_NDK_ROOT` or `NDK_HOME`, or the
+/// latest NDK installed in the SDK (`ndk/<version>` or `ndk-bundle`).
+///
+/// # Example
+///
+/// ```no_run
+/// use toolbelt::{detect_android_ndk, IncludeDirFormat};
+///
+/// let ndk = detect_android_ndk().unwrap();
+/// let arch = std::env::var("CARGO_CFG_TARGET_ARCH").unwrap();
+/// let include_dirs = ndk.include_dirs(&arch, IncludeDirFormat::CLANG);
+/// for dir in ndk.lib_dirs(&arch, 24) {
+///     println!("cargo:rustc-link-search=native={}", dir.display());
+/// }
+/// ```
+pub fn detect_android_ndk() -> Result<AndroidNdk, Error> {
+    let root = var_path(&NDK_VARS)
+        .or_else(|| {
+            let sdk = sdk_root()?;
+            latest_version(&sdk.join("ndk"))
+                .map(|version| sdk.join("ndk").join(version))
+                .or_else(|| Some(sdk.join("ndk-bundle")).filter(|dir| dir.is_dir()))
+        })
+        .ok_or_else(|| {
+            Error::new(
+                ErrorKind::NotFound,
+                "No Android NDK found, set ANDROID_NDK_HOME or install it with sdkmanager \
+                 \"ndk;<version>\"",
+            )
+        })?;
+    let ndk = AndroidNdk::at(&root)?;
+    debug!("Android NDK {} in {}", ndk.version, ndk.root.display());
+    Ok(ndk)
+}
+
+#[test]
+fn test_android_sdk() {
+    let root = Path::new("target/android_sdk");
+    let _ = fs::remove_dir_all(root);
+    for version in ["33.0.2", "34.0.0", "9.0.0"] {
+        let dir = root.join("build-tools").join(version);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("source.properties"), "").unwrap();
+    }
+    fs::create_dir_all(root.join("build-tools/35.0.0")).unwrap();
+    let sdk = AndroidSdk::at(root).unwrap();
+    assert_eq!(sdk.build_tools_version, "34.0.0");
+    assert!(sdk.apksigner.starts_with(root.join("build-tools/34.0.0")));
+
+    let ndk_root = root.join("ndk/26.1.10909125");
+    let sysroot = ndk_root
+        .join("toolchains/llvm/prebuilt")
+        .join(host_tag())
+        .join("sysroot");
+    fs::create_dir_all(sysroot.join("usr/include/aarch64-linux-android")).unwrap();
+    fs::create_dir_all(sysroot.join("usr/lib/aarch64-linux-android/24")).unwrap();
+    fs::write(
+        ndk_root.join("source.properties"),
+        "Pkg.Desc = Android NDK\nPkg.Revision = 26.1.10909125\n",
+    )
+    .unwrap();
+    let ndk = AndroidNdk::at(&ndk_root).unwrap();
+    assert_eq!(ndk.version, "26.1.10909125");
+    assert_eq!(ndk.sysroot, sysroot);
+    assert_eq!(
+        ndk.include_dirs("aarch64", IncludeDirFormat::PLAIN),
+        [
+            format!(
+                "{}",
+                sysroot.join("usr/include/aarch64-linux-android").display()
+            ),
+            format!("{}", sysroot.join("usr/include").display()),
+        ]
+    );
+    assert_eq!(
+        ndk.lib_dirs("aarch64", 24),
+        [
+            sysroot.join("usr/lib/aarch64-linux-android/24"),
+            sysroot.join("usr/lib/aarch64-linux-android"),
+        ]
+    );
+    assert!(ndk.clang("arm", 24).ends_with(format!(
+        "bin/armv7a-linux-androideabi24-clang{}",
+        if cfg!(windows) { ".cmd" } else { "" }
+    )));
+    assert_eq!(
+        AndroidNdk::at(root).unwrap_err().kind(),
+        ErrorKind::NotFound
+    );
+
+    fs::remove_dir_all(root).unwrap();
+}
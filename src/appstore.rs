@@ -0,0 +1,360 @@
+//! Checking a bundle against App Store requirements before it is uploaded.
+//!
+//! Apple rejects uploads without a valid privacy manifest (`PrivacyInfo.xcprivacy`), with
+//! undeclared uses of required-reason APIs, or with App Transport Security exceptions it doesn't
+//! accept. [`check_app_store_requirements`] reports these issues locally.
+
+use std::fmt;
+use std::fs;
+use std::io::Error;
+use std::path::{Path, PathBuf};
+
+use crate::bundle::read_info_plist;
+use crate::pipeline::policy_violation;
+use crate::plist::{Dictionary, Value};
+
+/// File name of the privacy manifest in the bundle's resources.
+pub const PRIVACY_MANIFEST: &str = "PrivacyInfo.xcprivacy";
+
+/// Required-reason API categories with the reasons Apple accepts and symbols indicating their use.
+const REQUIRED_REASON_APIS: &[(&str, &[&str], &[&str])] = &[
+    (
+        "NSPrivacyAccessedAPICategoryFileTimestamp",
+        &["DDA9.1", "C617.1", "3B52.1", "0A2A.1"],
+        &[
+            "_stat",
+            "_fstat",
+            "_fstatat",
+            "_lstat",
+            "_getattrlist",
+            "_fgetattrlist",
+            "_getattrlistat",
+            "_getattrlistbulk",
+        ],
+    ),
+    (
+        "NSPrivacyAccessedAPICategorySystemBootTime",
+        &["35F9.1", "8FFB.1", "3D61.1"],
+        &["_mach_absolute_time", "systemUptime"],
+    ),
+    (
+        "NSPrivacyAccessedAPICategoryDiskSpace",
+        &["85F4.1", "E174.1", "7D9E.1", "B728.1"],
+        &["_statfs", "_fstatfs", "_statvfs", "_fstatvfs"],
+    ),
+    (
+        "NSPrivacyAccessedAPICategoryActiveKeyboards",
+        &["3EC4.1", "54BD.1"],
+        &["activeInputModes"],
+    ),
+    (
+        "NSPrivacyAccessedAPICategoryUserDefaults",
+        &["CA92.1", "1C8F.1", "C56D.1", "AC6B.1"],
+        &["NSUserDefaults"],
+    ),
+];
+
+/// An App Store requirement the bundle violates.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Violation {
+    /// The file the issue was found in
+    pub file: PathBuf,
+    pub message: String,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.file.display(), self.message)
+    }
+}
+
+fn violation(file: &Path, message: String) -> Violation {
+    Violation {
+        file: file.to_path_buf(),
+        message,
+    }
+}
+
+/// Check an app bundle for the App Store's privacy manifest, required-reason API and App Transport
+/// Security requirements.
+///
+/// Returns the violations found, an empty list if the bundle complies. Only failing to read the
+/// bundle is an error.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::path::Path;
+/// use toolbelt::appstore::check_app_store_requirements;
+///
+/// for violation in check_app_store_requirements(Path::new("target/MyApp.app")).unwrap() {
+///     eprintln!("{}", violation);
+/// }
+/// ```
+pub fn check_app_store_requirements(bundle: &Path) -> Result<Vec<Violation>, Error> {
+    let info = read_info_plist(bundle)?;
+    let mut violations = check_app_transport_security(&bundle.join("Contents/Info.plist"), &info);
+
+    let executable = info
+        .get("CFBundleExecutable")
+        .and_then(Value::as_str)
+        .map(|name| bundle.join("Contents/MacOS").join(name));
+    let used = match &executable {
+        Some(executable) if executable.is_file() => used_api_categories(&fs::read(executable)?),
+        _ => Vec::new(),
+    };
+
+    let manifest = bundle.join("Contents/Resources").join(PRIVACY_MANIFEST);
+    if !manifest.is_file() {
+        violations.push(violation(
+            bundle,
+            format!("{} is missing", PRIVACY_MANIFEST),
+        ));
+        return Ok(violations);
+    }
+    match Value::read(&manifest)? {
+        Value::Dictionary(privacy) => {
+            violations.extend(check_privacy_manifest(&manifest, &privacy, &used));
+        }
+        _ => violations.push(violation(&manifest, "not a dictionary".to_string())),
+    }
+    Ok(violations)
+}
+
+/// Like [`check_app_store_requirements`], but fail with a policy violation (see
+/// [`crate::pipeline::FailureKind::Policy`]) listing all violations, e.g. in a pipeline step.
+pub fn enforce_app_store_requirements(bundle: &Path) -> Result<(), Error> {
+    let violations = check_app_store_requirements(bundle)?;
+    if violations.is_empty() {
+        return Ok(());
+    }
+    let list: Vec<String> = violations.iter().map(Violation::to_string).collect();
+    Err(policy_violation(format!(
+        "{} violates App Store requirements:\n{}",
+        bundle.display(),
+        list.join("\n")
+    )))
+}
+
+/// Required-reason API categories a binary appears to use, judging by the symbols it references.
+fn used_api_categories(binary: &[u8]) -> Vec<&'static str> {
+    REQUIRED_REASON_APIS
+        .iter()
+        .filter(|(_, _, symbols)| {
+            symbols
+                .iter()
+                .any(|symbol| references_symbol(binary, symbol.as_bytes()))
+        })
+        .map(|(category, _, _)| *category)
+        .collect()
+}
+
+/// `true` if the symbol occurs as a whole name, i.e. between NULs (or before a `$` suffix such as
+/// `$INODE64`).
+fn references_symbol(binary: &[u8], symbol: &[u8]) -> bool {
+    binary.windows(symbol.len() + 2).any(|w| {
+        w[0] == 0
+            && &w[1..=symbol.len()] == symbol
+            && matches!(w[symbol.len() + 1], 0 | b'$' | b':')
+    })
+}
+
+fn check_privacy_manifest(manifest: &Path, privacy: &Dictionary, used: &[&str]) -> Vec<Violation> {
+    let mut violations = Vec::new();
+
+    let tracking = matches!(privacy.get("NSPrivacyTracking"), Some(Value::Boolean(true)));
+    let domains = match privacy.get("NSPrivacyTrackingDomains") {
+        Some(Value::Array(domains)) => domains.len(),
+        _ => 0,
+    };
+    if tracking && domains == 0 {
+        violations.push(violation(
+            manifest,
+            "NSPrivacyTracking is enabled but NSPrivacyTrackingDomains is empty".to_string(),
+        ));
+    }
+
+    let mut declared = Vec::new();
+    let api_types = match privacy.get("NSPrivacyAccessedAPITypes") {
+        Some(Value::Array(api_types)) => api_types.as_slice(),
+        Some(_) => {
+            violations.push(violation(
+                manifest,
+                "NSPrivacyAccessedAPITypes is not an array".to_string(),
+            ));
+            &[]
+        }
+        None => &[],
+    };
+    for api_type in api_types {
+        let api_type = api_type.as_dictionary();
+        let Some(category) = api_type
+            .and_then(|t| t.get("NSPrivacyAccessedAPIType"))
+            .and_then(Value::as_str)
+        else {
+            violations.push(violation(
+                manifest,
+                "NSPrivacyAccessedAPITypes entry lacks NSPrivacyAccessedAPIType".to_string(),
+            ));
+            continue;
+        };
+        let Some((_, allowed, _)) = REQUIRED_REASON_APIS.iter().find(|(c, _, _)| *c == category)
+        else {
+            violations.push(violation(
+                manifest,
+                format!("unknown required-reason API category {}", category),
+            ));
+            continue;
+        };
+        declared.push(category);
+
+        let reasons = match api_type.and_then(|t| t.get("NSPrivacyAccessedAPITypeReasons")) {
+            Some(Value::Array(reasons)) => reasons.as_slice(),
+            _ => &[],
+        };
+        if reasons.is_empty() {
+            violations.push(violation(
+                manifest,
+                format!("{} declares no reasons", category),
+            ));
+        }
+        for reason in reasons {
+            match reason.as_str() {
+                Some(reason) if allowed.contains(&reason) => {}
+                Some(reason) => violations.push(violation(
+                    manifest,
+                    format!("{} is no valid reason for {}", reason, category),
+                )),
+                None => violations.push(violation(
+                    manifest,
+                    format!("{} reasons must be strings", category),
+                )),
+            }
+        }
+    }
+
+    for category in used {
+        if !declared.contains(category) {
+            violations.push(violation(
+                manifest,
+                format!(
+                    "the executable uses {} APIs without declaring a reason",
+                    category
+                ),
+            ));
+        }
+    }
+    violations
+}
+
+fn check_app_transport_security(info_plist: &Path, info: &Dictionary) -> Vec<Violation> {
+    let mut violations = Vec::new();
+    let Some(ats) = info
+        .get("NSAppTransportSecurity")
+        .and_then(Value::as_dictionary)
+    else {
+        return violations;
+    };
+
+    for key in [
+        "NSAllowsArbitraryLoads",
+        "NSAllowsArbitraryLoadsForMedia",
+        "NSAllowsArbitraryLoadsInWebContent",
+    ] {
+        if ats.get(key) == Some(&Value::Boolean(true)) {
+            violations.push(violation(
+                info_plist,
+                format!("ATS: {} disables App Transport Security", key),
+            ));
+        }
+    }
+
+    let domains = ats.get("NSExceptionDomains").and_then(Value::as_dictionary);
+    for (domain, exception) in domains.into_iter().flatten() {
+        let Some(exception) = exception.as_dictionary() else {
+            continue;
+        };
+        if exception.get("NSExceptionAllowsInsecureHTTPLoads") == Some(&Value::Boolean(true)) {
+            violations.push(violation(
+                info_plist,
+                format!("ATS: {} allows insecure HTTP loads", domain),
+            ));
+        }
+        if let Some(version) = exception
+            .get("NSExceptionMinimumTLSVersion")
+            .and_then(Value::as_str)
+        {
+            if matches!(version, "TLSv1.0" | "TLSv1.1") {
+                violations.push(violation(
+                    info_plist,
+                    format!("ATS: {} allows {}", domain, version),
+                ));
+            }
+        }
+    }
+    violations
+}
+
+#[test]
+fn test_check_app_store_requirements() {
+    let root = Path::new("target/check_app_store_requirements");
+    let _ = fs::remove_dir_all(root);
+    let app = root.join("MyApp.app");
+    fs::create_dir_all(app.join("Contents/MacOS")).unwrap();
+    fs::create_dir_all(app.join("Contents/Resources")).unwrap();
+    fs::write(
+        app.join("Contents/MacOS/MyApp"),
+        b"\0_mach_absolute_time\0_stat$INODE64\0_statistics\0",
+    )
+    .unwrap();
+
+    let mut exception = Dictionary::new();
+    exception.insert("NSExceptionAllowsInsecureHTTPLoads".into(), true.into());
+    let mut domains = Dictionary::new();
+    domains.insert("example.com".into(), exception.into());
+    let mut ats = Dictionary::new();
+    ats.insert("NSExceptionDomains".into(), domains.into());
+    let mut info = Dictionary::new();
+    info.insert("CFBundleExecutable".into(), "MyApp".into());
+    info.insert("NSAppTransportSecurity".into(), ats.into());
+    Value::Dictionary(info)
+        .write(&app.join("Contents/Info.plist"))
+        .unwrap();
+
+    let messages = |violations: Vec<Violation>| -> Vec<String> {
+        violations.into_iter().map(|v| v.message).collect()
+    };
+    assert_eq!(
+        messages(check_app_store_requirements(&app).unwrap()),
+        [
+            "ATS: example.com allows insecure HTTP loads",
+            "PrivacyInfo.xcprivacy is missing"
+        ]
+    );
+
+    let mut boot_time = Dictionary::new();
+    boot_time.insert(
+        "NSPrivacyAccessedAPIType".into(),
+        "NSPrivacyAccessedAPICategorySystemBootTime".into(),
+    );
+    boot_time.insert(
+        "NSPrivacyAccessedAPITypeReasons".into(),
+        vec!["CA92.1"].into(),
+    );
+    let mut privacy = Dictionary::new();
+    privacy.insert("NSPrivacyTracking".into(), true.into());
+    privacy.insert("NSPrivacyAccessedAPITypes".into(), vec![boot_time].into());
+    Value::Dictionary(privacy)
+        .write(&app.join("Contents/Resources").join(PRIVACY_MANIFEST))
+        .unwrap();
+    assert_eq!(
+        messages(check_app_store_requirements(&app).unwrap())[1..],
+        [
+            "NSPrivacyTracking is enabled but NSPrivacyTrackingDomains is empty",
+            "CA92.1 is no valid reason for NSPrivacyAccessedAPICategorySystemBootTime",
+            "the executable uses NSPrivacyAccessedAPICategoryFileTimestamp APIs without declaring a reason"
+        ]
+    );
+
+    fs::remove_dir_all(root).unwrap();
+}
@@ -0,0 +1,268 @@
+//! Processing assets while they are staged into a bundle.
+//!
+//! An [`AssetProcessor`] converts the files it handles (e.g. audio to CAF) instead of copying
+//! them verbatim, so converted assets don't have to be committed to the repository. Files no
+//! processor handles are copied.
+
+use std::fs;
+use std::io::Error;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::build_script::BuildScriptOutput;
+use crate::concurrency::parallel_map;
+use crate::copy::nested_destination;
+use crate::fileset::{FileSet, PatternOptions};
+#[cfg(target_os = "macos")]
+use crate::tool::Tool;
+
+/// A conversion applied to assets during staging.
+pub trait AssetProcessor: Send + Sync {
+    /// `true` if the processor handles this file.
+    fn handles(&self, source: &Path) -> bool;
+
+    /// Path of the processed file relative to the destination, e.g. with a new extension.
+    fn output_path(&self, relative_path: &Path) -> PathBuf {
+        relative_path.to_path_buf()
+    }
+
+    /// Process `source` into `destination`. The parent directory of `destination` exists.
+    fn process(&self, source: &Path, destination: &Path) -> Result<(), Error>;
+}
+
+/// Copies files with the given extensions verbatim.
+pub struct CopyProcessor {
+    pub extensions: Vec<String>,
+}
+
+impl AssetProcessor for CopyProcessor {
+    fn handles(&self, source: &Path) -> bool {
+        has_extension(source, &self.extensions)
+    }
+
+    fn process(&self, source: &Path, destination: &Path) -> Result<(), Error> {
+        fs::copy(source, destination).map(|_| ())
+    }
+}
+
+/// Converts audio files to Core Audio Format (`.caf`) with afconvert on macOS.
+///
+/// afconvert only exists on macOS, elsewhere the files are copied unchanged.
+pub struct AfconvertProcessor {
+    /// Extensions of the files to convert
+    pub extensions: Vec<String>,
+    /// Data format passed to `afconvert -d` (e.g. `ima4`, `aac`, `LEI16`)
+    pub data_format: String,
+}
+
+impl Default for AfconvertProcessor {
+    fn default() -> Self {
+        AfconvertProcessor {
+            extensions: ["wav", "aif", "aiff"].map(String::from).to_vec(),
+            data_format: "ima4".to_string(),
+        }
+    }
+}
+
+impl AssetProcessor for AfconvertProcessor {
+    fn handles(&self, source: &Path) -> bool {
+        has_extension(source, &self.extensions)
+    }
+
+    fn output_path(&self, relative_path: &Path) -> PathBuf {
+        if cfg!(target_os = "macos") {
+            relative_path.with_extension("caf")
+        } else {
+            relative_path.to_path_buf()
+        }
+    }
+
+    #[cfg(target_os = "macos")]
+    fn process(&self, source: &Path, destination: &Path) -> Result<(), Error> {
+        Tool::new("afconvert")
+            .args(["-f", "caff", "-d"])
+            .arg(&self.data_format)
+            .arg(source)
+            .arg(destination)
+            .run()
+            .map_err(Error::other)?;
+        Ok(())
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    fn process(&self, source: &Path, destination: &Path) -> Result<(), Error> {
+        fs::copy(source, destination).map(|_| ())
+    }
+}
+
+fn has_extension(path: &Path, extensions: &[String]) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| extensions.iter().any(|x| x.eq_ignore_ascii_case(e)))
+}
+
+/// Options for [`stage_assets_with_options`].
+#[derive(Clone, Debug, Default)]
+pub struct StageOptions {
+    /// Matching of the pattern and the walk, e.g. to leave out hidden files
+    pub patterns: PatternOptions,
+    /// Report the source directory and the staged files as inputs of a build script
+    pub build_script: Option<BuildScriptOutput>,
+}
+
+/// Stage files matching a glob pattern into a directory, running them through the first
+/// processor that handles them. Other files are copied. Returns the staged files.
+///
+/// Like [`crate::CopyPlanner::plan`], a destination inside the source is left out.
+///
+/// # Arguments
+///
+/// * `source` - the source directory
+/// * `destination` - the destination directory, e.g. a bundle's resources
+/// * `pattern` - a standard glob pattern selecting the files
+/// * `processors` - the processors to try, in order
+///
+/// # Example
+///
+/// ```no_run
+/// use std::path::Path;
+/// use std::sync::Arc;
+/// use toolbelt::assets::{stage_assets, AfconvertProcessor, AssetProcessor};
+///
+/// let processors: Vec<Arc<dyn AssetProcessor>> = vec![Arc::new(AfconvertProcessor::default())];
+/// stage_assets(
+///     Path::new("sounds"),
+///     Path::new("target/MyApp.app/Contents/Resources/Sounds"),
+///     "**/*",
+///     &processors,
+/// )
+/// .unwrap();
+/// ```
+pub fn stage_assets(
+    source: &Path,
+    destination: &Path,
+    pattern: &str,
+    processors: &[Arc<dyn AssetProcessor>],
+) -> Result<Vec<PathBuf>, Error> {
+    stage_assets_with_options(
+        source,
+        destination,
+        pattern,
+        processors,
+        &StageOptions::default(),
+    )
+}
+
+/// Like [`stage_assets`], with additional options.
+pub fn stage_assets_with_options(
+    source: &Path,
+    destination: &Path,
+    pattern: &str,
+    processors: &[Arc<dyn AssetProcessor>],
+    options: &StageOptions,
+) -> Result<Vec<PathBuf>, Error> {
+    let nested = nested_destination(&source.canonicalize()?, destination)?;
+    let mut files = Vec::new();
+    for entry in FileSet::new(source)
+        .pattern(pattern)
+        .options(options.patterns.clone())
+    {
+        let entry = entry?;
+        if nested.as_ref().is_some_and(|nested| {
+            entry
+                .path
+                .canonicalize()
+                .is_ok_and(|p| p.starts_with(nested))
+        }) {
+            continue;
+        }
+        if entry.metadata.is_file() {
+            files.push((entry.path, entry.relative_path));
+        }
+    }
+    if let Some(output) = &options.build_script {
+        output.rerun_if_changed(source);
+        for (path, _) in &files {
+            output.rerun_if_changed(path);
+        }
+    }
+
+    parallel_map(&files, |(path, relative_path)| {
+        let processor = processors.iter().find(|p| p.handles(path));
+        let output = destination.join(match processor {
+            Some(processor) => processor.output_path(relative_path),
+            None => relative_path.clone(),
+        });
+        if let Some(parent) = output.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        match processor {
+            Some(processor) => {
+                debug!("Process {} to {}", path.display(), output.display());
+                processor.process(path, &output)?;
+            }
+            None => {
+                fs::copy(path, &output)?;
+            }
+        }
+        Ok(output)
+    })
+    .into_iter()
+    .collect()
+}
+
+#[test]
+fn test_stage_assets() {
+    struct Upper;
+    impl AssetProcessor for Upper {
+        fn handles(&self, source: &Path) -> bool {
+            source.extension().is_some_and(|e| e == "txt")
+        }
+        fn output_path(&self, relative_path: &Path) -> PathBuf {
+            relative_path.with_extension("TXT")
+        }
+        fn process(&self, source: &Path, destination: &Path) -> Result<(), Error> {
+            let text = fs::read_to_string(source)?;
+            fs::write(destination, text.to_uppercase())
+        }
+    }
+
+    let root = Path::new("target/stage_assets");
+    let _ = fs::remove_dir_all(root);
+    fs::create_dir_all(root.join("src/sub")).unwrap();
+    fs::write(root.join("src/sub/a.txt"), "hello").unwrap();
+    fs::write(root.join("src/b.md"), "# b").unwrap();
+
+    let processors: Vec<Arc<dyn AssetProcessor>> = vec![Arc::new(Upper)];
+    let mut staged =
+        stage_assets(&root.join("src"), &root.join("dst"), "**/*", &processors).unwrap();
+    staged.sort();
+    assert_eq!(staged, [root.join("dst/b.md"), root.join("dst/sub/a.TXT")]);
+    assert_eq!(
+        fs::read_to_string(root.join("dst/sub/a.TXT")).unwrap(),
+        "HELLO"
+    );
+
+    let output = BuildScriptOutput::capture();
+    let options = StageOptions {
+        build_script: Some(output.clone()),
+        ..Default::default()
+    };
+    stage_assets_with_options(
+        &root.join("src"),
+        &root.join("dst"),
+        "**/*.md",
+        &processors,
+        &options,
+    )
+    .unwrap();
+    assert_eq!(
+        output.lines(),
+        [
+            format!("cargo:rerun-if-changed={}", root.join("src").display()),
+            format!("cargo:rerun-if-changed={}", root.join("src/b.md").display()),
+        ]
+    );
+
+    fs::remove_dir_all(root).unwrap();
+}
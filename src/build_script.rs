@@ -0,0 +1,154 @@
+//! Telling cargo which inputs a build script depends on.
+//!
+//! Without any `cargo:rerun-if-changed` line cargo reruns a build script whenever any file of the
+//! package changes, and never when an SDK outside of it does. Once a script prints one such line,
+//! cargo only reruns it for the listed paths, so every input has to be listed or the build goes
+//! stale. Functions that read inputs take an optional [`BuildScriptOutput`] and report every file,
+//! directory and environment variable they read to it.
+
+use std::collections::BTreeSet;
+use std::fmt;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+#[derive(Debug, Default)]
+struct State {
+    /// Lines printed or captured so far, each is only emitted once
+    emitted: BTreeSet<String>,
+    /// Captured lines in the order they were emitted, `None` to print them
+    captured: Option<Vec<String>>,
+}
+
+/// A sink for the `cargo:rerun-if-*` lines of a build script.
+///
+/// Clones share their state, so one sink can be passed to several options structs and each line
+/// is emitted once, however many functions report the same input.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::path::Path;
+/// use toolbelt::{copy_dir_with_options, BuildScriptOutput, CopyOptions};
+///
+/// let output = BuildScriptOutput::new();
+/// let options = CopyOptions {
+///     build_script: Some(output.clone()),
+///     ..Default::default()
+/// };
+/// copy_dir_with_options(Path::new("assets"), Path::new("target/assets"), "**/*.png", &options)
+///     .unwrap();
+/// output.rerun_if_changed(Path::new("build.rs"));
+/// ```
+#[derive(Clone, Default)]
+pub struct BuildScriptOutput {
+    state: Arc<Mutex<State>>,
+}
+
+impl BuildScriptOutput {
+    /// A sink printing the lines to stdout, where cargo reads them.
+    pub fn new() -> BuildScriptOutput {
+        BuildScriptOutput::default()
+    }
+
+    /// A sink collecting the lines instead of printing them, see [`BuildScriptOutput::lines`].
+    pub fn capture() -> BuildScriptOutput {
+        BuildScriptOutput {
+            state: Arc::new(Mutex::new(State {
+                captured: Some(Vec::new()),
+                ..State::default()
+            })),
+        }
+    }
+
+    fn emit(&self, line: String) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        if !state.emitted.insert(line.clone()) {
+            return;
+        }
+        match &mut state.captured {
+            Some(lines) => lines.push(line),
+            None => println!("{}", line),
+        }
+    }
+
+    /// Rerun the build script if the file changes, or for a directory, if anything below it
+    /// changes.
+    pub fn rerun_if_changed(&self, path: &Path) {
+        let path = path.display().to_string();
+        if path.contains('\n') {
+            warn!("Can't watch {:?}, cargo doesn't support line breaks", path);
+            return;
+        }
+        self.emit(format!("cargo:rerun-if-changed={}", path));
+    }
+
+    /// Rerun the build script if the environment variable is set, unset or changes its value.
+    pub fn rerun_if_env_changed(&self, name: &str) {
+        self.emit(format!("cargo:rerun-if-env-changed={}", name));
+    }
+
+    /// The lines of a [captured](BuildScriptOutput::capture) sink in the order they were emitted,
+    /// empty for a printing one.
+    pub fn lines(&self) -> Vec<String> {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.captured.clone().unwrap_or_default()
+    }
+}
+
+impl fmt::Debug for BuildScriptOutput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        f.debug_struct("BuildScriptOutput")
+            .field("emitted", &state.emitted.len())
+            .field("captured", &state.captured.is_some())
+            .finish()
+    }
+}
+
+/// Sinks are equal if they share their state.
+impl PartialEq for BuildScriptOutput {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.state, &other.state)
+    }
+}
+
+impl Eq for BuildScriptOutput {}
+
+#[test]
+fn test_build_script_output() {
+    let output = BuildScriptOutput::capture();
+    let shared = output.clone();
+    output.rerun_if_changed(Path::new("assets"));
+    shared.rerun_if_env_changed("FOO_SDK");
+    shared.rerun_if_changed(Path::new("assets"));
+    output.rerun_if_changed(Path::new("bad\nname"));
+    assert_eq!(
+        output.lines(),
+        [
+            "cargo:rerun-if-changed=assets",
+            "cargo:rerun-if-env-changed=FOO_SDK"
+        ]
+    );
+    assert_eq!(output, shared);
+    assert_ne!(output, BuildScriptOutput::capture());
+    assert!(BuildScriptOutput::new().lines().is_empty());
+
+    let destination = Path::new("target/build_script_output");
+    let _ = std::fs::remove_dir_all(destination);
+    let output = BuildScriptOutput::capture();
+    let options = crate::CopyOptions {
+        build_script: Some(output.clone()),
+        ..Default::default()
+    };
+    let source = Path::new("test/my_files").canonicalize().unwrap();
+    crate::copy_dir_with_options(&source, destination, "**/*.{txt,md}", &options).unwrap();
+    crate::copy_dir_with_options(&source, destination, "file2.csv", &options).unwrap();
+    let lines = output.lines();
+    let watched = |path: &Path| format!("cargo:rerun-if-changed={}", path.display());
+    assert_eq!(lines.len(), 4);
+    assert_eq!(lines[0], watched(&source));
+    assert!(lines.contains(&watched(&source.join("file1.txt"))));
+    assert!(lines.contains(&watched(&source.join("more_files/file3.md"))));
+    assert_eq!(lines[3], watched(&source.join("file2.csv")));
+    std::fs::remove_dir_all(destination).unwrap();
+}
@@ -0,0 +1,1085 @@
+//! Builders for macOS bundle layouts.
+//!
+//! [`BundleBuilder`] assembles loadable bundles: plain `.bundle` and `.plugin` bundles, Audio Unit
+//! components, Quick Look generators and app extensions (`.appex`). Each [`BundleKind`] adds the
+//! Info.plist keys the system needs to load it. [`AppBundleBuilder`] builds on it to assemble
+//! complete `.app` bundles including frameworks and signing.
+
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::assets::{stage_assets, AssetProcessor};
+use crate::codesign::{signature_team_id, CodesignOptions};
+use crate::config::Config;
+use crate::copy::{copy_dir_with_options, CopyOptions};
+use crate::install_name::{
+    add_rpath, change_dependency, rpath_name, set_install_name, FRAMEWORKS_RPATH,
+};
+use crate::macho;
+use crate::plist::{Dictionary, Value};
+use crate::provisioning::embed_provisioning_profile;
+
+/// Plugin type identifier of Quick Look generators.
+const QUICK_LOOK_GENERATOR_TYPE: &str = "5E2D9680-5022-40FA-B806-43349622E5B9";
+
+/// Description of an Audio Unit registered via the `AudioComponents` Info.plist key.
+#[derive(Clone, Debug)]
+pub struct AudioComponent {
+    /// Four character type code, e.g. `aufx` for effects
+    pub component_type: String,
+    /// Four character subtype code
+    pub subtype: String,
+    /// Four character manufacturer code
+    pub manufacturer: String,
+    /// Name shown to users, usually "Manufacturer: Name"
+    pub name: String,
+    pub version: i64,
+    /// Name of the factory function exported by the executable
+    pub factory_function: String,
+}
+
+/// The type of a bundle.
+#[derive(Clone, Debug)]
+pub enum BundleKind {
+    /// An application `.app`, see [`AppBundleBuilder`]
+    Application,
+    /// A plain loadable `.bundle`
+    Bundle,
+    /// A `.plugin` bundle loaded by a host application
+    Plugin,
+    /// An Audio Unit `.component`
+    AudioUnit(AudioComponent),
+    /// A Quick Look `.qlgenerator`
+    QuickLookGenerator {
+        /// Uniform type identifiers the generator handles
+        content_types: Vec<String>,
+        /// UUID of the plugin factory implemented by the executable
+        factory_uuid: String,
+    },
+    /// An app extension `.appex`
+    AppExtension {
+        /// e.g. `com.apple.quicklook.preview`
+        extension_point: String,
+        principal_class: String,
+    },
+}
+
+impl BundleKind {
+    /// File name extension of the bundle directory.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            BundleKind::Application => "app",
+            BundleKind::Bundle => "bundle",
+            BundleKind::Plugin => "plugin",
+            BundleKind::AudioUnit(_) => "component",
+            BundleKind::QuickLookGenerator { .. } => "qlgenerator",
+            BundleKind::AppExtension { .. } => "appex",
+        }
+    }
+
+    /// Value of the `CFBundlePackageType` key.
+    pub fn package_type(&self) -> &'static str {
+        match self {
+            BundleKind::Application => "APPL",
+            BundleKind::AppExtension { .. } => "XPC!",
+            _ => "BNDL",
+        }
+    }
+
+    /// Entitlements the bundle must be signed with to be loaded.
+    ///
+    /// App extensions are only loaded if they are sandboxed.
+    pub fn required_entitlements(&self) -> Dictionary {
+        let mut entitlements = Dictionary::new();
+        if let BundleKind::AppExtension { .. } = self {
+            entitlements.insert("com.apple.security.app-sandbox".into(), true.into());
+        }
+        entitlements
+    }
+
+    fn info(&self) -> Dictionary {
+        let mut info = Dictionary::new();
+        match self {
+            BundleKind::Application => {
+                info.insert("NSHighResolutionCapable".into(), true.into());
+            }
+            BundleKind::Bundle | BundleKind::Plugin => {}
+            BundleKind::AudioUnit(component) => {
+                let mut description = Dictionary::new();
+                description.insert("type".into(), component.component_type.as_str().into());
+                description.insert("subtype".into(), component.subtype.as_str().into());
+                description.insert(
+                    "manufacturer".into(),
+                    component.manufacturer.as_str().into(),
+                );
+                description.insert("name".into(), component.name.as_str().into());
+                description.insert("version".into(), component.version.into());
+                description.insert(
+                    "factoryFunction".into(),
+                    component.factory_function.as_str().into(),
+                );
+                description.insert("sandboxSafe".into(), true.into());
+                info.insert("AudioComponents".into(), vec![description].into());
+            }
+            BundleKind::QuickLookGenerator {
+                content_types,
+                factory_uuid,
+            } => {
+                let mut document_type = Dictionary::new();
+                document_type.insert("CFBundleTypeRole".into(), "QLGenerator".into());
+                document_type.insert("LSItemContentTypes".into(), content_types.clone().into());
+                info.insert("CFBundleDocumentTypes".into(), vec![document_type].into());
+
+                let mut factories = Dictionary::new();
+                factories.insert(
+                    factory_uuid.clone(),
+                    "QuickLookGeneratorPluginFactory".into(),
+                );
+                let mut types = Dictionary::new();
+                types.insert(
+                    QUICK_LOOK_GENERATOR_TYPE.into(),
+                    vec![factory_uuid.clone()].into(),
+                );
+                info.insert("CFPlugInDynamicRegistration".into(), "NO".into());
+                info.insert("CFPlugInFactories".into(), factories.into());
+                info.insert("CFPlugInTypes".into(), types.into());
+                info.insert("QLNeedsToBeRunInMainThread".into(), false.into());
+                info.insert("QLSupportsConcurrentRequests".into(), false.into());
+            }
+            BundleKind::AppExtension {
+                extension_point,
+                principal_class,
+            } => {
+                let mut extension = Dictionary::new();
+                extension.insert(
+                    "NSExtensionPointIdentifier".into(),
+                    extension_point.as_str().into(),
+                );
+                extension.insert(
+                    "NSExtensionPrincipalClass".into(),
+                    principal_class.as_str().into(),
+                );
+                info.insert("NSExtension".into(), extension.into());
+            }
+        }
+        info
+    }
+}
+
+/// Assembles a loadable bundle: `Name.ext/Contents/{Info.plist,MacOS,Resources}`.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::path::Path;
+/// use toolbelt::bundle::{BundleBuilder, BundleKind};
+///
+/// let appex = BundleBuilder::new(
+///     BundleKind::AppExtension {
+///         extension_point: "com.apple.quicklook.preview".into(),
+///         principal_class: "PreviewProvider".into(),
+///     },
+///     "Preview",
+///     "com.example.app.preview",
+/// )
+/// .executable(Path::new("target/release/preview"))
+/// .resources(Path::new("resources"), "**/*")
+/// .build(Path::new("target/bundles"))
+/// .unwrap();
+/// ```
+pub struct BundleBuilder {
+    kind: BundleKind,
+    name: String,
+    identifier: String,
+    version: String,
+    executable: Option<PathBuf>,
+    resources: Vec<(PathBuf, String)>,
+    processors: Vec<Arc<dyn AssetProcessor>>,
+    info: Dictionary,
+}
+
+impl BundleBuilder {
+    /// # Arguments
+    ///
+    /// * `kind` - the type of bundle
+    /// * `name` - the bundle name, also used as name of the executable
+    /// * `identifier` - the bundle identifier, e.g. `com.example.plugin`
+    pub fn new(kind: BundleKind, name: &str, identifier: &str) -> BundleBuilder {
+        BundleBuilder {
+            kind,
+            name: name.to_string(),
+            identifier: identifier.to_string(),
+            version: std::env::var("CARGO_PKG_VERSION").unwrap_or_else(|_| "1.0.0".into()),
+            executable: None,
+            resources: Vec::new(),
+            processors: Vec::new(),
+            info: Dictionary::new(),
+        }
+    }
+
+    /// Set the bundle version. Defaults to the version of the package being built.
+    pub fn version(mut self, version: &str) -> BundleBuilder {
+        self.version = version.to_string();
+        self
+    }
+
+    /// The executable to place in `Contents/MacOS`.
+    pub fn executable(mut self, path: &Path) -> BundleBuilder {
+        self.executable = Some(path.to_path_buf());
+        self
+    }
+
+    /// Copy files matching a glob pattern to `Contents/Resources`. May be called repeatedly.
+    pub fn resources(mut self, source: &Path, pattern: &str) -> BundleBuilder {
+        self.resources
+            .push((source.to_path_buf(), pattern.to_string()));
+        self
+    }
+
+    /// Run resources through an [`AssetProcessor`] while they are staged. Processors are tried in
+    /// the order they are added, files no processor handles are copied.
+    pub fn asset_processor<P: AssetProcessor + 'static>(mut self, processor: P) -> BundleBuilder {
+        self.processors.push(Arc::new(processor));
+        self
+    }
+
+    /// Add or override an Info.plist value.
+    pub fn info<V: Into<Value>>(mut self, key: &str, value: V) -> BundleBuilder {
+        self.info.insert(key.to_string(), value.into());
+        self
+    }
+
+    /// The kind of bundle being built.
+    pub fn kind(&self) -> &BundleKind {
+        &self.kind
+    }
+
+    /// The complete Info.plist contents.
+    pub fn info_plist(&self) -> Dictionary {
+        let mut info = Dictionary::new();
+        info.insert("CFBundleDevelopmentRegion".into(), "en".into());
+        if self.executable.is_some() {
+            info.insert("CFBundleExecutable".into(), self.name.as_str().into());
+        }
+        info.insert("CFBundleIdentifier".into(), self.identifier.as_str().into());
+        info.insert("CFBundleInfoDictionaryVersion".into(), "6.0".into());
+        info.insert("CFBundleName".into(), self.name.as_str().into());
+        info.insert(
+            "CFBundlePackageType".into(),
+            self.kind.package_type().into(),
+        );
+        info.insert(
+            "CFBundleShortVersionString".into(),
+            self.version.as_str().into(),
+        );
+        info.insert("CFBundleVersion".into(), self.version.as_str().into());
+        info.extend(self.kind.info());
+        info.extend(self.info.clone());
+        info
+    }
+
+    /// Create the bundle inside `output_dir`, replacing an existing bundle of the same name.
+    ///
+    /// Returns the path of the bundle.
+    pub fn build(&self, output_dir: &Path) -> Result<PathBuf, Error> {
+        validate_bundle_identifier(&self.identifier)?;
+        let bundle = output_dir.join(format!("{}.{}", self.name, self.kind.extension()));
+        debug!("Build bundle {}", bundle.display());
+        if bundle.exists() {
+            std::fs::remove_dir_all(&bundle)?;
+        }
+        let contents = bundle.join("Contents");
+        std::fs::create_dir_all(&contents)?;
+
+        if let Some(executable) = &self.executable {
+            let macos = contents.join("MacOS");
+            std::fs::create_dir_all(&macos)?;
+            install_executable(executable, &macos.join(&self.name))?;
+        }
+
+        let resources = contents.join("Resources");
+        for (source, pattern) in &self.resources {
+            if self.processors.is_empty() {
+                copy_dir_with_options(source, &resources, pattern, &CopyOptions::default())?;
+            } else {
+                stage_assets(source, &resources, pattern, &self.processors)?;
+            }
+        }
+
+        Value::Dictionary(self.info_plist()).write(&contents.join("Info.plist"))?;
+        Ok(bundle)
+    }
+}
+
+/// Assembles a macOS application:
+/// `Name.app/Contents/{Info.plist,PkgInfo,MacOS,Resources,Frameworks}`, optionally signing it.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::path::Path;
+/// use toolbelt::bundle::AppBundleBuilder;
+/// use toolbelt::CodesignOptions;
+///
+/// let app = AppBundleBuilder::new("MyApp", "com.example.myapp")
+///     .executable(Path::new("target/release/myapp"))
+///     .resources(Path::new("resources"), "**/*")
+///     .framework(Path::new("vendor/Sparkle.framework"))
+///     .info("LSMinimumSystemVersion", "11.0")
+///     .sign(CodesignOptions::default())
+///     .build(Path::new("target/release"))
+///     .unwrap();
+/// ```
+pub struct AppBundleBuilder {
+    bundle: BundleBuilder,
+    frameworks: Vec<PathBuf>,
+    app_extensions: Vec<PathBuf>,
+    provisioning_profile: Option<PathBuf>,
+    signing: Option<CodesignOptions>,
+}
+
+impl AppBundleBuilder {
+    /// # Arguments
+    ///
+    /// * `name` - the application name, also used as name of the executable
+    /// * `identifier` - the bundle identifier, e.g. `com.example.myapp`
+    pub fn new(name: &str, identifier: &str) -> AppBundleBuilder {
+        AppBundleBuilder {
+            bundle: BundleBuilder::new(BundleKind::Application, name, identifier),
+            frameworks: Vec::new(),
+            app_extensions: Vec::new(),
+            provisioning_profile: None,
+            signing: None,
+        }
+    }
+
+    /// Set the application version. Defaults to the version of the package being built.
+    pub fn version(mut self, version: &str) -> AppBundleBuilder {
+        self.bundle = self.bundle.version(version);
+        self
+    }
+
+    /// The executable to place in `Contents/MacOS`.
+    pub fn executable(mut self, path: &Path) -> AppBundleBuilder {
+        self.bundle = self.bundle.executable(path);
+        self
+    }
+
+    /// Copy files matching a glob pattern to `Contents/Resources`. May be called repeatedly.
+    pub fn resources(mut self, source: &Path, pattern: &str) -> AppBundleBuilder {
+        self.bundle = self.bundle.resources(source, pattern);
+        self
+    }
+
+    /// Run resources through an [`AssetProcessor`] while they are staged.
+    pub fn asset_processor<P: AssetProcessor + 'static>(
+        mut self,
+        processor: P,
+    ) -> AppBundleBuilder {
+        self.bundle = self.bundle.asset_processor(processor);
+        self
+    }
+
+    /// Embed a `.framework` bundle or a dynamic library in `Contents/Frameworks`. May be called
+    /// repeatedly.
+    pub fn framework(mut self, path: &Path) -> AppBundleBuilder {
+        self.frameworks.push(path.to_path_buf());
+        self
+    }
+
+    /// Embed an app extension in `Contents/PlugIns`, see [`embed_appex`]. May be called
+    /// repeatedly.
+    pub fn app_extension(mut self, path: &Path) -> AppBundleBuilder {
+        self.app_extensions.push(path.to_path_buf());
+        self
+    }
+
+    /// Add or override an Info.plist value.
+    pub fn info<V: Into<Value>>(mut self, key: &str, value: V) -> AppBundleBuilder {
+        self.bundle = self.bundle.info(key, value);
+        self
+    }
+
+    /// Embed a provisioning profile, see [`crate::provisioning::embed_provisioning_profile`].
+    pub fn provisioning_profile(mut self, path: &Path) -> AppBundleBuilder {
+        self.provisioning_profile = Some(path.to_path_buf());
+        self
+    }
+
+    /// Sign the application after it has been assembled.
+    pub fn sign(mut self, options: CodesignOptions) -> AppBundleBuilder {
+        self.signing = Some(options);
+        self
+    }
+
+    /// The complete Info.plist contents.
+    pub fn info_plist(&self) -> Dictionary {
+        self.bundle.info_plist()
+    }
+
+    /// Create the application inside `output_dir`, replacing an existing one of the same name.
+    ///
+    /// Embedded frameworks and app extensions are signed before the application itself, as the
+    /// application's signature seals everything inside it. Returns the path of the application.
+    pub fn build(&self, output_dir: &Path) -> Result<PathBuf, Error> {
+        let app = self.bundle.build(output_dir)?;
+        let contents = app.join("Contents");
+
+        // package type and creator code, still read by some tools
+        std::fs::write(contents.join("PkgInfo"), "APPL????")?;
+
+        if !self.frameworks.is_empty() {
+            embed_frameworks(&app, &self.frameworks, self.signing.as_ref())?;
+        }
+
+        for appex in &self.app_extensions {
+            embed_appex(&app, appex, self.signing.as_ref())?;
+        }
+
+        if let Some(profile) = &self.provisioning_profile {
+            embed_provisioning_profile(&app, profile)?;
+        }
+
+        if let Some(options) = &self.signing {
+            crate::codesign_with_options(&app, options).map_err(Error::other)?;
+        }
+
+        Ok(app)
+    }
+}
+
+/// Embed frameworks and dynamic libraries in an app's `Contents/Frameworks` so the app loads
+/// them from there.
+///
+/// Each library gets an `@rpath/...` install name, the references of the app's executable and
+/// the embedded libraries to it are rewritten, and the executable gets the
+/// [`FRAMEWORKS_RPATH`] rpath. As rewriting install names invalidates signatures, the embedded
+/// libraries are then signed with `signing`, or ad-hoc if they were changed and `signing` is
+/// `None`. Sign the app itself afterwards, its signature seals the embedded frameworks.
+///
+/// Files that aren't Mach-O binaries are copied unchanged. Returns the embedded paths.
+///
+/// # Arguments
+///
+/// * `app_bundle` - the `.app` bundle
+/// * `frameworks` - paths of `.framework` bundles or `.dylib` files
+/// * `signing` - the options the app is signed with. Identifier and entitlements only apply to
+///   the app and are left out for the frameworks.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::path::Path;
+/// use toolbelt::bundle::embed_frameworks;
+/// use toolbelt::{codesign_with_options, CodesignOptions};
+///
+/// let app = Path::new("target/release/MyApp.app");
+/// let options = CodesignOptions {
+///     identity: "Developer ID Application: Example Inc (ABCDE12345)".to_string(),
+///     ..Default::default()
+/// };
+/// embed_frameworks(app, &[Path::new("vendor/Sparkle.framework")], Some(&options)).unwrap();
+/// codesign_with_options(app, &options).unwrap();
+/// ```
+pub fn embed_frameworks<P: AsRef<Path>>(
+    app_bundle: &Path,
+    frameworks: &[P],
+    signing: Option<&CodesignOptions>,
+) -> Result<Vec<PathBuf>, Error> {
+    let directory = app_bundle.join("Contents/Frameworks");
+    std::fs::create_dir_all(&directory)?;
+
+    let mut embedded = Vec::new();
+    for framework in frameworks {
+        let framework = framework.as_ref();
+        let file_name = framework
+            .file_name()
+            .ok_or_else(|| invalid_bundle(framework, "not a framework path"))?;
+        let destination = directory.join(file_name);
+        if framework.is_dir() {
+            copy_dir_with_options(
+                framework,
+                &destination,
+                "**/*",
+                &CopyOptions {
+                    preserve_symlinks: true,
+                    ..Default::default()
+                },
+            )?;
+        } else {
+            std::fs::copy(framework, &destination)?;
+        }
+        embedded.push(destination);
+    }
+
+    // give every embedded library an @rpath install name
+    let mut binaries = Vec::new();
+    let mut renamed = Vec::new();
+    for library in &embedded {
+        let Some(binary) = library_binary(library) else {
+            continue;
+        };
+        let slices = macho::read(&binary)?;
+        if let Some(old) = slices.iter().find_map(|s| s.install_name.clone()) {
+            if !old.starts_with("@rpath/") {
+                let new = rpath_name(&old);
+                set_install_name(&binary, &new).map_err(Error::other)?;
+                renamed.push((old, new));
+            }
+        }
+        binaries.push((library, binary));
+    }
+
+    // point the executable and the libraries at the renamed libraries
+    let executable = read_info_plist(app_bundle)
+        .ok()
+        .and_then(|info| info.get("CFBundleExecutable")?.as_str().map(String::from))
+        .map(|name| app_bundle.join("Contents/MacOS").join(name))
+        .filter(|executable| library_binary(executable).is_some());
+    let linking = executable.iter().chain(binaries.iter().map(|(_, b)| b));
+    for binary in linking {
+        let slices = macho::read(binary)?;
+        for (old, new) in &renamed {
+            if slices.iter().any(|s| s.dylibs.contains(old)) {
+                change_dependency(binary, old, new).map_err(Error::other)?;
+            }
+        }
+    }
+    if let Some(executable) = &executable {
+        let slices = macho::read(executable)?;
+        if !slices
+            .iter()
+            .any(|s| s.rpaths.iter().any(|r| r == FRAMEWORKS_RPATH))
+        {
+            add_rpath(executable, FRAMEWORKS_RPATH).map_err(Error::other)?;
+        }
+    }
+
+    let options = match signing {
+        Some(options) => Some(options.clone()),
+        None if !renamed.is_empty() => Some(CodesignOptions::default()),
+        None => None,
+    };
+    if let Some(options) = options {
+        let nested = CodesignOptions {
+            // identifier and entitlements only apply to the application's executable
+            identifier: None,
+            entitlements: None,
+            ..options
+        };
+        for (library, _) in &binaries {
+            crate::codesign_with_options(library, &nested).map_err(Error::other)?;
+        }
+    }
+
+    Ok(embedded)
+}
+
+/// The Mach-O binary of a framework or dylib, `None` if there is none.
+fn library_binary(library: &Path) -> Option<PathBuf> {
+    let binary = if library.extension().is_some_and(|e| e == "framework") {
+        let name = library.file_stem()?;
+        let versions = library.join("Versions");
+        [library.join(name), versions.join("Current").join(name)]
+            .into_iter()
+            .chain(
+                std::fs::read_dir(&versions)
+                    .into_iter()
+                    .flatten()
+                    .flatten()
+                    .map(|version| version.path().join(name)),
+            )
+            .find(|path| path.is_file())?
+    } else {
+        library.to_path_buf()
+    };
+    let mut magic = [0; 4];
+    let mut file = std::fs::File::open(&binary).ok()?;
+    std::io::Read::read_exact(&mut file, &mut magic).ok()?;
+    macho::is_macho(&magic).then_some(binary)
+}
+
+fn invalid_bundle(bundle: &Path, what: &str) -> Error {
+    Error::new(
+        ErrorKind::InvalidData,
+        format!("{}: {}", bundle.display(), what),
+    )
+}
+
+/// Check that a bundle identifier only consists of dot-separated components of ASCII letters,
+/// digits and hyphens, as required by Apple.
+pub fn validate_bundle_identifier(identifier: &str) -> Result<(), Error> {
+    let valid = identifier.split('.').all(|component| {
+        !component.is_empty()
+            && component
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-')
+    });
+    if valid {
+        Ok(())
+    } else {
+        Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "Invalid bundle identifier {:?}, only letters, digits, hyphens and dots are allowed",
+                identifier
+            ),
+        ))
+    }
+}
+
+/// Derive a bundle identifier from a template like `com.{organization}.{crate_name}`.
+///
+/// Placeholders are looked up in the configuration (dotted keys like `{bundle.team}` work), then
+/// in the metadata cargo passes to build scripts: `{crate_name}` is the package name with
+/// underscores replaced by hyphens. The result is checked with [`validate_bundle_identifier`].
+///
+/// # Arguments
+///
+/// * `template` - the identifier with `{placeholder}`s
+/// * `config` - configuration supplying the placeholder values
+///
+/// # Example
+///
+/// ```no_run
+/// use std::path::Path;
+/// use toolbelt::{bundle_identifier, Config};
+///
+/// let config = Config::load(Path::new("toolbelt.toml")).unwrap();
+/// let identifier = bundle_identifier("com.{organization}.{crate_name}", &config).unwrap();
+/// ```
+pub fn bundle_identifier(template: &str, config: &Config) -> Result<String, Error> {
+    let mut identifier = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        identifier.push_str(&rest[..start]);
+        let end = rest[start..].find('}').ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                format!("Unclosed placeholder in bundle identifier {:?}", template),
+            )
+        })? + start;
+        let name = &rest[start + 1..end];
+        let value = match config.get_str(name) {
+            Some(value) => value.to_string(),
+            None if name == "crate_name" => std::env::var("CARGO_PKG_NAME")
+                .map(|name| name.replace('_', "-"))
+                .map_err(|_| {
+                    Error::new(
+                        ErrorKind::NotFound,
+                        "CARGO_PKG_NAME is not set for {crate_name}",
+                    )
+                })?,
+            None => {
+                return Err(Error::new(
+                    ErrorKind::NotFound,
+                    format!(
+                        "No value for {{{}}} in bundle identifier {:?}",
+                        name, template
+                    ),
+                ))
+            }
+        };
+        identifier.push_str(&value);
+        rest = &rest[end + 1..];
+    }
+    identifier.push_str(rest);
+    validate_bundle_identifier(&identifier)?;
+    Ok(identifier)
+}
+
+/// Read a bundle's Info.plist as dictionary.
+pub(crate) fn read_info_plist(bundle: &Path) -> Result<Dictionary, Error> {
+    match Value::read(&bundle.join("Contents/Info.plist"))? {
+        Value::Dictionary(info) => Ok(info),
+        _ => Err(invalid_bundle(bundle, "Info.plist is not a dictionary")),
+    }
+}
+
+/// Check an app extension's Info.plist for the keys the system needs to load it.
+fn validate_appex_info(appex: &Path, info: &Dictionary) -> Result<(), Error> {
+    for key in ["CFBundleIdentifier", "CFBundleExecutable"] {
+        if info.get(key).and_then(Value::as_str).is_none() {
+            return Err(invalid_bundle(appex, &format!("{} is missing", key)));
+        }
+    }
+    validate_bundle_identifier(info["CFBundleIdentifier"].as_str().unwrap())
+        .map_err(|e| invalid_bundle(appex, &e.to_string()))?;
+    if info.get("CFBundlePackageType").and_then(Value::as_str) != Some("XPC!") {
+        return Err(invalid_bundle(appex, "CFBundlePackageType must be XPC!"));
+    }
+    let extension = info
+        .get("NSExtension")
+        .and_then(Value::as_dictionary)
+        .ok_or_else(|| invalid_bundle(appex, "NSExtension is missing"))?;
+    if extension
+        .get("NSExtensionPointIdentifier")
+        .and_then(Value::as_str)
+        .is_none()
+    {
+        return Err(invalid_bundle(
+            appex,
+            "NSExtension.NSExtensionPointIdentifier is missing",
+        ));
+    }
+    if !["NSExtensionPrincipalClass", "NSExtensionMainStoryboard"]
+        .iter()
+        .any(|key| extension.contains_key(*key))
+    {
+        return Err(invalid_bundle(
+            appex,
+            "NSExtension needs NSExtensionPrincipalClass or NSExtensionMainStoryboard",
+        ));
+    }
+    Ok(())
+}
+
+/// Stage an app extension into an app bundle's `Contents/PlugIns` directory.
+///
+/// The extension's Info.plist is validated first. Its bundle identifier must be prefixed with
+/// the host app's identifier; if it isn't, the identifier is rewritten to
+/// `<host identifier>.<last component>` before the extension is signed.
+///
+/// With `signing`, the embedded extension is (re-)signed with the host's identity, keeping the
+/// entitlements of an existing signature or adding the sandbox entitlement extensions need.
+/// Without it, an already signed extension keeps its signature: it is rejected if its
+/// identifier would have to be rewritten, and if the host is signed too, both must have the
+/// same team ID. Sign the host afterwards, its signature seals the embedded extension.
+///
+/// Returns the path of the embedded extension.
+///
+/// # Arguments
+///
+/// * `app_bundle` - the host `.app` bundle
+/// * `appex_path` - the `.appex` bundle to embed
+/// * `signing` - the options the host is signed with. The identifier only applies to the host
+///   and is left out for the extension.
+pub fn embed_appex(
+    app_bundle: &Path,
+    appex_path: &Path,
+    signing: Option<&CodesignOptions>,
+) -> Result<PathBuf, Error> {
+    let host_info = read_info_plist(app_bundle)?;
+    let host_identifier = host_info
+        .get("CFBundleIdentifier")
+        .and_then(Value::as_str)
+        .ok_or_else(|| invalid_bundle(app_bundle, "CFBundleIdentifier is missing"))?;
+
+    validate_bundle_identifier(host_identifier)?;
+
+    let mut info = read_info_plist(appex_path)?;
+    validate_appex_info(appex_path, &info)?;
+
+    let identifier = info["CFBundleIdentifier"].as_str().unwrap().to_string();
+    let prefix = format!("{}.", host_identifier);
+    let aligned = (!identifier.starts_with(&prefix)).then(|| {
+        format!(
+            "{}{}",
+            prefix,
+            identifier.rsplit('.').next().unwrap_or(&identifier)
+        )
+    });
+
+    let signed = is_signed(appex_path);
+    if signed && signing.is_none() {
+        if let Some(aligned) = &aligned {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "{} is signed with identifier {}, which doesn't belong to host {}; \
+                     rewriting it would break the signature. Sign it as {} or re-sign it",
+                    appex_path.display(),
+                    identifier,
+                    host_identifier,
+                    aligned
+                ),
+            ));
+        }
+        if is_signed(app_bundle) {
+            let host_team = signature_team_id(app_bundle).map_err(Error::other)?;
+            let appex_team = signature_team_id(appex_path).map_err(Error::other)?;
+            if host_team != appex_team {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!(
+                        "team ID {} of {} doesn't match team ID {} of host {}",
+                        appex_team.as_deref().unwrap_or("(ad-hoc)"),
+                        appex_path.display(),
+                        host_team.as_deref().unwrap_or("(ad-hoc)"),
+                        app_bundle.display()
+                    ),
+                ));
+            }
+        }
+    }
+
+    let plugins = app_bundle.join("Contents/PlugIns");
+    let file_name = appex_path
+        .file_name()
+        .ok_or_else(|| invalid_bundle(appex_path, "not a bundle path"))?;
+    let embedded = plugins.join(file_name);
+    if embedded.exists() {
+        std::fs::remove_dir_all(&embedded)?;
+    }
+    std::fs::create_dir_all(&plugins)?;
+    copy_dir_with_options(
+        appex_path,
+        &embedded,
+        "**/*",
+        &CopyOptions {
+            preserve_symlinks: true,
+            ..Default::default()
+        },
+    )?;
+
+    if let Some(aligned) = aligned {
+        warn!(
+            "Bundle identifier {} of {} doesn't belong to host {}, using {}",
+            identifier,
+            appex_path.display(),
+            host_identifier,
+            aligned
+        );
+        info.insert("CFBundleIdentifier".into(), aligned.into());
+        Value::Dictionary(info).write(&embedded.join("Contents/Info.plist"))?;
+    }
+
+    if let Some(options) = signing {
+        sign_appex(&embedded, signed, options)?;
+    }
+
+    Ok(embedded)
+}
+
+/// Whether a bundle carries a code signature.
+fn is_signed(bundle: &Path) -> bool {
+    bundle.join("Contents/_CodeSignature").is_dir()
+}
+
+/// Sign an embedded app extension with the host's options.
+fn sign_appex(appex: &Path, signed: bool, options: &CodesignOptions) -> Result<(), Error> {
+    let mut options = CodesignOptions {
+        // the identifier only applies to the host, the extension's comes from its Info.plist
+        identifier: None,
+        entitlements: None,
+        force: true,
+        ..options.clone()
+    };
+    if signed {
+        options.preserve_metadata.push("entitlements".to_string());
+        return crate::codesign_with_options(appex, &options)
+            .map(|_| ())
+            .map_err(Error::other);
+    }
+
+    let entitlements = std::env::temp_dir().join(format!(
+        "toolbelt-appex-{}-{}.entitlements",
+        std::process::id(),
+        appex.file_stem().unwrap_or_default().to_string_lossy()
+    ));
+    let kind = BundleKind::AppExtension {
+        extension_point: String::new(),
+        principal_class: String::new(),
+    };
+    Value::Dictionary(kind.required_entitlements()).write(&entitlements)?;
+    options.entitlements = Some(entitlements.clone());
+    let result = crate::codesign_with_options(appex, &options);
+    let _ = std::fs::remove_file(&entitlements);
+    result.map(|_| ()).map_err(Error::other)
+}
+
+/// Copy an executable and make sure it has execute permission.
+pub(crate) fn install_executable(source: &Path, destination: &Path) -> Result<(), Error> {
+    std::fs::copy(source, destination)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(destination, std::fs::Permissions::from_mode(0o755))?;
+    }
+    Ok(())
+}
+
+#[test]
+fn test_bundle_builder() {
+    let output = Path::new("target/bundle_builder");
+    let bundle = BundleBuilder::new(
+        BundleKind::AppExtension {
+            extension_point: "com.apple.quicklook.preview".into(),
+            principal_class: "PreviewProvider".into(),
+        },
+        "Preview",
+        "com.example.preview",
+    )
+    .version("1.2.3")
+    .executable(Path::new("test/my_files/file1.txt"))
+    .resources(Path::new("test/my_files"), "*.csv")
+    .build(output)
+    .unwrap();
+
+    assert_eq!(bundle, output.join("Preview.appex"));
+    assert!(bundle.join("Contents/MacOS/Preview").exists());
+    assert!(bundle.join("Contents/Resources/file2.csv").exists());
+    let info = std::fs::read_to_string(bundle.join("Contents/Info.plist")).unwrap();
+    assert!(info.contains("<string>XPC!</string>"));
+    assert!(info.contains("<key>NSExtensionPointIdentifier</key>"));
+    assert!(info.contains("<string>1.2.3</string>"));
+
+    std::fs::remove_dir_all(output).unwrap();
+}
+
+#[test]
+fn test_embed_appex() {
+    let output = Path::new("target/embed_appex");
+    let host = BundleBuilder::new(BundleKind::Bundle, "Host", "com.example.host")
+        .build(output)
+        .unwrap();
+    let appex = BundleBuilder::new(
+        BundleKind::AppExtension {
+            extension_point: "com.apple.share-services".into(),
+            principal_class: "ShareViewController".into(),
+        },
+        "Share",
+        "com.other.share",
+    )
+    .executable(Path::new("test/my_files/file1.txt"))
+    .build(output)
+    .unwrap();
+
+    let embedded = embed_appex(&host, &appex, None).unwrap();
+    assert_eq!(embedded, host.join("Contents/PlugIns/Share.appex"));
+    assert!(embedded.join("Contents/MacOS/Share").exists());
+    let info = read_info_plist(&embedded).unwrap();
+    assert_eq!(
+        info["CFBundleIdentifier"].as_str(),
+        Some("com.example.host.share")
+    );
+
+    // a plain bundle is no app extension
+    assert!(embed_appex(&host, &host, None).is_err());
+
+    // rewriting the identifier of a signed extension would break its signature
+    std::fs::create_dir_all(appex.join("Contents/_CodeSignature")).unwrap();
+    let error = embed_appex(&host, &appex, None).unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::InvalidInput);
+    assert_eq!(
+        error.to_string(),
+        format!(
+            "{} is signed with identifier com.other.share, which doesn't belong to host \
+             com.example.host; rewriting it would break the signature. Sign it as \
+             com.example.host.share or re-sign it",
+            appex.display()
+        )
+    );
+
+    std::fs::remove_dir_all(output).unwrap();
+}
+
+#[test]
+fn test_app_bundle_builder() {
+    let output = Path::new("target/app_bundle_builder");
+    let framework = output.join("vendor/Kit.framework");
+    std::fs::create_dir_all(framework.join("Versions/A")).unwrap();
+    std::fs::write(framework.join("Versions/A/Kit"), "").unwrap();
+    let appex = BundleBuilder::new(
+        BundleKind::AppExtension {
+            extension_point: "com.apple.share-services".into(),
+            principal_class: "ShareViewController".into(),
+        },
+        "Share",
+        "com.example.myapp.share",
+    )
+    .executable(Path::new("test/my_files/file1.txt"))
+    .build(&output.join("vendor"))
+    .unwrap();
+
+    let app = AppBundleBuilder::new("MyApp", "com.example.myapp")
+        .version("2.0.0")
+        .executable(Path::new("test/my_files/file1.txt"))
+        .resources(Path::new("test/my_files"), "*.csv")
+        .framework(&framework)
+        .app_extension(&appex)
+        .info("LSMinimumSystemVersion", "11.0")
+        .build(output)
+        .unwrap();
+
+    assert_eq!(app, output.join("MyApp.app"));
+    assert_eq!(
+        std::fs::read_to_string(app.join("Contents/PkgInfo")).unwrap(),
+        "APPL????"
+    );
+    assert!(app.join("Contents/MacOS/MyApp").exists());
+    assert!(app.join("Contents/Resources/file2.csv").exists());
+    assert!(app
+        .join("Contents/PlugIns/Share.appex/Contents/MacOS/Share")
+        .exists());
+    assert!(app
+        .join("Contents/Frameworks/Kit.framework/Versions/A/Kit")
+        .exists());
+    let info = read_info_plist(&app).unwrap();
+    assert_eq!(info["CFBundlePackageType"].as_str(), Some("APPL"));
+    assert_eq!(info["LSMinimumSystemVersion"].as_str(), Some("11.0"));
+
+    std::fs::remove_dir_all(output).unwrap();
+}
+
+#[test]
+fn test_bundle_identifier() {
+    let config = Config::parse(
+        "organization = \"example\"\n[bundle]\nteam = \"tools\"\n",
+        None,
+        None,
+    )
+    .unwrap();
+    assert_eq!(
+        bundle_identifier("com.{organization}.{crate_name}", &config).unwrap(),
+        "com.example.toolbelt"
+    );
+    assert_eq!(
+        bundle_identifier("com.{organization}.{bundle.team}.cli", &config).unwrap(),
+        "com.example.tools.cli"
+    );
+    assert_eq!(
+        bundle_identifier("com.{missing}", &config)
+            .unwrap_err()
+            .kind(),
+        ErrorKind::NotFound
+    );
+    assert!(bundle_identifier("com.{organization", &config).is_err());
+
+    assert!(validate_bundle_identifier("com.example.my-app2").is_ok());
+    for invalid in [
+        "com.example.my_app",
+        "com..example",
+        "com.example.",
+        "com.exämple",
+        "",
+    ] {
+        assert!(validate_bundle_identifier(invalid).is_err(), "{}", invalid);
+    }
+}
+
+#[test]
+fn test_library_binary() {
+    let root = Path::new("target/library_binary");
+    let _ = std::fs::remove_dir_all(root);
+    let framework = root.join("Kit.framework");
+    std::fs::create_dir_all(framework.join("Versions/A")).unwrap();
+    let binary = macho::test_binary_linking(0x0100_000c, &["/usr/lib/libSystem.B.dylib"]);
+    std::fs::write(framework.join("Versions/A/Kit"), &binary).unwrap();
+    std::fs::write(root.join("libfoo.dylib"), &binary).unwrap();
+    std::fs::write(root.join("notes.txt"), "not a library").unwrap();
+
+    assert_eq!(
+        library_binary(&framework),
+        Some(framework.join("Versions/A/Kit"))
+    );
+    assert_eq!(
+        library_binary(&root.join("libfoo.dylib")),
+        Some(root.join("libfoo.dylib"))
+    );
+    assert_eq!(library_binary(&root.join("notes.txt")), None);
+    assert_eq!(library_binary(&root.join("Missing.framework")), None);
+
+    std::fs::remove_dir_all(root).unwrap();
+}
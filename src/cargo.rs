@@ -0,0 +1,221 @@
+//! Workspace information from `cargo metadata`: the workspace root, the target directory and the
+//! packages of the workspace with their versions and declared dependencies.
+//!
+//! Unlike the `CARGO_PKG_*` variables used by [`crate::get_name_from_cargo`], which describe the
+//! package compiling toolbelt, this covers every package of the workspace.
+
+use std::env;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+
+use inflector::cases::titlecase::to_title_case;
+
+use crate::json::{self, Value};
+use crate::tool::Tool;
+
+/// The section a dependency is declared in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DependencyKind {
+    /// `[dependencies]`
+    Normal,
+    /// `[dev-dependencies]`
+    Development,
+    /// `[build-dependencies]`
+    Build,
+}
+
+/// A dependency as declared in a package's manifest.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CargoDependency {
+    pub name: String,
+    /// The version requirement, e.g. `^0.4`
+    pub req: String,
+    pub kind: DependencyKind,
+    pub optional: bool,
+}
+
+/// A package of the workspace.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CargoPackage {
+    pub name: String,
+    pub version: String,
+    /// The package id cargo uses in `workspace_members`
+    pub id: String,
+    pub manifest_path: PathBuf,
+    pub dependencies: Vec<CargoDependency>,
+}
+
+impl CargoPackage {
+    /// The package name title case formatted, optionally adding the version number, like
+    /// [`crate::get_name_from_cargo`].
+    pub fn display_name(&self, with_version: bool) -> String {
+        let mut name = to_title_case(&self.name);
+        if with_version {
+            name += " ";
+            name += &self.version;
+        }
+        name
+    }
+
+    /// The package's directory.
+    pub fn dir(&self) -> &Path {
+        self.manifest_path.parent().unwrap_or(Path::new("."))
+    }
+}
+
+/// The output of `cargo metadata` for a workspace.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CargoMetadata {
+    pub workspace_root: PathBuf,
+    /// The target directory, honoring `CARGO_TARGET_DIR` and `build.target-dir`
+    pub target_directory: PathBuf,
+    /// The packages of the workspace
+    pub packages: Vec<CargoPackage>,
+}
+
+fn string(value: &Value, key: &str) -> Result<String, Error> {
+    value
+        .get(key)
+        .and_then(Value::as_str)
+        .map(str::to_string)
+        .ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("cargo metadata lacks {}", key),
+            )
+        })
+}
+
+fn array<'a>(value: &'a Value, key: &str) -> &'a [Value] {
+    value.get(key).and_then(Value::as_array).unwrap_or_default()
+}
+
+fn parse_dependency(value: &Value) -> Result<CargoDependency, Error> {
+    Ok(CargoDependency {
+        name: string(value, "name")?,
+        req: string(value, "req")?,
+        kind: match value.get("kind").and_then(Value::as_str) {
+            Some("dev") => DependencyKind::Development,
+            Some("build") => DependencyKind::Build,
+            _ => DependencyKind::Normal,
+        },
+        optional: value.get("optional") == Some(&Value::Bool(true)),
+    })
+}
+
+fn parse_package(value: &Value) -> Result<CargoPackage, Error> {
+    Ok(CargoPackage {
+        name: string(value, "name")?,
+        version: string(value, "version")?,
+        id: string(value, "id")?,
+        manifest_path: PathBuf::from(string(value, "manifest_path")?),
+        dependencies: array(value, "dependencies")
+            .iter()
+            .map(parse_dependency)
+            .collect::<Result<_, _>>()?,
+    })
+}
+
+impl CargoMetadata {
+    /// Run `cargo metadata` for the workspace of a manifest, or of the current directory if
+    /// `None`. Build scripts should pass `CARGO_MANIFEST_DIR`'s `Cargo.toml`. Uses the cargo
+    /// running the build (`CARGO`) if set.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use std::path::Path;
+    /// use toolbelt::cargo::CargoMetadata;
+    ///
+    /// let metadata = CargoMetadata::load(Some(Path::new("Cargo.toml"))).unwrap();
+    /// let app = metadata.package("my-app").unwrap();
+    /// println!("{}", app.display_name(true));
+    /// println!("{}", metadata.target_directory.display());
+    /// ```
+    pub fn load(manifest_path: Option<&Path>) -> Result<CargoMetadata, Error> {
+        let cargo = env::var_os("CARGO").unwrap_or_else(|| "cargo".into());
+        let mut tool = Tool::new(cargo);
+        tool.args(["metadata", "--format-version", "1", "--no-deps"]);
+        if let Some(manifest_path) = manifest_path {
+            tool.arg("--manifest-path").arg(manifest_path);
+        }
+        let output = tool.run().map_err(Error::other)?;
+        CargoMetadata::parse(&output.stdout)
+    }
+
+    /// Parse the JSON output of `cargo metadata --format-version 1`. Only the workspace members
+    /// are kept, also if the output includes the dependencies' packages.
+    pub fn parse(output: &str) -> Result<CargoMetadata, Error> {
+        let document = json::parse(output)?;
+        let members: Vec<&str> = array(&document, "workspace_members")
+            .iter()
+            .filter_map(Value::as_str)
+            .collect();
+        let packages = array(&document, "packages")
+            .iter()
+            .filter(|package| {
+                package
+                    .get("id")
+                    .and_then(Value::as_str)
+                    .is_some_and(|id| members.contains(&id))
+            })
+            .map(parse_package)
+            .collect::<Result<_, _>>()?;
+        Ok(CargoMetadata {
+            workspace_root: PathBuf::from(string(&document, "workspace_root")?),
+            target_directory: PathBuf::from(string(&document, "target_directory")?),
+            packages,
+        })
+    }
+
+    /// The workspace package named `name`.
+    pub fn package(&self, name: &str) -> Option<&CargoPackage> {
+        self.packages.iter().find(|package| package.name == name)
+    }
+}
+
+#[test]
+fn test_cargo_metadata() {
+    let metadata = CargoMetadata::parse(
+        r#"{
+  "packages": [
+    {
+      "name": "my-app", "version": "1.2.0", "id": "path+file:///ws/app#my-app@1.2.0",
+      "manifest_path": "/ws/app/Cargo.toml",
+      "dependencies": [
+        {"name": "log", "req": "^0.4", "kind": null, "optional": false},
+        {"name": "cc", "req": "^1", "kind": "build", "optional": false},
+        {"name": "serde", "req": "^1", "kind": null, "optional": true}
+      ]
+    },
+    {
+      "name": "log", "version": "0.4.20", "id": "registry+https://github.com/rust-lang/crates.io-index#log@0.4.20",
+      "manifest_path": "/registry/log/Cargo.toml", "dependencies": []
+    }
+  ],
+  "workspace_members": ["path+file:///ws/app#my-app@1.2.0"],
+  "workspace_root": "/ws",
+  "target_directory": "/ws/target",
+  "version": 1
+}"#,
+    )
+    .unwrap();
+    assert_eq!(metadata.workspace_root, PathBuf::from("/ws"));
+    assert_eq!(metadata.target_directory, PathBuf::from("/ws/target"));
+    assert_eq!(metadata.packages.len(), 1);
+    let app = metadata.package("my-app").unwrap();
+    assert_eq!(app.display_name(true), "My App 1.2.0");
+    assert_eq!(app.dir(), Path::new("/ws/app"));
+    assert_eq!(app.dependencies[1].kind, DependencyKind::Build);
+    assert!(app.dependencies[2].optional);
+    assert!(CargoMetadata::parse("{}").is_err());
+
+    let metadata = CargoMetadata::load(Some(Path::new("Cargo.toml"))).unwrap();
+    let toolbelt = metadata.package(env!("CARGO_PKG_NAME")).unwrap();
+    assert_eq!(toolbelt.version, env!("CARGO_PKG_VERSION"));
+    assert_eq!(
+        toolbelt.display_name(false),
+        crate::get_name_from_cargo(false)
+    );
+    assert!(toolbelt.dependencies.iter().any(|dep| dep.name == "log"));
+}
@@ -0,0 +1,606 @@
+//! Signing code with codesign and verifying signatures.
+
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::concurrency::parallel_map;
+use crate::fileset::{FileSet, PatternOptions};
+use crate::macho;
+use crate::pipeline::verification_failure;
+use crate::plist::{Dictionary, Value};
+use crate::tool::{Tool, ToolError, ToolOutput};
+use crate::xcrun;
+
+/// Extensions of bundles [`codesign_glob`] signs as a whole.
+const BUNDLE_EXTENSIONS: &[&str] = &[
+    "app",
+    "appex",
+    "bundle",
+    "framework",
+    "plugin",
+    "xpc",
+    "systemextension",
+];
+
+/// Options for [`crate::codesign_with_options`].
+#[derive(Clone, Debug)]
+pub struct CodesignOptions {
+    /// Signing identity (`--sign`), e.g. `Developer ID Application: Example Inc (TEAMID)` or the
+    /// SHA-1 of a certificate. `-` signs ad-hoc.
+    pub identity: String,
+    /// Code identifier (`--identifier`). codesign derives it from the Info.plist or file name
+    /// otherwise.
+    pub identifier: Option<String>,
+    /// Entitlements plist to embed (`--entitlements`)
+    pub entitlements: Option<PathBuf>,
+    /// Enable the hardened runtime (`--options runtime`), required for notarization
+    pub hardened_runtime: bool,
+    /// Sign nested code as well (`--deep`)
+    pub deep: bool,
+    /// Request a secure timestamp from Apple (`--timestamp`), required for notarization
+    pub timestamp: bool,
+    /// Keep these parts of an existing signature (`--preserve-metadata`, e.g. `entitlements`,
+    /// `requirements`, `flags`)
+    pub preserve_metadata: Vec<String>,
+    /// Replace an existing signature (`--force`)
+    pub force: bool,
+    /// Matching of the pattern passed to [`codesign_glob`] and the walk, e.g. a maximum depth
+    pub patterns: PatternOptions,
+}
+
+impl Default for CodesignOptions {
+    /// Ad-hoc signing, replacing any existing signature.
+    fn default() -> Self {
+        CodesignOptions {
+            identity: "-".to_string(),
+            identifier: None,
+            entitlements: None,
+            hardened_runtime: false,
+            deep: false,
+            timestamp: false,
+            preserve_metadata: Vec::new(),
+            force: true,
+            patterns: PatternOptions::default(),
+        }
+    }
+}
+
+impl CodesignOptions {
+    /// Add the options to a codesign invocation.
+    pub(crate) fn apply(&self, tool: &mut Tool) {
+        if self.force {
+            tool.arg("--force");
+        }
+        tool.arg("--sign").arg(&self.identity);
+        if let Some(identifier) = &self.identifier {
+            tool.arg("--identifier").arg(identifier);
+        }
+        if let Some(entitlements) = &self.entitlements {
+            tool.arg("--entitlements").arg(entitlements);
+        }
+        if self.hardened_runtime {
+            tool.args(["--options", "runtime"]);
+        }
+        if self.deep {
+            tool.arg("--deep");
+        }
+        if self.timestamp {
+            tool.arg("--timestamp");
+        }
+        if !self.preserve_metadata.is_empty() {
+            tool.arg(format!(
+                "--preserve-metadata={}",
+                self.preserve_metadata.join(",")
+            ));
+        }
+    }
+}
+
+#[test]
+fn test_codesign_options() {
+    let mut tool = Tool::new("codesign");
+    CodesignOptions {
+        identity: "Developer ID Application".to_string(),
+        identifier: Some("com.example.app".to_string()),
+        entitlements: Some("app.entitlements".into()),
+        hardened_runtime: true,
+        timestamp: true,
+        preserve_metadata: vec!["identifier".to_string(), "flags".to_string()],
+        ..Default::default()
+    }
+    .apply(&mut tool);
+    assert_eq!(
+        tool.command_line(),
+        "codesign --force --sign \"Developer ID Application\" --identifier com.example.app \
+         --entitlements app.entitlements \
+         --options runtime --timestamp --preserve-metadata=identifier,flags"
+    );
+}
+
+/// Entitlements of development builds: allow debuggers to attach.
+pub fn development_entitlements() -> Dictionary {
+    let mut entitlements = Dictionary::new();
+    entitlements.insert("com.apple.security.get-task-allow".into(), true.into());
+    entitlements
+}
+
+/// The identifier development signatures use: the bundle identifier of a bundle or the file name
+/// of a bare binary, so it doesn't change between builds.
+fn development_identifier(path: &Path) -> Result<String, Error> {
+    if path.is_dir() {
+        crate::bundle::read_info_plist(path)?
+            .get("CFBundleIdentifier")
+            .and_then(Value::as_str)
+            .map(str::to_string)
+            .ok_or_else(|| {
+                Error::new(
+                    ErrorKind::InvalidData,
+                    format!("{}: CFBundleIdentifier is missing", path.display()),
+                )
+            })
+    } else {
+        Ok(path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned())
+    }
+}
+
+/// The options signing the nested code and the outer code of a development build.
+///
+/// `--deep` isn't used: together with `--identifier` it would stamp the outer identifier onto
+/// every nested framework, dylib and extension and break their designated requirements. Nested
+/// code keeps the identifier codesign derives from its own Info.plist or file name.
+fn dev_sign_options(
+    path: &Path,
+    entitlements: &Path,
+) -> Result<(CodesignOptions, CodesignOptions), Error> {
+    let nested = CodesignOptions::default();
+    let outer = CodesignOptions {
+        identifier: Some(development_identifier(path)?),
+        entitlements: Some(entitlements.to_path_buf()),
+        ..Default::default()
+    };
+    Ok((nested, outer))
+}
+
+/// Sign a debug build ad-hoc so it runs and can be debugged locally without any certificate.
+///
+/// The nested code of a bundle is signed ad-hoc first, inner-most first, each with its own
+/// identifier. Then the bundle or binary itself is signed with a stable identifier and the
+/// [`development_entitlements`]. Development signatures are for local use only: they are
+/// rejected by Gatekeeper on other machines and can't be notarized. Use
+/// [`crate::codesign_with_options`] with a Developer ID for release builds.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::path::Path;
+/// use toolbelt::codesign::dev_sign;
+///
+/// dev_sign(Path::new("target/debug/MyApp.app")).unwrap();
+/// ```
+pub fn dev_sign(path: &Path) -> Result<ToolOutput, Error> {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let entitlements = std::env::temp_dir().join(format!(
+        "toolbelt-dev-sign-{}-{}.entitlements",
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    let (nested, outer) = dev_sign_options(path, &entitlements)?;
+    if path.is_dir() {
+        codesign_glob(path, "**/*", &nested)?;
+    }
+    Value::Dictionary(development_entitlements()).write(&entitlements)?;
+    let result = crate::codesign_with_options(path, &outer);
+    let _ = std::fs::remove_file(&entitlements);
+    result.map_err(Error::other)
+}
+
+#[test]
+fn test_development_identifier() {
+    let root = Path::new("target/development_identifier");
+    let _ = std::fs::remove_dir_all(root);
+    let app = root.join("MyApp.app");
+    std::fs::create_dir_all(app.join("Contents")).unwrap();
+    let mut info = Dictionary::new();
+    info.insert("CFBundleIdentifier".into(), "com.example.myapp".into());
+    Value::Dictionary(info)
+        .write(&app.join("Contents/Info.plist"))
+        .unwrap();
+
+    assert_eq!(development_identifier(&app).unwrap(), "com.example.myapp");
+    let (nested, outer) = dev_sign_options(&app, Path::new("dev.entitlements")).unwrap();
+    assert!(!nested.deep && nested.identifier.is_none() && nested.entitlements.is_none());
+    assert!(!outer.deep);
+    assert_eq!(outer.identifier.as_deref(), Some("com.example.myapp"));
+    assert_eq!(
+        development_identifier(Path::new("target/debug/mytool")).unwrap(),
+        "mytool"
+    );
+    assert_eq!(
+        development_entitlements()["com.apple.security.get-task-allow"],
+        Value::Boolean(true)
+    );
+
+    std::fs::remove_dir_all(root).unwrap();
+}
+
+/// Sign every Mach-O file and bundle matching a glob pattern, inner-most first.
+///
+/// Code has to be signed before the bundle containing it, so matches are signed level by level,
+/// starting with the deepest. Matches on the same level are signed in parallel. Directories are
+/// only signed if they are bundles (`.framework`, `.app`, `.bundle`, `.xpc`, …), files only if
+/// they are Mach-O binaries, and symbolic links not at all. Returns the signed paths in signing
+/// order.
+///
+/// # Arguments
+///
+/// * `root` - the directory to search, e.g. an app bundle
+/// * `pattern` - a standard glob pattern relative to `root`
+/// * `options` - signing identity, entitlements, hardened runtime, …
+///
+/// # Example
+///
+/// ```no_run
+/// use std::path::Path;
+/// use toolbelt::codesign::codesign_glob;
+/// use toolbelt::CodesignOptions;
+///
+/// let options = CodesignOptions {
+///     identity: "Developer ID Application: Example Inc (ABCDE12345)".to_string(),
+///     hardened_runtime: true,
+///     timestamp: true,
+///     ..Default::default()
+/// };
+/// codesign_glob(
+///     Path::new("target/MyApp.app"),
+///     "Contents/Frameworks/**/*.{dylib,framework}",
+///     &options,
+/// )
+/// .unwrap();
+/// ```
+pub fn codesign_glob(
+    root: &Path,
+    pattern: &str,
+    options: &CodesignOptions,
+) -> Result<Vec<PathBuf>, Error> {
+    let mut signed = Vec::new();
+    for level in signing_order(root, pattern, &options.patterns)? {
+        parallel_map(&level, |path| crate::codesign_with_options(path, options))
+            .into_iter()
+            .collect::<Result<Vec<_>, ToolError>>()
+            .map_err(Error::other)?;
+        signed.extend(level);
+    }
+    Ok(signed)
+}
+
+/// The code matching `pattern` below `root`, grouped by depth, deepest first.
+fn signing_order(
+    root: &Path,
+    pattern: &str,
+    options: &PatternOptions,
+) -> Result<Vec<Vec<PathBuf>>, Error> {
+    let mut matches = Vec::new();
+    for entry in FileSet::new(root).pattern(pattern).options(options.clone()) {
+        let entry = entry?;
+        let is_code = if entry.file_type.is_dir() {
+            entry
+                .path
+                .extension()
+                .and_then(|e| e.to_str())
+                .is_some_and(|e| BUNDLE_EXTENSIONS.contains(&e))
+        } else if entry.file_type.is_file() {
+            let mut magic = [0; 4];
+            std::fs::File::open(&entry.path)
+                .and_then(|mut file| std::io::Read::read_exact(&mut file, &mut magic))
+                .is_ok_and(|_| macho::is_macho(&magic))
+        } else {
+            false
+        };
+        if is_code {
+            matches.push((entry.relative_path.components().count(), entry.path));
+        }
+    }
+    matches.sort_by(|(a_depth, a), (b_depth, b)| b_depth.cmp(a_depth).then(a.cmp(b)));
+
+    let mut levels: Vec<(usize, Vec<PathBuf>)> = Vec::new();
+    for (depth, path) in matches {
+        match levels.last_mut() {
+            Some((level, paths)) if *level == depth => paths.push(path),
+            _ => levels.push((depth, vec![path])),
+        }
+    }
+    Ok(levels.into_iter().map(|(_, paths)| paths).collect())
+}
+
+#[test]
+fn test_signing_order() {
+    let root = Path::new("target/signing_order");
+    let _ = std::fs::remove_dir_all(root);
+    let frameworks = root.join("Contents/Frameworks");
+    let framework = frameworks.join("Kit.framework");
+    std::fs::create_dir_all(framework.join("Versions/A/Libraries")).unwrap();
+    let macho = [0xcf, 0xfa, 0xed, 0xfe, 0, 0, 0, 0];
+    std::fs::write(framework.join("Versions/A/Kit"), macho).unwrap();
+    std::fs::write(framework.join("Versions/A/Libraries/libz.dylib"), macho).unwrap();
+    std::fs::write(frameworks.join("libfoo.dylib"), macho).unwrap();
+    std::fs::write(frameworks.join("README.txt"), "not code").unwrap();
+
+    let levels =
+        signing_order(root, "Contents/Frameworks/**/*", &PatternOptions::default()).unwrap();
+    assert_eq!(
+        levels,
+        [
+            vec![framework.join("Versions/A/Libraries/libz.dylib")],
+            vec![framework.join("Versions/A/Kit")],
+            vec![framework.clone(), frameworks.join("libfoo.dylib")],
+        ]
+    );
+
+    std::fs::remove_dir_all(root).unwrap();
+}
+
+/// Result of [`codesign_verify`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SignatureVerification {
+    /// The signature is intact and all nested code is signed (`codesign --verify --deep --strict`)
+    pub valid: bool,
+    /// Gatekeeper accepts the code (`spctl --assess`)
+    pub accepted: bool,
+    /// The signing certificate's common name, `None` for ad-hoc signatures
+    pub identity: Option<String>,
+    /// The team identifier of the signing certificate
+    pub team_id: Option<String>,
+    /// Messages of failed checks
+    pub errors: Vec<String>,
+}
+
+impl SignatureVerification {
+    /// Take identity and team id from the output of `codesign --display --verbose=2`.
+    fn read_details(&mut self, details: &str) {
+        for line in details.lines() {
+            if let Some(authority) = line.strip_prefix("Authority=") {
+                // the first authority is the leaf certificate
+                self.identity.get_or_insert_with(|| authority.to_string());
+            } else if let Some(team_id) = line.strip_prefix("TeamIdentifier=") {
+                if team_id != "not set" {
+                    self.team_id = Some(team_id.to_string());
+                }
+            }
+        }
+    }
+}
+
+/// The team identifier code at `path` is signed with, `None` for ad-hoc signatures.
+pub(crate) fn signature_team_id(path: &Path) -> Result<Option<String>, ToolError> {
+    let details = xcrun::tool("codesign", None)?
+        .args(["--display", "--verbose=2"])
+        .arg(path)
+        .output()?;
+    let mut verification = SignatureVerification::default();
+    // codesign writes the details to stderr
+    verification.read_details(&details.stderr);
+    Ok(verification.team_id)
+}
+
+fn failure_messages(output: &ToolOutput) -> impl Iterator<Item = String> + '_ {
+    output
+        .stderr
+        .lines()
+        .chain(output.stdout.lines())
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+}
+
+/// Verify the code signature of a binary or bundle and check whether Gatekeeper accepts it.
+///
+/// Only failing to run codesign or spctl is an error. Invalid signatures are reported in the
+/// returned [`SignatureVerification`].
+///
+/// # Example
+///
+/// ```no_run
+/// use std::path::Path;
+/// use toolbelt::codesign_verify;
+///
+/// let verification = codesign_verify(Path::new("target/MyApp.app")).unwrap();
+/// assert!(verification.valid, "{:?}", verification.errors);
+/// assert_eq!(verification.team_id.as_deref(), Some("ABCDE12345"));
+/// ```
+pub fn codesign_verify(path: &Path) -> Result<SignatureVerification, ToolError> {
+    let mut verification = SignatureVerification::default();
+
+    let verify = xcrun::tool("codesign", None)?
+        .args(["--verify", "--deep", "--strict", "--verbose=2"])
+        .arg(path)
+        .output()?;
+    verification.valid = verify.status.success();
+    if !verification.valid {
+        verification.errors.extend(failure_messages(&verify));
+    }
+
+    let details = xcrun::tool("codesign", None)?
+        .args(["--display", "--verbose=2"])
+        .arg(path)
+        .output()?;
+    // codesign writes the details to stderr
+    verification.read_details(&details.stderr);
+
+    let assess = Tool::new("spctl")
+        .args(["--assess", "--type", "execute", "--verbose"])
+        .arg(path)
+        .output()?;
+    verification.accepted = assess.status.success();
+    if !verification.accepted {
+        verification.errors.extend(failure_messages(&assess));
+    }
+
+    Ok(verification)
+}
+
+#[test]
+fn test_signature_details() {
+    let mut verification = SignatureVerification::default();
+    verification.read_details(
+        "Executable=/Applications/MyApp.app/Contents/MacOS/MyApp\n\
+         Identifier=com.example.myapp\n\
+         Authority=Developer ID Application: Example Inc (ABCDE12345)\n\
+         Authority=Developer ID Certification Authority\n\
+         Authority=Apple Root CA\n\
+         TeamIdentifier=ABCDE12345\n",
+    );
+    assert_eq!(
+        verification.identity.as_deref(),
+        Some("Developer ID Application: Example Inc (ABCDE12345)")
+    );
+    assert_eq!(verification.team_id.as_deref(), Some("ABCDE12345"));
+
+    let mut ad_hoc = SignatureVerification::default();
+    ad_hoc.read_details("Signature=adhoc\nTeamIdentifier=not set\n");
+    assert_eq!(ad_hoc.identity, None);
+    assert_eq!(ad_hoc.team_id, None);
+}
+
+/// What Gatekeeper is asked to assess, see [`gatekeeper_assess`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AssessmentType {
+    /// Running an app or binary (`spctl -t execute`)
+    Execute,
+    /// Installing a `.pkg` (`spctl -t install`)
+    Install,
+    /// Opening a disk image (`spctl -t open --context context:primary-signature`)
+    Open,
+}
+
+impl AssessmentType {
+    /// The assessment matching the file: `Install` for `.pkg`, `Open` for `.dmg`, `Execute`
+    /// otherwise.
+    pub fn for_path(path: &Path) -> AssessmentType {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("pkg") | Some("mpkg") => AssessmentType::Install,
+            Some("dmg") => AssessmentType::Open,
+            _ => AssessmentType::Execute,
+        }
+    }
+}
+
+/// Gatekeeper's verdict on a signed app, package or disk image.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct GatekeeperAssessment {
+    pub accepted: bool,
+    /// Why it was accepted or rejected, e.g. `Notarized Developer ID` or
+    /// `Unnotarized Developer ID`
+    pub source: Option<String>,
+    /// The signing identity, e.g. `Developer ID Application: Example Inc (ABCDE12345)`
+    pub origin: Option<String>,
+    /// spctl's complete output
+    pub output: String,
+}
+
+impl GatekeeperAssessment {
+    /// `true` if Gatekeeper knows the code was notarized.
+    pub fn is_notarized(&self) -> bool {
+        self.source
+            .as_deref()
+            .is_some_and(|s| s.starts_with("Notarized"))
+    }
+
+    /// Fail unless Gatekeeper accepts the code as notarized, e.g. before publishing a release.
+    pub fn require_notarized(&self) -> Result<(), Error> {
+        if self.accepted && self.is_notarized() {
+            return Ok(());
+        }
+        Err(verification_failure(format!(
+            "Gatekeeper {} the code ({}), check that it was notarized and the ticket \
+                 stapled:\n{}",
+            if self.accepted { "accepts" } else { "rejects" },
+            self.source.as_deref().unwrap_or("no source"),
+            self.output.trim()
+        )))
+    }
+
+    fn parse(accepted: bool, output: &str) -> GatekeeperAssessment {
+        let field = |name: &str| {
+            output
+                .lines()
+                .find_map(|line| line.trim().strip_prefix(name))
+                .map(str::to_string)
+        };
+        GatekeeperAssessment {
+            accepted,
+            source: field("source="),
+            origin: field("origin="),
+            output: output.to_string(),
+        }
+    }
+}
+
+/// Ask Gatekeeper whether it lets users run an app, install a package or open a disk image
+/// (`spctl --assess -vvv`), like it would after downloading it.
+///
+/// Only failing to run spctl is an error; a rejection is reported in the returned
+/// [`GatekeeperAssessment`]. The assessment type is chosen by [`AssessmentType::for_path`].
+///
+/// # Example
+///
+/// ```no_run
+/// use std::path::Path;
+/// use toolbelt::codesign::gatekeeper_assess;
+///
+/// let assessment = gatekeeper_assess(Path::new("target/MyApp.dmg")).unwrap();
+/// assessment.require_notarized().unwrap();
+/// ```
+pub fn gatekeeper_assess(path: &Path) -> Result<GatekeeperAssessment, ToolError> {
+    let mut spctl = Tool::new("spctl");
+    spctl.args(["--assess", "-vvv", "--type"]);
+    match AssessmentType::for_path(path) {
+        AssessmentType::Execute => spctl.arg("execute"),
+        AssessmentType::Install => spctl.arg("install"),
+        AssessmentType::Open => spctl.args(["open", "--context", "context:primary-signature"]),
+    };
+    let output = spctl.arg(path).output()?;
+    // spctl writes the verdict to stderr
+    let text = format!("{}{}", output.stderr, output.stdout);
+    Ok(GatekeeperAssessment::parse(output.status.success(), &text))
+}
+
+#[test]
+fn test_gatekeeper_assessment() {
+    let accepted = GatekeeperAssessment::parse(
+        true,
+        "MyApp.app: accepted\n\
+         source=Notarized Developer ID\n\
+         origin=Developer ID Application: Example Inc (ABCDE12345)\n",
+    );
+    assert!(accepted.is_notarized());
+    assert_eq!(
+        accepted.origin.as_deref(),
+        Some("Developer ID Application: Example Inc (ABCDE12345)")
+    );
+    assert!(accepted.require_notarized().is_ok());
+
+    let rejected = GatekeeperAssessment::parse(
+        false,
+        "MyApp.app: rejected\nsource=Unnotarized Developer ID\n",
+    );
+    assert!(!rejected.is_notarized());
+    assert!(rejected
+        .require_notarized()
+        .unwrap_err()
+        .to_string()
+        .starts_with("Gatekeeper rejects the code (Unnotarized Developer ID)"));
+
+    assert_eq!(
+        AssessmentType::for_path(Path::new("MyApp.pkg")),
+        AssessmentType::Install
+    );
+    assert_eq!(
+        AssessmentType::for_path(Path::new("MyApp.app")),
+        AssessmentType::Execute
+    );
+}
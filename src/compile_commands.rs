@@ -0,0 +1,233 @@
+//! Writing `compile_commands.json`, the compilation database clangd and IDEs read to understand
+//! C, C++ and Objective-C sources: which compiler, include directories and defines each file is
+//! built with.
+
+use std::fmt::Display;
+use std::fs;
+use std::io::Error;
+use std::path::{Path, PathBuf};
+
+use crate::copy::absolute_path;
+use crate::fileset::{FileSet, PatternOptions};
+use crate::json::Value;
+
+/// One entry of a compilation database.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CompileCommand {
+    /// The working directory of the compiler
+    pub directory: PathBuf,
+    pub file: PathBuf,
+    /// The compiler and its arguments, ending with `-c <file>`
+    pub arguments: Vec<String>,
+}
+
+impl CompileCommand {
+    fn to_json(&self) -> Value {
+        Value::Object(vec![
+            (
+                "directory".to_string(),
+                Value::String(self.directory.display().to_string()),
+            ),
+            (
+                "file".to_string(),
+                Value::String(self.file.display().to_string()),
+            ),
+            (
+                "arguments".to_string(),
+                Value::Array(self.arguments.iter().cloned().map(Value::String).collect()),
+            ),
+        ])
+    }
+}
+
+/// Builds a compilation database for the sources below a directory, all compiled with the same
+/// flags.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::path::Path;
+/// use toolbelt::{
+///     get_sdk_defines, get_sdk_include_dirs, CompileCommands, DefineFormat, IncludeDirFormat,
+/// };
+///
+/// let include_dirs = get_sdk_include_dirs(["include/**"], "/opt/sdk/", IncludeDirFormat::CLANG);
+/// let defines = get_sdk_defines(&[("FOO_STATIC", None::<&str>)], DefineFormat::CLANG);
+/// CompileCommands::new(Path::new("native"))
+///     .sources("**/*.{c,cpp,m}")
+///     .flags(include_dirs)
+///     .flags(defines)
+///     .flags(["-std=c++17"])
+///     .write(Path::new("compile_commands.json"))
+///     .unwrap();
+/// ```
+#[derive(Clone, Debug)]
+pub struct CompileCommands {
+    root: PathBuf,
+    patterns: Vec<String>,
+    c_compiler: String,
+    cxx_compiler: String,
+    flags: Vec<String>,
+    options: PatternOptions,
+}
+
+impl CompileCommands {
+    /// # Arguments
+    ///
+    /// * `root` - the directory containing the sources and the compiler's working directory
+    pub fn new(root: &Path) -> CompileCommands {
+        CompileCommands {
+            root: root.to_path_buf(),
+            patterns: Vec::new(),
+            c_compiler: "clang".to_string(),
+            cxx_compiler: "clang++".to_string(),
+            flags: Vec::new(),
+            options: PatternOptions::default(),
+        }
+    }
+
+    /// Add a glob pattern of source files, relative to the root.
+    pub fn sources(mut self, pattern: &str) -> CompileCommands {
+        self.patterns.push(pattern.to_string());
+        self
+    }
+
+    /// Configure the matching of the source patterns and the walk, e.g. a maximum depth.
+    pub fn options(mut self, options: PatternOptions) -> CompileCommands {
+        self.options = options;
+        self
+    }
+
+    /// The compilers for C and Objective-C, and for C++ and Objective-C++ sources. Defaults to
+    /// `clang` and `clang++`.
+    pub fn compilers(mut self, c_compiler: &str, cxx_compiler: &str) -> CompileCommands {
+        self.c_compiler = c_compiler.to_string();
+        self.cxx_compiler = cxx_compiler.to_string();
+        self
+    }
+
+    /// Add compiler flags, e.g. the include directories of [`crate::get_sdk_include_dirs`] in
+    /// [`crate::IncludeDirFormat::CLANG`] format or the defines of [`crate::get_sdk_defines`].
+    pub fn flags<I>(mut self, flags: I) -> CompileCommands
+    where
+        I: IntoIterator,
+        I::Item: Display,
+    {
+        self.flags
+            .extend(flags.into_iter().map(|flag| flag.to_string()));
+        self
+    }
+
+    /// The entries for all source files, in the order of the patterns.
+    pub fn commands(&self) -> Result<Vec<CompileCommand>, Error> {
+        let directory = absolute_path(&self.root)?;
+        let mut files = FileSet::new(&directory).options(self.options.clone());
+        for pattern in &self.patterns {
+            files = files.pattern(pattern);
+        }
+        let mut commands = Vec::new();
+        for entry in files {
+            let entry = entry?;
+            if !entry.file_type.is_file() {
+                continue;
+            }
+            let extension = entry
+                .path
+                .extension()
+                .map(|extension| extension.to_string_lossy().to_lowercase());
+            let compiler = match extension.as_deref() {
+                Some("c" | "m") => &self.c_compiler,
+                _ => &self.cxx_compiler,
+            };
+            let mut arguments = vec![compiler.clone()];
+            arguments.extend(self.flags.iter().cloned());
+            arguments.push("-c".to_string());
+            arguments.push(entry.path.display().to_string());
+            commands.push(CompileCommand {
+                directory: directory.clone(),
+                file: entry.path,
+                arguments,
+            });
+        }
+        Ok(commands)
+    }
+
+    /// Write the compilation database to `path`, usually `compile_commands.json` in the
+    /// workspace root. Returns the number of entries.
+    pub fn write(&self, path: &Path) -> Result<usize, Error> {
+        let commands = self.commands()?;
+        let document = Value::Array(commands.iter().map(CompileCommand::to_json).collect());
+        if let Some(parent) = path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, document.to_pretty_string())?;
+        debug!(
+            "Wrote {} compile commands to {}",
+            commands.len(),
+            path.display()
+        );
+        Ok(commands.len())
+    }
+}
+
+#[test]
+fn test_compile_commands() {
+    let root = Path::new("target/compile_commands");
+    let _ = fs::remove_dir_all(root);
+    fs::create_dir_all(root.join("native/src")).unwrap();
+    fs::write(root.join("native/src/foo.c"), "int foo() { return 1; }").unwrap();
+    fs::write(root.join("native/src/bar.cpp"), "int bar() { return 2; }").unwrap();
+    fs::write(root.join("native/src/bar.h"), "int bar();").unwrap();
+
+    let commands = CompileCommands::new(&root.join("native"))
+        .sources("**/*.c")
+        .sources("**/*.{c,cpp}")
+        .flags(["-I/opt/sdk/include"])
+        .flags(crate::get_sdk_defines(
+            &[("FOO", Some("\"a b\""))],
+            crate::DefineFormat::CLANG,
+        ));
+    let output = root.join("out/compile_commands.json");
+    assert_eq!(commands.write(&output).unwrap(), 2);
+    let shallow = commands.clone().options(crate::PatternOptions {
+        max_depth: Some(1),
+        ..Default::default()
+    });
+    assert!(shallow.commands().unwrap().is_empty());
+
+    let document = crate::json::parse(&fs::read_to_string(&output).unwrap()).unwrap();
+    let entries = document.as_array().unwrap();
+    let directory = absolute_path(&root.join("native")).unwrap();
+    assert_eq!(
+        entries[0].get("directory").unwrap().as_str(),
+        Some(directory.to_str().unwrap())
+    );
+    assert_eq!(
+        entries[0].get("file").unwrap().as_str(),
+        Some(directory.join("src/foo.c").to_str().unwrap())
+    );
+    let arguments: Vec<&str> = entries[1]
+        .get("arguments")
+        .unwrap()
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|argument| argument.as_str().unwrap())
+        .collect();
+    let bar = directory.join("src/bar.cpp");
+    assert_eq!(
+        arguments,
+        [
+            "clang++",
+            "-I/opt/sdk/include",
+            "-DFOO=\"a b\"",
+            "-c",
+            bar.to_str().unwrap()
+        ]
+    );
+
+    fs::remove_dir_all(root).unwrap();
+}
@@ -0,0 +1,128 @@
+//! Shared limit for the number of parallel jobs toolbelt runs.
+//!
+//! All parallel work (copying, hashing, compiling resources, …) draws from one pool of job
+//! slots, so toolbelt doesn't oversubscribe the CPUs cargo is using for compilation at the same
+//! time. The limit is taken from [`set_max_jobs`] if called, otherwise from the `TOOLBELT_JOBS`
+//! or `NUM_JOBS` (set by cargo for build scripts) environment variables, falling back to the
+//! number of available CPUs.
+
+use std::sync::{Mutex, OnceLock};
+
+struct Slots {
+    max_jobs: usize,
+    /// Slots taken by helper threads. The thread calling [`parallel_map`] always works without
+    /// taking a slot, so nested calls can't deadlock.
+    used: usize,
+}
+
+fn slots() -> &'static Mutex<Slots> {
+    static SLOTS: OnceLock<Mutex<Slots>> = OnceLock::new();
+    SLOTS.get_or_init(|| {
+        Mutex::new(Slots {
+            max_jobs: default_max_jobs(),
+            used: 0,
+        })
+    })
+}
+
+fn default_max_jobs() -> usize {
+    ["TOOLBELT_JOBS", "NUM_JOBS"]
+        .iter()
+        .filter_map(|name| std::env::var(name).ok())
+        .find_map(|value| value.trim().parse::<usize>().ok())
+        .filter(|jobs| *jobs > 0)
+        .unwrap_or_else(|| {
+            std::thread::available_parallelism()
+                .map(|n| n.get())
+                .unwrap_or(1)
+        })
+}
+
+/// Limit the number of jobs toolbelt runs in parallel across all its functions.
+///
+/// A value of `1` makes all operations sequential. `0` is treated as `1`.
+pub fn set_max_jobs(jobs: usize) {
+    slots().lock().unwrap().max_jobs = jobs.max(1);
+}
+
+/// The maximum number of jobs toolbelt runs in parallel.
+pub fn max_jobs() -> usize {
+    slots().lock().unwrap().max_jobs
+}
+
+/// Take up to `wanted` free slots for helper threads.
+fn acquire(wanted: usize) -> usize {
+    let mut slots = slots().lock().unwrap();
+    // the calling thread counts as one job
+    let free = slots.max_jobs.saturating_sub(1).saturating_sub(slots.used);
+    let granted = wanted.min(free);
+    slots.used += granted;
+    granted
+}
+
+/// Returns the slots taken by [`acquire`], even if a job panics.
+struct Release(usize);
+
+impl Drop for Release {
+    fn drop(&mut self) {
+        slots().lock().unwrap().used -= self.0;
+    }
+}
+
+/// Apply `f` to all items, using as many threads as the shared job limit allows.
+///
+/// Results are returned in the order of `items`.
+pub(crate) fn parallel_map<T, R, F>(items: &[T], f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+{
+    let helpers = acquire(items.len().saturating_sub(1));
+    let _release = Release(helpers);
+    if helpers == 0 {
+        return items.iter().map(f).collect();
+    }
+
+    let next = Mutex::new(0usize);
+    let work = || {
+        let mut results = Vec::new();
+        loop {
+            let index = {
+                let mut next = next.lock().unwrap();
+                let index = *next;
+                *next += 1;
+                index
+            };
+            match items.get(index) {
+                Some(item) => results.push((index, f(item))),
+                None => return results,
+            }
+        }
+    };
+
+    let mut results = std::thread::scope(|scope| {
+        let handles: Vec<_> = (0..helpers).map(|_| scope.spawn(work)).collect();
+        let mut results = work();
+        for handle in handles {
+            results.extend(handle.join().unwrap());
+        }
+        results
+    });
+
+    results.sort_by_key(|(index, _)| *index);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+#[test]
+fn test_parallel_map() {
+    let items: Vec<u64> = (0..100).collect();
+    let squares = parallel_map(&items, |n| n * n);
+    assert_eq!(squares, items.iter().map(|n| n * n).collect::<Vec<_>>());
+
+    // nested calls share the slots without deadlocking
+    let sums = parallel_map(&items[..8], |n| {
+        parallel_map(&items, |m| m + n).iter().sum::<u64>()
+    });
+    assert_eq!(sums[0], 4950);
+}
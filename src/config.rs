@@ -0,0 +1,220 @@
+//! Configuration from `toolbelt.toml`, with per-environment overlays.
+//!
+//! Besides the base settings the file may contain overlays that are merged on top of them when
+//! selected:
+//!
+//! ```toml
+//! sdk_path = "/opt/sdks"
+//! jobs = 8
+//!
+//! [profile.ci]
+//! jobs = 2
+//!
+//! [target.'aarch64-apple-darwin']
+//! deployment_target = "11.0"
+//! ```
+//!
+//! The target overlay is applied first, then the profile overlay. Tables are merged key by key,
+//! all other values are replaced.
+
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+
+use crate::toml::{self, Table, Value};
+
+/// Default name of the configuration file.
+pub const CONFIG_FILE_NAME: &str = "toolbelt.toml";
+
+/// Environment variable selecting the profile overlay.
+pub const PROFILE_ENV: &str = "TOOLBELT_PROFILE";
+
+/// Merged configuration.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Config {
+    values: Table,
+}
+
+impl Config {
+    /// Load a configuration file, selecting the profile from `TOOLBELT_PROFILE` and the target
+    /// from `TOOLBELT_TARGET` or cargo's `TARGET`.
+    pub fn load(path: &Path) -> Result<Config, Error> {
+        let profile = std::env::var(PROFILE_ENV).ok();
+        let target = std::env::var("TOOLBELT_TARGET")
+            .or_else(|_| std::env::var("TARGET"))
+            .ok();
+        Config::load_for(path, profile.as_deref(), target.as_deref())
+    }
+
+    /// Load a configuration file with explicitly selected overlays.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - the configuration file
+    /// * `profile` - name of a `[profile.<name>]` overlay, which must exist if given
+    /// * `target` - target triple of an optional `[target.'<triple>']` overlay
+    pub fn load_for(
+        path: &Path,
+        profile: Option<&str>,
+        target: Option<&str>,
+    ) -> Result<Config, Error> {
+        let text = fs::read_to_string(path)?;
+        Config::parse(&text, profile, target)
+            .map_err(|e| Error::new(e.kind(), format!("{}: {}", path.display(), e)))
+    }
+
+    /// Parse configuration from a string, see [`Config::load_for`].
+    pub fn parse(text: &str, profile: Option<&str>, target: Option<&str>) -> Result<Config, Error> {
+        let mut values = toml::parse(text)?;
+        let profiles = take_table(&mut values, "profile")?;
+        let targets = take_table(&mut values, "target")?;
+
+        if let Some(target) = target {
+            if let Some(overlay) = targets.get(target) {
+                debug!("Applying config overlay for target {}", target);
+                merge(&mut values, overlay_table(overlay, "target", target)?);
+            }
+        }
+        if let Some(profile) = profile {
+            let overlay = profiles.get(profile).ok_or_else(|| {
+                Error::new(
+                    ErrorKind::NotFound,
+                    format!("Profile '{}' is not defined", profile),
+                )
+            })?;
+            debug!("Applying config overlay for profile {}", profile);
+            merge(&mut values, overlay_table(overlay, "profile", profile)?);
+        }
+
+        Ok(Config { values })
+    }
+
+    fn get(&self, key: &str) -> Option<&Value> {
+        let mut parts = key.split('.');
+        let mut value = self.values.get(parts.next()?)?;
+        for part in parts {
+            value = value.as_table()?.get(part)?;
+        }
+        Some(value)
+    }
+
+    /// Check whether a setting exists. Nested keys are separated by dots (`ios.team_id`).
+    pub fn contains(&self, key: &str) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// The keys of a table setting, e.g. the names of the `[sdk.<name>]` tables for `sdk`. Empty if
+    /// the setting doesn't exist or isn't a table.
+    pub fn keys(&self, key: &str) -> Vec<&str> {
+        self.get(key)
+            .and_then(Value::as_table)
+            .map(|table| table.keys().map(String::as_str).collect())
+            .unwrap_or_default()
+    }
+
+    /// A string setting. Nested keys are separated by dots (`ios.team_id`).
+    pub fn get_str(&self, key: &str) -> Option<&str> {
+        self.get(key)?.as_str()
+    }
+
+    /// An integer setting. Nested keys are separated by dots.
+    pub fn get_i64(&self, key: &str) -> Option<i64> {
+        match self.get(key)? {
+            Value::Integer(i) => Some(*i),
+            _ => None,
+        }
+    }
+
+    /// A boolean setting. Nested keys are separated by dots.
+    pub fn get_bool(&self, key: &str) -> Option<bool> {
+        match self.get(key)? {
+            Value::Boolean(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    /// An array of strings setting. Nested keys are separated by dots.
+    pub fn get_strings(&self, key: &str) -> Option<Vec<String>> {
+        match self.get(key)? {
+            Value::Array(items) => items
+                .iter()
+                .map(|item| item.as_str().map(str::to_string))
+                .collect(),
+            _ => None,
+        }
+    }
+}
+
+fn take_table(values: &mut Table, key: &str) -> Result<Table, Error> {
+    match values.remove(key) {
+        None => Ok(Table::new()),
+        Some(Value::Table(table)) => Ok(table),
+        Some(_) => Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("{} must be a table", key),
+        )),
+    }
+}
+
+fn overlay_table(overlay: &Value, kind: &str, name: &str) -> Result<Table, Error> {
+    overlay.as_table().cloned().ok_or_else(|| {
+        Error::new(
+            ErrorKind::InvalidData,
+            format!("{}.{} must be a table", kind, name),
+        )
+    })
+}
+
+/// Merge `overlay` into `base`, recursing into tables present in both.
+fn merge(base: &mut Table, overlay: Table) {
+    for (key, value) in overlay {
+        match (base.get_mut(&key), value) {
+            (Some(Value::Table(base)), Value::Table(overlay)) => merge(base, overlay),
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_config_overlays() {
+    let text = r#"
+jobs = 8
+features = ["a", "b"]
+
+[signing]
+identity = "-"
+timestamp = false
+
+[profile.ci]
+jobs = 2
+signing.timestamp = true
+
+[target.'aarch64-apple-darwin']
+jobs = 4
+deployment_target = "11.0"
+"#;
+
+    let base = Config::parse(text, None, None).unwrap();
+    assert_eq!(base.get_i64("jobs"), Some(8));
+    assert_eq!(base.get_bool("signing.timestamp"), Some(false));
+    assert_eq!(base.get_strings("features").unwrap(), ["a", "b"]);
+    assert!(!base.contains("profile"));
+    assert_eq!(base.keys("signing"), ["identity", "timestamp"]);
+    assert!(base.keys("jobs").is_empty());
+
+    let target = Config::parse(text, None, Some("aarch64-apple-darwin")).unwrap();
+    assert_eq!(target.get_i64("jobs"), Some(4));
+    assert_eq!(target.get_str("deployment_target"), Some("11.0"));
+
+    let ci = Config::parse(text, Some("ci"), Some("aarch64-apple-darwin")).unwrap();
+    assert_eq!(ci.get_i64("jobs"), Some(2));
+    assert_eq!(ci.get_str("signing.identity"), Some("-"));
+    assert_eq!(ci.get_bool("signing.timestamp"), Some(true));
+    assert_eq!(ci.get_str("deployment_target"), Some("11.0"));
+
+    // unknown targets are fine, unknown profiles are likely typos
+    assert!(Config::parse(text, None, Some("x86_64-pc-windows-msvc")).is_ok());
+    assert!(Config::parse(text, Some("nightly"), None).is_err());
+}
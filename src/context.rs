@@ -0,0 +1,260 @@
+//! Services shared by the steps of a [`crate::pipeline::Pipeline`].
+//!
+//! Steps receive a [`Context`] instead of reaching for globals: it runs tools, logs on behalf of
+//! the current step, hands out cache directories and secrets, and tells which target and profile
+//! are being built.
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+
+use crate::config::Config;
+use crate::pipeline::is_up_to_date;
+use crate::tool::{Tool, ToolError, ToolOutput};
+
+/// The target and build profile a pipeline builds for.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TargetProfile {
+    /// Target triple, e.g. `aarch64-apple-darwin`. `None` builds for the host.
+    pub target: Option<String>,
+    /// Cargo profile, e.g. `debug` or `release`
+    pub profile: String,
+    /// Configuration with the overlays for target and profile applied
+    pub config: Config,
+}
+
+impl Default for TargetProfile {
+    /// Target and profile cargo passes to build scripts (`TARGET`, `PROFILE`), without
+    /// configuration.
+    fn default() -> Self {
+        TargetProfile {
+            target: std::env::var("TARGET").ok(),
+            profile: std::env::var("PROFILE").unwrap_or_else(|_| "debug".to_string()),
+            config: Config::default(),
+        }
+    }
+}
+
+impl TargetProfile {
+    pub fn is_release(&self) -> bool {
+        self.profile == "release"
+    }
+}
+
+/// Secrets such as passwords and API keys for steps.
+///
+/// Values that weren't added explicitly are taken from environment variables of the same name.
+/// Secrets are never printed, `Debug` only shows their names.
+#[derive(Clone, Default)]
+pub struct Secrets {
+    values: BTreeMap<String, String>,
+}
+
+impl Secrets {
+    pub fn new() -> Secrets {
+        Secrets::default()
+    }
+
+    pub fn insert(mut self, name: &str, value: &str) -> Secrets {
+        self.values.insert(name.to_string(), value.to_string());
+        self
+    }
+
+    pub fn get(&self, name: &str) -> Option<String> {
+        self.values
+            .get(name)
+            .cloned()
+            .or_else(|| std::env::var(name).ok())
+    }
+}
+
+impl fmt::Debug for Secrets {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.values.keys()).finish()
+    }
+}
+
+/// What steps of a pipeline run against.
+pub struct Context {
+    staging_dir: PathBuf,
+    dry_run: bool,
+    target_profile: TargetProfile,
+    secrets: Secrets,
+    /// Name of the step currently running
+    step: RefCell<String>,
+    /// Command lines of the tools run so far, secrets masked
+    commands: RefCell<Vec<String>>,
+}
+
+impl Context {
+    pub(crate) fn new(
+        staging_dir: &Path,
+        dry_run: bool,
+        target_profile: TargetProfile,
+        secrets: Secrets,
+    ) -> Context {
+        Context {
+            staging_dir: staging_dir.to_path_buf(),
+            dry_run,
+            target_profile,
+            secrets,
+            step: RefCell::new(String::new()),
+            commands: RefCell::new(Vec::new()),
+        }
+    }
+
+    pub(crate) fn enter_step(&self, name: &str) {
+        *self.step.borrow_mut() = name.to_string();
+    }
+
+    pub(crate) fn take_commands(&self) -> Vec<String> {
+        self.commands.take()
+    }
+
+    /// The directory the pipeline builds into.
+    pub fn staging_dir(&self) -> &Path {
+        &self.staging_dir
+    }
+
+    /// `true` if the pipeline only reports what it would do.
+    pub fn is_dry_run(&self) -> bool {
+        self.dry_run
+    }
+
+    pub fn target_profile(&self) -> &TargetProfile {
+        &self.target_profile
+    }
+
+    /// Shortcut for the configuration of the target profile.
+    pub fn config(&self) -> &Config {
+        &self.target_profile.config
+    }
+
+    /// A secret by name. Pass it to tools with [`Tool::secret_arg`] so it isn't logged.
+    pub fn secret(&self, name: &str) -> Result<String, Error> {
+        self.secrets
+            .get(name)
+            .ok_or_else(|| Error::new(ErrorKind::NotFound, format!("Secret {} is not set", name)))
+    }
+
+    /// A directory for the current step to keep files between runs, created if needed.
+    pub fn cache_dir(&self) -> Result<PathBuf, Error> {
+        let step: String = self
+            .step
+            .borrow()
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+            .collect();
+        let dir = self.staging_dir.join(".cache").join(step);
+        std::fs::create_dir_all(&dir)?;
+        Ok(dir)
+    }
+
+    /// The cache directory shared by all products and steps of the pipeline, created if needed.
+    pub fn shared_cache_dir(&self) -> Result<PathBuf, Error> {
+        let dir = self.staging_dir.join(".cache/shared");
+        std::fs::create_dir_all(&dir)?;
+        Ok(dir)
+    }
+
+    /// An artifact several products or steps use, e.g. a framework embedded in the main app and
+    /// a helper app, built only once.
+    ///
+    /// `build` is called with the artifact's path in the [`Context::shared_cache_dir`] unless
+    /// the artifact is already newer than `inputs`, see [`is_up_to_date`]. Returns the path.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - file name of the artifact, e.g. `Shared.framework`
+    /// * `inputs` - files or directories the artifact is built from
+    /// * `build` - creates the artifact at the given path
+    pub fn shared_artifact<F>(
+        &self,
+        name: &str,
+        inputs: &[PathBuf],
+        build: F,
+    ) -> Result<PathBuf, Error>
+    where
+        F: FnOnce(&Path) -> Result<(), Error>,
+    {
+        let path = self.shared_cache_dir()?.join(name);
+        if is_up_to_date(inputs, std::slice::from_ref(&path))? {
+            self.debug(&format!("Reusing {}", path.display()));
+        } else {
+            self.debug(&format!("Building shared {}", name));
+            build(&path)?;
+        }
+        Ok(path)
+    }
+
+    /// Run a tool, recording its command line for the pipeline report.
+    pub fn run_tool(&self, tool: &mut Tool) -> Result<ToolOutput, ToolError> {
+        let command = tool.command_line();
+        self.debug(&format!("Running {}", command));
+        self.commands.borrow_mut().push(command);
+        tool.run()
+    }
+
+    pub fn info(&self, message: &str) {
+        info!("[{}] {}", self.step.borrow(), message);
+    }
+
+    pub fn warn(&self, message: &str) {
+        warn!("[{}] {}", self.step.borrow(), message);
+    }
+
+    pub fn debug(&self, message: &str) {
+        debug!("[{}] {}", self.step.borrow(), message);
+    }
+}
+
+#[test]
+fn test_context() {
+    let root = Path::new("target/context");
+    let _ = std::fs::remove_dir_all(root);
+    let secrets = Secrets::new().insert("NOTARY_PASSWORD", "hunter2");
+    assert_eq!(format!("{:?}", secrets), "{\"NOTARY_PASSWORD\"}");
+
+    let context = Context::new(root, false, TargetProfile::default(), secrets);
+    context.enter_step("compile shaders");
+    assert_eq!(context.secret("NOTARY_PASSWORD").unwrap(), "hunter2");
+    assert_eq!(
+        context
+            .secret("TOOLBELT_MISSING_SECRET")
+            .unwrap_err()
+            .kind(),
+        ErrorKind::NotFound
+    );
+    assert_eq!(
+        context.cache_dir().unwrap(),
+        root.join(".cache/compile-shaders")
+    );
+
+    let builds = std::cell::Cell::new(0);
+    let inputs = [PathBuf::from("test/my_files")];
+    for _ in 0..2 {
+        let artifact = context
+            .shared_artifact("files.txt", &inputs, |path| {
+                builds.set(builds.get() + 1);
+                std::fs::write(path, "files")
+            })
+            .unwrap();
+        assert_eq!(artifact, root.join(".cache/shared/files.txt"));
+    }
+    // the second request reuses the artifact
+    assert_eq!(builds.get(), 1);
+
+    let _ = context.run_tool(
+        Tool::new("toolbelt-missing-tool")
+            .arg("--password")
+            .secret_arg("hunter2"),
+    );
+    assert_eq!(
+        context.take_commands(),
+        ["toolbelt-missing-tool --password ***"]
+    );
+
+    std::fs::remove_dir_all(root).unwrap();
+}
@@ -0,0 +1,1326 @@
+//! Copying files selected by glob patterns, split into a plan and an execute phase.
+//!
+//! [`CopyPlanner`] walks the source directory and produces a [`CopyPlan`], a list of
+//! [`CopyAction`]s that can be inspected, filtered or serialized before being executed.
+//! [`copy_dir_with_options`] does both steps at once.
+//!
+//! Planning stats every matched file. The walk only reads the entries themselves; the metadata
+//! of symlink targets and destination files is then fetched in parallel, which matters on
+//! network file systems where each stat is a round trip.
+//!
+//! A plan can also write to [mirrors](CopyPlanner::mirror) of the destination, e.g. a local
+//! bundle and a network share. Each source file is then read once and written to all of them.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::{File, FileType, Metadata};
+use std::io::{Error, ErrorKind, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+use globset::GlobMatcher;
+
+use crate::build_script::BuildScriptOutput;
+use crate::concurrency::parallel_map;
+use crate::fileset::{FileSet, PatternOptions};
+use crate::json::Value;
+use crate::manifest::Manifest;
+
+/// Options for [`copy_dir_with_options`] and [`CopyPlanner`].
+#[derive(Clone, Debug, Default)]
+pub struct CopyOptions {
+    /// Write a JSON [`Manifest`] of all copied files, including those skipped as up to date, to
+    /// this path, relative to the destination (e.g. [`crate::manifest::MANIFEST_FILE_NAME`]).
+    pub manifest: Option<PathBuf>,
+    /// Recreate symbolic links at the destination instead of copying the files they point to.
+    pub preserve_symlinks: bool,
+    /// Skip files smaller than this many bytes
+    pub min_size: Option<u64>,
+    /// Skip files larger than this many bytes
+    pub max_size: Option<u64>,
+    /// Skip files last modified before this time
+    pub modified_since: Option<SystemTime>,
+    /// Skip files last modified at or after this time
+    pub modified_before: Option<SystemTime>,
+    /// Destination subdirectories for files matching a glob pattern, see [`CopyOptions::route`]
+    pub routes: Vec<(String, PathBuf)>,
+    /// Matching of the pattern and routes, and the walk of the source, e.g. its maximum depth.
+    /// With [`crate::DeniedEntryPolicy::Collect`], entries that can't be read are added to the
+    /// plan as skipped.
+    pub patterns: PatternOptions,
+    /// Skip files whose destination has the same size and is at least as new as the source
+    pub skip_unchanged: bool,
+    /// Report the source directory and the files to copy as inputs of a build script. The
+    /// directory isn't reported if the destination lies inside it, or each copy would trigger
+    /// the next build.
+    pub build_script: Option<BuildScriptOutput>,
+}
+
+impl CopyOptions {
+    /// Copy files matching `pattern` into `subdirectory` of the destination, keeping their path
+    /// relative to the source below it. The first matching route wins, other files are copied to
+    /// the destination itself.
+    ///
+    /// Patterns without a `/` are matched against the file name, others against the path
+    /// relative to the source. If routes are set, directories are only created as needed for the
+    /// files copied into them.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use std::path::Path;
+    /// use toolbelt::{copy_dir_with_options, CopyOptions};
+    ///
+    /// let options = CopyOptions::default()
+    ///     .route("*.dylib", "Frameworks")
+    ///     .route("*.nib", "Resources")
+    ///     .route("*.metallib", "Resources/Shaders");
+    /// copy_dir_with_options(
+    ///     Path::new("target/stage"),
+    ///     Path::new("target/MyApp.app/Contents"),
+    ///     "**/*",
+    ///     &options,
+    /// )
+    /// .unwrap();
+    /// ```
+    pub fn route<P: AsRef<Path>>(mut self, pattern: &str, subdirectory: P) -> CopyOptions {
+        self.routes
+            .push((pattern.to_string(), subdirectory.as_ref().to_path_buf()));
+        self
+    }
+
+    /// Why a file with the given metadata is excluded by the size and date filters, if it is.
+    fn filter(&self, metadata: &std::fs::Metadata) -> Option<SkipReason> {
+        let size = metadata.len();
+        if self.min_size.is_some_and(|min| size < min)
+            || self.max_size.is_some_and(|max| size > max)
+        {
+            return Some(SkipReason::Size);
+        }
+        if self.modified_since.is_some() || self.modified_before.is_some() {
+            let modified = metadata.modified().ok()?;
+            if self.modified_since.is_some_and(|since| modified < since)
+                || self
+                    .modified_before
+                    .is_some_and(|before| modified >= before)
+            {
+                return Some(SkipReason::Modified);
+            }
+        }
+        None
+    }
+}
+
+/// Why a matched entry is not copied.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SkipReason {
+    /// Sockets, FIFOs, device files and the like can't be copied
+    SpecialFile,
+    /// The file size is outside of [`CopyOptions::min_size`] and [`CopyOptions::max_size`]
+    Size,
+    /// The modification time is outside of [`CopyOptions::modified_since`] and
+    /// [`CopyOptions::modified_before`]
+    Modified,
+    /// Reading the entry wasn't permitted
+    PermissionDenied,
+    /// The destination is up to date, see [`CopyOptions::skip_unchanged`]
+    UpToDate,
+}
+
+impl fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SkipReason::SpecialFile => f.write_str("special file"),
+            SkipReason::Size => f.write_str("size out of range"),
+            SkipReason::Modified => f.write_str("modification time out of range"),
+            SkipReason::PermissionDenied => f.write_str("permission denied"),
+            SkipReason::UpToDate => f.write_str("up to date"),
+        }
+    }
+}
+
+/// Statistics of an executed [`CopyPlan`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CopyReport {
+    /// Number of files copied or linked
+    pub files: usize,
+    /// Total number of bytes copied
+    pub bytes: u64,
+    /// Number of matched entries that were skipped
+    pub skipped: usize,
+    /// Number of skipped entries per reason. Each skipped entry is logged at trace level.
+    pub skipped_by_reason: BTreeMap<SkipReason, usize>,
+    pub elapsed: Duration,
+    /// Results per destination, the destination of the plan first and then its mirrors
+    pub destinations: Vec<DestinationReport>,
+}
+
+/// What a [`CopyPlan`] wrote to one of its destinations.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DestinationReport {
+    pub destination: PathBuf,
+    /// Number of files copied or linked
+    pub files: usize,
+    /// Total number of bytes copied
+    pub bytes: u64,
+    /// The first error writing to the destination. Mirrors stop receiving files after an error.
+    pub error: Option<String>,
+}
+
+impl CopyReport {
+    /// Destinations that couldn't be written completely.
+    pub fn failed(&self) -> impl Iterator<Item = &DestinationReport> {
+        self.destinations.iter().filter(|d| d.error.is_some())
+    }
+
+    /// Bytes copied per second.
+    pub fn throughput(&self) -> f64 {
+        let seconds = self.elapsed.as_secs_f64();
+        if seconds > 0.0 {
+            self.bytes as f64 / seconds
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Format a byte count with a binary unit prefix, e.g. `1.5 MiB`.
+fn human_bytes(bytes: f64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes;
+    let mut unit = 0;
+    while value >= 1024.0 && unit + 1 < UNITS.len() {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", value, UNITS[0])
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+impl fmt::Display for CopyReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Copied {} files ({}) in {:.2}s ({}/s)",
+            self.files,
+            human_bytes(self.bytes as f64),
+            self.elapsed.as_secs_f64(),
+            human_bytes(self.throughput().round())
+        )?;
+        if self.skipped > 0 {
+            let reasons: Vec<String> = self
+                .skipped_by_reason
+                .iter()
+                .map(|(reason, count)| format!("{} {}", count, reason))
+                .collect();
+            write!(f, ", skipped {} ({})", self.skipped, reasons.join(", "))?;
+        }
+        if self.destinations.len() > 1 {
+            write!(f, " to {} destinations", self.destinations.len())?;
+        }
+        for failed in self.failed() {
+            write!(f, ", {} failed", failed.destination.display())?;
+        }
+        Ok(())
+    }
+}
+
+/// A single step of a [`CopyPlan`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CopyAction {
+    /// Create a directory including missing parents
+    CreateDir { path: PathBuf },
+    /// Copy a file
+    Copy {
+        source: PathBuf,
+        destination: PathBuf,
+    },
+    /// Create a symbolic link pointing to `target`
+    Link {
+        target: PathBuf,
+        destination: PathBuf,
+    },
+    /// Leave a matched entry alone
+    Skip { source: PathBuf, reason: SkipReason },
+}
+
+impl CopyAction {
+    fn to_json(&self) -> Value {
+        let path = |p: &Path| Value::from(p.display().to_string());
+        let members = match self {
+            CopyAction::CreateDir { path: dir } => {
+                vec![("action", "mkdir".into()), ("path", path(dir))]
+            }
+            CopyAction::Copy {
+                source,
+                destination,
+            } => vec![
+                ("action", "copy".into()),
+                ("source", path(source)),
+                ("destination", path(destination)),
+            ],
+            CopyAction::Link {
+                target,
+                destination,
+            } => vec![
+                ("action", "link".into()),
+                ("target", path(target)),
+                ("destination", path(destination)),
+            ],
+            CopyAction::Skip { source, reason } => vec![
+                ("action", "skip".into()),
+                ("source", path(source)),
+                ("reason", reason.to_string().into()),
+            ],
+        };
+        Value::Object(
+            members
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value))
+                .collect(),
+        )
+    }
+}
+
+/// Walks a source directory and plans which files to copy where.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::path::Path;
+/// use toolbelt::copy::{CopyAction, CopyPlanner};
+///
+/// let mut plan = CopyPlanner::new(Path::new("assets"), Path::new("target/assets"), "**/*")
+///     .plan()
+///     .unwrap();
+/// // don't ship any editor backups
+/// plan.retain(|action| match action {
+///     CopyAction::Copy { source, .. } => source.extension().map_or(true, |e| e != "bak"),
+///     _ => true,
+/// });
+/// println!("{}", plan.to_json());
+/// plan.execute().unwrap();
+/// ```
+pub struct CopyPlanner {
+    source: PathBuf,
+    destination: PathBuf,
+    mirrors: Vec<PathBuf>,
+    pattern: String,
+    options: CopyOptions,
+}
+
+impl CopyPlanner {
+    /// # Arguments
+    ///
+    /// * `source` - the source path
+    /// * `destination` - the destination path
+    /// * `pattern` - a standard glob pattern selecting the files to be copied
+    pub fn new(source: &Path, destination: &Path, pattern: &str) -> CopyPlanner {
+        CopyPlanner {
+            source: source.to_path_buf(),
+            destination: destination.to_path_buf(),
+            mirrors: Vec::new(),
+            pattern: pattern.to_string(),
+            options: CopyOptions::default(),
+        }
+    }
+
+    /// Also copy to another destination, which receives the same tree as the destination. Each
+    /// source file is read only once.
+    ///
+    /// A mirror that fails doesn't stop the copy, its error is recorded in the
+    /// [`CopyReport::destinations`]. Errors writing to the destination itself still fail
+    /// [`CopyPlan::execute`].
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use std::path::Path;
+    /// use toolbelt::copy::CopyPlanner;
+    ///
+    /// let report = CopyPlanner::new(Path::new("target/release/bundle"), Path::new("dist"), "**/*")
+    ///     .mirror(Path::new("/Volumes/builds/nightly"))
+    ///     .plan()
+    ///     .unwrap()
+    ///     .execute()
+    ///     .unwrap();
+    /// for failed in report.failed() {
+    ///     eprintln!("{}: {}", failed.destination.display(), failed.error.as_ref().unwrap());
+    /// }
+    /// ```
+    pub fn mirror(mut self, destination: &Path) -> CopyPlanner {
+        self.mirrors.push(destination.to_path_buf());
+        self
+    }
+
+    /// Set the options used for planning and execution.
+    pub fn options(mut self, options: CopyOptions) -> CopyPlanner {
+        self.options = options;
+        self
+    }
+
+    /// Report the watched directory and the sources of the planned copies to the build script.
+    fn report_inputs(&self, watched: Option<&Path>, plan: &CopyPlan) {
+        let Some(output) = &self.options.build_script else {
+            return;
+        };
+        if let Some(dir) = watched {
+            output.rerun_if_changed(dir);
+        }
+        for action in &plan.actions {
+            if let CopyAction::Copy { source, .. } = action {
+                output.rerun_if_changed(source);
+            }
+        }
+    }
+
+    /// Walk the source directory and create the list of actions.
+    ///
+    /// A pattern without any glob metacharacters is taken as the exact path of a file relative
+    /// to the source. If it names a directory, the directory is copied with all its contents.
+    /// Such a path must stay inside the source: absolute paths and `..` are rejected.
+    ///
+    /// Copying a directory onto itself is an error. If the destination lies inside the source,
+    /// its contents are left out of the plan, so earlier copies aren't copied again.
+    pub fn plan(&self) -> Result<CopyPlan, Error> {
+        let source_path = self.source.canonicalize()?;
+        let mut nested_destinations = Vec::new();
+        // files may only be copied onto themselves if source and destination overlap
+        let mut overlapping = false;
+        for (i, destination) in std::iter::once(&self.destination)
+            .chain(&self.mirrors)
+            .enumerate()
+        {
+            let nested = match nested_destination(&source_path, destination) {
+                Ok(nested) => nested,
+                // an unreachable mirror is reported when executing the plan
+                Err(e) if i > 0 && e.kind() != ErrorKind::InvalidInput => continue,
+                Err(e) => return Err(e),
+            };
+            if let Some(nested) = nested {
+                nested_destinations.push(nested);
+                overlapping = true;
+            } else if source_path.starts_with(absolute_path(destination)?) {
+                overlapping = true;
+            }
+        }
+        let mut plan = CopyPlan {
+            destination: self.destination.clone(),
+            mirrors: self.mirrors.clone(),
+            actions: Vec::new(),
+            manifest: self.options.manifest.clone(),
+            up_to_date: BTreeMap::new(),
+        };
+        let mut existing_paths: Vec<PathBuf> = Vec::new();
+        let mut pattern = self.pattern.clone();
+        let mut watched = source_path.clone();
+        let routes = self
+            .options
+            .routes
+            .iter()
+            .map(|(pattern, subdirectory)| {
+                self.options
+                    .patterns
+                    .glob(pattern)
+                    .map(|glob| (glob.compile_matcher(), subdirectory.clone()))
+                    .map_err(|e| Error::new(ErrorKind::InvalidInput, e))
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        if is_literal(&pattern) {
+            if Path::new(&pattern)
+                .components()
+                .any(|c| !matches!(c, Component::Normal(_) | Component::CurDir))
+            {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("{} leads outside of {}", pattern, self.source.display()),
+                ));
+            }
+            let literal_path = source_path.join(&pattern);
+            let metadata = literal_path.symlink_metadata()?;
+            let file_type = metadata.file_type();
+            let entries = [(literal_path, metadata)];
+            let prefetched = self.prefetch(&source_path, &entries, &routes);
+            self.plan_entry(
+                &source_path,
+                &entries[0].0,
+                &prefetched[0],
+                &routes,
+                &mut plan,
+                &mut existing_paths,
+            )?;
+            if !file_type.is_dir() {
+                if overlapping {
+                    plan.reject_self_copies()?;
+                }
+                self.report_inputs(None, &plan);
+                return Ok(plan);
+            }
+            watched = entries[0].0.clone();
+            pattern = format!("{}/**", pattern.trim_end_matches('/'));
+        }
+
+        let mut entries = FileSet::new(&source_path)
+            .pattern(&pattern)
+            .options(self.options.patterns.clone())
+            .into_iter();
+        let mut matched = Vec::new();
+        for entry in &mut entries {
+            let entry = entry?;
+            if nested_destinations
+                .iter()
+                .any(|d| entry.path.starts_with(d))
+            {
+                trace!("Leave out {}: inside the destination", entry.path.display());
+                continue;
+            }
+            matched.push((entry.path, entry.metadata));
+        }
+        let prefetched = self.prefetch(&source_path, &matched, &routes);
+        for ((path, _), prefetched) in matched.iter().zip(&prefetched) {
+            self.plan_entry(
+                &source_path,
+                path,
+                prefetched,
+                &routes,
+                &mut plan,
+                &mut existing_paths,
+            )?;
+        }
+        for path in entries.denied() {
+            plan.actions.push(CopyAction::Skip {
+                source: path.clone(),
+                reason: SkipReason::PermissionDenied,
+            });
+        }
+
+        if overlapping {
+            plan.reject_self_copies()?;
+        }
+        let watched = Some(watched.as_path()).filter(|_| nested_destinations.is_empty());
+        self.report_inputs(watched, &plan);
+        Ok(plan)
+    }
+
+    /// Where a matched entry is copied to, taking routes into account.
+    fn destination_file(
+        &self,
+        source_path: &Path,
+        path: &Path,
+        routes: &[(GlobMatcher, PathBuf)],
+    ) -> PathBuf {
+        let relative_path = path.strip_prefix(source_path).unwrap();
+        let route = routes.iter().find(|(matcher, _)| {
+            if matcher.glob().glob().contains('/') {
+                matcher.is_match(relative_path)
+            } else {
+                relative_path
+                    .file_name()
+                    .is_some_and(|name| matcher.is_match(name))
+            }
+        });
+        match route {
+            Some((_, subdirectory)) => self.destination.join(subdirectory).join(relative_path),
+            None => self.destination.join(relative_path),
+        }
+    }
+
+    /// Fetch the metadata planning needs beyond what the walk read, in parallel: the targets of
+    /// symbolic links and, with [`CopyOptions::skip_unchanged`], the destination files in the
+    /// destination and all mirrors.
+    fn prefetch(
+        &self,
+        source_path: &Path,
+        entries: &[(PathBuf, Metadata)],
+        routes: &[(GlobMatcher, PathBuf)],
+    ) -> Vec<Prefetched> {
+        parallel_map(entries, |(path, metadata)| {
+            let file_type = metadata.file_type();
+            let source = if file_type.is_dir()
+                || (file_type.is_symlink() && self.options.preserve_symlinks)
+            {
+                None
+            } else if file_type.is_symlink() {
+                path.metadata().ok()
+            } else {
+                Some(metadata.clone())
+            };
+            let destinations = if self.options.skip_unchanged && source.is_some() {
+                let destination_file = self.destination_file(source_path, path, routes);
+                let relative_path = destination_file.strip_prefix(&self.destination).unwrap();
+                std::iter::once(destination_file.metadata().ok())
+                    .chain(
+                        self.mirrors
+                            .iter()
+                            .map(|mirror| mirror.join(relative_path).metadata().ok()),
+                    )
+                    .collect()
+            } else {
+                Vec::new()
+            };
+            Prefetched {
+                file_type,
+                source,
+                destinations,
+            }
+        })
+    }
+
+    fn plan_entry(
+        &self,
+        source_path: &Path,
+        path: &Path,
+        prefetched: &Prefetched,
+        routes: &[(GlobMatcher, PathBuf)],
+        plan: &mut CopyPlan,
+        existing_paths: &mut Vec<PathBuf>,
+    ) -> Result<(), Error> {
+        let actions = &mut plan.actions;
+        let destination_file = self.destination_file(source_path, path, routes);
+        let file_type = prefetched.file_type;
+
+        if file_type.is_dir() {
+            if !routes.is_empty() {
+                return Ok(());
+            }
+            if !existing_paths.contains(&destination_file) {
+                existing_paths.push(destination_file.clone());
+                actions.push(CopyAction::CreateDir {
+                    path: destination_file,
+                });
+            }
+            return Ok(());
+        }
+
+        let source_metadata = prefetched.source.as_ref().filter(|m| m.is_file());
+        let copied = !(file_type.is_symlink() && self.options.preserve_symlinks)
+            && source_metadata.is_some();
+        if let Some(metadata) = source_metadata.filter(|_| copied) {
+            let reason = self.options.filter(metadata).or_else(|| {
+                let unchanged = !prefetched.destinations.is_empty()
+                    && prefetched.destinations.iter().all(|destination| {
+                        destination
+                            .as_ref()
+                            .is_some_and(|destination| is_unchanged(metadata, destination))
+                    });
+                unchanged.then_some(SkipReason::UpToDate)
+            });
+            if let Some(reason) = reason {
+                if reason == SkipReason::UpToDate {
+                    plan.up_to_date
+                        .insert(path.to_path_buf(), destination_file.clone());
+                }
+                actions.push(CopyAction::Skip {
+                    source: path.to_path_buf(),
+                    reason,
+                });
+                return Ok(());
+            }
+        }
+
+        let complete_destination_path = destination_file.parent().unwrap().to_path_buf();
+        if !existing_paths.contains(&complete_destination_path) {
+            existing_paths.push(complete_destination_path.clone());
+            actions.push(CopyAction::CreateDir {
+                path: complete_destination_path,
+            });
+        }
+
+        if file_type.is_symlink() && self.options.preserve_symlinks {
+            actions.push(CopyAction::Link {
+                target: std::fs::read_link(path)?,
+                destination: destination_file,
+            });
+        } else if copied {
+            actions.push(CopyAction::Copy {
+                source: path.to_path_buf(),
+                destination: destination_file,
+            });
+        } else {
+            actions.push(CopyAction::Skip {
+                source: path.to_path_buf(),
+                reason: SkipReason::SpecialFile,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Metadata of a matched entry fetched by [`CopyPlanner::prefetch`].
+struct Prefetched {
+    /// Type of the entry itself
+    file_type: FileType,
+    /// The file to copy, following symbolic links. `None` for directories, preserved links and
+    /// dangling links.
+    source: Option<Metadata>,
+    /// The existing destination files, in the destination and each mirror. Empty unless
+    /// [`CopyOptions::skip_unchanged`] is set.
+    destinations: Vec<Option<Metadata>>,
+}
+
+/// `true` if the destination has the source's size and isn't older.
+fn is_unchanged(source: &Metadata, destination: &Metadata) -> bool {
+    destination.is_file()
+        && destination.len() == source.len()
+        && matches!(
+            (source.modified(), destination.modified()),
+            (Ok(source), Ok(destination)) if destination >= source
+        )
+}
+
+/// The canonical form of a path that may not exist yet: its nearest existing ancestor is
+/// canonicalized and the remaining components are appended.
+pub(crate) fn absolute_path(path: &Path) -> Result<PathBuf, Error> {
+    let mut existing = path;
+    let mut rest = Vec::new();
+    loop {
+        match existing.canonicalize() {
+            Ok(canonical) => {
+                return Ok(rest.iter().rev().fold(canonical, |p, c| p.join(c)));
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                let Some(name) = existing.file_name() else {
+                    return Err(e);
+                };
+                rest.push(name.to_os_string());
+                existing = match existing.parent() {
+                    Some(parent) if !parent.as_os_str().is_empty() => parent,
+                    _ => Path::new("."),
+                };
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// The canonical destination if it lies inside the (canonical) source directory, which then
+/// has to be excluded when walking the source. Copying a directory onto itself is an error.
+pub(crate) fn nested_destination(
+    source: &Path,
+    destination: &Path,
+) -> Result<Option<PathBuf>, Error> {
+    let destination = absolute_path(destination)?;
+    if destination == source {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("Can't copy {} onto itself", source.display()),
+        ));
+    }
+    Ok(destination.starts_with(source).then_some(destination))
+}
+
+/// `true` if the pattern doesn't contain any glob metacharacters.
+fn is_literal(pattern: &str) -> bool {
+    !pattern.contains(['*', '?', '[', ']', '{', '}'])
+}
+
+/// The actions needed to perform a copy, created by [`CopyPlanner::plan`].
+#[derive(Clone, Debug)]
+pub struct CopyPlan {
+    destination: PathBuf,
+    mirrors: Vec<PathBuf>,
+    actions: Vec<CopyAction>,
+    manifest: Option<PathBuf>,
+    /// Destination of each source skipped as up to date, still listed in the manifest
+    up_to_date: BTreeMap<PathBuf, PathBuf>,
+}
+
+impl CopyPlan {
+    /// The planned actions in execution order.
+    pub fn actions(&self) -> &[CopyAction] {
+        &self.actions
+    }
+
+    /// Keep only the actions for which `f` returns `true`.
+    pub fn retain<F>(&mut self, f: F)
+    where
+        F: FnMut(&CopyAction) -> bool,
+    {
+        self.actions.retain(f);
+    }
+
+    /// Serialize the plan as JSON, e.g. for a dry run or to keep it as a build artifact.
+    pub fn to_json(&self) -> String {
+        Value::Object(vec![
+            (
+                "destination".to_string(),
+                self.destination.display().to_string().into(),
+            ),
+            (
+                "mirrors".to_string(),
+                Value::Array(
+                    self.mirrors
+                        .iter()
+                        .map(|mirror| mirror.display().to_string().into())
+                        .collect(),
+                ),
+            ),
+            (
+                "actions".to_string(),
+                Value::Array(self.actions.iter().map(CopyAction::to_json).collect()),
+            ),
+        ])
+        .to_pretty_string()
+    }
+
+    /// `path` below the destination moved to the same place below a mirror.
+    fn mirror_path(&self, path: &Path, mirror: &Path) -> PathBuf {
+        mirror.join(path.strip_prefix(&self.destination).unwrap())
+    }
+
+    fn reject_self_copies(&self) -> Result<(), Error> {
+        for action in &self.actions {
+            if let CopyAction::Copy {
+                source,
+                destination,
+            } = action
+            {
+                let mirrored = self
+                    .mirrors
+                    .iter()
+                    .map(|m| self.mirror_path(destination, m));
+                for destination in std::iter::once(destination.clone()).chain(mirrored) {
+                    // mirrors that can't be resolved are reported when executing the plan
+                    if absolute_path(&destination).is_ok_and(|d| d == *source) {
+                        return Err(Error::new(
+                            ErrorKind::InvalidInput,
+                            format!("{} would be copied onto itself", source.display()),
+                        ));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Perform all actions of the plan.
+    ///
+    /// Directories are created first, then files are copied in parallel within the limit set by
+    /// [`crate::concurrency::set_max_jobs`].
+    pub fn execute(&self) -> Result<CopyReport, Error> {
+        let start = Instant::now();
+        let mut report = CopyReport::default();
+        let mut transfers = Vec::new();
+        let mut mirrors: Vec<DestinationReport> = self
+            .mirrors
+            .iter()
+            .map(|mirror| DestinationReport {
+                destination: mirror.clone(),
+                ..Default::default()
+            })
+            .collect();
+
+        for action in &self.actions {
+            match action {
+                CopyAction::CreateDir { path } => {
+                    if !path.exists() {
+                        // make sure the destination path exists
+                        std::fs::create_dir_all(path)?;
+                    }
+                    for mirror in mirrors.iter_mut().filter(|m| m.error.is_none()) {
+                        let path = self.mirror_path(path, &mirror.destination);
+                        if let Err(e) = std::fs::create_dir_all(&path) {
+                            mirror.error = Some(format!("{}: {}", path.display(), e));
+                        }
+                    }
+                }
+                CopyAction::Copy { .. } | CopyAction::Link { .. } => transfers.push(action),
+                CopyAction::Skip { source, reason } => {
+                    trace!("Skip {}: {}", source.display(), reason);
+                    report.skipped += 1;
+                    *report.skipped_by_reason.entry(*reason).or_default() += 1;
+                }
+            }
+        }
+
+        // mirrors that failed to create their directories don't receive any files
+        let active: Vec<&Path> = mirrors
+            .iter()
+            .filter(|m| m.error.is_none())
+            .map(|m| m.destination.as_path())
+            .collect();
+        let transferred = parallel_map(&transfers, |action| {
+            let destination = match action {
+                CopyAction::Copy { destination, .. } | CopyAction::Link { destination, .. } => {
+                    destination
+                }
+                _ => unreachable!(),
+            };
+            let paths: Vec<PathBuf> = std::iter::once(destination.clone())
+                .chain(active.iter().map(|m| self.mirror_path(destination, m)))
+                .collect();
+            let mut results = match action {
+                CopyAction::Copy { source, .. } if paths.len() == 1 => {
+                    vec![std::fs::copy(source, destination)]
+                }
+                CopyAction::Copy { source, .. } => fan_out(source, &paths),
+                CopyAction::Link { target, .. } => paths
+                    .iter()
+                    .map(|path| {
+                        if path.symlink_metadata().is_ok() {
+                            std::fs::remove_file(path)?;
+                        }
+                        create_symlink(target, path).map(|_| 0)
+                    })
+                    .collect(),
+                _ => unreachable!(),
+            };
+            let mirrored = results
+                .split_off(1)
+                .into_iter()
+                .zip(&paths[1..])
+                .map(|(result, path)| result.map_err(|e| format!("{}: {}", path.display(), e)))
+                .collect::<Vec<_>>();
+            results
+                .pop()
+                .unwrap()
+                .map(|bytes| (destination.clone(), bytes, mirrored))
+        })
+        .into_iter()
+        .collect::<Result<Vec<_>, Error>>()?;
+        report.files = transferred.len();
+        report.bytes = transferred.iter().map(|(_, bytes, _)| bytes).sum();
+
+        let mut active = mirrors
+            .iter_mut()
+            .filter(|m| m.error.is_none())
+            .collect::<Vec<_>>();
+        for (_, _, mirrored) in &transferred {
+            for (mirror, result) in active.iter_mut().zip(mirrored) {
+                match result {
+                    Ok(bytes) => {
+                        mirror.files += 1;
+                        mirror.bytes += bytes;
+                    }
+                    Err(e) => {
+                        mirror.error.get_or_insert_with(|| e.clone());
+                    }
+                }
+            }
+        }
+
+        if let Some(manifest) = &self.manifest {
+            // files skipped as up to date are already at the destination
+            let up_to_date = self.actions.iter().filter_map(|action| match action {
+                CopyAction::Skip {
+                    source,
+                    reason: SkipReason::UpToDate,
+                } => self.up_to_date.get(source),
+                _ => None,
+            });
+            let mut files: Vec<&PathBuf> = transferred
+                .iter()
+                .map(|(file, _, _)| file)
+                .chain(up_to_date)
+                .collect();
+            files.sort();
+            let relative_files = files
+                .into_iter()
+                .filter_map(|file| file.strip_prefix(&self.destination).ok());
+            let manifest_path = self.destination.join(manifest);
+            Manifest::from_files(&self.destination, relative_files)?.write(&manifest_path)?;
+            for mirror in mirrors.iter_mut().filter(|m| m.error.is_none()) {
+                let path = self.mirror_path(&manifest_path, &mirror.destination);
+                if let Err(e) = std::fs::copy(&manifest_path, &path) {
+                    mirror.error = Some(format!("{}: {}", path.display(), e));
+                }
+            }
+        }
+
+        report.destinations.push(DestinationReport {
+            destination: self.destination.clone(),
+            files: report.files,
+            bytes: report.bytes,
+            error: None,
+        });
+        for mirror in &mirrors {
+            if let Some(error) = &mirror.error {
+                warn!("Mirror {} failed: {}", mirror.destination.display(), error);
+            }
+        }
+        report.destinations.extend(mirrors);
+        report.elapsed = start.elapsed();
+        info!("{} to {}", report, self.destination.display());
+        Ok(report)
+    }
+}
+
+/// Copy a file to several destinations, reading it only once. Returns the number of bytes
+/// written to each destination, with errors reported per destination.
+fn fan_out(source: &Path, destinations: &[PathBuf]) -> Vec<Result<u64, Error>> {
+    let fail_all = |e: Error| {
+        destinations
+            .iter()
+            .map(|_| Err(Error::new(e.kind(), e.to_string())))
+            .collect()
+    };
+    let (mut input, permissions) = match File::open(source).and_then(|file| {
+        file.metadata()
+            .map(|metadata| (file, metadata.permissions()))
+    }) {
+        Ok(opened) => opened,
+        Err(e) => return fail_all(e),
+    };
+    let mut outputs: Vec<Result<(File, u64), Error>> = destinations
+        .iter()
+        .map(|path| File::create(path).map(|file| (file, 0)))
+        .collect();
+
+    let mut buffer = vec![0; 256 * 1024];
+    loop {
+        let read = match input.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return fail_all(e),
+        };
+        for output in &mut outputs {
+            if let Ok((file, written)) = output {
+                match file.write_all(&buffer[..read]) {
+                    Ok(()) => *written += read as u64,
+                    Err(e) => *output = Err(e),
+                }
+            }
+        }
+    }
+
+    outputs
+        .into_iter()
+        .map(|output| {
+            let (file, written) = output?;
+            file.set_permissions(permissions.clone())?;
+            Ok(written)
+        })
+        .collect()
+}
+
+#[cfg(unix)]
+fn create_symlink(target: &Path, link: &Path) -> Result<(), Error> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(windows)]
+fn create_symlink(target: &Path, link: &Path) -> Result<(), Error> {
+    let resolved = link.parent().unwrap().join(target);
+    if resolved.is_dir() {
+        std::os::windows::fs::symlink_dir(target, link)
+    } else {
+        std::os::windows::fs::symlink_file(target, link)
+    }
+}
+
+/// Copy files from one directory to another like [`crate::copy_dir_with_pattern`], with
+/// additional options. Returns statistics about the copied files.
+///
+/// # Arguments
+///
+/// * `source` - the source path
+/// * `destination` - the destination path
+/// * `pattern` - a standard glob pattern selecting the files to be copied
+/// * `options` - see [`CopyOptions`]
+///
+/// # Example
+///
+/// ```no_run
+/// use std::path::Path;
+/// use toolbelt::{copy_dir_with_options, manifest::MANIFEST_FILE_NAME, CopyOptions};
+///
+/// let options = CopyOptions {
+///     manifest: Some(MANIFEST_FILE_NAME.into()),
+///     ..Default::default()
+/// };
+/// copy_dir_with_options(Path::new("assets"), Path::new("target/assets"), "**/*.png", &options)
+///     .unwrap();
+/// ```
+pub fn copy_dir_with_options(
+    source: &Path,
+    destination: &Path,
+    pattern: &str,
+    options: &CopyOptions,
+) -> Result<CopyReport, Error> {
+    CopyPlanner::new(source, destination, pattern)
+        .options(options.clone())
+        .plan()?
+        .execute()
+}
+
+#[test]
+fn test_copy_dir_with_manifest() {
+    let destination_path: &Path = Path::new("target/dest_files_manifest/");
+    let options = CopyOptions {
+        manifest: Some(crate::manifest::MANIFEST_FILE_NAME.into()),
+        ..Default::default()
+    };
+    copy_dir_with_options(
+        Path::new("test/my_files/"),
+        destination_path,
+        "*.{txt,md}",
+        &options,
+    )
+    .unwrap();
+
+    let manifest =
+        Manifest::read(&destination_path.join(crate::manifest::MANIFEST_FILE_NAME)).unwrap();
+    let paths: Vec<&str> = manifest.entries.iter().map(|e| e.path.as_str()).collect();
+    assert_eq!(paths, ["file1.txt", "more_files/file3.md"]);
+
+    std::fs::remove_dir_all(destination_path).unwrap();
+}
+
+#[test]
+fn test_copy_plan() {
+    let destination_path = Path::new("target/dest_files_plan");
+    let mut plan = CopyPlanner::new(Path::new("test/my_files"), destination_path, "**/*")
+        .plan()
+        .unwrap();
+
+    assert!(plan.actions().contains(&CopyAction::CreateDir {
+        path: destination_path.join("more_files")
+    }));
+    plan.retain(|action| match action {
+        CopyAction::Copy { source, .. } => source.extension().is_some_and(|e| e == "csv"),
+        _ => true,
+    });
+    assert!(plan.to_json().contains("\"action\": \"copy\""));
+    let report = plan.execute().unwrap();
+    assert_eq!(report.files, 1);
+    assert_eq!(report.bytes, 0);
+
+    assert!(destination_path.join("file2.csv").exists());
+    assert!(!destination_path.join("file1.txt").exists());
+    assert!(destination_path.join("more_files").is_dir());
+
+    std::fs::remove_dir_all(destination_path).unwrap();
+}
+
+#[test]
+fn test_copy_literal_outside_source() {
+    for pattern in ["../my_files/file1.txt", "/etc/hosts"] {
+        let error = CopyPlanner::new(
+            Path::new("test/my_files"),
+            Path::new("target/dest_files_outside"),
+            pattern,
+        )
+        .plan()
+        .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput, "{}", pattern);
+    }
+    assert!(!Path::new("target/dest_files_outside").exists());
+}
+
+#[test]
+fn test_copy_filters() {
+    let root = Path::new("target/copy_filters");
+    let _ = std::fs::remove_dir_all(root);
+    std::fs::create_dir_all(root.join("src")).unwrap();
+    std::fs::write(root.join("src/small.txt"), "1").unwrap();
+    std::fs::write(root.join("src/large.txt"), "1234567890").unwrap();
+
+    let options = CopyOptions {
+        max_size: Some(5),
+        ..Default::default()
+    };
+    let report =
+        copy_dir_with_options(&root.join("src"), &root.join("dst"), "*.txt", &options).unwrap();
+    assert_eq!((report.files, report.skipped), (1, 1));
+    assert_eq!(report.skipped_by_reason[&SkipReason::Size], 1);
+    assert!(report
+        .to_string()
+        .contains(", skipped 1 (1 size out of range)"));
+    assert!(root.join("dst/small.txt").exists());
+    assert!(!root.join("dst/large.txt").exists());
+
+    let options = CopyOptions {
+        modified_since: Some(SystemTime::now() + Duration::from_secs(3600)),
+        ..Default::default()
+    };
+    let plan = CopyPlanner::new(&root.join("src"), &root.join("dst"), "*.txt")
+        .options(options)
+        .plan()
+        .unwrap();
+    assert!(plan.actions().iter().all(|action| matches!(
+        action,
+        CopyAction::Skip {
+            reason: SkipReason::Modified,
+            ..
+        }
+    )));
+
+    std::fs::remove_dir_all(root).unwrap();
+}
+
+#[test]
+fn test_copy_routes() {
+    let destination_path = Path::new("target/dest_files_routes");
+    let options = CopyOptions::default()
+        .route("*.md", "Docs")
+        .route("*.{txt,csv}", "Data");
+    copy_dir_with_options(
+        Path::new("test/my_files"),
+        destination_path,
+        "**/*",
+        &options,
+    )
+    .unwrap();
+
+    assert!(destination_path.join("Data/file1.txt").exists());
+    assert!(destination_path.join("Data/file2.csv").exists());
+    assert!(destination_path.join("Docs/more_files/file3.md").exists());
+    assert!(!destination_path.join("more_files").exists());
+
+    assert!(
+        CopyPlanner::new(Path::new("test/my_files"), destination_path, "**/*")
+            .options(CopyOptions::default().route("[", "x"))
+            .plan()
+            .is_err()
+    );
+
+    std::fs::remove_dir_all(destination_path).unwrap();
+}
+
+#[test]
+fn test_copy_into_source() {
+    let root = Path::new("target/copy_into_source");
+    let _ = std::fs::remove_dir_all(root);
+    std::fs::create_dir_all(root.join("sub")).unwrap();
+    std::fs::write(root.join("a.txt"), "a").unwrap();
+    std::fs::write(root.join("sub/b.txt"), "b").unwrap();
+
+    let error = CopyPlanner::new(root, &root.join("sub/.."), "**/*")
+        .plan()
+        .unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::InvalidInput);
+
+    // the destination inside the source isn't copied into itself, not even on the second run
+    let destination = root.join("out");
+    for _ in 0..2 {
+        copy_dir_with_options(root, &destination, "**/*", &CopyOptions::default()).unwrap();
+    }
+    assert!(destination.join("sub/b.txt").is_file());
+    assert!(!destination.join("out").exists());
+
+    // the source inside the destination: files would be copied onto themselves
+    let error = CopyPlanner::new(&root.join("sub"), root, "**/*")
+        .options(CopyOptions::default().route("*.txt", "sub"))
+        .plan()
+        .unwrap_err();
+    assert!(error.to_string().ends_with("would be copied onto itself"));
+
+    std::fs::remove_dir_all(root).unwrap();
+}
+
+#[test]
+fn test_copy_skip_unchanged() {
+    let root = Path::new("target/copy_skip_unchanged");
+    let _ = std::fs::remove_dir_all(root);
+    std::fs::create_dir_all(root.join("src")).unwrap();
+    std::fs::write(root.join("src/a.txt"), "a").unwrap();
+    std::fs::write(root.join("src/b.txt"), "b").unwrap();
+    let options = CopyOptions {
+        skip_unchanged: true,
+        ..Default::default()
+    };
+    let planner = CopyPlanner::new(&root.join("src"), &root.join("dst"), "*.txt").options(options);
+
+    let report = planner.plan().unwrap().execute().unwrap();
+    assert_eq!((report.files, report.skipped), (2, 0));
+
+    std::fs::write(root.join("src/b.txt"), "bb").unwrap();
+    let plan = planner.plan().unwrap();
+    assert!(plan.actions().contains(&CopyAction::Skip {
+        source: root.join("src/a.txt").canonicalize().unwrap(),
+        reason: SkipReason::UpToDate,
+    }));
+    let report = plan.execute().unwrap();
+    assert_eq!((report.files, report.skipped), (1, 1));
+    assert_eq!(
+        std::fs::read_to_string(root.join("dst/b.txt")).unwrap(),
+        "bb"
+    );
+
+    std::fs::remove_dir_all(root).unwrap();
+}
+
+#[test]
+fn test_copy_skip_unchanged_manifest() {
+    let root = Path::new("target/copy_skip_unchanged_manifest");
+    let _ = std::fs::remove_dir_all(root);
+    std::fs::create_dir_all(root.join("src/sub")).unwrap();
+    std::fs::write(root.join("src/a.txt"), "a").unwrap();
+    std::fs::write(root.join("src/sub/b.txt"), "b").unwrap();
+    let options = CopyOptions {
+        manifest: Some(crate::manifest::MANIFEST_FILE_NAME.into()),
+        skip_unchanged: true,
+        ..Default::default()
+    };
+    let copy = || copy_dir_with_options(&root.join("src"), &root.join("dst"), "**/*.txt", &options);
+    let manifest = || {
+        let manifest =
+            Manifest::read(&root.join("dst").join(crate::manifest::MANIFEST_FILE_NAME)).unwrap();
+        manifest
+            .entries
+            .into_iter()
+            .map(|entry| entry.path)
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(copy().unwrap().files, 2);
+    assert_eq!(manifest(), ["a.txt", "sub/b.txt"]);
+    std::fs::write(root.join("src/sub/b.txt"), "bb").unwrap();
+    let report = copy().unwrap();
+    assert_eq!((report.files, report.skipped), (1, 1));
+    assert_eq!(manifest(), ["a.txt", "sub/b.txt"]);
+    let manifest_path = root.join("dst").join(crate::manifest::MANIFEST_FILE_NAME);
+    assert!(crate::verify_manifest(&root.join("dst"), &manifest_path)
+        .unwrap()
+        .is_ok());
+
+    std::fs::remove_dir_all(root).unwrap();
+}
+
+#[test]
+fn test_copy_mirrors() {
+    let root = Path::new("target/copy_mirrors");
+    let _ = std::fs::remove_dir_all(root);
+    std::fs::create_dir_all(root.join("src/sub")).unwrap();
+    std::fs::write(root.join("src/a.txt"), "a").unwrap();
+    std::fs::write(root.join("src/sub/b.txt"), "bb").unwrap();
+    // a file where the second mirror's directory should be
+    std::fs::write(root.join("blocked"), "").unwrap();
+
+    let report = CopyPlanner::new(&root.join("src"), &root.join("dst"), "**/*")
+        .mirror(&root.join("mirror"))
+        .mirror(&root.join("blocked/mirror"))
+        .options(CopyOptions {
+            manifest: Some(crate::manifest::MANIFEST_FILE_NAME.into()),
+            ..Default::default()
+        })
+        .plan()
+        .unwrap()
+        .execute()
+        .unwrap();
+    assert_eq!((report.files, report.bytes), (2, 3));
+    assert_eq!(report.destinations.len(), 3);
+    assert_eq!(
+        (report.destinations[1].files, report.destinations[1].bytes),
+        (2, 3)
+    );
+    assert_eq!(
+        report.failed().map(|d| &d.destination).collect::<Vec<_>>(),
+        [&root.join("blocked/mirror")]
+    );
+    assert_eq!(
+        std::fs::read_to_string(root.join("mirror/sub/b.txt")).unwrap(),
+        "bb"
+    );
+    assert!(root
+        .join("mirror")
+        .join(crate::manifest::MANIFEST_FILE_NAME)
+        .is_file());
+
+    std::fs::remove_dir_all(root).unwrap();
+}
@@ -0,0 +1,165 @@
+//! Creating disk images for distributing apps with hdiutil.
+
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+
+use crate::tool::Tool;
+
+/// Compression of a disk image (`hdiutil create -format`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DmgFormat {
+    /// zlib compressed, readable on all macOS versions (`UDZO`)
+    Zlib,
+    /// bzip2 compressed (`UDBZ`)
+    Bzip2,
+    /// LZFSE compressed, macOS 10.11 and later (`ULFO`)
+    Lzfse,
+    /// LZMA compressed, macOS 10.15 and later (`ULMO`)
+    Lzma,
+    /// Uncompressed read-only (`UDRO`)
+    Uncompressed,
+}
+
+impl DmgFormat {
+    fn hdiutil_name(self) -> &'static str {
+        match self {
+            DmgFormat::Zlib => "UDZO",
+            DmgFormat::Bzip2 => "UDBZ",
+            DmgFormat::Lzfse => "ULFO",
+            DmgFormat::Lzma => "ULMO",
+            DmgFormat::Uncompressed => "UDRO",
+        }
+    }
+}
+
+/// Options for [`create_dmg`].
+#[derive(Clone, Debug)]
+pub struct DmgOptions {
+    /// Name of the mounted volume, defaults to the app's name
+    pub volume_name: Option<String>,
+    pub format: DmgFormat,
+    /// Add a symlink to `/Applications` so the app can be installed by dragging it there
+    pub applications_symlink: bool,
+}
+
+impl Default for DmgOptions {
+    fn default() -> Self {
+        DmgOptions {
+            volume_name: None,
+            format: DmgFormat::Zlib,
+            applications_symlink: true,
+        }
+    }
+}
+
+/// Create a disk image containing an app bundle using hdiutil from macOS.
+///
+/// The app is staged into a temporary folder next to the disk image, together with the
+/// `/Applications` symlink if requested. An existing disk image is replaced.
+///
+/// # Arguments
+///
+/// * `app_bundle` - the `.app` bundle to distribute, signed if it is going to be notarized
+/// * `dmg_path` - the disk image to write
+/// * `options` - volume name, compression and symlink
+///
+/// # Example
+///
+/// ```no_run
+/// use std::path::Path;
+/// use toolbelt::dmg::{create_dmg, DmgOptions};
+///
+/// create_dmg(
+///     Path::new("target/MyApp.app"),
+///     Path::new("target/MyApp-1.0.dmg"),
+///     &DmgOptions::default(),
+/// )
+/// .unwrap();
+/// ```
+pub fn create_dmg(app_bundle: &Path, dmg_path: &Path, options: &DmgOptions) -> Result<(), Error> {
+    let app_name = app_bundle.file_name().ok_or_else(|| {
+        Error::new(
+            ErrorKind::InvalidInput,
+            format!("{} is not an app bundle", app_bundle.display()),
+        )
+    })?;
+    if !app_bundle.is_dir() {
+        return Err(Error::new(
+            ErrorKind::NotFound,
+            format!("{} not found", app_bundle.display()),
+        ));
+    }
+    let volume_name = match &options.volume_name {
+        Some(name) => name.clone(),
+        None => Path::new(app_name)
+            .file_stem()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned(),
+    };
+
+    let mut staging = dmg_path.as_os_str().to_owned();
+    staging.push(".staging");
+    let staging = PathBuf::from(staging);
+    if staging.exists() {
+        std::fs::remove_dir_all(&staging)?;
+    }
+    std::fs::create_dir_all(&staging)?;
+    let result = stage_and_create(
+        app_bundle,
+        app_name.as_ref(),
+        &staging,
+        dmg_path,
+        &volume_name,
+        options,
+    );
+    std::fs::remove_dir_all(&staging)?;
+    result
+}
+
+fn stage_and_create(
+    app_bundle: &Path,
+    app_name: &Path,
+    staging: &Path,
+    dmg_path: &Path,
+    volume_name: &str,
+    options: &DmgOptions,
+) -> Result<(), Error> {
+    // ditto keeps symlinks, extended attributes and the code signature intact
+    Tool::new("ditto")
+        .arg(app_bundle)
+        .arg(staging.join(app_name))
+        .run()
+        .map_err(Error::other)?;
+    if options.applications_symlink {
+        #[cfg(unix)]
+        std::os::unix::fs::symlink("/Applications", staging.join("Applications"))?;
+    }
+
+    Tool::new("hdiutil")
+        .arg("create")
+        .arg("-volname")
+        .arg(volume_name)
+        .arg("-srcfolder")
+        .arg(staging)
+        .arg("-ov")
+        .arg("-format")
+        .arg(options.format.hdiutil_name())
+        .arg(dmg_path)
+        .run()
+        .map_err(Error::other)?;
+    info!("Created {}", dmg_path.display());
+    Ok(())
+}
+
+#[test]
+fn test_create_dmg_checks_app() {
+    let error = create_dmg(
+        Path::new("target/create_dmg/Missing.app"),
+        Path::new("target/create_dmg/Missing.dmg"),
+        &DmgOptions::default(),
+    )
+    .unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::NotFound);
+    assert_eq!(DmgFormat::Lzfse.hdiutil_name(), "ULFO");
+}
@@ -0,0 +1,195 @@
+//! Fetch files over HTTP(S) using `curl`.
+//!
+//! Only available with the `http` feature enabled.
+
+use std::ffi::OsString;
+use std::fmt;
+use std::io::Error;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::hash::Sha256;
+use crate::messages::{message, Message};
+
+/// Errors reported by [`download`].
+#[derive(Debug)]
+pub enum DownloadError {
+    /// Reading or writing the local file failed, or `curl` could not be started.
+    Io(Error),
+    /// `curl` ran but reported a failure.
+    Transfer { url: String, stderr: String },
+    /// The downloaded file doesn't match the expected checksum.
+    ChecksumMismatch {
+        path: PathBuf,
+        expected: Sha256,
+        actual: Sha256,
+    },
+}
+
+impl fmt::Display for DownloadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DownloadError::Io(e) => write!(f, "{}", e),
+            DownloadError::Transfer { url, stderr } => write!(
+                f,
+                "{}",
+                message(
+                    Message::DownloadFailed,
+                    &[("url", url), ("reason", &stderr.trim())]
+                )
+            ),
+            DownloadError::ChecksumMismatch {
+                path,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "{}",
+                message(
+                    Message::ChecksumMismatch,
+                    &[
+                        ("path", &path.display()),
+                        ("expected", expected),
+                        ("actual", actual)
+                    ]
+                )
+            ),
+        }
+    }
+}
+
+impl std::error::Error for DownloadError {}
+
+impl From<Error> for DownloadError {
+    fn from(e: Error) -> Self {
+        DownloadError::Io(e)
+    }
+}
+
+/// Download a file, optionally verifying its SHA-256 checksum.
+///
+/// The data is first written to `<destination>.part` and only moved to `destination` once the
+/// transfer is complete and the checksum matched. An existing `.part` file from an interrupted
+/// run is resumed instead of starting over. If `destination` already exists and matches the
+/// checksum, nothing is downloaded at all.
+///
+/// # Arguments
+///
+/// * `url` - the URL to fetch
+/// * `destination` - path of the file to create. Missing parent directories will be created.
+/// * `checksum` - the expected SHA-256 digest of the file, if known
+///
+/// # Example
+///
+/// ```no_run
+/// use std::path::Path;
+/// use toolbelt::{download, Sha256};
+///
+/// let checksum: Sha256 = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+///     .parse()
+///     .unwrap();
+/// download(
+///     "https://example.com/sdk.zip",
+///     Path::new("target/sdk.zip"),
+///     Some(checksum),
+/// )
+/// .unwrap();
+/// ```
+pub fn download(
+    url: &str,
+    destination: &Path,
+    checksum: Option<Sha256>,
+) -> Result<PathBuf, DownloadError> {
+    if destination.exists() {
+        match checksum {
+            Some(expected) if Sha256::of_file(destination)? == expected => {
+                debug!("{} is already up to date", destination.display());
+                return Ok(destination.to_path_buf());
+            }
+            None => {
+                debug!("{} already exists", destination.display());
+                return Ok(destination.to_path_buf());
+            }
+            _ => std::fs::remove_file(destination)?,
+        }
+    }
+
+    if let Some(parent) = destination.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    let mut partial = OsString::from(destination.as_os_str());
+    partial.push(".part");
+    let partial = PathBuf::from(partial);
+
+    if let Err(e) = fetch(url, &partial) {
+        if !partial.exists() {
+            return Err(e);
+        }
+        // the server may not support ranges or the partial file is corrupt, start over
+        warn!("Resuming {} failed, restarting the download", url);
+        std::fs::remove_file(&partial)?;
+        fetch(url, &partial)?;
+    }
+
+    if let Some(expected) = checksum {
+        let actual = Sha256::of_file(&partial)?;
+        if actual != expected {
+            std::fs::remove_file(&partial)?;
+            return Err(DownloadError::ChecksumMismatch {
+                path: destination.to_path_buf(),
+                expected,
+                actual,
+            });
+        }
+    }
+
+    std::fs::rename(&partial, destination)?;
+    Ok(destination.to_path_buf())
+}
+
+fn fetch(url: &str, partial: &Path) -> Result<(), DownloadError> {
+    debug!("Download {} to {}", url, partial.display());
+    let output = Command::new("curl")
+        .arg("--fail")
+        .arg("--location")
+        .arg("--silent")
+        .arg("--show-error")
+        .arg("--continue-at")
+        .arg("-")
+        .arg("--output")
+        .arg(partial)
+        .arg(url)
+        .output()?;
+
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(DownloadError::Transfer {
+            url: url.to_string(),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        })
+    }
+}
+
+#[test]
+fn test_download_file_url() {
+    let source = std::env::current_dir()
+        .unwrap()
+        .join("test/my_files/file1.txt");
+    let url = format!("file://{}", source.display());
+    let destination = Path::new("target/download/file1.txt");
+    let _ = std::fs::remove_file(destination);
+
+    let checksum = Sha256::of_file(&source).unwrap();
+    download(&url, destination, Some(checksum)).unwrap();
+    assert!(destination.exists());
+
+    let wrong = Sha256::digest(b"something else");
+    std::fs::remove_file(destination).unwrap();
+    match download(&url, destination, Some(wrong)) {
+        Err(DownloadError::ChecksumMismatch { .. }) => {}
+        other => panic!("unexpected result {:?}", other),
+    }
+    assert!(!destination.exists());
+}
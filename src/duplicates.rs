@@ -0,0 +1,215 @@
+//! Find files with identical content.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Error;
+use std::path::{Path, PathBuf};
+
+use crate::concurrency::parallel_map;
+use crate::fileset::{FileSet, PatternOptions};
+use crate::hash::Sha256;
+
+/// Files sharing the same content.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DuplicateGroup {
+    pub sha256: Sha256,
+    /// Size of each file in bytes
+    pub size: u64,
+    /// The identical files, sorted
+    pub paths: Vec<PathBuf>,
+}
+
+impl DuplicateGroup {
+    /// Bytes that could be saved by keeping only one of the files.
+    pub fn redundant_bytes(&self) -> u64 {
+        self.size * (self.paths.len() as u64 - 1)
+    }
+}
+
+/// Report groups of files with identical content below one or more directories.
+///
+/// Only files of equal size are hashed, so unique files are cheap to rule out. Groups are sorted
+/// by the space they waste, largest first.
+///
+/// # Arguments
+///
+/// * `roots` - directories to search
+/// * `pattern` - a standard glob pattern selecting the files to compare (e.g. `**/*.png`)
+///
+/// # Example
+///
+/// ```no_run
+/// use std::path::Path;
+/// use toolbelt::find_duplicates;
+///
+/// for group in find_duplicates(&[Path::new("resources")], "**/*").unwrap() {
+///     println!("{} bytes wasted by {:?}", group.redundant_bytes(), group.paths);
+/// }
+/// ```
+pub fn find_duplicates<P: AsRef<Path>>(
+    roots: &[P],
+    pattern: &str,
+) -> Result<Vec<DuplicateGroup>, Error> {
+    find_duplicates_with_options(roots, pattern, &PatternOptions::default())
+}
+
+/// Like [`find_duplicates`], with the matching and the walk configured, e.g. to leave out hidden
+/// files.
+pub fn find_duplicates_with_options<P: AsRef<Path>>(
+    roots: &[P],
+    pattern: &str,
+    options: &PatternOptions,
+) -> Result<Vec<DuplicateGroup>, Error> {
+    let mut by_size: BTreeMap<u64, Vec<PathBuf>> = BTreeMap::new();
+
+    for root in roots {
+        let files = FileSet::new(root.as_ref())
+            .pattern(pattern)
+            .options(options.clone());
+        for entry in files {
+            let entry = entry?;
+            if !entry.file_type.is_file() {
+                continue;
+            }
+            let paths = by_size.entry(entry.metadata.len()).or_default();
+            if !paths.contains(&entry.path) {
+                paths.push(entry.path);
+            }
+        }
+    }
+
+    let candidates: Vec<(u64, PathBuf)> = by_size
+        .into_iter()
+        .filter(|(_, paths)| paths.len() > 1)
+        .flat_map(|(size, paths)| paths.into_iter().map(move |path| (size, path)))
+        .collect();
+    let hashes = parallel_map(&candidates, |(_, path)| Sha256::of_file(path));
+
+    let mut by_content: BTreeMap<(u64, Sha256), Vec<PathBuf>> = BTreeMap::new();
+    for ((size, path), hash) in candidates.into_iter().zip(hashes) {
+        by_content.entry((size, hash?)).or_default().push(path);
+    }
+
+    let mut groups: Vec<DuplicateGroup> = by_content
+        .into_iter()
+        .filter(|(_, paths)| paths.len() > 1)
+        .map(|((size, sha256), mut paths)| {
+            paths.sort();
+            DuplicateGroup {
+                sha256,
+                size,
+                paths,
+            }
+        })
+        .collect();
+    groups.sort_by_key(|group| std::cmp::Reverse(group.redundant_bytes()));
+    Ok(groups)
+}
+
+/// `true` if both paths are hard links to the same file.
+#[cfg(unix)]
+fn is_same_file(a: &Path, b: &Path) -> Result<bool, Error> {
+    use std::os::unix::fs::MetadataExt;
+    let (a, b) = (fs::metadata(a)?, fs::metadata(b)?);
+    Ok(a.dev() == b.dev() && a.ino() == b.ino())
+}
+
+#[cfg(not(unix))]
+fn is_same_file(_a: &Path, _b: &Path) -> Result<bool, Error> {
+    Ok(false)
+}
+
+/// Replace byte-identical files below a directory with hard links to one copy. Returns the
+/// number of bytes saved.
+///
+/// Meant for staged bundle resources, where localizations (`en.lproj`, `de.lproj`, ...) and
+/// architecture variants often carry the same images or nibs. Files that already are hard links
+/// to each other don't count as saved.
+///
+/// # Arguments
+///
+/// * `dir` - the directory to deduplicate, e.g. a bundle's resources
+/// * `pattern` - a standard glob pattern selecting the files to consider
+///
+/// # Example
+///
+/// ```no_run
+/// use std::path::Path;
+/// use toolbelt::duplicates::link_duplicates;
+///
+/// let saved = link_duplicates(Path::new("target/MyApp.app/Contents/Resources"), "**/*").unwrap();
+/// println!("Saved {} bytes", saved);
+/// ```
+pub fn link_duplicates(dir: &Path, pattern: &str) -> Result<u64, Error> {
+    let mut saved = 0;
+    for group in find_duplicates(&[dir], pattern)? {
+        let (original, duplicates) = group.paths.split_first().unwrap();
+        for duplicate in duplicates {
+            if is_same_file(original, duplicate)? {
+                continue;
+            }
+            // link next to the duplicate first so it is replaced atomically
+            let mut link = duplicate.clone().into_os_string();
+            link.push(".link");
+            let link = PathBuf::from(link);
+            let _ = fs::remove_file(&link);
+            fs::hard_link(original, &link)?;
+            fs::rename(&link, duplicate)?;
+            debug!("Linked {} to {}", duplicate.display(), original.display());
+            saved += group.size;
+        }
+    }
+    if saved > 0 {
+        info!("Deduplicating {} saved {} bytes", dir.display(), saved);
+    }
+    Ok(saved)
+}
+
+#[test]
+fn test_find_duplicates() {
+    let root = Path::new("target/find_duplicates");
+    let _ = std::fs::remove_dir_all(root);
+    std::fs::create_dir_all(root.join("a")).unwrap();
+    std::fs::create_dir_all(root.join("b")).unwrap();
+    std::fs::write(root.join("a/one.txt"), "same").unwrap();
+    std::fs::write(root.join("b/two.txt"), "same").unwrap();
+    std::fs::write(root.join("b/three.txt"), "diff").unwrap();
+
+    let groups = find_duplicates(&[root.join("a"), root.join("b")], "*.txt").unwrap();
+    assert_eq!(groups.len(), 1);
+    assert_eq!(
+        groups[0].paths,
+        [root.join("a/one.txt"), root.join("b/two.txt")]
+    );
+    assert_eq!(groups[0].redundant_bytes(), 4);
+
+    std::fs::remove_dir_all(root).unwrap();
+}
+
+#[test]
+fn test_link_duplicates() {
+    let root = Path::new("target/link_duplicates");
+    let _ = fs::remove_dir_all(root);
+    fs::create_dir_all(root.join("en.lproj")).unwrap();
+    fs::create_dir_all(root.join("de.lproj")).unwrap();
+    fs::write(root.join("en.lproj/Main.nib"), "nib data").unwrap();
+    fs::write(root.join("de.lproj/Main.nib"), "nib data").unwrap();
+    fs::write(root.join("de.lproj/Other.nib"), "other").unwrap();
+
+    assert_eq!(link_duplicates(root, "**/*").unwrap(), 8);
+    assert_eq!(
+        fs::read_to_string(root.join("en.lproj/Main.nib")).unwrap(),
+        "nib data"
+    );
+    if cfg!(unix) {
+        assert!(is_same_file(
+            &root.join("de.lproj/Main.nib"),
+            &root.join("en.lproj/Main.nib")
+        )
+        .unwrap());
+        // already linked
+        assert_eq!(link_duplicates(root, "**/*").unwrap(), 0);
+    }
+
+    fs::remove_dir_all(root).unwrap();
+}
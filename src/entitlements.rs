@@ -0,0 +1,214 @@
+//! Creating `.entitlements` property lists for [`crate::CodesignOptions::entitlements`].
+//!
+//! [`Entitlements`] sets the common entitlements by name (App Sandbox, hardened runtime
+//! exceptions, keychain access and app groups); anything else can be set by key.
+
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+
+use crate::plist::{Dictionary, Value};
+
+/// Exceptions from the hardened runtime (`codesign --options runtime`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RuntimeException {
+    /// Create writable and executable memory with `MAP_JIT`
+    AllowJit,
+    /// Create writable and executable memory without `MAP_JIT`
+    AllowUnsignedExecutableMemory,
+    /// Honor `DYLD_*` environment variables
+    AllowDyldEnvironmentVariables,
+    /// Load plug-ins and frameworks signed by other teams
+    DisableLibraryValidation,
+    /// Modify executable memory pages
+    DisableExecutablePageProtection,
+    /// Attach to other processes as a debugger
+    Debugger,
+}
+
+impl RuntimeException {
+    /// The entitlement key, e.g. `com.apple.security.cs.allow-jit`.
+    pub fn key(self) -> &'static str {
+        match self {
+            RuntimeException::AllowJit => "com.apple.security.cs.allow-jit",
+            RuntimeException::AllowUnsignedExecutableMemory => {
+                "com.apple.security.cs.allow-unsigned-executable-memory"
+            }
+            RuntimeException::AllowDyldEnvironmentVariables => {
+                "com.apple.security.cs.allow-dyld-environment-variables"
+            }
+            RuntimeException::DisableLibraryValidation => {
+                "com.apple.security.cs.disable-library-validation"
+            }
+            RuntimeException::DisableExecutablePageProtection => {
+                "com.apple.security.cs.disable-executable-page-protection"
+            }
+            RuntimeException::Debugger => "com.apple.security.cs.debugger",
+        }
+    }
+}
+
+/// An entitlements file being created.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::path::Path;
+/// use toolbelt::entitlements::{Entitlements, RuntimeException};
+/// use toolbelt::{codesign_with_options, CodesignOptions};
+///
+/// let path = Path::new("target/MyApp.entitlements");
+/// Entitlements::new()
+///     .app_sandbox(true)
+///     .network_client(true)
+///     .app_group("ABCDE12345.com.example.shared")
+///     .keychain_access_group("ABCDE12345.com.example.myapp")
+///     .runtime_exception(RuntimeException::AllowJit)
+///     .write(path)
+///     .unwrap();
+/// let options = CodesignOptions {
+///     entitlements: Some(path.to_path_buf()),
+///     hardened_runtime: true,
+///     ..Default::default()
+/// };
+/// codesign_with_options(Path::new("target/MyApp.app"), &options).unwrap();
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Entitlements {
+    values: Dictionary,
+}
+
+impl Entitlements {
+    /// No entitlements.
+    pub fn new() -> Entitlements {
+        Entitlements::default()
+    }
+
+    /// Read an existing entitlements file to extend it.
+    pub fn read(path: &Path) -> Result<Entitlements, Error> {
+        match Value::read(path)? {
+            Value::Dictionary(values) => Ok(Entitlements { values }),
+            _ => Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("{} is not a dictionary", path.display()),
+            )),
+        }
+    }
+
+    /// Set an entitlement, replacing an existing one.
+    pub fn set<V: Into<Value>>(mut self, key: &str, value: V) -> Entitlements {
+        self.values.insert(key.to_string(), value.into());
+        self
+    }
+
+    /// Append a value to an array entitlement unless it is already listed.
+    fn append(mut self, key: &str, value: &str) -> Entitlements {
+        let entry = self
+            .values
+            .entry(key.to_string())
+            .or_insert_with(|| Value::Array(Vec::new()));
+        if !matches!(entry, Value::Array(_)) {
+            *entry = Value::Array(Vec::new());
+        }
+        if let Value::Array(values) = entry {
+            if !values.iter().any(|v| v.as_str() == Some(value)) {
+                values.push(value.into());
+            }
+        }
+        self
+    }
+
+    /// Run in the App Sandbox (`com.apple.security.app-sandbox`), required on the Mac App Store.
+    pub fn app_sandbox(self, enabled: bool) -> Entitlements {
+        self.set("com.apple.security.app-sandbox", enabled)
+    }
+
+    /// Allow outgoing network connections from the sandbox.
+    pub fn network_client(self, enabled: bool) -> Entitlements {
+        self.set("com.apple.security.network.client", enabled)
+    }
+
+    /// Allow incoming network connections to the sandbox.
+    pub fn network_server(self, enabled: bool) -> Entitlements {
+        self.set("com.apple.security.network.server", enabled)
+    }
+
+    /// Allow debuggers to attach (`com.apple.security.get-task-allow`), for development builds.
+    pub fn get_task_allow(self, enabled: bool) -> Entitlements {
+        self.set("com.apple.security.get-task-allow", enabled)
+    }
+
+    /// Add an exception from the hardened runtime.
+    pub fn runtime_exception(self, exception: RuntimeException) -> Entitlements {
+        self.set(exception.key(), true)
+    }
+
+    /// Add a keychain access group (`keychain-access-groups`), prefixed with the team id, e.g.
+    /// `ABCDE12345.com.example.shared`.
+    pub fn keychain_access_group(self, group: &str) -> Entitlements {
+        self.append("keychain-access-groups", group)
+    }
+
+    /// Add an app group (`com.apple.security.application-groups`) for sharing data between apps
+    /// and extensions, e.g. `ABCDE12345.com.example.shared` on macOS or `group.com.example` on
+    /// iOS.
+    pub fn app_group(self, group: &str) -> Entitlements {
+        self.append("com.apple.security.application-groups", group)
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.values.get(key)
+    }
+
+    pub fn as_dictionary(&self) -> &Dictionary {
+        &self.values
+    }
+
+    pub fn into_dictionary(self) -> Dictionary {
+        self.values
+    }
+
+    /// Write as XML property list file.
+    pub fn write(&self, path: &Path) -> Result<(), Error> {
+        Value::Dictionary(self.values.clone()).write(path)
+    }
+}
+
+impl From<Dictionary> for Entitlements {
+    fn from(values: Dictionary) -> Self {
+        Entitlements { values }
+    }
+}
+
+#[test]
+fn test_entitlements() {
+    let root = Path::new("target/entitlements");
+    let _ = std::fs::remove_dir_all(root);
+    std::fs::create_dir_all(root).unwrap();
+    let path = root.join("app.entitlements");
+
+    Entitlements::from(crate::codesign::development_entitlements())
+        .app_sandbox(true)
+        .app_group("ABCDE12345.com.example.shared")
+        .app_group("ABCDE12345.com.example.shared")
+        .keychain_access_group("ABCDE12345.com.example.myapp")
+        .runtime_exception(RuntimeException::DisableLibraryValidation)
+        .write(&path)
+        .unwrap();
+
+    let entitlements = Entitlements::read(&path).unwrap();
+    assert_eq!(
+        entitlements.get("com.apple.security.get-task-allow"),
+        Some(&Value::Boolean(true))
+    );
+    assert_eq!(
+        entitlements.get("com.apple.security.application-groups"),
+        Some(&vec!["ABCDE12345.com.example.shared"].into())
+    );
+    assert_eq!(
+        entitlements.get("com.apple.security.cs.disable-library-validation"),
+        Some(&Value::Boolean(true))
+    );
+    assert_eq!(entitlements.as_dictionary().len(), 5);
+
+    std::fs::remove_dir_all(root).unwrap();
+}
@@ -0,0 +1,254 @@
+//! Loading environment variables from `.env` files, so settings like the SDK paths read by
+//! [`crate::get_sdk_path`] can be configured per checkout instead of per shell.
+//!
+//! The files contain one `NAME=value` assignment per line, optionally preceded by `export`.
+//! Lines starting with `#` are comments. Values may be quoted: single quoted values are taken
+//! literally, double quoted values support `\n`, `\t`, `\"` and `\\` escapes. `${NAME}` in
+//! unquoted and double quoted values is replaced by the value of a variable defined before or
+//! set in the environment.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+
+/// Variables loaded by [`load_env`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct EnvFile {
+    /// Value and defining file of every variable
+    values: BTreeMap<String, (String, PathBuf)>,
+}
+
+/// Parse a double quoted value after the opening quote, returning the value and the rest of the
+/// line.
+fn parse_double_quoted(text: &str) -> Option<(String, &str)> {
+    let mut value = String::new();
+    let mut chars = text.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Some((value, &text[i + 1..])),
+            '\\' => match chars.next()?.1 {
+                'n' => value.push('\n'),
+                't' => value.push('\t'),
+                'r' => value.push('\r'),
+                c => value.push(c),
+            },
+            c => value.push(c),
+        }
+    }
+    None
+}
+
+impl EnvFile {
+    /// Replace `${NAME}` by the value of a variable loaded before or set in the environment.
+    fn expand(&self, value: &str) -> String {
+        let mut expanded = String::new();
+        let mut rest = value;
+        while let Some(start) = rest.find("${") {
+            let Some(end) = rest[start..].find('}') else {
+                break;
+            };
+            expanded.push_str(&rest[..start]);
+            let name = &rest[start + 2..start + end];
+            if let Some(value) = self.get_str(name) {
+                expanded.push_str(&value);
+            }
+            rest = &rest[start + end + 1..];
+        }
+        expanded.push_str(rest);
+        expanded
+    }
+
+    /// Parse the assignments of a file, overriding variables of files parsed before.
+    fn parse(&mut self, text: &str, path: &Path) -> Result<(), Error> {
+        for (number, line) in text.lines().enumerate() {
+            let invalid = |reason: &str| {
+                Error::new(
+                    ErrorKind::InvalidData,
+                    format!("{}:{}: {}", path.display(), number + 1, reason),
+                )
+            };
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let line = line.strip_prefix("export ").unwrap_or(line);
+            let (name, value) = line
+                .split_once('=')
+                .ok_or_else(|| invalid("expected NAME=value"))?;
+            let name = name.trim();
+            if name.is_empty()
+                || !name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+            {
+                return Err(invalid(&format!("invalid variable name '{}'", name)));
+            }
+            let value = value.trim();
+            let (value, rest) = if let Some(quoted) = value.strip_prefix('\'') {
+                let end = quoted
+                    .find('\'')
+                    .ok_or_else(|| invalid("unterminated quote"))?;
+                (quoted[..end].to_string(), &quoted[end + 1..])
+            } else if let Some(quoted) = value.strip_prefix('"') {
+                let (value, rest) =
+                    parse_double_quoted(quoted).ok_or_else(|| invalid("unterminated quote"))?;
+                (self.expand(&value), rest)
+            } else {
+                let value = match value.find(" #") {
+                    Some(comment) => value[..comment].trim_end(),
+                    None => value,
+                };
+                (self.expand(value), "")
+            };
+            let rest = rest.trim();
+            if !rest.is_empty() && !rest.starts_with('#') {
+                return Err(invalid("unexpected text after the closing quote"));
+            }
+            self.values
+                .insert(name.to_string(), (value, path.to_path_buf()));
+        }
+        Ok(())
+    }
+
+    /// The effective value of a variable: the environment's if set, otherwise the files'.
+    pub fn get_str(&self, name: &str) -> Option<String> {
+        env::var(name)
+            .ok()
+            .or_else(|| self.values.get(name).map(|(value, _)| value.clone()))
+    }
+
+    /// A path variable.
+    pub fn get_path(&self, name: &str) -> Option<PathBuf> {
+        self.get_str(name).map(PathBuf::from)
+    }
+
+    /// An integer variable, `None` if it isn't set or not a number.
+    pub fn get_i64(&self, name: &str) -> Option<i64> {
+        self.get_str(name)?.trim().parse().ok()
+    }
+
+    /// A boolean variable: `1`, `true`, `yes` and `on` are true, `0`, `false`, `no`, `off` and the
+    /// empty string false, regardless of case.
+    pub fn get_bool(&self, name: &str) -> Option<bool> {
+        match self.get_str(name)?.trim().to_lowercase().as_str() {
+            "1" | "true" | "yes" | "on" => Some(true),
+            "0" | "false" | "no" | "off" | "" => Some(false),
+            _ => None,
+        }
+    }
+
+    /// The file that defined a variable last, `None` if no file defines it.
+    pub fn source(&self, name: &str) -> Option<&Path> {
+        self.values.get(name).map(|(_, path)| path.as_path())
+    }
+
+    /// The names of the variables defined by the files.
+    pub fn names(&self) -> Vec<&str> {
+        self.values.keys().map(String::as_str).collect()
+    }
+}
+
+/// Load environment files and set their variables in the process environment.
+///
+/// Later files override earlier ones, e.g. a `.env.local` that isn't checked in overrides the
+/// shared `.env`. Variables already set in the environment override all files, so a variable
+/// set in the shell or by CI always wins. Missing files are skipped.
+///
+/// # Example
+///
+/// ```no_run
+/// use toolbelt::{get_sdk_path, load_env};
+///
+/// // .env: FOO_SDK=${HOME}/SDKs/foo
+/// let env = load_env(&[".env", ".env.local"]).unwrap();
+/// let sdk_path = get_sdk_path("FOO_SDK").unwrap();
+/// let verbose = env.get_bool("FOO_VERBOSE").unwrap_or(false);
+/// ```
+pub fn load_env<P: AsRef<Path>>(files: &[P]) -> Result<EnvFile, Error> {
+    let mut env_file = EnvFile::default();
+    for path in files {
+        let path = path.as_ref();
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                debug!("Skipping {}, it doesn't exist", path.display());
+                continue;
+            }
+            Err(e) => return Err(Error::new(e.kind(), format!("{}: {}", path.display(), e))),
+        };
+        env_file.parse(&text, path)?;
+    }
+    for (name, (value, path)) in &env_file.values {
+        if env::var_os(name).is_none() {
+            debug!("Setting {} from {}", name, path.display());
+            env::set_var(name, value);
+        }
+    }
+    Ok(env_file)
+}
+
+#[test]
+fn test_load_env() {
+    let root = Path::new("target/load_env");
+    let _ = fs::remove_dir_all(root);
+    fs::create_dir_all(root).unwrap();
+    fs::write(
+        root.join(".env"),
+        "# shared settings\n\
+         TOOLBELT_ENV_SDK=${TOOLBELT_ENV_HOME}/SDKs/foo\n\
+         export TOOLBELT_ENV_JOBS = 4 # per machine\n\
+         TOOLBELT_ENV_VERBOSE=yes\n\
+         TOOLBELT_ENV_SHELL=from file\n",
+    )
+    .unwrap();
+    fs::write(
+        root.join(".env.local"),
+        "TOOLBELT_ENV_JOBS=8\n\
+         TOOLBELT_ENV_LITERAL='${not} expanded'\n\
+         TOOLBELT_ENV_QUOTED=\"a \\\"b\\\"\\n\" # comment\n",
+    )
+    .unwrap();
+    env::set_var("TOOLBELT_ENV_HOME", "/home/me");
+    env::set_var("TOOLBELT_ENV_SHELL", "from shell");
+
+    let env_file = load_env(&[
+        root.join(".env"),
+        root.join(".env.local"),
+        root.join(".env.missing"),
+    ])
+    .unwrap();
+    assert_eq!(env::var("TOOLBELT_ENV_SDK").unwrap(), "/home/me/SDKs/foo");
+    assert_eq!(env_file.get_i64("TOOLBELT_ENV_JOBS"), Some(8));
+    assert_eq!(
+        env_file.source("TOOLBELT_ENV_JOBS"),
+        Some(root.join(".env.local").as_path())
+    );
+    assert_eq!(env_file.get_bool("TOOLBELT_ENV_VERBOSE"), Some(true));
+    assert_eq!(
+        env_file.get_str("TOOLBELT_ENV_LITERAL").unwrap(),
+        "${not} expanded"
+    );
+    assert_eq!(
+        env_file.get_str("TOOLBELT_ENV_QUOTED").unwrap(),
+        "a \"b\"\n"
+    );
+    assert_eq!(env::var("TOOLBELT_ENV_SHELL").unwrap(), "from shell");
+    assert_eq!(
+        env_file.get_str("TOOLBELT_ENV_SHELL").unwrap(),
+        "from shell"
+    );
+    assert_eq!(env_file.names().len(), 6);
+
+    fs::write(root.join(".env.bad"), "OK=1\nno assignment\n").unwrap();
+    let e = load_env(&[root.join(".env.bad")]).unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::InvalidData);
+    assert!(e.to_string().ends_with(".env.bad:2: expected NAME=value"));
+
+    for name in env_file.names() {
+        env::remove_var(name);
+    }
+    env::remove_var("TOOLBELT_ENV_HOME");
+    fs::remove_dir_all(root).unwrap();
+}
@@ -0,0 +1,529 @@
+//! Selecting files below a directory by glob patterns.
+//!
+//! A [`FileSet`] only does the matching: it yields a [`FileEntry`] for every match, leaving the
+//! processing to the caller.
+//!
+//! Walks stop at an optional maximum depth. When symbolic links are followed, a link pointing to
+//! one of its own ancestor directories (same device and inode) ends the walk with an error naming
+//! the cycle instead of descending into it forever. Entries the walk isn't allowed to read are
+//! handled according to a [`DeniedEntryPolicy`].
+//!
+//! [`PatternOptions`] configures both the matching and the walk. The same options are accepted
+//! by the other pattern-taking APIs of the crate, and convert to the options of the underlying
+//! `glob`, `globset` and `globwalk` engines.
+
+use std::collections::HashSet;
+use std::fs::{FileType, Metadata};
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+
+use glob::MatchOptions;
+use globset::{Glob, GlobBuilder};
+use globwalk::{GlobWalker, GlobWalkerBuilder};
+
+/// A file or directory matched by a [`FileSet`].
+#[derive(Clone, Debug)]
+pub struct FileEntry {
+    /// Full path of the entry
+    pub path: PathBuf,
+    /// Path relative to the root of the file set
+    pub relative_path: PathBuf,
+    pub metadata: Metadata,
+    /// Type of the entry itself, not of the file a symbolic link points to
+    pub file_type: FileType,
+    /// The pattern that matched the entry
+    pub pattern: String,
+}
+
+/// What a directory walk does with entries it isn't permitted to read.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DeniedEntryPolicy {
+    /// Leave them out and log a warning
+    #[default]
+    SkipAndWarn,
+    /// Leave them out and record them, e.g. see [`FileSetIter::denied`]
+    Collect,
+    /// Stop the walk with the error
+    FailFast,
+}
+
+/// How glob patterns match and how far the directory walk matching them descends.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::path::Path;
+/// use toolbelt::fileset::{FileSet, PatternOptions};
+///
+/// let options = PatternOptions {
+///     case_sensitive: false,
+///     hidden_files: false,
+///     max_depth: Some(2),
+///     ..Default::default()
+/// };
+/// for entry in FileSet::new(Path::new("resources")).pattern("*.PNG").options(options) {
+///     println!("{}", entry.unwrap().relative_path.display());
+/// }
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PatternOptions {
+    /// Match letters only in the same case. Defaults to `true`.
+    pub case_sensitive: bool,
+    /// Wildcards other than `**` don't match `/`. Directory walks of a [`FileSet`] always treat
+    /// `/` literally, this applies to patterns matched against whole paths such as
+    /// [`crate::CopyOptions::route`].
+    pub literal_separator: bool,
+    /// Match files and directories whose name starts with a `.`. Defaults to `true`.
+    pub hidden_files: bool,
+    /// Maximum depth below the root, `1` only visits its direct children. `None` for no limit.
+    pub max_depth: Option<usize>,
+    /// Descend into directories symbolic links point to. Symlink cycles are detected.
+    pub follow_links: bool,
+    /// Handling of directories and files that can't be read, see [`DeniedEntryPolicy`]. Not to
+    /// be confused with `permissions::PermissionPolicy`, which fixes file modes.
+    pub permission_denied: DeniedEntryPolicy,
+}
+
+impl Default for PatternOptions {
+    fn default() -> Self {
+        PatternOptions {
+            case_sensitive: true,
+            literal_separator: false,
+            hidden_files: true,
+            max_depth: None,
+            follow_links: false,
+            permission_denied: DeniedEntryPolicy::default(),
+        }
+    }
+}
+
+impl PatternOptions {
+    /// The options for matching with the `glob` crate.
+    pub fn match_options(&self) -> MatchOptions {
+        MatchOptions {
+            case_sensitive: self.case_sensitive,
+            require_literal_separator: self.literal_separator,
+            require_literal_leading_dot: !self.hidden_files,
+        }
+    }
+
+    /// Compile a pattern for matching with the `globset` crate.
+    pub fn glob(&self, pattern: &str) -> Result<Glob, globset::Error> {
+        GlobBuilder::new(pattern)
+            .case_insensitive(!self.case_sensitive)
+            .literal_separator(self.literal_separator)
+            .build()
+    }
+
+    /// A `globwalk` walker for the pattern below `root`, with case sensitivity, depth and
+    /// symbolic links configured. Hidden files have to be filtered by the caller, see
+    /// [`PatternOptions::is_hidden`].
+    pub fn walker(&self, root: &Path, pattern: &str) -> GlobWalkerBuilder {
+        let builder = GlobWalkerBuilder::new(root, pattern)
+            .case_insensitive(!self.case_sensitive)
+            .follow_links(self.follow_links);
+        match self.max_depth {
+            Some(max_depth) => builder.max_depth(max_depth),
+            None => builder,
+        }
+    }
+
+    /// `true` if the path relative to the walk's root is left out because it is hidden.
+    pub fn is_hidden(&self, relative_path: &Path) -> bool {
+        !self.hidden_files
+            && relative_path
+                .components()
+                .any(|c| c.as_os_str().to_string_lossy().starts_with('.'))
+    }
+}
+
+/// Paths matching a pattern of the `glob` crate, e.g. `res/*.rc` or an absolute path. Unlike the
+/// patterns of a [`FileSet`], a `*` doesn't match in subdirectories. The maximum depth counts
+/// from the last directory of the pattern without wildcards.
+pub(crate) fn glob_paths(pattern: &str, options: &PatternOptions) -> Result<Vec<PathBuf>, Error> {
+    let paths = glob::glob_with(pattern, options.match_options())
+        .map_err(|e| Error::new(ErrorKind::InvalidInput, e))?;
+    let base_depth = Path::new(pattern)
+        .components()
+        .take_while(|c| !c.as_os_str().to_string_lossy().contains(['*', '?', '[']))
+        .count();
+    let mut matched = Vec::new();
+    for path in paths {
+        let path = match path {
+            Ok(path) => path,
+            Err(e)
+                if e.error().kind() == ErrorKind::PermissionDenied
+                    && options.permission_denied != DeniedEntryPolicy::FailFast =>
+            {
+                warn!("Skipping {}: {}", e.path().display(), e.error());
+                continue;
+            }
+            Err(e) => return Err(e.into_error()),
+        };
+        if options
+            .max_depth
+            .is_some_and(|max_depth| path.components().count() > base_depth + max_depth)
+        {
+            continue;
+        }
+        matched.push(path);
+    }
+    Ok(matched)
+}
+
+/// Convert an error from walking a directory, describing symlink cycles.
+pub(crate) fn walk_error(error: walkdir::Error) -> Error {
+    if let (Some(path), Some(ancestor)) = (error.path(), error.loop_ancestor()) {
+        return Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "Symlink cycle: {} points back to its ancestor {}",
+                path.display(),
+                ancestor.display()
+            ),
+        );
+    }
+    Error::from(error)
+}
+
+/// Files below a root directory matching one or more glob patterns.
+///
+/// Iterating a file set walks the root once per pattern. Entries matched by several patterns are
+/// reported only for the first of them.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::path::Path;
+/// use toolbelt::fileset::FileSet;
+///
+/// for entry in FileSet::new(Path::new("resources")).pattern("**/*.lproj/*.strings") {
+///     let entry = entry.unwrap();
+///     println!("{} ({} bytes)", entry.relative_path.display(), entry.metadata.len());
+/// }
+/// ```
+#[derive(Clone, Debug)]
+pub struct FileSet {
+    root: PathBuf,
+    patterns: Vec<String>,
+    options: PatternOptions,
+}
+
+impl FileSet {
+    /// # Arguments
+    ///
+    /// * `root` - the directory to search
+    pub fn new(root: &Path) -> FileSet {
+        FileSet {
+            root: root.to_path_buf(),
+            patterns: Vec::new(),
+            options: PatternOptions::default(),
+        }
+    }
+
+    /// Add a standard glob pattern (e.g. `*.{txt,csv}` or `**/*`), relative to the root.
+    pub fn pattern(mut self, pattern: &str) -> FileSet {
+        self.patterns.push(pattern.to_string());
+        self
+    }
+
+    /// Configure matching and the walk, e.g. case-insensitive matching or a maximum depth.
+    pub fn options(mut self, options: PatternOptions) -> FileSet {
+        self.options = options;
+        self
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+}
+
+impl IntoIterator for FileSet {
+    type Item = Result<FileEntry, Error>;
+    type IntoIter = FileSetIter;
+
+    fn into_iter(self) -> FileSetIter {
+        FileSetIter {
+            root: self.root,
+            patterns: self.patterns.into_iter().rev().collect(),
+            options: self.options,
+            current: None,
+            seen: HashSet::new(),
+            denied: Vec::new(),
+        }
+    }
+}
+
+/// Iterator over the entries of a [`FileSet`].
+pub struct FileSetIter {
+    root: PathBuf,
+    /// Patterns still to walk, in reverse order
+    patterns: Vec<String>,
+    options: PatternOptions,
+    current: Option<(String, GlobWalker)>,
+    seen: HashSet<PathBuf>,
+    denied: Vec<PathBuf>,
+}
+
+impl FileSetIter {
+    /// Entries left out because reading them wasn't permitted, with
+    /// [`DeniedEntryPolicy::Collect`].
+    pub fn denied(&self) -> &[PathBuf] {
+        &self.denied
+    }
+
+    /// Apply the permission policy to an error at `path`. Returns the error if the walk fails.
+    fn check(&mut self, path: Option<&Path>, error: Error) -> Option<Error> {
+        if error.kind() != ErrorKind::PermissionDenied {
+            return Some(error);
+        }
+        let path = path.unwrap_or(&self.root).to_path_buf();
+        match self.options.permission_denied {
+            DeniedEntryPolicy::SkipAndWarn => {
+                warn!("Skipping {}: {}", path.display(), error);
+                None
+            }
+            DeniedEntryPolicy::Collect => {
+                self.denied.push(path);
+                None
+            }
+            DeniedEntryPolicy::FailFast => Some(error),
+        }
+    }
+}
+
+impl Iterator for FileSetIter {
+    type Item = Result<FileEntry, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (pattern, walker) = match &mut self.current {
+                Some(current) => current,
+                None => {
+                    let pattern = self.patterns.pop()?;
+                    match self.options.walker(&self.root, &pattern).build() {
+                        Ok(walker) => self.current = Some((pattern, walker)),
+                        Err(e) => return Some(Err(Error::from(e))),
+                    }
+                    continue;
+                }
+            };
+
+            let pattern = pattern.clone();
+            let entry = match walker.next() {
+                Some(Ok(entry)) => entry,
+                Some(Err(e)) => {
+                    let path = e.path().map(Path::to_path_buf);
+                    match self.check(path.as_deref(), walk_error(e)) {
+                        Some(error) => return Some(Err(error)),
+                        None => continue,
+                    }
+                }
+                None => {
+                    self.current = None;
+                    continue;
+                }
+            };
+            if !self.seen.insert(entry.path().to_path_buf()) {
+                continue;
+            }
+            let relative_path = entry
+                .path()
+                .strip_prefix(&self.root)
+                .unwrap_or(entry.path())
+                .to_path_buf();
+            if self.options.is_hidden(&relative_path) {
+                trace!("Leave out {}: hidden", entry.path().display());
+                continue;
+            }
+
+            let metadata = match entry.metadata() {
+                Ok(metadata) => metadata,
+                Err(e) => match self.check(Some(entry.path()), Error::from(e)) {
+                    Some(error) => return Some(Err(error)),
+                    None => continue,
+                },
+            };
+            return Some(Ok(FileEntry {
+                path: entry.path().to_path_buf(),
+                relative_path,
+                metadata,
+                file_type: entry.file_type(),
+                pattern,
+            }));
+        }
+    }
+}
+
+#[test]
+fn test_file_set() {
+    let entries: Vec<FileEntry> = FileSet::new(Path::new("test/my_files"))
+        .pattern("*.txt")
+        .pattern("**/*.{txt,md}")
+        .into_iter()
+        .collect::<Result<_, _>>()
+        .unwrap();
+
+    let mut found: Vec<(String, &str)> = entries
+        .iter()
+        .map(|e| (e.relative_path.display().to_string(), e.pattern.as_str()))
+        .collect();
+    found.sort();
+    assert_eq!(
+        found,
+        [
+            ("file1.txt".to_string(), "*.txt"),
+            ("more_files/file3.md".to_string(), "**/*.{txt,md}")
+        ]
+    );
+    assert!(entries.iter().all(|e| e.file_type.is_file()));
+
+    assert!(FileSet::new(Path::new("test/my_files"))
+        .pattern("[")
+        .into_iter()
+        .next()
+        .unwrap()
+        .is_err());
+}
+
+#[test]
+fn test_file_set_walk_options() {
+    let root = Path::new("target/file_set_walk_options");
+    let _ = std::fs::remove_dir_all(root);
+    std::fs::create_dir_all(root.join("a/b")).unwrap();
+    std::fs::write(root.join("a/one.txt"), "1").unwrap();
+    std::fs::write(root.join("a/b/two.txt"), "2").unwrap();
+
+    let relative_paths = |options: PatternOptions| -> Result<Vec<PathBuf>, Error> {
+        let mut paths = FileSet::new(root)
+            .pattern("**/*.txt")
+            .options(options)
+            .into_iter()
+            .map(|entry| entry.map(|e| e.relative_path))
+            .collect::<Result<Vec<_>, _>>()?;
+        paths.sort();
+        Ok(paths)
+    };
+    let shallow = PatternOptions {
+        max_depth: Some(2),
+        ..PatternOptions::default()
+    };
+    assert_eq!(relative_paths(shallow).unwrap(), [Path::new("a/one.txt")]);
+
+    // the depth of glob patterns counts from the directory before the first wildcard
+    let glob = |max_depth| {
+        let options = PatternOptions {
+            max_depth,
+            ..PatternOptions::default()
+        };
+        glob_paths("target/file_set_walk_options/a/**/*.txt", &options).unwrap()
+    };
+    assert_eq!(glob(None).len(), 2);
+    assert_eq!(glob(Some(1)), [root.join("a/one.txt")]);
+
+    #[cfg(unix)]
+    {
+        std::os::unix::fs::symlink("../..", root.join("a/b/up")).unwrap();
+        // not followed by default
+        assert_eq!(relative_paths(PatternOptions::default()).unwrap().len(), 2);
+
+        let follow = PatternOptions {
+            follow_links: true,
+            ..PatternOptions::default()
+        };
+        let error = relative_paths(follow).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        assert!(error.to_string().starts_with("Symlink cycle: "));
+    }
+
+    std::fs::remove_dir_all(root).unwrap();
+}
+
+#[test]
+fn test_pattern_options() {
+    let root = Path::new("target/pattern_options");
+    let _ = std::fs::remove_dir_all(root);
+    std::fs::create_dir_all(root.join(".git")).unwrap();
+    std::fs::write(root.join("Icon.PNG"), "").unwrap();
+    std::fs::write(root.join(".git/logo.png"), "").unwrap();
+
+    let options = PatternOptions {
+        case_sensitive: false,
+        hidden_files: false,
+        ..Default::default()
+    };
+    let paths: Vec<PathBuf> = FileSet::new(root)
+        .pattern("**/*.png")
+        .options(options.clone())
+        .into_iter()
+        .map(|entry| entry.unwrap().relative_path)
+        .collect();
+    assert_eq!(paths, [Path::new("Icon.PNG")]);
+
+    assert!(glob::Pattern::new("*.png")
+        .unwrap()
+        .matches_with("Icon.PNG", options.match_options()));
+    assert!(!glob::Pattern::new("*")
+        .unwrap()
+        .matches_with(".git", options.match_options()));
+    let literal = PatternOptions {
+        literal_separator: true,
+        ..Default::default()
+    };
+    assert!(!literal
+        .glob("*.png")
+        .unwrap()
+        .compile_matcher()
+        .is_match("a/b.png"));
+    assert!(options
+        .glob("*.png")
+        .unwrap()
+        .compile_matcher()
+        .is_match("a/B.PNG"));
+
+    std::fs::remove_dir_all(root).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn test_file_set_permission_denied() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let root = Path::new("target/file_set_permission_denied");
+    let _ = std::fs::remove_dir_all(root);
+    std::fs::create_dir_all(root.join("locked")).unwrap();
+    std::fs::write(root.join("locked/secret.txt"), "").unwrap();
+    std::fs::write(root.join("open.txt"), "").unwrap();
+    let locked = root.join("locked");
+    std::fs::set_permissions(&locked, std::fs::Permissions::from_mode(0o000)).unwrap();
+
+    // root may read the directory anyway
+    if std::fs::read_dir(&locked).is_err() {
+        let walk = |policy| {
+            let mut entries = FileSet::new(root)
+                .pattern("**/*.txt")
+                .options(PatternOptions {
+                    permission_denied: policy,
+                    ..PatternOptions::default()
+                })
+                .into_iter();
+            let found: Result<Vec<PathBuf>, Error> =
+                (&mut entries).map(|e| e.map(|e| e.relative_path)).collect();
+            (found, entries.denied().to_vec())
+        };
+
+        let (found, denied) = walk(DeniedEntryPolicy::SkipAndWarn);
+        assert_eq!(found.unwrap(), [Path::new("open.txt")]);
+        assert!(denied.is_empty());
+
+        let (found, denied) = walk(DeniedEntryPolicy::Collect);
+        assert_eq!(found.unwrap(), [Path::new("open.txt")]);
+        assert_eq!(denied, [locked.as_path()]);
+
+        let (found, _) = walk(DeniedEntryPolicy::FailFast);
+        assert_eq!(found.unwrap_err().kind(), ErrorKind::PermissionDenied);
+    }
+
+    std::fs::set_permissions(&locked, std::fs::Permissions::from_mode(0o755)).unwrap();
+    std::fs::remove_dir_all(root).unwrap();
+}
@@ -0,0 +1,125 @@
+//! Options for compiling Interface Builder files with ibtool.
+
+use crate::build_script::BuildScriptOutput;
+use crate::fileset::PatternOptions;
+use crate::plist::Value;
+use crate::tool::Tool;
+
+/// Options for [`crate::compile_xib_to_nib_with_options`].
+#[derive(Clone, Debug, Default)]
+pub struct IbtoolOptions {
+    /// Devices to compile for (`--target-device`, e.g. `mac`, `iphone`, `ipad`)
+    pub target_devices: Vec<String>,
+    /// Swift module of the custom classes referenced by the files (`--module`)
+    pub module: Option<String>,
+    /// Oldest OS version the compiled files must support (`--minimum-deployment-target`)
+    pub minimum_deployment_target: Option<String>,
+    /// Report errors and warnings as an XML plist (`--errors --warnings --output-format xml1`).
+    /// Reported warnings are logged, errors end up in the returned error.
+    pub diagnostics: bool,
+    /// Flatten the compiled NIB files (`--flatten YES/NO`). ibtool's default is used if unset.
+    pub flatten: Option<bool>,
+    /// Report the source directory and the compiled files as inputs of a build script
+    pub build_script: Option<BuildScriptOutput>,
+    /// Matching of the `.xib` files below the source directory and the walk, e.g. a maximum
+    /// depth
+    pub patterns: PatternOptions,
+}
+
+impl IbtoolOptions {
+    /// Add the options to an ibtool invocation.
+    pub(crate) fn apply(&self, tool: &mut Tool) {
+        for device in &self.target_devices {
+            tool.arg("--target-device").arg(device);
+        }
+        if let Some(module) = &self.module {
+            tool.arg("--module").arg(module);
+        }
+        if let Some(target) = &self.minimum_deployment_target {
+            tool.arg("--minimum-deployment-target").arg(target);
+        }
+        if self.diagnostics {
+            tool.args(["--errors", "--warnings", "--output-format", "xml1"]);
+        }
+        if let Some(flatten) = self.flatten {
+            tool.arg("--flatten")
+                .arg(if flatten { "YES" } else { "NO" });
+        }
+    }
+}
+
+/// Messages in ibtool's plist output, grouped by the key they are reported under
+/// (e.g. `com.apple.ibtool.document.warnings`).
+pub(crate) fn diagnostics(output: &str) -> Vec<(String, String)> {
+    fn collect(group: &str, value: &Value, messages: &mut Vec<(String, String)>) {
+        match value {
+            Value::Dictionary(dict) => {
+                let text = dict
+                    .get("description")
+                    .or_else(|| dict.get("message"))
+                    .and_then(Value::as_str);
+                match text {
+                    Some(text) => messages.push((group.to_string(), text.to_string())),
+                    None => dict
+                        .values()
+                        .for_each(|value| collect(group, value, messages)),
+                }
+            }
+            Value::Array(items) => items
+                .iter()
+                .for_each(|value| collect(group, value, messages)),
+            _ => {}
+        }
+    }
+
+    let mut messages = Vec::new();
+    if let Ok(Value::Dictionary(root)) = Value::from_xml(output) {
+        for (group, value) in &root {
+            collect(group, value, &mut messages);
+        }
+    }
+    messages
+}
+
+#[test]
+fn test_ibtool_diagnostics() {
+    let output = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+	<key>com.apple.ibtool.document.warnings</key>
+	<dict>
+		<key>Xyz-12-abc</key>
+		<array>
+			<dict>
+				<key>message</key>
+				<string>Frame for "Label" will be different at run time.</string>
+				<key>type</key>
+				<string>Warning</string>
+			</dict>
+		</array>
+	</dict>
+	<key>com.apple.ibtool.errors</key>
+	<array>
+		<dict>
+			<key>description</key>
+			<string>The document could not be opened.</string>
+		</dict>
+	</array>
+</dict>
+</plist>"#;
+    assert_eq!(
+        diagnostics(output),
+        [
+            (
+                "com.apple.ibtool.document.warnings".to_string(),
+                "Frame for \"Label\" will be different at run time.".to_string()
+            ),
+            (
+                "com.apple.ibtool.errors".to_string(),
+                "The document could not be opened.".to_string()
+            )
+        ]
+    );
+    assert!(diagnostics("not a plist").is_empty());
+}
@@ -0,0 +1,89 @@
+//! Editing install names and rpaths of Mach-O binaries with install_name_tool from Xcode.
+//!
+//! Dynamic libraries copied into `Contents/Frameworks` must be found relative to the app, not at
+//! the path they were built at. That means giving them an `@rpath/...` install name, rewriting
+//! the references of binaries linking them, and adding an rpath like
+//! `@executable_path/../Frameworks` to the executable.
+
+use std::path::Path;
+
+use crate::tool::ToolError;
+use crate::xcrun;
+
+/// Rpath of a macOS app's executable pointing at `Contents/Frameworks`.
+pub const FRAMEWORKS_RPATH: &str = "@executable_path/../Frameworks";
+
+/// The `@rpath/...` form of a library path: `@rpath/<file name>` for dylibs and
+/// `@rpath/Foo.framework/...` for frameworks.
+pub fn rpath_name(library: &str) -> String {
+    let components: Vec<&str> = library.split('/').collect();
+    match components.iter().position(|c| c.ends_with(".framework")) {
+        Some(framework) => format!("@rpath/{}", components[framework..].join("/")),
+        None => format!("@rpath/{}", components.last().unwrap_or(&library)),
+    }
+}
+
+fn install_name_tool(binary: &Path, args: &[&str]) -> Result<(), ToolError> {
+    xcrun::tool("install_name_tool", None)?
+        .args(args)
+        .arg(binary)
+        .run()?;
+    Ok(())
+}
+
+/// Set the install name (`LC_ID_DYLIB`) of a dynamic library, e.g. to `@rpath/libfoo.dylib`.
+pub fn set_install_name(dylib: &Path, install_name: &str) -> Result<(), ToolError> {
+    install_name_tool(dylib, &["-id", install_name])
+}
+
+/// Change the path a binary loads a dependency from.
+///
+/// # Arguments
+///
+/// * `binary` - the executable or library linking the dependency
+/// * `old` - the dependency path recorded in the binary, e.g. `/usr/local/lib/libfoo.dylib`
+/// * `new` - the path to load it from instead, e.g. `@rpath/libfoo.dylib`
+pub fn change_dependency(binary: &Path, old: &str, new: &str) -> Result<(), ToolError> {
+    install_name_tool(binary, &["-change", old, new])
+}
+
+/// Make a binary load a dependency via its rpaths, returning the new `@rpath/...` path.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::path::Path;
+/// use toolbelt::install_name::{add_rpath, use_rpath, FRAMEWORKS_RPATH};
+///
+/// let executable = Path::new("target/MyApp.app/Contents/MacOS/MyApp");
+/// use_rpath(executable, "/usr/local/lib/libfoo.dylib").unwrap();
+/// add_rpath(executable, FRAMEWORKS_RPATH).unwrap();
+/// ```
+pub fn use_rpath(binary: &Path, dependency: &str) -> Result<String, ToolError> {
+    let new = rpath_name(dependency);
+    change_dependency(binary, dependency, &new)?;
+    Ok(new)
+}
+
+/// Add a runtime search path (`LC_RPATH`) to a binary.
+pub fn add_rpath(binary: &Path, rpath: &str) -> Result<(), ToolError> {
+    install_name_tool(binary, &["-add_rpath", rpath])
+}
+
+/// Remove a runtime search path from a binary.
+pub fn delete_rpath(binary: &Path, rpath: &str) -> Result<(), ToolError> {
+    install_name_tool(binary, &["-delete_rpath", rpath])
+}
+
+#[test]
+fn test_rpath_name() {
+    assert_eq!(
+        rpath_name("/usr/local/lib/libfoo.1.dylib"),
+        "@rpath/libfoo.1.dylib"
+    );
+    assert_eq!(
+        rpath_name("/Library/Frameworks/Foo.framework/Versions/A/Foo"),
+        "@rpath/Foo.framework/Versions/A/Foo"
+    );
+    assert_eq!(rpath_name("libbar.dylib"), "@rpath/libbar.dylib");
+}
@@ -0,0 +1,376 @@
+//! Minimal JSON reader and writer for the files toolbelt produces and consumes
+//! (manifests, copy plans, tool output).
+
+use std::fmt::{self, Write};
+use std::io::{Error, ErrorKind};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    /// Members in document order.
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Object(members) => members.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            Value::Number(n) if *n >= 0.0 && n.fract() == 0.0 => Some(*n as u64),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Value::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    /// Serialize with two space indentation.
+    pub fn to_pretty_string(&self) -> String {
+        let mut out = String::new();
+        self.write_pretty(&mut out, 0).unwrap();
+        out.push('\n');
+        out
+    }
+
+    fn write_pretty(&self, out: &mut String, indent: usize) -> fmt::Result {
+        match self {
+            Value::Array(items) if !items.is_empty() => {
+                out.push_str("[\n");
+                for (i, item) in items.iter().enumerate() {
+                    push_indent(out, indent + 1);
+                    item.write_pretty(out, indent + 1)?;
+                    out.push_str(if i + 1 < items.len() { ",\n" } else { "\n" });
+                }
+                push_indent(out, indent);
+                out.push(']');
+            }
+            Value::Object(members) if !members.is_empty() => {
+                out.push_str("{\n");
+                for (i, (key, value)) in members.iter().enumerate() {
+                    push_indent(out, indent + 1);
+                    write_string(out, key)?;
+                    out.push_str(": ");
+                    value.write_pretty(out, indent + 1)?;
+                    out.push_str(if i + 1 < members.len() { ",\n" } else { "\n" });
+                }
+                push_indent(out, indent);
+                out.push('}');
+            }
+            other => write!(out, "{}", other)?,
+        }
+        Ok(())
+    }
+}
+
+fn push_indent(out: &mut String, indent: usize) {
+    for _ in 0..indent {
+        out.push_str("  ");
+    }
+}
+
+fn write_string<W: Write>(out: &mut W, s: &str) -> fmt::Result {
+    out.write_char('"')?;
+    for c in s.chars() {
+        match c {
+            '"' => out.write_str("\\\"")?,
+            '\\' => out.write_str("\\\\")?,
+            '\n' => out.write_str("\\n")?,
+            '\r' => out.write_str("\\r")?,
+            '\t' => out.write_str("\\t")?,
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32)?,
+            c => out.write_char(c)?,
+        }
+    }
+    out.write_char('"')
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Null => f.write_str("null"),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Number(n) if n.fract() == 0.0 && n.abs() < 9.0e15 => write!(f, "{}", *n as i64),
+            Value::Number(n) => write!(f, "{}", n),
+            Value::String(s) => write_string(f, s),
+            Value::Array(items) => {
+                f.write_char('[')?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        f.write_char(',')?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                f.write_char(']')
+            }
+            Value::Object(members) => {
+                f.write_char('{')?;
+                for (i, (key, value)) in members.iter().enumerate() {
+                    if i > 0 {
+                        f.write_char(',')?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                f.write_char('}')
+            }
+        }
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Self {
+        Value::String(s.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Self {
+        Value::String(s)
+    }
+}
+
+impl From<u64> for Value {
+    fn from(n: u64) -> Self {
+        Value::Number(n as f64)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
+        Value::Bool(b)
+    }
+}
+
+/// Parse a JSON document.
+pub fn parse(input: &str) -> Result<Value, Error> {
+    let mut parser = Parser {
+        chars: input.char_indices().peekable(),
+        input,
+    };
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    match parser.chars.next() {
+        None => Ok(value),
+        Some((pos, _)) => Err(parser.error(pos, "trailing characters")),
+    }
+}
+
+struct Parser<'a> {
+    chars: std::iter::Peekable<std::str::CharIndices<'a>>,
+    input: &'a str,
+}
+
+impl Parser<'_> {
+    fn error(&self, pos: usize, what: &str) -> Error {
+        Error::new(
+            ErrorKind::InvalidData,
+            format!("Invalid JSON at byte {}: {}", pos, what),
+        )
+    }
+
+    fn position(&mut self) -> usize {
+        self.chars.peek().map_or(self.input.len(), |(pos, _)| *pos)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some((_, c)) = self.chars.peek() {
+            if c.is_whitespace() {
+                self.chars.next();
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), Error> {
+        self.skip_whitespace();
+        match self.chars.next() {
+            Some((_, c)) if c == expected => Ok(()),
+            Some((pos, _)) => Err(self.error(pos, &format!("expected '{}'", expected))),
+            None => Err(self.error(self.input.len(), "unexpected end")),
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, Error> {
+        self.skip_whitespace();
+        let pos = self.position();
+        match self.chars.peek().map(|(_, c)| *c) {
+            Some('{') => self.object(),
+            Some('[') => self.array(),
+            Some('"') => Ok(Value::String(self.string()?)),
+            Some('t') => self.literal("true", Value::Bool(true)),
+            Some('f') => self.literal("false", Value::Bool(false)),
+            Some('n') => self.literal("null", Value::Null),
+            Some(c) if c == '-' || c.is_ascii_digit() => self.number(),
+            Some(_) => Err(self.error(pos, "unexpected character")),
+            None => Err(self.error(pos, "unexpected end")),
+        }
+    }
+
+    fn literal(&mut self, word: &str, value: Value) -> Result<Value, Error> {
+        let pos = self.position();
+        if self.input[pos..].starts_with(word) {
+            for _ in 0..word.len() {
+                self.chars.next();
+            }
+            Ok(value)
+        } else {
+            Err(self.error(pos, "unknown literal"))
+        }
+    }
+
+    fn number(&mut self) -> Result<Value, Error> {
+        let start = self.position();
+        while let Some((_, c)) = self.chars.peek() {
+            if c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | 'e' | 'E') {
+                self.chars.next();
+            } else {
+                break;
+            }
+        }
+        let end = self.position();
+        self.input[start..end]
+            .parse()
+            .map(Value::Number)
+            .map_err(|_| self.error(start, "invalid number"))
+    }
+
+    fn string(&mut self) -> Result<String, Error> {
+        self.expect('"')?;
+        let mut s = String::new();
+        loop {
+            match self.chars.next() {
+                Some((_, '"')) => return Ok(s),
+                Some((pos, '\\')) => match self.chars.next() {
+                    Some((_, '"')) => s.push('"'),
+                    Some((_, '\\')) => s.push('\\'),
+                    Some((_, '/')) => s.push('/'),
+                    Some((_, 'b')) => s.push('\u{8}'),
+                    Some((_, 'f')) => s.push('\u{c}'),
+                    Some((_, 'n')) => s.push('\n'),
+                    Some((_, 'r')) => s.push('\r'),
+                    Some((_, 't')) => s.push('\t'),
+                    Some((_, 'u')) => {
+                        let mut code = self.hex4(pos)?;
+                        if (0xd800..0xdc00).contains(&code) {
+                            // surrogate pair
+                            self.expect('\\')?;
+                            self.expect('u')?;
+                            let low = self.hex4(pos)?;
+                            if !(0xdc00..0xe000).contains(&low) {
+                                return Err(self.error(pos, "bad escape"));
+                            }
+                            code = 0x10000 + ((code - 0xd800) << 10) + (low - 0xdc00);
+                        }
+                        s.push(char::from_u32(code).ok_or_else(|| self.error(pos, "bad escape"))?);
+                    }
+                    _ => return Err(self.error(pos, "bad escape")),
+                },
+                Some((_, c)) => s.push(c),
+                None => return Err(self.error(self.input.len(), "unterminated string")),
+            }
+        }
+    }
+
+    fn hex4(&mut self, pos: usize) -> Result<u32, Error> {
+        let mut code = 0;
+        for _ in 0..4 {
+            let digit = self
+                .chars
+                .next()
+                .and_then(|(_, c)| c.to_digit(16))
+                .ok_or_else(|| self.error(pos, "bad unicode escape"))?;
+            code = code * 16 + digit;
+        }
+        Ok(code)
+    }
+
+    fn array(&mut self) -> Result<Value, Error> {
+        self.expect('[')?;
+        let mut items = Vec::new();
+        self.skip_whitespace();
+        if let Some((_, ']')) = self.chars.peek() {
+            self.chars.next();
+            return Ok(Value::Array(items));
+        }
+        loop {
+            items.push(self.value()?);
+            self.skip_whitespace();
+            match self.chars.next() {
+                Some((_, ',')) => continue,
+                Some((_, ']')) => return Ok(Value::Array(items)),
+                Some((pos, _)) => return Err(self.error(pos, "expected ',' or ']'")),
+                None => return Err(self.error(self.input.len(), "unexpected end")),
+            }
+        }
+    }
+
+    fn object(&mut self) -> Result<Value, Error> {
+        self.expect('{')?;
+        let mut members = Vec::new();
+        self.skip_whitespace();
+        if let Some((_, '}')) = self.chars.peek() {
+            self.chars.next();
+            return Ok(Value::Object(members));
+        }
+        loop {
+            self.skip_whitespace();
+            let key = self.string()?;
+            self.expect(':')?;
+            members.push((key, self.value()?));
+            self.skip_whitespace();
+            match self.chars.next() {
+                Some((_, ',')) => continue,
+                Some((_, '}')) => return Ok(Value::Object(members)),
+                Some((pos, _)) => return Err(self.error(pos, "expected ',' or '}'")),
+                None => return Err(self.error(self.input.len(), "unexpected end")),
+            }
+        }
+    }
+}
+
+#[test]
+fn test_json_roundtrip() {
+    let doc = r#"{"name": "a \"b\"\n", "size": 42, "list": [true, false, null, -1.5e2], "empty": {}, "u": "ä😀"}"#;
+    let value = parse(doc).unwrap();
+    assert_eq!(value.get("name").unwrap().as_str(), Some("a \"b\"\n"));
+    assert_eq!(value.get("size").unwrap().as_u64(), Some(42));
+    assert_eq!(value.get("u").unwrap().as_str(), Some("ä😀"));
+    assert_eq!(
+        value.get("list").unwrap().as_array().unwrap()[3],
+        Value::Number(-150.0)
+    );
+    assert_eq!(parse(&value.to_pretty_string()).unwrap(), value);
+    assert_eq!(parse(&value.to_string()).unwrap(), value);
+    assert!(parse("{\"a\": }").is_err());
+    assert!(parse("[1, 2] x").is_err());
+    assert_eq!(
+        parse(r#"["\ud83d\ude00"]"#).unwrap().as_array().unwrap()[0].as_str(),
+        Some("😀")
+    );
+    assert!(parse(r#"["\ud800\u0041"]"#).is_err());
+    assert!(crate::manifest::Manifest::from_json(
+        r#"{"files":[{"path":"\ud800\u0041","size":1,"sha256":"x"}]}"#
+    )
+    .is_err());
+}
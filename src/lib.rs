@@ -5,20 +5,106 @@ extern crate log;
 
 use std::fmt::Display;
 use std::{
-    io::Error,
+    io::{Error, ErrorKind},
     path::{Path, PathBuf},
-    process::Command,
 };
 
-use glob::glob_with;
 use glob::MatchOptions;
 use inflector::cases::titlecase::to_title_case;
+use messages::{message, Message};
+use tool::{ToolError, ToolOutput};
+
+pub mod actool;
+pub mod android;
+pub mod appstore;
+pub mod assets;
+pub mod build_script;
+pub mod bundle;
+pub mod cargo;
+pub mod codesign;
+pub mod compile_commands;
+pub mod concurrency;
+pub mod config;
+pub mod context;
+pub mod copy;
+pub mod dmg;
+#[cfg(feature = "http")]
+pub mod download;
+pub mod duplicates;
+pub mod entitlements;
+pub mod env_file;
+pub mod fileset;
+pub mod hash;
+pub mod ibtool;
+pub mod install_name;
+mod json;
+pub mod linux;
+pub mod lipo;
+pub mod localization;
+pub mod macho;
+pub mod manifest;
+pub mod messages;
+pub mod metal;
+pub mod momc;
+pub mod notarize;
+#[cfg(unix)]
+pub mod permissions;
+pub mod pipeline;
+pub mod pkg;
+pub mod pkg_config;
+pub mod plist;
+pub mod provisioning;
+pub mod sdk;
+pub mod simctl;
+pub mod symbols;
+pub mod sysroot;
+mod toml;
+pub mod tool;
+pub mod vcpkg;
+pub mod windows;
+pub mod xcodebuild;
+pub mod xcrun;
+
+pub use actool::{compile_asset_catalog, ActoolOptions};
+pub use android::sdk::{detect_android_ndk, detect_android_sdk, AndroidNdk, AndroidSdk};
+pub use build_script::BuildScriptOutput;
+pub use bundle::{bundle_identifier, embed_appex, embed_frameworks};
+pub use cargo::{CargoDependency, CargoMetadata, CargoPackage, DependencyKind};
+pub use codesign::{
+    codesign_glob, codesign_verify, gatekeeper_assess, CodesignOptions, SignatureVerification,
+};
+pub use compile_commands::{CompileCommand, CompileCommands};
+pub use config::Config;
+pub use copy::{copy_dir_with_options, CopyOptions, CopyPlanner, CopyReport, DestinationReport};
+#[cfg(feature = "http")]
+pub use download::{download, DownloadError};
+pub use duplicates::{find_duplicates, find_duplicates_with_options};
+pub use entitlements::Entitlements;
+pub use env_file::{load_env, EnvFile};
+pub use fileset::{DeniedEntryPolicy, FileEntry, FileSet, PatternOptions};
+pub use hash::Sha256;
+pub use ibtool::IbtoolOptions;
+pub use lipo::{lipo_create, lipo_info};
+pub use macho::verify_dsym_match;
+pub use manifest::{verify_manifest, Manifest, ManifestVerification};
+#[cfg(unix)]
+pub use permissions::{audit_permissions, fix_permissions};
+pub use pkg_config::{pkg_config_flags, PkgConfig};
+pub use sdk::{
+    get_sdk_path, get_sdk_path_or_exit, get_sdk_path_with_fallbacks, get_sdk_path_with_output,
+    get_sdk_version, require_sdk_version, Sdk, SdkError, SdkSet, SdkSpec, VersionMarker,
+};
+pub use sysroot::{assemble_sysroot, SysrootSpec};
+pub use tool::CompileError;
+pub use vcpkg::{detect_vcpkg, Vcpkg};
+pub use windows::sdk::{detect_windows_sdk, WindowsSdk};
+pub use xcrun::{apple_sdk_path, AppleSdk};
 
 pub fn version() -> u32 {
     ((env!("CARGO_PKG_VERSION_MAJOR").parse::<u32>().unwrap() & 7) << 19)
         | ((env!("CARGO_PKG_VERSION_MINOR").parse::<u32>().unwrap() & 15) << 15)
         | ((env!("CARGO_PKG_VERSION_PATCH").parse::<u32>().unwrap() & 15) << 11)
-        | ((env!("CARGO_PKG_VERSION_PRE").parse::<u32>().unwrap_or(0) & 511) << 0)
+        | (env!("CARGO_PKG_VERSION_PRE").parse::<u32>().unwrap_or(0) & 511)
 }
 
 /// Copy files from one directory to another. Use a glob pattern to select the files to be copied.
@@ -28,41 +114,14 @@ pub fn version() -> u32 {
 /// * `source` - the source path. It will be converted to a PathBuf.
 /// * `destination` - the destination path. It will be converted to a PathBuf.
 /// * `pattern` - a standard glob pattern (e.g. *.{txt,csv} or **/*) that will be used to choose the files to be copied.
+///   A pattern without glob metacharacters (e.g. `docs/readme.txt`) copies exactly that file.
 ///
 pub fn copy_dir_with_pattern(
     source: &Path,
     destination: &Path,
     pattern: &str,
 ) -> Result<(), Error> {
-    let source_path: PathBuf = PathBuf::from(&source).canonicalize().unwrap();
-    let source_with_glob = source_path.join(pattern);
-    let destination_path = PathBuf::from(destination);
-
-    let mut existing_paths: Vec<PathBuf> = Vec::new();
-
-    for entry in globwalk::glob(format!("{}", source_with_glob.display()))
-        .unwrap()
-        .flatten()
-    {
-        let mut destination_sub_path = entry
-            .path()
-            .strip_prefix(&source_path)
-            .unwrap()
-            .to_path_buf();
-        destination_sub_path.set_file_name("");
-        let complete_destination_path = destination_path.join(destination_sub_path);
-
-        if !existing_paths.contains(&complete_destination_path) {
-            existing_paths.push(complete_destination_path.clone());
-            if !complete_destination_path.exists() {
-                // make sure the destination path exists
-                std::fs::create_dir_all(&complete_destination_path)?;
-            }
-        }
-
-        let destination_file = complete_destination_path.join(&entry.file_name());
-        std::fs::copy(entry.path(), destination_file)?;
-    }
+    copy_dir_with_options(source, destination, pattern, &CopyOptions::default())?;
     Ok(())
 }
 
@@ -84,23 +143,26 @@ fn test_copy_dir_with_pattern() {
     assert!(Path::new("target/dest_files/more_files/file3.md").exists());
 
     // clean up
-    fs::remove_dir_all(destination_path);
-
-    /* Having no glob pattern currently doesn't seem to work with GlobWalker,
-        thus this test will fail.
+    fs::remove_dir_all(destination_path).unwrap();
 
-    // copy or test files
+    // patterns without glob metacharacters are exact relative paths
     if let Err(e) = copy_dir_with_pattern(source_path, destination_path, "file1.txt") {
         eprintln!("Error copying files: {:?}", e);
     }
+    if let Err(e) = copy_dir_with_pattern(source_path, destination_path, "more_files/file3.md") {
+        eprintln!("Error copying files: {:?}", e);
+    }
 
     // check the copy worked
-    assert_eq!(Path::new("target/dest_files/file1.txt").exists(), true);
+    assert!(Path::new("target/dest_files/file1.txt").exists());
+    assert!(Path::new("target/dest_files/more_files/file3.md").exists());
+    assert!(!Path::new("target/dest_files/file2.csv").exists());
 
     // clean up
-    fs::remove_dir_all(destination_path);
+    fs::remove_dir_all(destination_path).unwrap();
 
-     */
+    // a missing literal file is an error
+    assert!(copy_dir_with_pattern(source_path, destination_path, "missing.txt").is_err());
 }
 
 #[test]
@@ -118,41 +180,116 @@ fn test_basic_glob() {
 /// * `source` – source path to tool for *.xib files
 /// * `destination` - destination path to copy compiler *.nib file to
 ///
-/// The current implementation **flattens** the directory structure.
+/// The directory structure below `source` is recreated at the destination, so
+/// `Base.lproj/Main.xib` is compiled to `Base.lproj/Main.nib`.
+///
+/// Stops at the first XIB file ibtool fails to compile. The error names that file and carries
+/// ibtool's exit status, stdout and stderr.
 ///
-pub fn compile_xib_to_nib(source: &Path, destination: &Path) {
+pub fn compile_xib_to_nib(source: &Path, destination: &Path) -> Result<(), CompileError> {
+    compile_xib_to_nib_with_options(source, destination, &IbtoolOptions::default())
+}
+
+/// Like [`compile_xib_to_nib`], passing additional options to ibtool.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::path::Path;
+/// use toolbelt::{compile_xib_to_nib_with_options, IbtoolOptions};
+///
+/// let options = IbtoolOptions {
+///     module: Some("MyApp".to_string()),
+///     minimum_deployment_target: Some("11.0".to_string()),
+///     diagnostics: true,
+///     ..Default::default()
+/// };
+/// compile_xib_to_nib_with_options(Path::new("ui"), Path::new("target/ui"), &options).unwrap();
+/// ```
+pub fn compile_xib_to_nib_with_options(
+    source: &Path,
+    destination: &Path,
+    options: &IbtoolOptions,
+) -> Result<(), CompileError> {
     /*
     Compile xib to nib
     find . -name "*.xib" -type f | awk '{sub(/.xib/,"");print}' | xargs -I % ibtool --compile %.nib %.xib
 
     Piped commands reference: https://rust-lang-nursery.github.io/rust-cookbook/os/external.html#run-piped-external-commands
      */
-    let source_with_glob = PathBuf::from(source).join("*.xib");
-    debug!("source with glob {:?}", &source_with_glob);
-
-    for entry in globwalk::glob(source_with_glob.to_str().unwrap())
-        .unwrap()
-        .flatten()
-    {
-        // TODO: preserve source directory structure at destination
-        let mut nib_path = PathBuf::from(destination);
-        nib_path = nib_path.join(entry.file_name());
+    debug!("source with glob {:?}", source.join("*.xib"));
+    if let Some(output) = &options.build_script {
+        output.rerun_if_changed(source);
+    }
+
+    let xibs = FileSet::new(source)
+        .pattern("*.xib")
+        .options(options.patterns.clone());
+    for entry in xibs {
+        let entry = entry?;
+        if !entry.file_type.is_file() {
+            continue;
+        }
+        let xib = entry.path;
+        if let Some(output) = &options.build_script {
+            output.rerun_if_changed(&xib);
+        }
+        let mut nib_path = PathBuf::from(destination).join(&entry.relative_path);
         nib_path.set_extension("nib");
         debug!("{:?}", &nib_path);
 
+        if let Some(nib_dir) = nib_path.parent() {
+            std::fs::create_dir_all(nib_dir)?;
+        }
+
         debug!(
             "Compile xib from {:?} to {:?}",
-            entry.path().display(),
+            xib.display(),
             nib_path.display()
         );
-        let _compile_xibs = Command::new("ibtool")
+        let mut ibtool = xcrun::tool("ibtool", None).map_err(|source| CompileError::Tool {
+            file: xib.clone(),
+            source,
+        })?;
+        options.apply(&mut ibtool);
+        let output = ibtool
             .arg("--compile")
             .arg(nib_path)
-            .arg(entry.path())
-            .output()
-            // .unwrap();
-            .map_err(|_| "Failed to run compile xibs.".to_string());
+            .arg(&xib)
+            .run()
+            .map_err(|source| CompileError::Tool {
+                file: xib.clone(),
+                source,
+            })?;
+        if options.diagnostics {
+            for (_, message) in ibtool::diagnostics(&output.stdout) {
+                warn!("{}: {}", xib.display(), message);
+            }
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_compile_xib_to_nib_reports_failing_file() {
+    let root = Path::new("target/compile_xib_to_nib");
+    let _ = std::fs::remove_dir_all(root);
+    std::fs::create_dir_all(root.join("src/Base.lproj")).unwrap();
+
+    // nothing to compile
+    compile_xib_to_nib(&root.join("src"), &root.join("out")).unwrap();
+
+    // ibtool is missing or rejects the file, either way the file is named in the error
+    std::fs::write(root.join("src/Base.lproj/Main.xib"), "not a xib").unwrap();
+    match compile_xib_to_nib(&root.join("src"), &root.join("out")) {
+        Err(CompileError::Tool { file, .. }) => {
+            assert_eq!(file, root.join("src/Base.lproj/Main.xib"))
+        }
+        other => panic!("unexpected {:?}", other),
     }
+    assert!(root.join("out/Base.lproj").is_dir());
+
+    std::fs::remove_dir_all(root).unwrap();
 }
 
 /// Sign a package using codesign from Xcode
@@ -161,55 +298,74 @@ pub fn compile_xib_to_nib(source: &Path, destination: &Path) {
 ///
 /// * `package` - Path to the package's root folder
 ///
-/// Attention: No error handling in place yet.
+/// Signs ad-hoc, use [`codesign_with_options`] to sign with an identity.
 ///
-pub fn codesign(package: &Path) {
-    let _signer = Command::new("codesign")
-        .arg("--force")
-        .arg("--sign")
-        .arg("-")
-        .arg(package)
-        .output()
-        // .unwrap();
-        .map_err(|_| "Failed to sign package.".to_string());
+pub fn codesign(package: &Path) -> Result<ToolOutput, ToolError> {
+    codesign_with_options(package, &CodesignOptions::default())
 }
 
-/// Reads a SDK path from an environment variable and returns a PathBuf pointing to it.
-///
-/// # Arguments
+/// Sign a package using codesign from Xcode with an identity, entitlements, the hardened
+/// runtime, …
 ///
-/// * `sdk_name` – A string containing the name of the environment variable that shall contain the SDK path
+/// Returns codesign's output, or an error with its exit status and output if signing failed.
 ///
 /// # Example
 ///
-/// ```no run
-/// use toolbelt::get_sdk_path;
-/// let sdk_path = get_sdk_path(env!("THE_SDK"));
+/// ```no_run
+/// use std::path::Path;
+/// use toolbelt::{codesign_with_options, CodesignOptions};
+///
+/// let options = CodesignOptions {
+///     identity: "Developer ID Application: Example Inc (ABCDE12345)".to_string(),
+///     entitlements: Some("MyApp.entitlements".into()),
+///     hardened_runtime: true,
+///     timestamp: true,
+///     ..Default::default()
+/// };
+/// codesign_with_options(Path::new("target/MyApp.app"), &options).unwrap();
 /// ```
-pub fn get_sdk_path(sdk_name: &str) -> PathBuf {
-    let sdk_path: PathBuf = sdk_name
-        .to_string()
-        .parse()
-        .unwrap_or_else(|_| panic!("{} env variable configuration error.", sdk_name));
-
-    if !sdk_path.exists() {
-        eprintln!(
-            "Please download & unpack the SDK into {}",
-            sdk_path.display()
-        );
-        std::process::exit(1);
-    }
-
-    sdk_path
+pub fn codesign_with_options(
+    package: &Path,
+    options: &CodesignOptions,
+) -> Result<ToolOutput, ToolError> {
+    let mut signer = xcrun::tool("codesign", None)?;
+    options.apply(&mut signer);
+    signer.arg(package).run()
 }
 
+/// How include directories are formatted, see [`get_sdk_include_dirs`].
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum IncludeDirFormat {
+    /// The plain path
     PLAIN,
+    /// `-I<path>` for clang and gcc
     CLANG,
+    /// `/I<path>` for cl.exe
+    MSVC,
+    /// `-isystem<path>` for system headers, whose warnings clang and gcc suppress
+    ISYSTEM,
+    /// The path preceded by a custom prefix, e.g. `-iquote` or `--include-directory=`
+    Custom(String),
+}
+
+impl IncludeDirFormat {
+    /// Format an include directory.
+    pub fn format(&self, dir: &Path) -> String {
+        match self {
+            IncludeDirFormat::PLAIN => format!("{}", dir.display()),
+            IncludeDirFormat::CLANG => format!("-I{}", dir.display()),
+            IncludeDirFormat::MSVC => format!("/I{}", dir.display()),
+            IncludeDirFormat::ISYSTEM => format!("-isystem{}", dir.display()),
+            IncludeDirFormat::Custom(prefix) => format!("{}{}", prefix, dir.display()),
+        }
+    }
 }
 
 /// Returns an expanded list of header directories based on a list of paths incl. glob patterns
 ///
+/// Symbolic links are followed, links pointing back to one of their ancestors are reported as
+/// cycles and not descended into.
+///
 /// # Arguments
 ///
 /// * `sdk_header_dirs` – List of glob patterns for directories to includem
@@ -217,13 +373,17 @@ pub enum IncludeDirFormat {
 /// * `format` – Format of returned directories. One of
 ///     * IncludeDirFormat::PLAIN for a plain list
 ///     * IncludeDirFormat::CLANG for clang style format (starting with `-I`)
+///     * IncludeDirFormat::MSVC for cl.exe style format (starting with `/I`)
+///     * IncludeDirFormat::ISYSTEM for system headers (starting with `-isystem`)
+///     * IncludeDirFormat::Custom with the prefix to use
 ///
 /// # Example
 ///
 /// ```no run
 /// use toolbelt::{get_sdk_path, get_sdk_include_dirs, IncludeDirFormat};
 ///
-/// let sdk_path = get_sdk_path(env!("THE_SDK")).to_str().unwrap();
+/// let sdk_path = get_sdk_path("THE_SDK").unwrap();
+/// let sdk_path = sdk_path.to_str().unwrap();
 /// let include_dirs = [
 ///     "headers/common/**"];
 /// get_sdk_include_dirs(include_dirs, sdk_path, IncludeDirFormat::CLANG);
@@ -237,35 +397,751 @@ where
     I: IntoIterator,
     I::Item: Display,
 {
-    let options = MatchOptions {
+    let options = PatternOptions {
         case_sensitive: false,
-        require_literal_separator: false,
-        require_literal_leading_dot: false,
+        follow_links: true,
+        ..PatternOptions::default()
     };
+    get_sdk_include_dirs_with_options(sdk_header_dirs, sdk_path, format, &options)
+}
 
-    let mut incl_dirs = Vec::new();
+/// Like [`get_sdk_include_dirs`], but returns each directory as a path together with its flag, so
+/// it can be checked, deduplicated or made relative without parsing the flag.
+///
+/// # Example
+///
+/// ```no_run
+/// use toolbelt::{get_sdk_include_paths, IncludeDirFormat};
+///
+/// for dir in get_sdk_include_paths(["headers/**"], "/opt/sdk/", IncludeDirFormat::CLANG) {
+///     if dir.path.join("gl.h").is_file() {
+///         println!("{} provides gl.h, pass {}", dir.path.display(), dir.flag);
+///     }
+/// }
+/// ```
+pub fn get_sdk_include_paths<I>(
+    sdk_header_dirs: I,
+    sdk_path: &str,
+    format: IncludeDirFormat,
+) -> Vec<IncludeDir>
+where
+    I: IntoIterator,
+    I::Item: Display,
+{
+    let options = PatternOptions {
+        case_sensitive: false,
+        follow_links: true,
+        ..PatternOptions::default()
+    };
+    let expansion = expand_sdk_include_dirs(sdk_header_dirs, sdk_path, format, &options);
+    warn_unmatched(&expansion, sdk_path, Message::IncludePatternUnmatched);
+    expansion.include_dirs
+}
+
+/// Like [`get_sdk_include_dirs`], but with the matching and the search configured, e.g. with the
+/// depth limited or symbolic links not followed. [`get_sdk_include_dirs`] matches
+/// case-insensitively and follows symbolic links.
+///
+/// # Arguments
+///
+/// * `sdk_header_dirs` – List of glob patterns for directories to include
+/// * `sdk_path` - Root SDK path
+/// * `format` – Format of returned directories
+/// * `options` - The maximum depth counts from `sdk_path`
+pub fn get_sdk_include_dirs_with_options<I>(
+    sdk_header_dirs: I,
+    sdk_path: &str,
+    format: IncludeDirFormat,
+    options: &PatternOptions,
+) -> Vec<String>
+where
+    I: IntoIterator,
+    I::Item: Display,
+{
+    let expansion = expand_sdk_include_dirs(sdk_header_dirs, sdk_path, format, options);
+    warn_unmatched(&expansion, sdk_path, Message::IncludePatternUnmatched);
+    expansion.dirs
+}
+
+fn warn_unmatched(expansion: &IncludeDirExpansion, sdk_path: &str, unmatched: Message) {
+    for pattern in expansion.unmatched() {
+        warn!(
+            "{}",
+            message(unmatched, &[("pattern", &pattern), ("sdk", &sdk_path)])
+        );
+    }
+}
+
+/// An include directory found by [`expand_sdk_include_dirs`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IncludeDir {
+    pub path: PathBuf,
+    /// The path in the requested format, e.g. `-I/opt/sdk/include`
+    pub flag: String,
+}
+
+impl Display for IncludeDir {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.flag)
+    }
+}
+
+/// How often a pattern passed to [`expand_sdk_include_dirs`] matched.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IncludePatternStats {
+    pub pattern: String,
+    /// Number of directories (or files) the pattern expanded to, including those listed for an
+    /// earlier pattern already
+    pub matches: usize,
+}
+
+/// Result of [`expand_sdk_include_dirs`]: the include directories and per-pattern statistics.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct IncludeDirExpansion {
+    /// The expanded directories in the requested format
+    pub dirs: Vec<String>,
+    /// The expanded directories with their paths, in the same order as `dirs`
+    pub include_dirs: Vec<IncludeDir>,
+    /// One entry per pattern, in the order given
+    pub patterns: Vec<IncludePatternStats>,
+}
+
+impl IncludeDirExpansion {
+    /// Patterns that matched nothing, most likely typos.
+    pub fn unmatched(&self) -> Vec<&str> {
+        self.patterns
+            .iter()
+            .filter(|p| p.matches == 0)
+            .map(|p| p.pattern.as_str())
+            .collect()
+    }
+}
+
+impl Display for IncludeDirExpansion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Expanded {} include patterns to {} directories",
+            self.patterns.len(),
+            self.dirs.len()
+        )?;
+        let unmatched = self.unmatched();
+        if !unmatched.is_empty() {
+            write!(f, ", no matches for {}", unmatched.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Like [`get_sdk_include_dirs_with_options`], but also report how many directories each pattern
+/// expanded to, e.g. to fail a build on patterns that matched nothing.
+///
+/// The result is reproducible: the directories are listed in the order of the patterns, and the
+/// directories matched by one pattern are sorted by name, parents before their subdirectories. A
+/// directory matched by several patterns, or reached through a symbolic link, is listed once at
+/// the position of the first pattern, which keeps its priority in the compiler's search order.
+///
+/// # Example
+///
+/// ```no_run
+/// use toolbelt::{expand_sdk_include_dirs, IncludeDirFormat, PatternOptions};
+///
+/// let expansion = expand_sdk_include_dirs(
+///     ["headers/common/**", "headers/mac/gl"],
+///     "/opt/sdk/",
+///     IncludeDirFormat::CLANG,
+///     &PatternOptions::default(),
+/// );
+/// println!("{}", expansion);
+/// assert!(expansion.unmatched().is_empty(), "typo in include patterns");
+/// ```
+pub fn expand_sdk_include_dirs<I>(
+    sdk_header_dirs: I,
+    sdk_path: &str,
+    format: IncludeDirFormat,
+    options: &PatternOptions,
+) -> IncludeDirExpansion
+where
+    I: IntoIterator,
+    I::Item: Display,
+{
+    let mut expansion = IncludeDirExpansion::default();
     let sdk = PathBuf::from(sdk_path);
+    let mut seen = std::collections::HashSet::new();
 
     for hdir in sdk_header_dirs.into_iter() {
-        for entry in glob_with(&format!("{}{}", sdk_path, hdir).to_string(), options)
-            .expect("Failed to read glob pattern")
-        {
+        let mut matches = 0;
+        for entry in expand_dir_pattern(&sdk, &format!("{}{}", sdk_path, hdir), options) {
             match entry {
                 Ok(path) => {
+                    matches += 1;
                     let ipath = sdk.join(path);
-                    match &format {
-                        IncludeDirFormat::CLANG => {
-                            incl_dirs.push(format!("-I{}", &ipath.display()))
-                        }
-                        IncludeDirFormat::PLAIN => incl_dirs.push(format!("{}", &ipath.display())),
+                    let canonical = ipath.canonicalize().unwrap_or_else(|_| ipath.clone());
+                    if !seen.insert(canonical) {
+                        debug!("Skipping {}, it is included already", ipath.display());
+                        continue;
                     }
+                    let flag = format.format(&ipath);
+                    expansion.dirs.push(flag.clone());
+                    expansion
+                        .include_dirs
+                        .push(IncludeDir { path: ipath, flag });
                 }
-                Err(e) => eprintln!("{:?}", e),
+                Err(e) => warn!("{}", e),
             }
         }
+        debug!("Include pattern {} matched {} directories", hdir, matches);
+        expansion.patterns.push(IncludePatternStats {
+            pattern: hdir.to_string(),
+            matches,
+        });
+    }
+
+    expansion
+}
+
+/// How library directories are formatted, see [`get_sdk_lib_dirs`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LibDirFormat {
+    /// The plain path
+    PLAIN,
+    /// `-L<path>` for clang and gcc
+    CLANG,
+    /// `/LIBPATH:<path>` for link.exe
+    MSVC,
+}
+
+impl LibDirFormat {
+    /// Format a library directory.
+    pub fn format(&self, dir: &Path) -> String {
+        match self {
+            LibDirFormat::PLAIN => format!("{}", dir.display()),
+            LibDirFormat::CLANG => format!("-L{}", dir.display()),
+            LibDirFormat::MSVC => format!("/LIBPATH:{}", dir.display()),
+        }
     }
+}
+
+/// Returns an expanded list of library directories based on a list of paths incl. glob patterns,
+/// the counterpart of [`get_sdk_include_dirs`].
+///
+/// # Arguments
+///
+/// * `sdk_lib_dirs` – List of glob patterns for library directories
+/// * `sdk_path` - Root SDK path. Library directories are relative to this one
+/// * `format` – Format of returned directories. One of
+///     * LibDirFormat::PLAIN for a plain list
+///     * LibDirFormat::CLANG for clang style format (starting with `-L`)
+///     * LibDirFormat::MSVC for link.exe style format (starting with `/LIBPATH:`)
+///
+/// # Example
+///
+/// ```no_run
+/// use toolbelt::{get_sdk_lib_dirs, LibDirFormat};
+///
+/// let flags = get_sdk_lib_dirs(["lib/x64", "lib/vendor/*"], "/opt/sdk/", LibDirFormat::CLANG);
+/// ```
+pub fn get_sdk_lib_dirs<I>(sdk_lib_dirs: I, sdk_path: &str, format: LibDirFormat) -> Vec<String>
+where
+    I: IntoIterator,
+    I::Item: Display,
+{
+    get_sdk_lib_paths(sdk_lib_dirs, sdk_path)
+        .iter()
+        .map(|dir| format.format(dir))
+        .collect()
+}
+
+pub(crate) fn get_sdk_lib_paths<I>(sdk_lib_dirs: I, sdk_path: &str) -> Vec<PathBuf>
+where
+    I: IntoIterator,
+    I::Item: Display,
+{
+    let options = PatternOptions {
+        case_sensitive: false,
+        follow_links: true,
+        ..PatternOptions::default()
+    };
+    let expansion =
+        expand_sdk_include_dirs(sdk_lib_dirs, sdk_path, IncludeDirFormat::PLAIN, &options);
+    warn_unmatched(&expansion, sdk_path, Message::LibPatternUnmatched);
+    expansion
+        .include_dirs
+        .into_iter()
+        .map(|dir| dir.path)
+        .collect()
+}
+
+/// Like [`get_sdk_lib_dirs`], but prints a `cargo:rustc-link-search=native=<dir>` line for every
+/// library directory, so cargo passes them to the linker. Returns the directories.
+///
+/// # Example
+///
+/// ```no_run
+/// // build.rs
+/// use toolbelt::{emit_sdk_link_search, get_sdk_path};
+///
+/// let sdk_path = get_sdk_path("THE_SDK").unwrap();
+/// let sdk_path = format!("{}/", sdk_path.display());
+/// emit_sdk_link_search(["lib/x64"], &sdk_path);
+/// println!("cargo:rustc-link-lib=static=thesdk");
+/// ```
+pub fn emit_sdk_link_search<I>(sdk_lib_dirs: I, sdk_path: &str) -> Vec<PathBuf>
+where
+    I: IntoIterator,
+    I::Item: Display,
+{
+    let dirs = get_sdk_lib_paths(sdk_lib_dirs, sdk_path);
+    for dir in &dirs {
+        println!("cargo:rustc-link-search=native={}", dir.display());
+    }
+    dirs
+}
+
+/// Returns the `-F` flags for the framework search paths of an SDK. Apple SDKs ship frameworks
+/// instead of separate include and library directories, `-F` makes both their headers and their
+/// binaries available to clang.
+///
+/// Patterns may match the directories containing the frameworks (`Frameworks`) or the framework
+/// bundles themselves (`**/*.framework`), which are replaced by their parent directories.
+///
+/// # Arguments
+///
+/// * `sdk_framework_dirs` – List of glob patterns for framework directories
+/// * `sdk_path` - Root SDK path. Framework directories are relative to this one
+///
+/// # Example
+///
+/// ```no_run
+/// use toolbelt::get_framework_dirs;
+///
+/// let flags = get_framework_dirs(["Frameworks", "Extras/*.framework"], "/opt/sdk/");
+/// ```
+pub fn get_framework_dirs<I>(sdk_framework_dirs: I, sdk_path: &str) -> Vec<String>
+where
+    I: IntoIterator,
+    I::Item: Display,
+{
+    get_sdk_framework_paths(sdk_framework_dirs, sdk_path)
+        .iter()
+        .map(|dir| format!("-F{}", dir.display()))
+        .collect()
+}
+
+fn get_sdk_framework_paths<I>(sdk_framework_dirs: I, sdk_path: &str) -> Vec<PathBuf>
+where
+    I: IntoIterator,
+    I::Item: Display,
+{
+    let options = PatternOptions {
+        case_sensitive: false,
+        follow_links: true,
+        ..PatternOptions::default()
+    };
+    let expansion = expand_sdk_include_dirs(
+        sdk_framework_dirs,
+        sdk_path,
+        IncludeDirFormat::PLAIN,
+        &options,
+    );
+    warn_unmatched(&expansion, sdk_path, Message::FrameworkPatternUnmatched);
+    let mut dirs: Vec<PathBuf> = Vec::new();
+    for dir in expansion.include_dirs {
+        let dir = match dir.path.extension() {
+            Some(extension) if extension == "framework" => dir.path.parent().unwrap().to_path_buf(),
+            _ => dir.path,
+        };
+        if !dirs.contains(&dir) {
+            dirs.push(dir);
+        }
+    }
+    dirs
+}
+
+/// Like [`get_framework_dirs`], but prints a `cargo:rustc-link-arg=-F<dir>` line for every
+/// framework directory, so the linker finds the frameworks. Returns the directories.
+///
+/// # Example
+///
+/// ```no_run
+/// // build.rs
+/// use toolbelt::{emit_sdk_framework_search, get_sdk_path};
+///
+/// let sdk_path = get_sdk_path("THE_SDK").unwrap();
+/// let sdk_path = format!("{}/", sdk_path.display());
+/// emit_sdk_framework_search(["Frameworks"], &sdk_path);
+/// println!("cargo:rustc-link-lib=framework=TheSdk");
+/// ```
+pub fn emit_sdk_framework_search<I>(sdk_framework_dirs: I, sdk_path: &str) -> Vec<PathBuf>
+where
+    I: IntoIterator,
+    I::Item: Display,
+{
+    let dirs = get_sdk_framework_paths(sdk_framework_dirs, sdk_path);
+    for dir in &dirs {
+        println!("cargo:rustc-link-arg=-F{}", dir.display());
+    }
+    dirs
+}
+
+/// How preprocessor defines are formatted, see [`get_sdk_defines`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DefineFormat {
+    /// `NAME` or `NAME=VALUE`
+    PLAIN,
+    /// `-DNAME` or `-DNAME=VALUE` for clang and gcc
+    CLANG,
+    /// `/DNAME` or `/DNAME=VALUE` for cl.exe
+    MSVC,
+}
+
+impl DefineFormat {
+    /// Format a define with an optional value.
+    pub fn format(&self, name: &str, value: Option<&str>) -> String {
+        let define = match value {
+            Some(value) => format!("{}={}", name, value),
+            None => name.to_string(),
+        };
+        match self {
+            DefineFormat::PLAIN => define,
+            DefineFormat::CLANG => format!("-D{}", define),
+            DefineFormat::MSVC => format!("/D{}", define),
+        }
+    }
+}
+
+/// Returns the flags for a list of preprocessor defines. Every define is a single argument, to be
+/// passed to the compiler as is and not through a shell. Values are used verbatim, string values
+/// are best written with [`c_string_literal`].
+///
+/// # Arguments
+///
+/// * `defines` – List of names with optional values
+/// * `format` – Format of returned defines. One of
+///     * DefineFormat::PLAIN for `NAME=VALUE`
+///     * DefineFormat::CLANG for clang style format (starting with `-D`)
+///     * DefineFormat::MSVC for cl.exe style format (starting with `/D`)
+///
+/// # Example
+///
+/// ```
+/// use toolbelt::{c_string_literal, get_sdk_defines, DefineFormat};
+///
+/// let version = c_string_literal("1.2 beta");
+/// let defines = [("FOO_STATIC", None), ("FOO_VERSION", Some(version.as_str()))];
+/// assert_eq!(
+///     get_sdk_defines(&defines, DefineFormat::CLANG),
+///     ["-DFOO_STATIC", "-DFOO_VERSION=\"1.2 beta\""]
+/// );
+/// ```
+pub fn get_sdk_defines<N, V>(defines: &[(N, Option<V>)], format: DefineFormat) -> Vec<String>
+where
+    N: AsRef<str>,
+    V: AsRef<str>,
+{
+    defines
+        .iter()
+        .map(|(name, value)| format.format(name.as_ref(), value.as_ref().map(AsRef::as_ref)))
+        .collect()
+}
+
+/// Quote a string as C string literal, escaping backslashes, quotes and control characters, for
+/// defines whose value is a string.
+pub fn c_string_literal(value: &str) -> String {
+    let mut literal = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => literal.push_str("\\\""),
+            '\\' => literal.push_str("\\\\"),
+            '\n' => literal.push_str("\\n"),
+            '\r' => literal.push_str("\\r"),
+            '\t' => literal.push_str("\\t"),
+            // octal escapes end after three digits, unlike hexadecimal ones
+            c if c.is_ascii_control() => literal.push_str(&format!("\\{:03o}", c as u32)),
+            c => literal.push(c),
+        }
+    }
+    literal.push('"');
+    literal
+}
+
+/// Paths matching a glob pattern, in the order `glob` returns them: `**` matches any number of
+/// directories, a trailing `**` or `/` only directories.
+fn expand_dir_pattern(
+    sdk: &Path,
+    pattern: &str,
+    options: &PatternOptions,
+) -> Vec<Result<PathBuf, Error>> {
+    let match_options = options.match_options();
+    let pattern_path = Path::new(pattern);
+    let is_literal =
+        |c: &std::path::Component| !c.as_os_str().to_string_lossy().contains(['*', '?', '[']);
+    let base: PathBuf = pattern_path.components().take_while(is_literal).collect();
+    let components = pattern_path
+        .components()
+        .skip_while(is_literal)
+        .map(|c| glob::Pattern::new(&c.as_os_str().to_string_lossy()))
+        .collect::<Result<Vec<_>, _>>()
+        .expect("Failed to read glob pattern");
+    let require_dir =
+        pattern.ends_with('/') || components.last().is_some_and(|c| c.as_str() == "**");
+
+    let mut walker = walkdir::WalkDir::new(&base)
+        .follow_links(options.follow_links)
+        .sort_by(|a, b| a.file_name().cmp(b.file_name()));
+    if let Some(max_depth) = options.max_depth {
+        let base_depth = base
+            .strip_prefix(sdk)
+            .map_or(0, |relative| relative.components().count());
+        match max_depth.checked_sub(base_depth) {
+            Some(max_depth) => walker = walker.max_depth(max_depth),
+            None => return Vec::new(),
+        }
+    }
+
+    let names = |path: &Path| -> Vec<String> {
+        path.strip_prefix(&base)
+            .unwrap_or(path)
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy().into_owned())
+            .collect()
+    };
+    let mut paths = Vec::new();
+    let mut walker = walker.into_iter();
+    while let Some(entry) = walker.next() {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e)
+                if e.io_error()
+                    .is_some_and(|e| e.kind() == ErrorKind::NotFound) =>
+            {
+                continue
+            }
+            Err(e) => {
+                paths.push(Err(fileset::walk_error(e)));
+                continue;
+            }
+        };
+        let names = names(entry.path());
+        let is_dir = entry.file_type().is_dir();
+        if is_dir && !could_match(&components, &names, match_options) {
+            walker.skip_current_dir();
+            continue;
+        }
+        if (is_dir || !require_dir) && matches_components(&components, &names, match_options) {
+            paths.push(Ok(entry.into_path()));
+        }
+    }
+    paths
+}
+
+/// `true` if the path components match the glob pattern components.
+fn matches_components(patterns: &[glob::Pattern], names: &[String], options: MatchOptions) -> bool {
+    match patterns.split_first() {
+        None => names.is_empty(),
+        Some((pattern, rest)) if pattern.as_str() == "**" => {
+            (0..=names.len()).any(|skip| matches_components(rest, &names[skip..], options))
+        }
+        Some((pattern, rest)) => names.split_first().is_some_and(|(name, names)| {
+            pattern.matches_with(name, options) && matches_components(rest, names, options)
+        }),
+    }
+}
+
+/// `true` if paths below a directory with these components can match the pattern components.
+fn could_match(patterns: &[glob::Pattern], names: &[String], options: MatchOptions) -> bool {
+    match (patterns.split_first(), names.split_first()) {
+        (_, None) => true,
+        (None, Some(_)) => false,
+        (Some((pattern, _)), Some(_)) if pattern.as_str() == "**" => true,
+        (Some((pattern, rest)), Some((name, names))) => {
+            pattern.matches_with(name, options) && could_match(rest, names, options)
+        }
+    }
+}
+
+/// Create an SDK with a few header directories below `target/<name>`, returning the directory and
+/// the SDK path as passed to the include functions.
+#[cfg(test)]
+fn test_sdk(name: &str) -> (PathBuf, String) {
+    let root = Path::new("target").join(name);
+    let _ = std::fs::remove_dir_all(&root);
+    std::fs::create_dir_all(root.join("headers/common/gl/ext")).unwrap();
+    std::fs::create_dir_all(root.join("headers/mac/gl")).unwrap();
+    std::fs::write(root.join("headers/common/gl/gl.h"), "").unwrap();
+    let sdk_path = format!("{}/", root.canonicalize().unwrap().display());
+    (root, sdk_path)
+}
+
+#[test]
+fn test_get_sdk_include_dirs() {
+    let (root, sdk_path) = test_sdk("sdk_include_dirs");
+    let dirs = get_sdk_include_dirs(
+        ["headers/common/**", "headers/*/gl"],
+        &sdk_path,
+        IncludeDirFormat::PLAIN,
+    );
+    let relative: Vec<&str> = dirs.iter().map(|d| &d[sdk_path.len()..]).collect();
+    assert_eq!(
+        relative,
+        [
+            "headers/common",
+            "headers/common/gl",
+            "headers/common/gl/ext",
+            "headers/mac/gl",
+        ]
+    );
+    std::fs::remove_dir_all(root).unwrap();
+}
+
+#[test]
+fn test_sdk_include_dirs_max_depth() {
+    let (root, sdk_path) = test_sdk("sdk_include_dirs_max_depth");
+    let options = PatternOptions {
+        max_depth: Some(3),
+        ..PatternOptions::default()
+    };
+    assert_eq!(
+        get_sdk_include_dirs_with_options(
+            ["headers/common/**"],
+            &sdk_path,
+            IncludeDirFormat::CLANG,
+            &options
+        ),
+        [
+            format!("-I{}headers/common", sdk_path),
+            format!("-I{}headers/common/gl", sdk_path)
+        ]
+    );
+    std::fs::remove_dir_all(root).unwrap();
+}
+
+#[test]
+fn test_expand_sdk_include_dirs() {
+    let (root, sdk_path) = test_sdk("expand_sdk_include_dirs");
+    let expansion = expand_sdk_include_dirs(
+        ["headers/*/gl", "header/common/**"],
+        &sdk_path,
+        IncludeDirFormat::PLAIN,
+        &PatternOptions::default(),
+    );
+    assert_eq!(expansion.patterns[0].matches, 2);
+    assert_eq!(
+        expansion.include_dirs[1].path,
+        root.canonicalize().unwrap().join("headers/mac/gl")
+    );
+    assert_eq!(expansion.include_dirs[1].flag, expansion.dirs[1]);
+    assert_eq!(expansion.unmatched(), ["header/common/**"]);
+    assert_eq!(
+        expansion.to_string(),
+        "Expanded 2 include patterns to 2 directories, no matches for header/common/**"
+    );
+    std::fs::remove_dir_all(root).unwrap();
+}
+
+#[test]
+fn test_sdk_include_dirs_priority() {
+    let (root, sdk_path) = test_sdk("sdk_include_dirs_priority");
+    // a directory matched by an earlier pattern keeps its position
+    let prioritized = get_sdk_include_dirs(
+        ["headers/mac/gl", "headers/*/gl"],
+        &sdk_path,
+        IncludeDirFormat::PLAIN,
+    );
+    assert_eq!(
+        prioritized,
+        [
+            format!("{}headers/mac/gl", sdk_path),
+            format!("{}headers/common/gl", sdk_path)
+        ]
+    );
+    std::fs::remove_dir_all(root).unwrap();
+}
+
+#[test]
+fn test_get_sdk_include_paths() {
+    let (root, sdk_path) = test_sdk("sdk_include_paths");
+    let paths = get_sdk_include_paths(["headers/mac/*"], &sdk_path, IncludeDirFormat::CLANG);
+    assert_eq!(paths.len(), 1);
+    assert!(paths[0].path.is_dir());
+    assert_eq!(
+        paths[0].to_string(),
+        format!("-I{}headers/mac/gl", sdk_path)
+    );
+    std::fs::remove_dir_all(root).unwrap();
+}
+
+#[test]
+fn test_get_sdk_lib_dirs() {
+    let (root, sdk_path) = test_sdk("sdk_lib_dirs");
+    assert_eq!(
+        get_sdk_lib_dirs(["headers/mac/gl"], &sdk_path, LibDirFormat::MSVC),
+        [format!("/LIBPATH:{}headers/mac/gl", sdk_path)]
+    );
+    assert_eq!(
+        emit_sdk_link_search(["headers/*/gl", "lib"], &sdk_path).len(),
+        2
+    );
+    std::fs::remove_dir_all(root).unwrap();
+}
+
+#[test]
+fn test_get_sdk_defines() {
+    assert_eq!(
+        get_sdk_defines(&[("A", None), ("B", Some("1"))], DefineFormat::MSVC),
+        ["/DA", "/DB=1"]
+    );
+    assert_eq!(
+        c_string_literal("say \"hi\"\\\n\u{1}"),
+        r#""say \"hi\"\\\n\001""#
+    );
+}
+
+#[test]
+fn test_get_framework_dirs() {
+    let (root, sdk_path) = test_sdk("sdk_framework_dirs");
+    std::fs::create_dir_all(root.join("Frameworks/GL.framework/Headers")).unwrap();
+    std::fs::create_dir_all(root.join("Frameworks/AL.framework")).unwrap();
+    assert_eq!(
+        get_framework_dirs(["Frameworks/*.framework", "frameworks"], &sdk_path),
+        [format!("-F{}Frameworks", sdk_path)]
+    );
+    assert_eq!(
+        emit_sdk_framework_search(["Frameworks"], &sdk_path),
+        [root.canonicalize().unwrap().join("Frameworks")]
+    );
+    std::fs::remove_dir_all(root).unwrap();
+}
+
+#[test]
+fn test_include_dir_format() {
+    let gl = Path::new("/sdk/gl");
+    assert_eq!(IncludeDirFormat::MSVC.format(gl), "/I/sdk/gl");
+    assert_eq!(IncludeDirFormat::ISYSTEM.format(gl), "-isystem/sdk/gl");
+    assert_eq!(
+        IncludeDirFormat::Custom("-iquote".to_string()).format(gl),
+        "-iquote/sdk/gl"
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn test_sdk_include_dirs_symlinks() {
+    let (root, sdk_path) = test_sdk("sdk_include_dirs_symlinks");
+    // a link back up doesn't make the walk loop
+    std::os::unix::fs::symlink("..", root.join("headers/common/gl/ext/up")).unwrap();
+    let dirs = get_sdk_include_dirs(["headers/common/**"], &sdk_path, IncludeDirFormat::PLAIN);
+    assert_eq!(dirs.len(), 3);
 
-    incl_dirs
+    // the same directory through a symbolic link is listed once
+    std::os::unix::fs::symlink("common", root.join("headers/alias")).unwrap();
+    let dirs = get_sdk_include_dirs(
+        ["headers/common", "headers/alias"],
+        &sdk_path,
+        IncludeDirFormat::PLAIN,
+    );
+    assert_eq!(dirs, [format!("{}headers/common", sdk_path)]);
+    std::fs::remove_dir_all(root).unwrap();
 }
 
 /// Return the package name from Cargo.toml title case formatted
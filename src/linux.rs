@@ -0,0 +1,15 @@
+//! Linux counterparts of the Apple tooling: shared library dependencies and search paths of ELF
+//! binaries, desktop entries, icon themes, AppImages, and Debian and RPM packages.
+
+pub mod appimage;
+mod archive;
+pub mod deb;
+pub mod desktop;
+pub mod elf;
+pub mod icons;
+pub mod package;
+pub mod patchelf;
+pub mod rpm;
+
+pub use desktop::{desktop_entry, install_desktop_entry, DesktopEntry};
+pub use package::{FileMapping, PackageFile, PackageMetadata};
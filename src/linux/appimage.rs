@@ -0,0 +1,254 @@
+//! Packaging applications as AppImages, the Linux counterpart of [`crate::bundle`].
+//!
+//! [`AppImageBuilder`] lays out an AppDir: the executable in `usr/bin`, bundled libraries in
+//! `usr/lib`, resources in `usr/share/<executable>`, the icon in the hicolor theme below
+//! `usr/share/icons`, and the desktop entry, icon and `AppRun` launcher at the top. appimagetool
+//! then turns the AppDir into a single executable file.
+
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+
+use super::desktop::{desktop_entry, DesktopEntry};
+use super::icons::install_icons;
+use crate::bundle::install_executable;
+use crate::copy::{copy_dir_with_options, CopyOptions};
+use crate::tool::Tool;
+
+/// The architecture name appimagetool expects in `ARCH`, e.g. `x86_64` or `armhf`.
+fn appimage_arch(arch: &str) -> &str {
+    match arch {
+        "x86" | "i586" | "i686" => "i686",
+        "arm" => "armhf",
+        other => other,
+    }
+}
+
+/// The `AppRun` launcher, pointing the dynamic loader at the bundled libraries.
+fn app_run(executable: &str, libraries: bool) -> String {
+    let mut script = String::from("#!/bin/sh\nHERE=\"$(dirname \"$(readlink -f \"$0\")\")\"\n");
+    if libraries {
+        script.push_str(
+            "export LD_LIBRARY_PATH=\"$HERE/usr/lib${LD_LIBRARY_PATH:+:$LD_LIBRARY_PATH}\"\n",
+        );
+    }
+    script.push_str(&format!("exec \"$HERE/usr/bin/{}\" \"$@\"\n", executable));
+    script
+}
+
+/// Assembles an AppDir and packages it as AppImage.
+///
+/// The desktop entry's `Exec` is the name of the executable in `usr/bin`, its `Icon` the name of
+/// the icon without extension.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::path::Path;
+/// use toolbelt::linux::appimage::AppImageBuilder;
+/// use toolbelt::linux::DesktopEntry;
+///
+/// let entry = DesktopEntry {
+///     categories: vec!["Utility".to_string()],
+///     ..Default::default()
+/// };
+/// let appimage = AppImageBuilder::new(entry)
+///     .executable(Path::new("target/release/myapp"))
+///     .icon(Path::new("icons/myapp.png"))
+///     .library(Path::new("vendor/libfoo.so.1"))
+///     .resources(Path::new("resources"), "**/*")
+///     .build(Path::new("target/release"))
+///     .unwrap();
+/// ```
+pub struct AppImageBuilder {
+    entry: DesktopEntry,
+    executable: Option<PathBuf>,
+    icon: Option<PathBuf>,
+    libraries: Vec<PathBuf>,
+    resources: Vec<(PathBuf, String)>,
+}
+
+impl AppImageBuilder {
+    pub fn new(entry: DesktopEntry) -> AppImageBuilder {
+        AppImageBuilder {
+            entry,
+            executable: None,
+            icon: None,
+            libraries: Vec::new(),
+            resources: Vec::new(),
+        }
+    }
+
+    /// The executable to place in `usr/bin`, named after the desktop entry's `Exec`.
+    pub fn executable(mut self, path: &Path) -> AppImageBuilder {
+        self.executable = Some(path.to_path_buf());
+        self
+    }
+
+    /// The application icon, a `.png` or `.svg` file.
+    pub fn icon(mut self, path: &Path) -> AppImageBuilder {
+        self.icon = Some(path.to_path_buf());
+        self
+    }
+
+    /// Bundle a shared library in `usr/lib`. May be called repeatedly.
+    pub fn library(mut self, path: &Path) -> AppImageBuilder {
+        self.libraries.push(path.to_path_buf());
+        self
+    }
+
+    /// Copy files matching a glob pattern to `usr/share/<executable>`. May be called repeatedly.
+    pub fn resources(mut self, source: &Path, pattern: &str) -> AppImageBuilder {
+        self.resources
+            .push((source.to_path_buf(), pattern.to_string()));
+        self
+    }
+
+    /// The name of the executable, the first word of the desktop entry's `Exec`.
+    fn executable_name(&self) -> Result<&str, Error> {
+        self.entry
+            .exec
+            .split_whitespace()
+            .next()
+            .filter(|name| !name.contains('/'))
+            .ok_or_else(|| {
+                Error::new(
+                    ErrorKind::InvalidInput,
+                    format!(
+                        "Exec of {} must name the executable in usr/bin",
+                        self.entry.id
+                    ),
+                )
+            })
+    }
+
+    /// Create `<Name>.AppDir` inside `output_dir`, replacing an existing one, and return its
+    /// path.
+    pub fn build_app_dir(&self, output_dir: &Path) -> Result<PathBuf, Error> {
+        let name = self.executable_name()?;
+        let executable = self.executable.as_ref().ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                format!("No executable for {}", self.entry.id),
+            )
+        })?;
+        let app_dir = output_dir.join(format!("{}.AppDir", self.entry.name.replace(' ', "_")));
+        debug!("Build AppDir {}", app_dir.display());
+        if app_dir.exists() {
+            fs::remove_dir_all(&app_dir)?;
+        }
+        let bin = app_dir.join("usr/bin");
+        fs::create_dir_all(&bin)?;
+        install_executable(executable, &bin.join(name))?;
+
+        let lib = app_dir.join("usr/lib");
+        for library in &self.libraries {
+            let file_name = library.file_name().ok_or_else(|| {
+                Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("{} is not a file", library.display()),
+                )
+            })?;
+            fs::create_dir_all(&lib)?;
+            fs::copy(library, lib.join(file_name))?;
+        }
+
+        let share = app_dir.join("usr/share").join(name);
+        for (source, pattern) in &self.resources {
+            copy_dir_with_options(source, &share, pattern, &CopyOptions::default())?;
+        }
+
+        let mut entry = self.entry.clone();
+        if let Some(icon) = &self.icon {
+            let extension = icon.extension().and_then(|e| e.to_str()).unwrap_or("png");
+            let icon_name = entry.icon.clone().unwrap_or_else(|| name.to_string());
+            let icon_file = format!("{}.{}", icon_name, extension);
+            fs::copy(icon, app_dir.join(&icon_file))?;
+            #[cfg(unix)]
+            std::os::unix::fs::symlink(&icon_file, app_dir.join(".DirIcon"))?;
+            install_icons(&[icon], &icon_name, &app_dir.join("usr"))?;
+            entry.icon = Some(icon_name);
+        }
+        desktop_entry(&entry, &app_dir)?;
+
+        let launcher = app_dir.join("AppRun");
+        fs::write(&launcher, app_run(name, !self.libraries.is_empty()))?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&launcher, fs::Permissions::from_mode(0o755))?;
+        }
+        Ok(app_dir)
+    }
+
+    /// Build the AppDir inside `output_dir` and package it with appimagetool as
+    /// `<Name>-<arch>.AppImage`. Returns the path of the AppImage.
+    pub fn build(&self, output_dir: &Path) -> Result<PathBuf, Error> {
+        let app_dir = self.build_app_dir(output_dir)?;
+        let arch = std::env::var("CARGO_CFG_TARGET_ARCH")
+            .unwrap_or_else(|_| std::env::consts::ARCH.to_string());
+        let arch = appimage_arch(&arch);
+        let appimage = output_dir.join(format!(
+            "{}-{}.AppImage",
+            self.entry.name.replace(' ', "_"),
+            arch
+        ));
+        Tool::new("appimagetool")
+            .env("ARCH", arch)
+            .arg("--no-appstream")
+            .arg(&app_dir)
+            .arg(&appimage)
+            .run()
+            .map_err(Error::other)?;
+        Ok(appimage)
+    }
+}
+
+#[test]
+fn test_appimage_builder() {
+    let output = Path::new("target/appimage_builder");
+    let _ = fs::remove_dir_all(output);
+    fs::create_dir_all(output).unwrap();
+    let icon = output.join("icon.svg");
+    fs::write(&icon, "<svg/>").unwrap();
+    let entry = DesktopEntry {
+        id: "com.example.MyApp".to_string(),
+        name: "My App".to_string(),
+        comment: None,
+        exec: "myapp %F".to_string(),
+        icon: None,
+        categories: vec!["Utility".to_string()],
+        mime_types: Vec::new(),
+        terminal: false,
+    };
+    let app_dir = AppImageBuilder::new(entry.clone())
+        .executable(Path::new("test/my_files/file1.txt"))
+        .icon(&icon)
+        .library(Path::new("test/my_files/file1.txt"))
+        .resources(Path::new("test/my_files"), "*.csv")
+        .build_app_dir(output)
+        .unwrap();
+    assert_eq!(app_dir, output.join("My_App.AppDir"));
+    assert!(app_dir.join("usr/bin/myapp").is_file());
+    assert!(app_dir.join("usr/lib/file1.txt").is_file());
+    assert!(app_dir.join("myapp.svg").is_file());
+    assert!(app_dir
+        .join("usr/share/icons/hicolor/scalable/apps/myapp.svg")
+        .is_file());
+    assert!(app_dir.join("usr/share/myapp/file2.csv").is_file());
+    let desktop = fs::read_to_string(app_dir.join("com.example.MyApp.desktop")).unwrap();
+    assert!(desktop.contains("\nIcon=myapp\n"));
+    let app_run = fs::read_to_string(app_dir.join("AppRun")).unwrap();
+    assert!(app_run.contains("LD_LIBRARY_PATH=\"$HERE/usr/lib"));
+    assert!(app_run.ends_with("exec \"$HERE/usr/bin/myapp\" \"$@\"\n"));
+
+    let e = AppImageBuilder::new(DesktopEntry {
+        exec: "/usr/bin/myapp".to_string(),
+        ..entry
+    })
+    .build_app_dir(output)
+    .unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::InvalidInput);
+
+    fs::remove_dir_all(output).unwrap();
+}
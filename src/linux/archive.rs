@@ -0,0 +1,196 @@
+//! Minimal writers for the archive formats of Linux packages: ustar for the contents of Debian
+//! packages and the `ar` container around them.
+
+use std::collections::BTreeSet;
+use std::io::{Error, ErrorKind, Read, Write};
+
+const BLOCK: usize = 512;
+
+/// Write `value` as zero padded octal number terminated by NUL into `field`.
+fn octal(field: &mut [u8], value: u64) -> Result<(), Error> {
+    let text = format!("{:0width$o}", value, width = field.len() - 1);
+    if text.len() >= field.len() {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("{} does not fit into a tar header", value),
+        ));
+    }
+    field[..text.len()].copy_from_slice(text.as_bytes());
+    field[text.len()] = 0;
+    Ok(())
+}
+
+/// A ustar archive being written. Parent directories are added automatically.
+pub(crate) struct TarWriter<W: Write> {
+    out: W,
+    mtime: u64,
+    dirs: BTreeSet<String>,
+}
+
+impl<W: Write> TarWriter<W> {
+    /// All entries are recorded with modification time `mtime` and owned by root.
+    pub(crate) fn new(out: W, mtime: u64) -> TarWriter<W> {
+        TarWriter {
+            out,
+            mtime,
+            dirs: BTreeSet::new(),
+        }
+    }
+
+    fn header(&mut self, path: &str, mode: u32, size: u64, kind: u8) -> Result<(), Error> {
+        let mut header = [0u8; BLOCK];
+        let (prefix, name) = if path.len() <= 100 {
+            ("", path)
+        } else {
+            // split at a `/` so the name fits 100 and the prefix 155 bytes
+            path.char_indices()
+                .filter(|&(i, c)| c == '/' && i <= 155 && path.len() - i - 1 <= 100)
+                .map(|(i, _)| (&path[..i], &path[i + 1..]))
+                .next()
+                .ok_or_else(|| {
+                    Error::new(
+                        ErrorKind::InvalidInput,
+                        format!("{} is too long for a tar archive", path),
+                    )
+                })?
+        };
+        header[..name.len()].copy_from_slice(name.as_bytes());
+        octal(&mut header[100..108], u64::from(mode))?;
+        octal(&mut header[108..116], 0)?;
+        octal(&mut header[116..124], 0)?;
+        octal(&mut header[124..136], size)?;
+        octal(&mut header[136..148], self.mtime)?;
+        header[156] = kind;
+        header[257..263].copy_from_slice(b"ustar\0");
+        header[263..265].copy_from_slice(b"00");
+        header[265..269].copy_from_slice(b"root");
+        header[297..301].copy_from_slice(b"root");
+        header[345..345 + prefix.len()].copy_from_slice(prefix.as_bytes());
+        // the checksum is computed with the checksum field filled with spaces
+        header[148..156].copy_from_slice(b"        ");
+        let checksum: u64 = header.iter().map(|&b| u64::from(b)).sum();
+        octal(&mut header[148..155], checksum)?;
+        header[155] = b' ';
+        self.out.write_all(&header)
+    }
+
+    /// Add the directories leading to `path`, e.g. `./usr/` and `./usr/bin/` for
+    /// `./usr/bin/myapp`.
+    fn parents(&mut self, path: &str) -> Result<(), Error> {
+        let mut end = 0;
+        while let Some(i) = path[end..].find('/') {
+            end += i + 1;
+            let dir = &path[..end];
+            if dir != "./" && self.dirs.insert(dir.to_string()) {
+                self.header(dir, 0o755, 0, b'5')?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Add a file, e.g. `./usr/bin/myapp`, reading `size` bytes of contents from `data`.
+    pub(crate) fn file<R: Read>(
+        &mut self,
+        path: &str,
+        mode: u32,
+        size: u64,
+        data: &mut R,
+    ) -> Result<(), Error> {
+        self.parents(path)?;
+        self.header(path, mode, size, b'0')?;
+        let copied = std::io::copy(&mut data.take(size), &mut self.out)?;
+        if copied != size {
+            return Err(Error::new(
+                ErrorKind::UnexpectedEof,
+                format!("{} changed while it was archived", path),
+            ));
+        }
+        let padding = (BLOCK - (size as usize % BLOCK)) % BLOCK;
+        self.out.write_all(&[0; BLOCK][..padding])
+    }
+
+    /// Write the end of archive marker and return the writer.
+    pub(crate) fn finish(mut self) -> Result<W, Error> {
+        self.out.write_all(&[0; 2 * BLOCK])?;
+        Ok(self.out)
+    }
+}
+
+/// Write an `ar` archive of the given members (name, size, contents), as used by Debian
+/// packages.
+pub(crate) fn write_ar<W: Write>(
+    out: &mut W,
+    mtime: u64,
+    members: &mut [(&str, u64, &mut dyn Read)],
+) -> Result<(), Error> {
+    out.write_all(b"!<arch>\n")?;
+    for (name, size, data) in members.iter_mut() {
+        if name.len() > 16 {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("{} is too long for an ar archive", name),
+            ));
+        }
+        writeln!(
+            out,
+            "{:<16}{:<12}{:<6}{:<6}{:<8o}{:<10}`",
+            name, mtime, 0, 0, 0o100644, size
+        )?;
+        let copied = std::io::copy(&mut data.take(*size), out)?;
+        if copied != *size {
+            return Err(Error::new(
+                ErrorKind::UnexpectedEof,
+                format!("{} is shorter than expected", name),
+            ));
+        }
+        if *size % 2 == 1 {
+            out.write_all(b"\n")?;
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_archive_writers() {
+    let mut tar = TarWriter::new(Vec::new(), 1_700_000_000);
+    tar.file("./usr/bin/myapp", 0o755, 5, &mut &b"hello"[..])
+        .unwrap();
+    let long = format!("./usr/share/{}/file.txt", "x".repeat(120));
+    tar.file(&long, 0o644, 0, &mut &b""[..]).unwrap();
+    let data = tar.finish().unwrap();
+    // ./usr/, ./usr/bin/, the file and its padded contents, ...
+    assert_eq!(&data[..6], b"./usr/");
+    assert_eq!(data[156], b'5');
+    assert_eq!(&data[2 * BLOCK..2 * BLOCK + 15], b"./usr/bin/myapp");
+    assert_eq!(&data[2 * BLOCK + 124..2 * BLOCK + 136], b"00000000005\0");
+    assert_eq!(&data[3 * BLOCK..3 * BLOCK + 5], b"hello");
+    assert_eq!(data.len() % BLOCK, 0);
+    let checksum: u64 = data[..BLOCK]
+        .iter()
+        .enumerate()
+        .map(|(i, &b)| {
+            if (148..156).contains(&i) {
+                32
+            } else {
+                u64::from(b)
+            }
+        })
+        .sum();
+    let recorded = std::str::from_utf8(&data[148..154]).unwrap();
+    assert_eq!(u64::from_str_radix(recorded, 8).unwrap(), checksum);
+
+    let mut ar = Vec::new();
+    write_ar(
+        &mut ar,
+        0,
+        &mut [
+            ("debian-binary", 4, &mut &b"2.0\n"[..]),
+            ("x", 1, &mut &b"y"[..]),
+        ],
+    )
+    .unwrap();
+    assert!(ar.starts_with(
+        b"!<arch>\ndebian-binary   0           0     0     100644  4         `\n2.0\n"
+    ));
+    assert_eq!(ar.len(), 8 + 60 + 4 + 60 + 2);
+}
@@ -0,0 +1,191 @@
+//! Building Debian packages (`.deb`) without dpkg-deb.
+//!
+//! A Debian package is an `ar` archive of three members: `debian-binary` with the format
+//! version, `control.tar` with the package metadata and `data.tar` with the installed files. The
+//! tar archives are stored uncompressed, which dpkg accepts since version 1.17.6.
+
+use std::fmt::Write as _;
+use std::fs::{self, File};
+use std::io::{BufWriter, Error, Write};
+use std::path::{Path, PathBuf};
+
+use super::archive::{write_ar, TarWriter};
+#[cfg(test)]
+use super::package::test_metadata;
+use super::package::{build_time, FileMapping, PackageMetadata};
+
+/// Debian's name for a Rust architecture, e.g. `amd64` for `x86_64`.
+pub fn deb_arch(arch: &str) -> &str {
+    match arch {
+        "x86_64" => "amd64",
+        "aarch64" => "arm64",
+        "x86" | "i586" | "i686" => "i386",
+        "arm" => "armhf",
+        "powerpc64" => "ppc64el",
+        "riscv64" | "riscv64gc" => "riscv64",
+        other => other,
+    }
+}
+
+/// The Debian version: pre-releases sort before the release (`1.2.3~beta.1`), followed by the
+/// packaging revision.
+fn deb_version(metadata: &PackageMetadata) -> String {
+    format!(
+        "{}-{}",
+        metadata.version.replace('-', "~"),
+        metadata.release
+    )
+}
+
+/// The `control` file.
+fn control(metadata: &PackageMetadata, installed_size: u64) -> String {
+    let mut control = String::new();
+    let _ = writeln!(control, "Package: {}", metadata.name);
+    let _ = writeln!(control, "Version: {}", deb_version(metadata));
+    let _ = writeln!(control, "Architecture: {}", deb_arch(&metadata.arch));
+    let _ = writeln!(control, "Maintainer: {}", metadata.maintainer);
+    let _ = writeln!(control, "Installed-Size: {}", installed_size.div_ceil(1024));
+    if !metadata.depends.is_empty() {
+        let _ = writeln!(control, "Depends: {}", metadata.depends.join(", "));
+    }
+    control.push_str("Section: misc\nPriority: optional\n");
+    if let Some(homepage) = &metadata.homepage {
+        let _ = writeln!(control, "Homepage: {}", homepage);
+    }
+    let _ = writeln!(control, "Description: {}", metadata.summary);
+    if metadata.description.trim() != metadata.summary.trim() {
+        for line in metadata.description.trim().lines() {
+            if line.trim().is_empty() {
+                control.push_str(" .\n");
+            } else {
+                let _ = writeln!(control, " {}", line);
+            }
+        }
+    }
+    control
+}
+
+/// Build `<name>_<version>_<arch>.deb` in `output_dir` and return its path.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::path::Path;
+/// use toolbelt::linux::deb::build_deb;
+/// use toolbelt::linux::{FileMapping, PackageMetadata};
+///
+/// let files = FileMapping::new()
+///     .executable(Path::new("target/release/myapp"), "/usr/bin/myapp");
+/// let mut metadata = PackageMetadata::from_cargo_env().unwrap();
+/// metadata.depends.push("libc6 (>= 2.31)".to_string());
+/// build_deb(&files, &metadata, Path::new("target/release")).unwrap();
+/// ```
+pub fn build_deb(
+    files: &FileMapping,
+    metadata: &PackageMetadata,
+    output_dir: &Path,
+) -> Result<PathBuf, Error> {
+    metadata.validate()?;
+    files.validate()?;
+    fs::create_dir_all(output_dir)?;
+    let deb = output_dir.join(format!(
+        "{}_{}_{}.deb",
+        metadata.name,
+        deb_version(metadata),
+        deb_arch(&metadata.arch)
+    ));
+    debug!("Build {} with {} files", deb.display(), files.files.len());
+    let mtime = build_time();
+
+    let mut sorted: Vec<_> = files.files.iter().collect();
+    sorted.sort_by(|a, b| a.destination.cmp(&b.destination));
+    let data_path = deb.with_extension("data.tar");
+    let mut installed_size = 0;
+    let result = (|| {
+        let mut data = TarWriter::new(BufWriter::new(File::create(&data_path)?), mtime);
+        for file in sorted {
+            let mut source = File::open(&file.source)?;
+            let size = source.metadata()?.len();
+            installed_size += size;
+            data.file(
+                &format!(".{}", file.destination),
+                file.mode,
+                size,
+                &mut source,
+            )?;
+        }
+        data.finish()?.flush()?;
+
+        let mut control_tar = TarWriter::new(Vec::new(), mtime);
+        let control = control(metadata, installed_size);
+        control_tar.file(
+            "./control",
+            0o644,
+            control.len() as u64,
+            &mut control.as_bytes(),
+        )?;
+        let control_tar = control_tar.finish()?;
+
+        let mut data = File::open(&data_path)?;
+        let data_size = data.metadata()?.len();
+        let mut out = BufWriter::new(File::create(&deb)?);
+        write_ar(
+            &mut out,
+            mtime,
+            &mut [
+                ("debian-binary", 4, &mut &b"2.0\n"[..]),
+                (
+                    "control.tar",
+                    control_tar.len() as u64,
+                    &mut control_tar.as_slice(),
+                ),
+                ("data.tar", data_size, &mut data),
+            ],
+        )?;
+        out.flush()
+    })();
+    let _ = fs::remove_file(&data_path);
+    result?;
+    Ok(deb)
+}
+
+#[test]
+fn test_build_deb() {
+    let output = Path::new("target/build_deb");
+    let _ = fs::remove_dir_all(output);
+    let files = FileMapping::new()
+        .executable(Path::new("test/my_files/file1.txt"), "/usr/bin/myapp")
+        .dir(Path::new("test/my_files"), "*.csv", "/usr/share/myapp")
+        .unwrap();
+    let metadata = PackageMetadata {
+        version: "1.2.3-beta.1".to_string(),
+        description: "An app\n\nthat does things.".to_string(),
+        depends: vec!["libc6".to_string()],
+        ..test_metadata()
+    };
+    let control = control(&metadata, 2000);
+    assert!(control.contains("Version: 1.2.3~beta.1-1\nArchitecture: amd64\n"));
+    assert!(control.contains("Installed-Size: 2\nDepends: libc6\n"));
+    assert!(control.ends_with("Description: An app\n An app\n .\n that does things.\n"));
+
+    let deb = build_deb(&files, &metadata, output).unwrap();
+    assert_eq!(deb, output.join("my-app_1.2.3~beta.1-1_amd64.deb"));
+    let data = fs::read(&deb).unwrap();
+    assert!(data.starts_with(b"!<arch>\ndebian-binary   "));
+    let find = |needle: &[u8]| data.windows(needle.len()).any(|w| w == needle);
+    assert!(find(b"control.tar     "));
+    assert!(find(b"./usr/share/myapp/file2.csv"));
+    assert!(find(b"Package: my-app\n"));
+    assert!(!output.join("my-app_1.2.3~beta.1-1_amd64.data.tar").exists());
+
+    #[cfg(unix)]
+    if let Ok(output) = crate::tool::Tool::new("dpkg-deb")
+        .arg("--info")
+        .arg(&deb)
+        .run()
+    {
+        assert!(output.stdout.contains("Package: my-app"));
+    }
+
+    fs::remove_dir_all(output).unwrap();
+}
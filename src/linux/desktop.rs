@@ -0,0 +1,203 @@
+//! Desktop entries (`.desktop` files) announcing an application to Linux desktop environments.
+//!
+//! The files follow the freedesktop.org Desktop Entry Specification 1.5. They are written next to
+//! the other packaging inputs or installed for the current user below
+//! `$XDG_DATA_HOME/applications`.
+
+use std::env;
+use std::ffi::OsString;
+use std::fmt::Write;
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+
+use inflector::cases::titlecase::to_title_case;
+
+/// Contents of a desktop entry of type `Application`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DesktopEntry {
+    /// Desktop file id, the file name without `.desktop`, e.g. `com.example.MyApp`
+    pub id: String,
+    /// Name shown in menus, defaults to the package name title cased like
+    /// [`crate::get_name_from_cargo`]
+    pub name: String,
+    /// Tooltip, defaults to the package description
+    pub comment: Option<String>,
+    /// Command line, e.g. `myapp %F`
+    pub exec: String,
+    /// Icon name from the icon theme or an absolute path
+    pub icon: Option<String>,
+    /// Registered categories, e.g. `Graphics`, `Utility`
+    pub categories: Vec<String>,
+    /// MIME types the application opens, e.g. `image/png`
+    pub mime_types: Vec<String>,
+    /// Run in a terminal
+    pub terminal: bool,
+}
+
+impl Default for DesktopEntry {
+    fn default() -> Self {
+        let package = env::var("CARGO_PKG_NAME").unwrap_or_default();
+        DesktopEntry {
+            id: package.clone(),
+            name: to_title_case(&package),
+            comment: env::var("CARGO_PKG_DESCRIPTION")
+                .ok()
+                .filter(|d| !d.is_empty()),
+            exec: package.clone(),
+            icon: (!package.is_empty()).then_some(package),
+            categories: Vec::new(),
+            mime_types: Vec::new(),
+            terminal: false,
+        }
+    }
+}
+
+/// Escape a string value: `\s`, `\n`, `\t`, `\r` and `\\`.
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for (i, c) in value.chars().enumerate() {
+        match c {
+            ' ' if i == 0 => escaped.push_str("\\s"),
+            '\n' => escaped.push_str("\\n"),
+            '\t' => escaped.push_str("\\t"),
+            '\r' => escaped.push_str("\\r"),
+            '\\' => escaped.push_str("\\\\"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+impl DesktopEntry {
+    fn validate(&self) -> Result<(), Error> {
+        let invalid = |message: String| Err(Error::new(ErrorKind::InvalidInput, message));
+        if self.id.is_empty() || self.id.contains(['/', '\\']) {
+            return invalid(format!("Invalid desktop file id \"{}\"", self.id));
+        }
+        if self.name.is_empty() {
+            return invalid(format!("{}.desktop lacks a name", self.id));
+        }
+        if self.exec.is_empty() {
+            return invalid(format!("{}.desktop lacks a command line", self.id));
+        }
+        if let Some(item) = self
+            .categories
+            .iter()
+            .chain(&self.mime_types)
+            .find(|item| item.is_empty() || item.contains([';', '\n']))
+        {
+            return invalid(format!("Invalid list item \"{}\"", item));
+        }
+        Ok(())
+    }
+
+    /// The desktop entry file contents.
+    pub fn to_desktop_file(&self) -> String {
+        let mut file = String::from("[Desktop Entry]\nType=Application\nVersion=1.5\n");
+        let _ = writeln!(file, "Name={}", escape(&self.name));
+        if let Some(comment) = &self.comment {
+            let _ = writeln!(file, "Comment={}", escape(comment));
+        }
+        let _ = writeln!(file, "Exec={}", escape(&self.exec));
+        if let Some(icon) = &self.icon {
+            let _ = writeln!(file, "Icon={}", escape(icon));
+        }
+        let _ = writeln!(file, "Terminal={}", self.terminal);
+        for (key, list) in [
+            ("Categories", &self.categories),
+            ("MimeType", &self.mime_types),
+        ] {
+            if !list.is_empty() {
+                let _ = writeln!(file, "{}={};", key, list.join(";"));
+            }
+        }
+        file
+    }
+}
+
+/// Write `<id>.desktop` into `dir` and return its path.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::path::Path;
+/// use toolbelt::linux::{desktop_entry, DesktopEntry};
+///
+/// let entry = DesktopEntry {
+///     id: "com.example.MyApp".to_string(),
+///     exec: "myapp %F".to_string(),
+///     categories: vec!["Graphics".to_string()],
+///     mime_types: vec!["image/png".to_string()],
+///     ..Default::default()
+/// };
+/// desktop_entry(&entry, Path::new("target/package")).unwrap();
+/// ```
+pub fn desktop_entry(entry: &DesktopEntry, dir: &Path) -> Result<PathBuf, Error> {
+    entry.validate()?;
+    fs::create_dir_all(dir)?;
+    let path = dir.join(format!("{}.desktop", entry.id));
+    fs::write(&path, entry.to_desktop_file())?;
+    Ok(path)
+}
+
+/// `$XDG_DATA_HOME/applications`, falling back to `~/.local/share/applications`.
+fn applications_dir(xdg_data_home: Option<OsString>, home: Option<OsString>) -> Option<PathBuf> {
+    let data_home = match xdg_data_home.filter(|dir| Path::new(dir).is_absolute()) {
+        Some(dir) => PathBuf::from(dir),
+        None => Path::new(&home?).join(".local/share"),
+    };
+    Some(data_home.join("applications"))
+}
+
+/// Install the desktop entry for the current user below `$XDG_DATA_HOME/applications`.
+pub fn install_desktop_entry(entry: &DesktopEntry) -> Result<PathBuf, Error> {
+    let dir = applications_dir(env::var_os("XDG_DATA_HOME"), env::var_os("HOME"))
+        .ok_or_else(|| Error::new(ErrorKind::NotFound, "Neither XDG_DATA_HOME nor HOME is set"))?;
+    let path = desktop_entry(entry, &dir)?;
+    info!("Installed {}", path.display());
+    Ok(path)
+}
+
+#[test]
+fn test_desktop_entry() {
+    let root = Path::new("target/desktop_entry");
+    let _ = fs::remove_dir_all(root);
+
+    let mut entry = DesktopEntry {
+        id: "com.example.MyApp".to_string(),
+        name: "My App".to_string(),
+        comment: Some(" Edit\nimages".to_string()),
+        exec: "myapp %F".to_string(),
+        icon: Some("com.example.MyApp".to_string()),
+        categories: vec!["Graphics".to_string(), "Viewer".to_string()],
+        mime_types: vec!["image/png".to_string()],
+        terminal: false,
+    };
+    let path = desktop_entry(&entry, root).unwrap();
+    assert_eq!(path, root.join("com.example.MyApp.desktop"));
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        "[Desktop Entry]\nType=Application\nVersion=1.5\nName=My App\n\
+         Comment=\\sEdit\\nimages\nExec=myapp %F\nIcon=com.example.MyApp\nTerminal=false\n\
+         Categories=Graphics;Viewer;\nMimeType=image/png;\n"
+    );
+
+    entry.categories.push("Bad;Category".to_string());
+    assert_eq!(
+        desktop_entry(&entry, root).unwrap_err().kind(),
+        ErrorKind::InvalidInput
+    );
+
+    assert_eq!(
+        applications_dir(None, Some("/home/me".into())),
+        Some(PathBuf::from("/home/me/.local/share/applications"))
+    );
+    assert_eq!(
+        applications_dir(Some("/data".into()), Some("/home/me".into())),
+        Some(PathBuf::from("/data/applications"))
+    );
+    assert_eq!(applications_dir(None, None), None);
+
+    fs::remove_dir_all(root).unwrap();
+}
@@ -0,0 +1,397 @@
+//! Minimal reader for ELF binaries, the Linux counterpart of [`crate::macho`].
+//!
+//! Only the dynamic section is decoded: the libraries a binary needs, its soname and its search
+//! paths. It is located through the program headers, so stripped binaries can be read too.
+
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+
+const ELF_MAGIC: &[u8] = b"\x7fELF";
+
+const PT_LOAD: u32 = 1;
+const PT_DYNAMIC: u32 = 2;
+
+const DT_NULL: u64 = 0;
+const DT_NEEDED: u64 = 1;
+const DT_STRTAB: u64 = 5;
+const DT_SONAME: u64 = 14;
+const DT_RPATH: u64 = 15;
+const DT_RUNPATH: u64 = 29;
+
+pub const EM_386: u16 = 3;
+pub const EM_ARM: u16 = 40;
+pub const EM_X86_64: u16 = 62;
+pub const EM_AARCH64: u16 = 183;
+pub const EM_RISCV: u16 = 243;
+
+/// The dynamic linking information of an ELF file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ElfFile {
+    /// `true` for 64-bit binaries
+    pub is_64: bool,
+    /// Target machine (`e_machine`), e.g. [`EM_X86_64`]
+    pub machine: u16,
+    /// Name of a shared library (`DT_SONAME`)
+    pub soname: Option<String>,
+    /// Sonames of the needed libraries (`DT_NEEDED`), in load order
+    pub needed: Vec<String>,
+    /// Search path searched before `LD_LIBRARY_PATH` (`DT_RPATH`)
+    pub rpath: Option<String>,
+    /// Search path searched after `LD_LIBRARY_PATH` (`DT_RUNPATH`)
+    pub runpath: Option<String>,
+}
+
+impl ElfFile {
+    /// Architecture name as used by Rust (e.g. `x86_64`, `aarch64`).
+    pub fn arch_name(&self) -> String {
+        match self.machine {
+            EM_386 => "x86".to_string(),
+            EM_ARM => "arm".to_string(),
+            EM_X86_64 => "x86_64".to_string(),
+            EM_AARCH64 => "aarch64".to_string(),
+            EM_RISCV if self.is_64 => "riscv64".to_string(),
+            EM_RISCV => "riscv32".to_string(),
+            machine => format!("machine{}", machine),
+        }
+    }
+}
+
+fn invalid(what: &str) -> Error {
+    Error::new(
+        ErrorKind::InvalidData,
+        format!("Invalid ELF file: {}", what),
+    )
+}
+
+/// `offset + length`, failing for offsets beyond the address space instead of wrapping around.
+fn checked_offset(offset: u64, length: u64) -> Result<u64, Error> {
+    offset
+        .checked_add(length)
+        .ok_or_else(|| invalid("offset out of range"))
+}
+
+/// Reads fields of the file's class and byte order.
+struct Reader<'a> {
+    data: &'a [u8],
+    is_64: bool,
+    big_endian: bool,
+}
+
+impl Reader<'_> {
+    fn bytes<const N: usize>(&self, offset: u64) -> Result<[u8; N], Error> {
+        let offset = usize::try_from(offset).map_err(|_| invalid("truncated"))?;
+        let end = offset.checked_add(N).ok_or_else(|| invalid("truncated"))?;
+        self.data
+            .get(offset..end)
+            .and_then(|b| b.try_into().ok())
+            .ok_or_else(|| invalid("truncated"))
+    }
+
+    fn u16(&self, offset: u64) -> Result<u16, Error> {
+        let bytes = self.bytes(offset)?;
+        Ok(if self.big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        })
+    }
+
+    fn u32(&self, offset: u64) -> Result<u32, Error> {
+        let bytes = self.bytes(offset)?;
+        Ok(if self.big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        })
+    }
+
+    /// A word of the file's class: 8 bytes in 64-bit files, 4 bytes in 32-bit files.
+    fn word(&self, offset: u64) -> Result<u64, Error> {
+        if !self.is_64 {
+            return self.u32(offset).map(u64::from);
+        }
+        let bytes = self.bytes(offset)?;
+        Ok(if self.big_endian {
+            u64::from_be_bytes(bytes)
+        } else {
+            u64::from_le_bytes(bytes)
+        })
+    }
+
+    /// The NUL terminated string at `offset`.
+    fn string(&self, offset: u64) -> Result<String, Error> {
+        let bytes = usize::try_from(offset)
+            .ok()
+            .and_then(|offset| self.data.get(offset..))
+            .ok_or_else(|| invalid("string outside of file"))?;
+        let end = bytes
+            .iter()
+            .position(|b| *b == 0)
+            .ok_or_else(|| invalid("unterminated string"))?;
+        Ok(String::from_utf8_lossy(&bytes[..end]).into_owned())
+    }
+}
+
+/// Check whether `data` starts like an ELF file.
+pub fn is_elf(data: &[u8]) -> bool {
+    data.starts_with(ELF_MAGIC)
+}
+
+/// Decode the dynamic linking information of an ELF file. Statically linked binaries have no
+/// needed libraries.
+pub fn parse(data: &[u8]) -> Result<ElfFile, Error> {
+    if !is_elf(data) || data.len() < 16 {
+        return Err(invalid("unknown magic"));
+    }
+    let r = Reader {
+        data,
+        is_64: match data[4] {
+            1 => false,
+            2 => true,
+            _ => return Err(invalid("unknown class")),
+        },
+        big_endian: match data[5] {
+            1 => false,
+            2 => true,
+            _ => return Err(invalid("unknown byte order")),
+        },
+    };
+    let machine = r.u16(18)?;
+    let (phoff, phentsize, phnum) = if r.is_64 {
+        (r.word(32)?, r.u16(54)?, r.u16(56)?)
+    } else {
+        (r.word(28)?, r.u16(42)?, r.u16(44)?)
+    };
+
+    // (offset, vaddr, filesz) of the loaded segments, to map addresses to file offsets
+    let mut loads = Vec::new();
+    let mut dynamic = None;
+    for i in 0..u64::from(phnum) {
+        let header = checked_offset(phoff, i * u64::from(phentsize))?;
+        let kind = r.u32(header)?;
+        let (offset, vaddr, filesz) = if r.is_64 {
+            (
+                r.word(checked_offset(header, 8)?)?,
+                r.word(checked_offset(header, 16)?)?,
+                r.word(checked_offset(header, 32)?)?,
+            )
+        } else {
+            (
+                r.word(checked_offset(header, 4)?)?,
+                r.word(checked_offset(header, 8)?)?,
+                r.word(checked_offset(header, 16)?)?,
+            )
+        };
+        match kind {
+            PT_LOAD => {
+                checked_offset(vaddr, filesz)?;
+                loads.push((offset, vaddr, filesz));
+            }
+            PT_DYNAMIC => dynamic = Some((offset, filesz)),
+            _ => {}
+        }
+    }
+
+    let mut file = ElfFile {
+        is_64: r.is_64,
+        machine,
+        soname: None,
+        needed: Vec::new(),
+        rpath: None,
+        runpath: None,
+    };
+    let Some((dynamic, size)) = dynamic else {
+        return Ok(file);
+    };
+    let entry_size = if r.is_64 { 16 } else { 8 };
+    let mut entries = Vec::new();
+    let mut strtab = None;
+    for i in 0..size / entry_size {
+        let entry = checked_offset(dynamic, i * entry_size)?;
+        let tag = r.word(entry)?;
+        let value = r.word(checked_offset(entry, entry_size / 2)?)?;
+        match tag {
+            DT_NULL => break,
+            DT_STRTAB => strtab = Some(value),
+            DT_NEEDED | DT_SONAME | DT_RPATH | DT_RUNPATH => entries.push((tag, value)),
+            _ => {}
+        }
+    }
+    if entries.is_empty() {
+        return Ok(file);
+    }
+    let strtab = strtab.ok_or_else(|| invalid("dynamic section without string table"))?;
+    let (offset, vaddr, _) = loads
+        .iter()
+        // the segment ends were checked when reading the program headers
+        .find(|(_, vaddr, filesz)| (*vaddr..vaddr + filesz).contains(&strtab))
+        .ok_or_else(|| invalid("string table outside of loaded segments"))?;
+    let strtab = checked_offset(strtab - vaddr, *offset)?;
+    for (tag, value) in entries {
+        let string = r.string(checked_offset(strtab, value)?)?;
+        match tag {
+            DT_NEEDED => file.needed.push(string),
+            DT_SONAME => file.soname = Some(string),
+            DT_RPATH => file.rpath = Some(string),
+            _ => file.runpath = Some(string),
+        }
+    }
+    Ok(file)
+}
+
+/// Decode the dynamic linking information of an ELF file.
+pub fn read(path: &Path) -> Result<ElfFile, Error> {
+    parse(&fs::read(path)?).map_err(|e| Error::new(e.kind(), format!("{}: {}", path.display(), e)))
+}
+
+/// The sonames of the shared libraries a binary needs, e.g. `libc.so.6` or `libfoo.so.1`.
+///
+/// Only the direct dependencies are listed, as recorded in the binary; they are resolved by the
+/// dynamic loader at run time. Use [`is_system_library`] to find those that have to be bundled.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::path::Path;
+/// use toolbelt::linux::elf::{is_system_library, list_so_dependencies};
+///
+/// for library in list_so_dependencies(Path::new("target/release/mytool")).unwrap() {
+///     if !is_system_library(&library) {
+///         println!("needs bundling: {}", library);
+///     }
+/// }
+/// ```
+pub fn list_so_dependencies(binary: &Path) -> Result<Vec<String>, Error> {
+    Ok(read(binary)?.needed)
+}
+
+/// `true` for the libraries of the C runtime and the dynamic loader, which every Linux
+/// distribution provides and which must not be bundled.
+pub fn is_system_library(soname: &str) -> bool {
+    let name = soname.split(".so").next().unwrap_or(soname);
+    matches!(
+        name,
+        "libc"
+            | "libm"
+            | "libdl"
+            | "libpthread"
+            | "librt"
+            | "libutil"
+            | "libresolv"
+            | "libgcc_s"
+            | "linux-vdso"
+            | "linux-gate"
+    ) || name.starts_with("ld-linux")
+}
+
+/// Build a little-endian 64-bit ELF file with a dynamic section, loaded at `0x1000`.
+#[cfg(test)]
+fn test_elf(soname: Option<&str>, needed: &[&str], runpath: Option<&str>) -> Vec<u8> {
+    let mut strings = vec![0u8];
+    let mut dynamic = Vec::new();
+    let mut add = |tag: u64, value: &str| {
+        dynamic.push((tag, strings.len() as u64));
+        strings.extend(value.as_bytes());
+        strings.push(0);
+    };
+    if let Some(soname) = soname {
+        add(DT_SONAME, soname);
+    }
+    for library in needed {
+        add(DT_NEEDED, library);
+    }
+    if let Some(runpath) = runpath {
+        add(DT_RUNPATH, runpath);
+    }
+    // header, two program headers, the string table and the dynamic section
+    let strtab = 64 + 2 * 56;
+    let dynamic_offset = (strtab + strings.len()).next_multiple_of(8);
+    dynamic.push((DT_STRTAB, 0x1000 + strtab as u64));
+    dynamic.push((DT_NULL, 0));
+    let end = dynamic_offset + dynamic.len() * 16;
+
+    let mut data = Vec::new();
+    data.extend(ELF_MAGIC);
+    data.extend([2, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+    data.extend(3u16.to_le_bytes());
+    data.extend(EM_X86_64.to_le_bytes());
+    data.extend(1u32.to_le_bytes());
+    for value in [0u64, 64, 0] {
+        data.extend(value.to_le_bytes());
+    }
+    data.extend(0u32.to_le_bytes());
+    for value in [64u16, 56, 2, 64, 0, 0] {
+        data.extend(value.to_le_bytes());
+    }
+    for (kind, offset, size) in [
+        (PT_LOAD, 0, end),
+        (PT_DYNAMIC, dynamic_offset, end - dynamic_offset),
+    ] {
+        data.extend(kind.to_le_bytes());
+        data.extend(4u32.to_le_bytes());
+        for value in [offset, 0x1000 + offset, 0x1000 + offset, size, size, 8] {
+            data.extend((value as u64).to_le_bytes());
+        }
+    }
+    data.extend(&strings);
+    data.resize(dynamic_offset, 0);
+    for (tag, value) in dynamic {
+        data.extend(tag.to_le_bytes());
+        data.extend(value.to_le_bytes());
+    }
+    data
+}
+
+#[test]
+fn test_list_so_dependencies() {
+    let root = Path::new("target/list_so_dependencies");
+    let _ = fs::remove_dir_all(root);
+    fs::create_dir_all(root).unwrap();
+    let library = root.join("libfoo.so.1");
+    fs::write(
+        &library,
+        test_elf(
+            Some("libfoo.so.1"),
+            &["libbar.so.2", "libc.so.6"],
+            Some("$ORIGIN"),
+        ),
+    )
+    .unwrap();
+
+    let elf = read(&library).unwrap();
+    assert_eq!(elf.arch_name(), "x86_64");
+    assert_eq!(elf.soname.as_deref(), Some("libfoo.so.1"));
+    assert_eq!(elf.runpath.as_deref(), Some("$ORIGIN"));
+    assert_eq!(elf.rpath, None);
+    let needed = list_so_dependencies(&library).unwrap();
+    assert_eq!(needed, ["libbar.so.2", "libc.so.6"]);
+    assert_eq!(
+        needed
+            .iter()
+            .filter(|l| !is_system_library(l))
+            .collect::<Vec<_>>(),
+        ["libbar.so.2"]
+    );
+    assert!(is_system_library("ld-linux-x86-64.so.2"));
+
+    fs::write(root.join("text"), "#!/bin/sh").unwrap();
+    assert!(read(&root.join("text")).is_err());
+
+    // offsets and sizes running past the end of the address space
+    let mut elf = test_elf(None, &["libbar.so.2"], None);
+    elf[32..40].copy_from_slice(&u64::MAX.to_le_bytes());
+    assert_eq!(parse(&elf).unwrap_err().kind(), ErrorKind::InvalidData);
+    let mut elf = test_elf(None, &["libbar.so.2"], None);
+    // file size of the loaded segment
+    elf[64 + 32..64 + 40].copy_from_slice(&u64::MAX.to_le_bytes());
+    assert_eq!(parse(&elf).unwrap_err().kind(), ErrorKind::InvalidData);
+
+    // the test executable itself links the C library
+    #[cfg(all(target_os = "linux", target_env = "gnu"))]
+    assert!(list_so_dependencies(&std::env::current_exe().unwrap())
+        .unwrap()
+        .contains(&"libc.so.6".to_string()));
+
+    fs::remove_dir_all(root).unwrap();
+}
@@ -0,0 +1,177 @@
+//! Installing application icons into the hicolor icon theme, the fallback theme every Linux
+//! desktop looks up application icons in.
+//!
+//! PNG icons go to `share/icons/hicolor/<size>x<size>/apps/<name>.png`, SVG icons to
+//! `share/icons/hicolor/scalable/apps/<name>.svg`.
+
+use std::fs::{self, File};
+use std::io::{Error, ErrorKind, Read};
+use std::path::{Path, PathBuf};
+
+use super::package::FileMapping;
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+/// Read the size of a square PNG icon from its header.
+fn png_size(path: &Path) -> Result<u32, Error> {
+    let mut header = [0u8; 24];
+    File::open(path)?.read_exact(&mut header).map_err(|_| {
+        Error::new(
+            ErrorKind::InvalidData,
+            format!("{}: not a PNG image", path.display()),
+        )
+    })?;
+    // The IHDR chunk follows the signature: length, type, width, height
+    if !header.starts_with(PNG_SIGNATURE) || &header[12..16] != b"IHDR" {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("{}: not a PNG image", path.display()),
+        ));
+    }
+    let width = u32::from_be_bytes([header[16], header[17], header[18], header[19]]);
+    let height = u32::from_be_bytes([header[20], header[21], header[22], header[23]]);
+    if width != height {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("{}: {}x{} is not square", path.display(), width, height),
+        ));
+    }
+    Ok(width)
+}
+
+/// The path of an icon in the hicolor theme, relative to the prefix, e.g.
+/// `share/icons/hicolor/48x48/apps/myapp.png` for a 48x48 PNG named `myapp`.
+pub fn hicolor_path(icon: &Path, name: &str) -> Result<String, Error> {
+    match icon.extension().and_then(|e| e.to_str()) {
+        Some("png") => {
+            let size = png_size(icon)?;
+            Ok(format!(
+                "share/icons/hicolor/{0}x{0}/apps/{1}.png",
+                size, name
+            ))
+        }
+        Some("svg") => Ok(format!("share/icons/hicolor/scalable/apps/{}.svg", name)),
+        _ => Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("{} is neither a PNG nor an SVG icon", icon.display()),
+        )),
+    }
+}
+
+/// The hicolor paths of several icons, failing if two of them end up at the same path.
+fn hicolor_paths<P: AsRef<Path>>(icons: &[P], name: &str) -> Result<Vec<String>, Error> {
+    let mut paths: Vec<String> = Vec::new();
+    for icon in icons {
+        let path = hicolor_path(icon.as_ref(), name)?;
+        if paths.contains(&path) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("Several icons for {}", path),
+            ));
+        }
+        paths.push(path);
+    }
+    Ok(paths)
+}
+
+/// Copy icons named `name` into the hicolor layout below `prefix`, e.g. a staging directory's
+/// `usr`, and return the installed paths.
+///
+/// # Arguments
+///
+/// * `icons` - PNG icons of different sizes and optionally an SVG icon
+/// * `name` - The icon name, as used in the `Icon` key of the desktop entry
+/// * `prefix` - Directory containing `share`
+///
+/// # Example
+///
+/// ```no_run
+/// use std::path::Path;
+/// use toolbelt::linux::icons::install_icons;
+///
+/// let installed = install_icons(
+///     &["icons/icon_48.png", "icons/icon_256.png", "icons/icon.svg"],
+///     "myapp",
+///     Path::new("target/staging/usr"),
+/// )
+/// .unwrap();
+/// ```
+pub fn install_icons<P: AsRef<Path>>(
+    icons: &[P],
+    name: &str,
+    prefix: &Path,
+) -> Result<Vec<PathBuf>, Error> {
+    let paths = hicolor_paths(icons, name)?;
+    let mut installed = Vec::new();
+    for (icon, path) in icons.iter().zip(paths) {
+        let destination = prefix.join(path);
+        if let Some(parent) = destination.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::copy(icon, &destination)?;
+        installed.push(destination);
+    }
+    Ok(installed)
+}
+
+impl FileMapping {
+    /// Install icons named `name` into the hicolor theme below `/usr/share/icons`.
+    pub fn icons<P: AsRef<Path>>(self, icons: &[P], name: &str) -> Result<FileMapping, Error> {
+        let paths = hicolor_paths(icons, name)?;
+        Ok(icons.iter().zip(paths).fold(self, |files, (icon, path)| {
+            files.file(icon.as_ref(), &format!("/usr/{}", path))
+        }))
+    }
+}
+
+#[test]
+fn test_install_icons() {
+    let root = Path::new("target/install_icons");
+    let _ = fs::remove_dir_all(root);
+    fs::create_dir_all(root).unwrap();
+    // Only the header is read, so a signature and an IHDR chunk make a PNG good enough
+    let png = |name: &str, width: u32, height: u32| {
+        let mut data = PNG_SIGNATURE.to_vec();
+        data.extend_from_slice(&13u32.to_be_bytes());
+        data.extend_from_slice(b"IHDR");
+        data.extend_from_slice(&width.to_be_bytes());
+        data.extend_from_slice(&height.to_be_bytes());
+        data.extend_from_slice(&[8, 6, 0, 0, 0, 0, 0, 0, 0]);
+        let path = root.join(name);
+        fs::write(&path, data).unwrap();
+        path
+    };
+    let icons = [
+        png("icon_48.png", 48, 48),
+        png("icon_256.png", 256, 256),
+        root.join("icon.svg"),
+    ];
+    fs::write(&icons[2], "<svg/>").unwrap();
+
+    let prefix = root.join("usr");
+    let installed = install_icons(&icons, "myapp", &prefix).unwrap();
+    assert_eq!(
+        installed,
+        [
+            prefix.join("share/icons/hicolor/48x48/apps/myapp.png"),
+            prefix.join("share/icons/hicolor/256x256/apps/myapp.png"),
+            prefix.join("share/icons/hicolor/scalable/apps/myapp.svg"),
+        ]
+    );
+    assert!(installed.iter().all(|path| path.is_file()));
+
+    let files = FileMapping::new().icons(&icons, "myapp").unwrap();
+    assert_eq!(
+        files.files[1].destination,
+        "/usr/share/icons/hicolor/256x256/apps/myapp.png"
+    );
+
+    let e = install_icons(&[png("wide.png", 48, 32)], "myapp", &prefix).unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::InvalidData);
+    let e = install_icons(&[&icons[0], &png("other.png", 48, 48)], "myapp", &prefix).unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::InvalidInput);
+    let e = hicolor_path(Path::new("icon.ico"), "myapp").unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::InvalidInput);
+
+    fs::remove_dir_all(root).unwrap();
+}
@@ -0,0 +1,296 @@
+//! The declarative description shared by the Linux package builders: which files go where
+//! ([`FileMapping`]) and what the package is ([`PackageMetadata`]).
+
+use std::env;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+
+use crate::fileset::{FileSet, PatternOptions};
+use crate::manifest::Manifest;
+
+/// A file to install.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PackageFile {
+    pub source: PathBuf,
+    /// Absolute installation path, e.g. `/usr/bin/myapp`
+    pub destination: String,
+    /// Permissions, e.g. `0o755` for executables
+    pub mode: u32,
+}
+
+/// The files a package installs, mapped from the build tree to their installation paths.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::path::Path;
+/// use toolbelt::linux::FileMapping;
+///
+/// let files = FileMapping::new()
+///     .executable(Path::new("target/release/myapp"), "/usr/bin/myapp")
+///     .file(Path::new("myapp.desktop"), "/usr/share/applications/myapp.desktop")
+///     .dir(Path::new("resources"), "**/*", "/usr/share/myapp")
+///     .unwrap();
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FileMapping {
+    pub files: Vec<PackageFile>,
+}
+
+/// Join an installation directory and a relative path, normalizing separators.
+fn install_path(dir: &str, relative: &str) -> String {
+    let path = format!("/{}/{}", dir, relative.replace('\\', "/"));
+    let components: Vec<&str> = path.split('/').filter(|c| !c.is_empty()).collect();
+    format!("/{}", components.join("/"))
+}
+
+impl FileMapping {
+    pub fn new() -> FileMapping {
+        FileMapping::default()
+    }
+
+    /// Install a file with mode `0644`.
+    pub fn file(mut self, source: &Path, destination: &str) -> FileMapping {
+        self.files.push(PackageFile {
+            source: source.to_path_buf(),
+            destination: install_path(destination, ""),
+            mode: 0o644,
+        });
+        self
+    }
+
+    /// Install an executable with mode `0755`.
+    pub fn executable(mut self, source: &Path, destination: &str) -> FileMapping {
+        self.files.push(PackageFile {
+            source: source.to_path_buf(),
+            destination: install_path(destination, ""),
+            mode: 0o755,
+        });
+        self
+    }
+
+    /// Install the files below `source` matching a glob pattern into `destination`, keeping
+    /// their relative paths.
+    pub fn dir(
+        self,
+        source: &Path,
+        pattern: &str,
+        destination: &str,
+    ) -> Result<FileMapping, Error> {
+        self.dir_with_options(source, pattern, destination, &PatternOptions::default())
+    }
+
+    /// Like [`FileMapping::dir`], with the matching and the walk configured, e.g. a maximum
+    /// depth.
+    pub fn dir_with_options(
+        mut self,
+        source: &Path,
+        pattern: &str,
+        destination: &str,
+        options: &PatternOptions,
+    ) -> Result<FileMapping, Error> {
+        for entry in FileSet::new(source)
+            .pattern(pattern)
+            .options(options.clone())
+        {
+            let entry = entry?;
+            if entry.file_type.is_file() {
+                self.files.push(PackageFile {
+                    destination: install_path(
+                        destination,
+                        &crate::manifest::manifest_path(&entry.relative_path),
+                    ),
+                    source: entry.path,
+                    mode: 0o644,
+                });
+            }
+        }
+        Ok(self)
+    }
+
+    /// Install the files listed in a manifest, relative to `root`, into `destination`.
+    pub fn from_manifest(root: &Path, manifest: &Manifest, destination: &str) -> FileMapping {
+        FileMapping {
+            files: manifest
+                .entries
+                .iter()
+                .map(|entry| PackageFile {
+                    source: root.join(&entry.path),
+                    destination: install_path(destination, &entry.path),
+                    mode: 0o644,
+                })
+                .collect(),
+        }
+    }
+
+    /// Fail if there are no files or two files are installed to the same path.
+    pub(crate) fn validate(&self) -> Result<(), Error> {
+        if self.files.is_empty() {
+            return Err(Error::new(ErrorKind::InvalidInput, "No files to package"));
+        }
+        let mut destinations: Vec<&str> =
+            self.files.iter().map(|f| f.destination.as_str()).collect();
+        destinations.sort_unstable();
+        if let Some(pair) = destinations.windows(2).find(|pair| pair[0] == pair[1]) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("Several files are installed to {}", pair[0]),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// What a package is, for the package manager.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PackageMetadata {
+    /// Package name, lower case, e.g. `my-app`
+    pub name: String,
+    /// Upstream version, e.g. `1.2.3`
+    pub version: String,
+    /// Packaging revision, starting at 1
+    pub release: u32,
+    /// One line summary
+    pub summary: String,
+    /// Longer description, may span several lines
+    pub description: String,
+    /// `Name <email>`
+    pub maintainer: String,
+    pub homepage: Option<String>,
+    /// SPDX license expression
+    pub license: Option<String>,
+    /// Packages this one depends on, in the syntax of the package format
+    pub depends: Vec<String>,
+    /// Target architecture in Rust's naming, e.g. `x86_64`
+    pub arch: String,
+}
+
+impl PackageMetadata {
+    /// Metadata of the package being built, from the environment cargo sets for build scripts:
+    /// `CARGO_PKG_NAME`, `CARGO_PKG_VERSION`, `CARGO_PKG_DESCRIPTION`, the first of
+    /// `CARGO_PKG_AUTHORS` as maintainer, `CARGO_PKG_HOMEPAGE`, `CARGO_PKG_LICENSE` and
+    /// `CARGO_CFG_TARGET_ARCH`.
+    pub fn from_cargo_env() -> Result<PackageMetadata, Error> {
+        let var = |name: &str| {
+            env::var(name).map_err(|_| {
+                Error::new(
+                    ErrorKind::NotFound,
+                    format!("{} is not set, call this from a build script", name),
+                )
+            })
+        };
+        let optional = |name: &str| env::var(name).ok().filter(|value| !value.is_empty());
+        let description = optional("CARGO_PKG_DESCRIPTION").unwrap_or_default();
+        Ok(PackageMetadata {
+            name: var("CARGO_PKG_NAME")?.to_lowercase().replace('_', "-"),
+            version: var("CARGO_PKG_VERSION")?,
+            release: 1,
+            summary: description.lines().next().unwrap_or_default().to_string(),
+            description,
+            maintainer: optional("CARGO_PKG_AUTHORS")
+                .and_then(|authors| authors.split(':').next().map(str::to_string))
+                .unwrap_or_default(),
+            homepage: optional("CARGO_PKG_HOMEPAGE"),
+            license: optional("CARGO_PKG_LICENSE"),
+            depends: Vec::new(),
+            arch: env::var("CARGO_CFG_TARGET_ARCH")
+                .unwrap_or_else(|_| env::consts::ARCH.to_string()),
+        })
+    }
+
+    /// Fail unless the fields every package format needs are set.
+    pub(crate) fn validate(&self) -> Result<(), Error> {
+        let invalid = |message: String| Err(Error::new(ErrorKind::InvalidInput, message));
+        if self.name.len() < 2
+            || !self.name.starts_with(|c: char| c.is_ascii_alphanumeric())
+            || !self
+                .name
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || "+-.".contains(c))
+        {
+            return invalid(format!("Invalid package name \"{}\"", self.name));
+        }
+        if self.version.is_empty() || self.version.contains(char::is_whitespace) {
+            return invalid(format!("Invalid version \"{}\"", self.version));
+        }
+        if self.summary.is_empty() {
+            return invalid(format!("{} lacks a summary", self.name));
+        }
+        if self.maintainer.is_empty() {
+            return invalid(format!("{} lacks a maintainer", self.name));
+        }
+        Ok(())
+    }
+}
+
+/// Seconds since the epoch to record as modification time: `SOURCE_DATE_EPOCH` for reproducible
+/// builds, or now.
+pub(crate) fn build_time() -> u64 {
+    env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|epoch| epoch.parse().ok())
+        .unwrap_or_else(|| {
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0)
+        })
+}
+
+/// Metadata of a test package, `my-app` 1.2.3 for x86_64 without dependencies.
+#[cfg(test)]
+pub(crate) fn test_metadata() -> PackageMetadata {
+    PackageMetadata {
+        name: "my-app".to_string(),
+        version: "1.2.3".to_string(),
+        release: 1,
+        summary: "An app".to_string(),
+        description: "An app".to_string(),
+        maintainer: "Jane Doe <jane@example.com>".to_string(),
+        homepage: None,
+        license: None,
+        depends: Vec::new(),
+        arch: "x86_64".to_string(),
+    }
+}
+
+#[test]
+fn test_file_mapping() {
+    let files = FileMapping::new()
+        .executable(Path::new("target/release/myapp"), "/usr/bin/myapp")
+        .dir(Path::new("test/my_files"), "**/*", "usr/share/myapp/")
+        .unwrap();
+    assert_eq!(files.files[0].destination, "/usr/bin/myapp");
+    assert_eq!(files.files[0].mode, 0o755);
+    assert!(files
+        .files
+        .iter()
+        .any(|f| f.destination == "/usr/share/myapp/file2.csv"));
+    assert!(files.validate().is_ok());
+    let shallow = FileMapping::new()
+        .dir_with_options(
+            Path::new("test/my_files"),
+            "**/*",
+            "usr/share/myapp",
+            &PatternOptions {
+                max_depth: Some(1),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    assert!(shallow
+        .files
+        .iter()
+        .all(|f| !f.destination.contains("more_files")));
+    assert_eq!(shallow.files.len(), 2);
+    let files = files.file(Path::new("other.csv"), "/usr/share/myapp/file2.csv");
+    assert_eq!(
+        files.validate().unwrap_err().kind(),
+        ErrorKind::InvalidInput
+    );
+
+    let mut metadata = test_metadata();
+    assert!(metadata.validate().is_ok());
+    metadata.name = "My_App".to_string();
+    assert!(metadata.validate().is_err());
+}
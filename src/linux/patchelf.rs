@@ -0,0 +1,122 @@
+//! Editing the search paths and dependencies of ELF binaries with patchelf, the Linux counterpart
+//! of [`crate::install_name`].
+//!
+//! Shared libraries bundled with an application must be found relative to the executable, not in
+//! the system directories. That means giving the executable a search path like `$ORIGIN/../lib`,
+//! where `$ORIGIN` is expanded by the dynamic loader to the directory of the binary.
+
+use std::path::{Component, Path};
+
+use crate::tool::{Tool, ToolError};
+
+/// Search path of an executable in `bin` pointing at the sibling `lib` directory.
+pub const ORIGIN_LIB_RPATH: &str = "$ORIGIN/../lib";
+
+/// The `$ORIGIN` relative search path leading from the directory of a binary to a library
+/// directory, e.g. `$ORIGIN/../lib` from `usr/bin` to `usr/lib`.
+///
+/// Both directories must be relative to the same root, e.g. an AppDir or a package staging
+/// directory.
+pub fn origin_rpath(binary_dir: &Path, library_dir: &Path) -> String {
+    let components = |path: &Path| -> Vec<String> {
+        path.components()
+            .filter_map(|c| match c {
+                Component::Normal(name) => Some(name.to_string_lossy().into_owned()),
+                _ => None,
+            })
+            .collect()
+    };
+    let from = components(binary_dir);
+    let to = components(library_dir);
+    let common = from.iter().zip(&to).take_while(|(a, b)| a == b).count();
+    let mut rpath = String::from("$ORIGIN");
+    for _ in common..from.len() {
+        rpath.push_str("/..");
+    }
+    for component in &to[common..] {
+        rpath.push('/');
+        rpath.push_str(component);
+    }
+    rpath
+}
+
+fn patchelf(binary: &Path, args: &[&str]) -> Result<(), ToolError> {
+    Tool::new("patchelf").args(args).arg(binary).run()?;
+    Ok(())
+}
+
+/// Replace the search path of a binary with `DT_RUNPATH`, which `LD_LIBRARY_PATH` takes
+/// precedence over. Several directories are separated by `:`.
+pub fn set_runpath(binary: &Path, runpath: &str) -> Result<(), ToolError> {
+    patchelf(binary, &["--set-rpath", runpath])
+}
+
+/// Replace the search path of a binary with `DT_RPATH`, which takes precedence over
+/// `LD_LIBRARY_PATH` and also applies to the dependencies of the binary's dependencies.
+pub fn set_rpath(binary: &Path, rpath: &str) -> Result<(), ToolError> {
+    patchelf(binary, &["--force-rpath", "--set-rpath", rpath])
+}
+
+/// Append a directory to the search path of a binary, keeping the existing ones.
+pub fn add_rpath(binary: &Path, rpath: &str) -> Result<(), ToolError> {
+    patchelf(binary, &["--add-rpath", rpath])
+}
+
+/// Remove the search path of a binary, both `DT_RPATH` and `DT_RUNPATH`.
+pub fn remove_rpath(binary: &Path) -> Result<(), ToolError> {
+    patchelf(binary, &["--remove-rpath"])
+}
+
+/// Set the soname (`DT_SONAME`) of a shared library, e.g. to `libfoo.so.1`.
+pub fn set_soname(library: &Path, soname: &str) -> Result<(), ToolError> {
+    patchelf(library, &["--set-soname", soname])
+}
+
+/// Change a library a binary needs.
+///
+/// # Arguments
+///
+/// * `binary` - the executable or library needing the dependency
+/// * `old` - the dependency recorded in the binary, e.g. `libfoo.so.1`
+/// * `new` - the library to load instead, e.g. `libfoo-bundled.so.1`
+///
+/// # Example
+///
+/// ```no_run
+/// use std::path::Path;
+/// use toolbelt::linux::patchelf::{replace_needed, set_runpath, ORIGIN_LIB_RPATH};
+///
+/// let executable = Path::new("target/MyApp.AppDir/usr/bin/myapp");
+/// replace_needed(executable, "libfoo.so", "libfoo.so.1").unwrap();
+/// set_runpath(executable, ORIGIN_LIB_RPATH).unwrap();
+/// ```
+pub fn replace_needed(binary: &Path, old: &str, new: &str) -> Result<(), ToolError> {
+    patchelf(binary, &["--replace-needed", old, new])
+}
+
+#[test]
+fn test_patchelf() {
+    assert_eq!(
+        origin_rpath(Path::new("usr/bin"), Path::new("usr/lib")),
+        ORIGIN_LIB_RPATH
+    );
+    assert_eq!(
+        origin_rpath(Path::new("/opt/myapp"), Path::new("/opt/myapp/lib/x86_64")),
+        "$ORIGIN/lib/x86_64"
+    );
+    assert_eq!(origin_rpath(Path::new("lib"), Path::new("lib")), "$ORIGIN");
+
+    // patchelf is rarely installed, so only check the edit if it is
+    #[cfg(target_os = "linux")]
+    if Tool::new("patchelf").arg("--version").run().is_ok() {
+        let root = Path::new("target/patchelf");
+        let _ = std::fs::remove_dir_all(root);
+        std::fs::create_dir_all(root).unwrap();
+        let binary = root.join("binary");
+        std::fs::copy(std::env::current_exe().unwrap(), &binary).unwrap();
+        set_runpath(&binary, ORIGIN_LIB_RPATH).unwrap();
+        let elf = super::elf::read(&binary).unwrap();
+        assert_eq!(elf.runpath.as_deref(), Some(ORIGIN_LIB_RPATH));
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,175 @@
+//! Building RPM packages with rpmbuild.
+//!
+//! The spec file is generated from the same [`FileMapping`] and [`PackageMetadata`] as Debian
+//! packages ([`super::deb::build_deb`]): its `%install` section copies the mapped files into the
+//! build root and `%files` lists them with their permissions.
+
+use std::fmt::Write as _;
+use std::fs;
+use std::io::Error;
+use std::path::{Path, PathBuf};
+
+#[cfg(test)]
+use super::package::test_metadata;
+use super::package::{FileMapping, PackageMetadata};
+use crate::copy::absolute_path;
+use crate::tool::Tool;
+
+/// RPM's name for a Rust architecture, e.g. `armv7hl` for `arm`.
+pub fn rpm_arch(arch: &str) -> &str {
+    match arch {
+        "x86" | "i586" | "i686" => "i686",
+        "arm" => "armv7hl",
+        "powerpc64" => "ppc64le",
+        "riscv64gc" => "riscv64",
+        other => other,
+    }
+}
+
+/// The RPM version, which can't contain `-`: pre-releases sort before the release
+/// (`1.2.3~beta.1`).
+fn rpm_version(version: &str) -> String {
+    version.replace('-', "~")
+}
+
+/// Quote a path for the shell commands of the spec file.
+fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('%', "%%").replace('\'', "'\\''"))
+}
+
+/// The spec file; `files` must have absolute sources.
+fn spec(files: &FileMapping, metadata: &PackageMetadata) -> String {
+    let mut spec = String::from(
+        "%global debug_package %{nil}\n%global _build_id_links none\n\
+         %global __os_install_post %{nil}\n",
+    );
+    let _ = writeln!(spec, "Name: {}", metadata.name);
+    let _ = writeln!(spec, "Version: {}", rpm_version(&metadata.version));
+    let _ = writeln!(spec, "Release: {}", metadata.release);
+    let _ = writeln!(spec, "Summary: {}", metadata.summary);
+    let _ = writeln!(
+        spec,
+        "License: {}",
+        metadata.license.as_deref().unwrap_or("Proprietary")
+    );
+    if let Some(homepage) = &metadata.homepage {
+        let _ = writeln!(spec, "URL: {}", homepage);
+    }
+    let _ = writeln!(spec, "Packager: {}", metadata.maintainer);
+    for depend in &metadata.depends {
+        let _ = writeln!(spec, "Requires: {}", depend);
+    }
+    let _ = writeln!(spec, "\n%description\n{}", metadata.description.trim());
+
+    spec.push_str("\n%install\n");
+    for file in &files.files {
+        let _ = writeln!(
+            spec,
+            "install -D -m {:04o} {} \"%{{buildroot}}\"{}",
+            file.mode,
+            shell_quote(&file.source.to_string_lossy()),
+            shell_quote(&file.destination)
+        );
+    }
+    spec.push_str("\n%files\n");
+    for file in &files.files {
+        let _ = writeln!(
+            spec,
+            "%attr({:04o},root,root) \"{}\"",
+            file.mode,
+            file.destination.replace('%', "%%")
+        );
+    }
+    spec
+}
+
+/// Build `<name>-<version>-<release>.<arch>.rpm` in `output_dir` with rpmbuild and return its
+/// path. The spec file is written next to it.
+///
+/// Dependencies in [`PackageMetadata::depends`] are taken as `Requires`, in RPM syntax, e.g.
+/// `glibc >= 2.31`.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::path::Path;
+/// use toolbelt::linux::rpm::build_rpm;
+/// use toolbelt::linux::{FileMapping, PackageMetadata};
+///
+/// let files = FileMapping::new()
+///     .executable(Path::new("target/release/myapp"), "/usr/bin/myapp");
+/// let metadata = PackageMetadata::from_cargo_env().unwrap();
+/// build_rpm(&files, &metadata, Path::new("target/release")).unwrap();
+/// ```
+pub fn build_rpm(
+    files: &FileMapping,
+    metadata: &PackageMetadata,
+    output_dir: &Path,
+) -> Result<PathBuf, Error> {
+    metadata.validate()?;
+    files.validate()?;
+    fs::create_dir_all(output_dir)?;
+    let output_dir = absolute_path(output_dir)?;
+    let mut absolute = files.clone();
+    for file in &mut absolute.files {
+        file.source = absolute_path(&file.source)?;
+    }
+    let arch = rpm_arch(&metadata.arch);
+    let name = format!(
+        "{}-{}-{}.{}",
+        metadata.name,
+        rpm_version(&metadata.version),
+        metadata.release,
+        arch
+    );
+    let spec_path = output_dir.join(format!("{}.spec", name));
+    fs::write(&spec_path, spec(&absolute, metadata))?;
+
+    let top_dir = output_dir.join(format!("{}.rpmbuild", name));
+    debug!("Build {}.rpm with {} files", name, files.files.len());
+    let result = Tool::new("rpmbuild")
+        .arg("-bb")
+        .args(["--target", arch])
+        .arg("--define")
+        .arg(format!("_topdir {}", top_dir.display()))
+        .arg("--define")
+        .arg(format!("_rpmdir {}", output_dir.display()))
+        .args([
+            "--define",
+            "_build_name_fmt %%{NAME}-%%{VERSION}-%%{RELEASE}.%%{ARCH}.rpm",
+        ])
+        .arg(&spec_path)
+        .run()
+        .map_err(Error::other);
+    let _ = fs::remove_dir_all(&top_dir);
+    result?;
+    Ok(output_dir.join(format!("{}.rpm", name)))
+}
+
+#[test]
+fn test_rpm_spec() {
+    let files = FileMapping::new()
+        .executable(Path::new("/build/my app"), "/usr/bin/myapp")
+        .file(Path::new("/build/it's.txt"), "/usr/share/myapp/100%.txt");
+    let metadata = PackageMetadata {
+        version: "1.2.3-beta.1".to_string(),
+        release: 2,
+        description: "An app\nthat does things.".to_string(),
+        homepage: Some("https://example.com".to_string()),
+        license: Some("MIT".to_string()),
+        depends: vec!["glibc >= 2.31".to_string()],
+        ..test_metadata()
+    };
+    let spec = spec(&files, &metadata);
+    assert!(spec.contains("Name: my-app\nVersion: 1.2.3~beta.1\nRelease: 2\n"));
+    assert!(spec.contains("License: MIT\nURL: https://example.com\n"));
+    assert!(spec.contains("Requires: glibc >= 2.31\n"));
+    assert!(spec.contains("\n%description\nAn app\nthat does things.\n"));
+    assert!(spec.contains("install -D -m 0755 '/build/my app' \"%{buildroot}\"'/usr/bin/myapp'\n"));
+    assert!(spec.contains("install -D -m 0644 '/build/it'\\''s.txt'"));
+    assert!(spec.ends_with(
+        "%files\n%attr(0755,root,root) \"/usr/bin/myapp\"\n\
+         %attr(0644,root,root) \"/usr/share/myapp/100%%.txt\"\n"
+    ));
+    assert_eq!(rpm_arch("arm"), "armv7hl");
+}
@@ -0,0 +1,105 @@
+//! Merging binaries built for different architectures into universal binaries.
+
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+
+use crate::macho;
+use crate::xcrun;
+
+/// The architectures contained in a Mach-O binary, e.g. `["x86_64", "arm64"]`.
+///
+/// Like `lipo -archs`, but the binary is read directly so this works without Xcode.
+pub fn lipo_info(path: &Path) -> Result<Vec<String>, Error> {
+    Ok(macho::read(path)?
+        .iter()
+        .map(macho::Slice::arch_name)
+        .collect())
+}
+
+/// Merge binaries for different architectures into one universal binary using lipo from Xcode.
+///
+/// The inputs are checked first: they must be Mach-O files and may not contain an architecture
+/// twice.
+///
+/// # Arguments
+///
+/// * `inputs` - the binaries to merge, thin or universal
+/// * `output` - the universal binary to write
+///
+/// # Example
+///
+/// ```no_run
+/// use std::path::Path;
+/// use toolbelt::lipo_create;
+///
+/// lipo_create(
+///     &[
+///         Path::new("target/x86_64-apple-darwin/release/mytool"),
+///         Path::new("target/aarch64-apple-darwin/release/mytool"),
+///     ],
+///     Path::new("target/universal/mytool"),
+/// )
+/// .unwrap();
+/// ```
+pub fn lipo_create<P: AsRef<Path>>(inputs: &[P], output: &Path) -> Result<(), Error> {
+    let mut archs: Vec<(String, &Path)> = Vec::new();
+    for input in inputs {
+        let input = input.as_ref();
+        for arch in lipo_info(input)? {
+            if let Some((_, other)) = archs.iter().find(|(a, _)| *a == arch) {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!(
+                        "{} and {} both contain {}",
+                        other.display(),
+                        input.display(),
+                        arch
+                    ),
+                ));
+            }
+            archs.push((arch, input));
+        }
+    }
+
+    if let Some(parent) = output.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    xcrun::tool("lipo", None)
+        .map_err(Error::other)?
+        .arg("-create")
+        .args(inputs.iter().map(AsRef::as_ref))
+        .arg("-output")
+        .arg(output)
+        .run()
+        .map_err(Error::other)?;
+    debug!(
+        "Created universal binary {} ({})",
+        output.display(),
+        archs
+            .iter()
+            .map(|(a, _)| a.as_str())
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+    Ok(())
+}
+
+#[test]
+fn test_lipo() {
+    let root = Path::new("target/lipo");
+    let _ = std::fs::remove_dir_all(root);
+    std::fs::create_dir_all(root).unwrap();
+    let x86 = macho::test_binary(macho::CPU_TYPE_X86_64, [1; 16]);
+    let arm = macho::test_binary(macho::CPU_TYPE_ARM64, [2; 16]);
+    std::fs::write(root.join("x86_64"), &x86).unwrap();
+    std::fs::write(root.join("arm64"), &arm).unwrap();
+    std::fs::write(root.join("fat"), macho::test_universal(&[x86, arm])).unwrap();
+
+    assert_eq!(lipo_info(&root.join("fat")).unwrap(), ["x86_64", "arm64"]);
+    let error =
+        lipo_create(&[root.join("fat"), root.join("arm64")], &root.join("out")).unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::InvalidInput);
+    assert!(error.to_string().ends_with("both contain arm64"));
+
+    std::fs::remove_dir_all(root).unwrap();
+}
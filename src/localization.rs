@@ -0,0 +1,154 @@
+//! Localized `InfoPlist.strings` files.
+//!
+//! Localized values for `Info.plist` keys (the display name, usage descriptions shown in
+//! permission prompts, …) live in `<language>.lproj/InfoPlist.strings` inside the bundle's
+//! resources. App Store review checks these for every localization the app declares.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+
+use crate::toml;
+
+/// Localized Info.plist values: language (e.g. `en`, `de`, `pt-BR`) to key to value.
+pub type LocalizedStrings = BTreeMap<String, BTreeMap<String, String>>;
+
+/// File name of the localized Info.plist values inside an `.lproj` folder.
+pub const INFO_PLIST_STRINGS: &str = "InfoPlist.strings";
+
+/// Read localized Info.plist values from a TOML file with one table per language.
+///
+/// ```toml
+/// [en]
+/// CFBundleDisplayName = "Recorder"
+/// NSMicrophoneUsageDescription = "Records audio from the microphone."
+///
+/// [de]
+/// CFBundleDisplayName = "Rekorder"
+/// NSMicrophoneUsageDescription = "Nimmt Audio über das Mikrofon auf."
+/// ```
+///
+/// # Arguments
+///
+/// * `path` - the TOML file
+pub fn read_localized_strings(path: &Path) -> Result<LocalizedStrings, Error> {
+    let root = toml::parse(&fs::read_to_string(path)?)
+        .map_err(|e| Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+
+    let mut strings = LocalizedStrings::new();
+    for (language, table) in root {
+        let table = table.as_table().ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("{}: {} is not a table", path.display(), language),
+            )
+        })?;
+        let mut values = BTreeMap::new();
+        for (key, value) in table {
+            let value = value.as_str().ok_or_else(|| {
+                Error::new(
+                    ErrorKind::InvalidData,
+                    format!("{}: {}.{} is not a string", path.display(), language, key),
+                )
+            })?;
+            values.insert(key.clone(), value.to_string());
+        }
+        strings.insert(language, values);
+    }
+    Ok(strings)
+}
+
+/// Render a `.strings` file (`"key" = "value";` per line).
+pub fn strings_file_contents(values: &BTreeMap<String, String>) -> String {
+    let mut out = String::new();
+    for (key, value) in values {
+        out.push_str(&format!("\"{}\" = \"{}\";\n", escape(key), escape(value)));
+    }
+    out
+}
+
+fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Write `<language>.lproj/InfoPlist.strings` for every language into a bundle's resources.
+///
+/// Files are UTF-8 encoded, which Xcode and the App Store accept. Returns the written files.
+///
+/// # Arguments
+///
+/// * `strings` - the localized values per language
+/// * `resources_dir` - the bundle's resources directory (e.g. `App.app/Contents/Resources`)
+///
+/// # Example
+///
+/// ```no_run
+/// use std::path::Path;
+/// use toolbelt::localization::{read_localized_strings, write_info_plist_strings};
+///
+/// let strings = read_localized_strings(Path::new("InfoPlist.toml")).unwrap();
+/// write_info_plist_strings(&strings, Path::new("target/Recorder.app/Contents/Resources")).unwrap();
+/// ```
+pub fn write_info_plist_strings(
+    strings: &LocalizedStrings,
+    resources_dir: &Path,
+) -> Result<Vec<PathBuf>, Error> {
+    let mut written = Vec::new();
+    for (language, values) in strings {
+        if language.is_empty() || language.contains(['/', '\\', '.']) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("Invalid language identifier '{}'", language),
+            ));
+        }
+        let lproj = resources_dir.join(format!("{}.lproj", language));
+        fs::create_dir_all(&lproj)?;
+        let path = lproj.join(INFO_PLIST_STRINGS);
+        debug!("Writing {}", path.display());
+        fs::write(&path, strings_file_contents(values))?;
+        written.push(path);
+    }
+    Ok(written)
+}
+
+#[test]
+fn test_write_info_plist_strings() {
+    let root = Path::new("target/info_plist_strings");
+    let _ = fs::remove_dir_all(root);
+    fs::create_dir_all(root).unwrap();
+    fs::write(
+        root.join("strings.toml"),
+        "[en]\nCFBundleDisplayName = \"Recorder\"\n\n[de]\nCFBundleDisplayName = \"\\\"Rekorder\\\"\"\nNSMicrophoneUsageDescription = \"Nimmt Audio auf.\"\n",
+    )
+    .unwrap();
+
+    let strings = read_localized_strings(&root.join("strings.toml")).unwrap();
+    let written = write_info_plist_strings(&strings, &root.join("Resources")).unwrap();
+    assert_eq!(
+        written,
+        [
+            root.join("Resources/de.lproj/InfoPlist.strings"),
+            root.join("Resources/en.lproj/InfoPlist.strings")
+        ]
+    );
+    assert_eq!(
+        fs::read_to_string(&written[0]).unwrap(),
+        "\"CFBundleDisplayName\" = \"\\\"Rekorder\\\"\";\n\"NSMicrophoneUsageDescription\" = \"Nimmt Audio auf.\";\n"
+    );
+
+    fs::write(root.join("bad.toml"), "en = \"flat\"\n").unwrap();
+    assert!(read_localized_strings(&root.join("bad.toml")).is_err());
+
+    fs::remove_dir_all(root).unwrap();
+}
@@ -0,0 +1,510 @@
+//! Minimal reader for Mach-O binaries, including universal (fat) files.
+//!
+//! Only the headers and load commands toolbelt needs are decoded, so no Xcode tools are required
+//! to inspect binaries.
+
+use std::fmt;
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+
+use crate::pipeline::verification_failure;
+
+const FAT_MAGIC: u32 = 0xcafe_babe;
+const FAT_MAGIC_64: u32 = 0xcafe_babf;
+const MH_MAGIC: u32 = 0xfeed_face;
+const MH_MAGIC_64: u32 = 0xfeed_facf;
+const MH_CIGAM: u32 = 0xcefa_edfe;
+const MH_CIGAM_64: u32 = 0xcffa_edfe;
+
+const LC_UUID: u32 = 0x1b;
+const LC_LOAD_DYLIB: u32 = 0xc;
+const LC_ID_DYLIB: u32 = 0xd;
+const LC_LOAD_WEAK_DYLIB: u32 = 0x8000_0018;
+const LC_RPATH: u32 = 0x8000_001c;
+const LC_REEXPORT_DYLIB: u32 = 0x8000_001f;
+const LC_LAZY_LOAD_DYLIB: u32 = 0x20;
+const LC_LOAD_UPWARD_DYLIB: u32 = 0x8000_0023;
+
+pub const CPU_TYPE_X86: u32 = 7;
+pub const CPU_TYPE_X86_64: u32 = 0x0100_0007;
+pub const CPU_TYPE_ARM: u32 = 12;
+pub const CPU_TYPE_ARM64: u32 = 0x0100_000c;
+
+/// The UUID identifying a Mach-O build, shared by a binary and its dSYM.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Uuid(pub [u8; 16]);
+
+impl fmt::Display for Uuid {
+    /// Uppercase and hyphenated, as printed by `dwarfdump --uuid`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, byte) in self.0.iter().enumerate() {
+            if matches!(i, 4 | 6 | 8 | 10) {
+                f.write_str("-")?;
+            }
+            write!(f, "{:02X}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+/// One architecture of a Mach-O file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Slice {
+    pub cpu_type: u32,
+    pub cpu_subtype: u32,
+    pub uuid: Option<Uuid>,
+    /// Install name of a dynamic library (`LC_ID_DYLIB`)
+    pub install_name: Option<String>,
+    /// Install names of the linked libraries, in load order
+    pub dylibs: Vec<String>,
+    /// Runtime search paths (`LC_RPATH`)
+    pub rpaths: Vec<String>,
+}
+
+impl Slice {
+    /// Architecture name as used by lipo and Xcode (e.g. `arm64`, `x86_64`).
+    pub fn arch_name(&self) -> String {
+        let subtype = self.cpu_subtype & 0x00ff_ffff;
+        match (self.cpu_type, subtype) {
+            (CPU_TYPE_X86_64, 8) => "x86_64h".to_string(),
+            (CPU_TYPE_X86_64, _) => "x86_64".to_string(),
+            (CPU_TYPE_X86, _) => "i386".to_string(),
+            (CPU_TYPE_ARM64, 2) => "arm64e".to_string(),
+            (CPU_TYPE_ARM64, _) => "arm64".to_string(),
+            (CPU_TYPE_ARM, 9) => "armv7".to_string(),
+            (CPU_TYPE_ARM, 11) => "armv7s".to_string(),
+            (CPU_TYPE_ARM, _) => "arm".to_string(),
+            (cpu_type, subtype) => format!("cpu{}/{}", cpu_type, subtype),
+        }
+    }
+}
+
+fn invalid(what: &str) -> Error {
+    Error::new(
+        ErrorKind::InvalidData,
+        format!("Invalid Mach-O file: {}", what),
+    )
+}
+
+/// `offset + length`, failing for offsets beyond the address space instead of wrapping around.
+fn checked_offset(offset: usize, length: usize) -> Result<usize, Error> {
+    offset
+        .checked_add(length)
+        .ok_or_else(|| invalid("offset out of range"))
+}
+
+fn read_u32(data: &[u8], offset: usize, big_endian: bool) -> Result<u32, Error> {
+    let bytes: [u8; 4] = data
+        .get(offset..checked_offset(offset, 4)?)
+        .and_then(|b| b.try_into().ok())
+        .ok_or_else(|| invalid("truncated"))?;
+    Ok(if big_endian {
+        u32::from_be_bytes(bytes)
+    } else {
+        u32::from_le_bytes(bytes)
+    })
+}
+
+fn read_u64(data: &[u8], offset: usize, big_endian: bool) -> Result<u64, Error> {
+    let bytes: [u8; 8] = data
+        .get(offset..checked_offset(offset, 8)?)
+        .and_then(|b| b.try_into().ok())
+        .ok_or_else(|| invalid("truncated"))?;
+    Ok(if big_endian {
+        u64::from_be_bytes(bytes)
+    } else {
+        u64::from_le_bytes(bytes)
+    })
+}
+
+/// Check whether `data` starts like a Mach-O or universal file.
+pub fn is_macho(data: &[u8]) -> bool {
+    data.len() >= 4
+        && matches!(
+            u32::from_be_bytes([data[0], data[1], data[2], data[3]]),
+            FAT_MAGIC | FAT_MAGIC_64 | MH_MAGIC | MH_MAGIC_64 | MH_CIGAM | MH_CIGAM_64
+        )
+}
+
+/// Decode all architectures of a Mach-O or universal file.
+pub fn parse(data: &[u8]) -> Result<Vec<Slice>, Error> {
+    let magic = read_u32(data, 0, true)?;
+    match magic {
+        FAT_MAGIC | FAT_MAGIC_64 => {
+            let wide = magic == FAT_MAGIC_64;
+            let count = read_u32(data, 4, true)? as usize;
+            let entry_size = if wide { 32 } else { 20 };
+            (0..count)
+                .map(|i| {
+                    let entry = i
+                        .checked_mul(entry_size)
+                        .ok_or_else(|| invalid("offset out of range"))
+                        .and_then(|entry| checked_offset(entry, 8))?;
+                    let (offset, size) = if wide {
+                        (
+                            read_u64(data, checked_offset(entry, 8)?, true)?,
+                            read_u64(data, checked_offset(entry, 16)?, true)?,
+                        )
+                    } else {
+                        (
+                            read_u32(data, checked_offset(entry, 8)?, true)? as u64,
+                            read_u32(data, checked_offset(entry, 12)?, true)? as u64,
+                        )
+                    };
+                    let start = usize::try_from(offset).ok();
+                    let end = offset
+                        .checked_add(size)
+                        .and_then(|e| usize::try_from(e).ok());
+                    let slice = start
+                        .zip(end)
+                        .and_then(|(start, end)| data.get(start..end))
+                        .ok_or_else(|| invalid("architecture outside of file"))?;
+                    parse_thin(slice)
+                })
+                .collect()
+        }
+        _ => Ok(vec![parse_thin(data)?]),
+    }
+}
+
+fn parse_thin(data: &[u8]) -> Result<Slice, Error> {
+    let (big_endian, header_size) = match read_u32(data, 0, true)? {
+        MH_MAGIC => (true, 28),
+        MH_MAGIC_64 => (true, 32),
+        MH_CIGAM => (false, 28),
+        MH_CIGAM_64 => (false, 32),
+        _ => return Err(invalid("unknown magic")),
+    };
+    let cpu_type = read_u32(data, 4, big_endian)?;
+    let cpu_subtype = read_u32(data, 8, big_endian)?;
+    let command_count = read_u32(data, 16, big_endian)?;
+
+    let mut uuid = None;
+    let mut install_name = None;
+    let mut dylibs = Vec::new();
+    let mut rpaths = Vec::new();
+    let mut offset = header_size;
+    for _ in 0..command_count {
+        let command = read_u32(data, offset, big_endian)?;
+        let size = read_u32(data, checked_offset(offset, 4)?, big_endian)? as usize;
+        if size < 8 {
+            return Err(invalid("load command too small"));
+        }
+        if command == LC_UUID {
+            let bytes = data
+                .get(checked_offset(offset, 8)?..checked_offset(offset, 24)?)
+                .ok_or_else(|| invalid("truncated"))?;
+            uuid = Some(Uuid(bytes.try_into().unwrap()));
+        }
+        match command {
+            LC_LOAD_DYLIB | LC_LOAD_WEAK_DYLIB | LC_REEXPORT_DYLIB | LC_LAZY_LOAD_DYLIB
+            | LC_LOAD_UPWARD_DYLIB => dylibs.push(command_string(data, offset, size, big_endian)?),
+            LC_ID_DYLIB => install_name = Some(command_string(data, offset, size, big_endian)?),
+            LC_RPATH => rpaths.push(command_string(data, offset, size, big_endian)?),
+            _ => {}
+        }
+        offset = checked_offset(offset, size)?;
+    }
+
+    Ok(Slice {
+        cpu_type,
+        cpu_subtype,
+        uuid,
+        install_name,
+        dylibs,
+        rpaths,
+    })
+}
+
+/// The string a load command references by its offset, stored at byte 8 of the command.
+fn command_string(
+    data: &[u8],
+    command: usize,
+    size: usize,
+    big_endian: bool,
+) -> Result<String, Error> {
+    let start = checked_offset(
+        command,
+        read_u32(data, checked_offset(command, 8)?, big_endian)? as usize,
+    )?;
+    let bytes = data
+        .get(start..checked_offset(command, size)?)
+        .ok_or_else(|| invalid("load command string outside of command"))?;
+    let end = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+    Ok(String::from_utf8_lossy(&bytes[..end]).into_owned())
+}
+
+/// Decode all architectures of a Mach-O or universal file.
+pub fn read(path: &Path) -> Result<Vec<Slice>, Error> {
+    parse(&fs::read(path)?).map_err(|e| Error::new(e.kind(), format!("{}: {}", path.display(), e)))
+}
+
+/// The libraries a binary links, across all its architectures.
+///
+/// Install names are returned as recorded, e.g. `/usr/lib/libSystem.B.dylib` or
+/// `@rpath/libfoo.dylib`. Use [`is_system_library`] to find those that have to be bundled.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::path::Path;
+/// use toolbelt::macho::{is_system_library, list_dylib_dependencies};
+///
+/// for dylib in list_dylib_dependencies(Path::new("target/release/mytool")).unwrap() {
+///     if !is_system_library(&dylib) {
+///         println!("needs bundling: {}", dylib);
+///     }
+/// }
+/// ```
+pub fn list_dylib_dependencies(binary: &Path) -> Result<Vec<String>, Error> {
+    let mut dylibs: Vec<String> = Vec::new();
+    for slice in read(binary)? {
+        for dylib in slice.dylibs {
+            if !dylibs.contains(&dylib) {
+                dylibs.push(dylib);
+            }
+        }
+    }
+    Ok(dylibs)
+}
+
+/// `true` for libraries shipped with macOS, which are never bundled.
+pub fn is_system_library(install_name: &str) -> bool {
+    install_name.starts_with("/usr/lib/") || install_name.starts_with("/System/Library/")
+}
+
+/// The DWARF files inside a `.dSYM` bundle, or `dsym` itself if it is a file.
+fn dsym_files(dsym: &Path) -> Result<Vec<PathBuf>, Error> {
+    if dsym.is_file() {
+        return Ok(vec![dsym.to_path_buf()]);
+    }
+    let dwarf = dsym.join("Contents/Resources/DWARF");
+    let mut files = Vec::new();
+    for entry in fs::read_dir(&dwarf)? {
+        let entry = entry?;
+        if entry.file_type()?.is_file() {
+            files.push(entry.path());
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Check that a dSYM contains the debug symbols of a binary.
+///
+/// Every architecture of the binary must have a UUID, and a slice with the same UUID must exist in
+/// the dSYM. Otherwise an error listing the UUIDs of both is returned, as crash reports of the
+/// binary couldn't be symbolicated with this dSYM.
+///
+/// # Arguments
+///
+/// * `binary` - the executable or library (e.g. `MyApp.app/Contents/MacOS/MyApp`)
+/// * `dsym` - the `.dSYM` bundle or the DWARF file inside of it
+///
+/// # Example
+///
+/// ```no_run
+/// use std::path::Path;
+/// use toolbelt::verify_dsym_match;
+///
+/// verify_dsym_match(
+///     Path::new("target/MyApp.app/Contents/MacOS/MyApp"),
+///     Path::new("target/MyApp.app.dSYM"),
+/// )
+/// .unwrap();
+/// ```
+pub fn verify_dsym_match(binary: &Path, dsym: &Path) -> Result<(), Error> {
+    let binary_slices = read(binary)?;
+    let mut dsym_slices = Vec::new();
+    for file in dsym_files(dsym)? {
+        dsym_slices.extend(read(&file)?);
+    }
+
+    let describe = |slices: &[Slice]| {
+        slices
+            .iter()
+            .map(|s| match s.uuid {
+                Some(uuid) => format!("{} {}", s.arch_name(), uuid),
+                None => format!("{} (no UUID)", s.arch_name()),
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+
+    let matches = binary_slices
+        .iter()
+        .all(|slice| slice.uuid.is_some() && dsym_slices.iter().any(|d| d.uuid == slice.uuid));
+    if matches {
+        debug!("{} matches {}", dsym.display(), binary.display());
+        Ok(())
+    } else {
+        Err(verification_failure(format!(
+            "dSYM {} doesn't match {}: binary has [{}], dSYM has [{}]",
+            dsym.display(),
+            binary.display(),
+            describe(&binary_slices),
+            describe(&dsym_slices)
+        )))
+    }
+}
+
+/// Build a little-endian 64-bit Mach-O header followed by an LC_UUID command.
+#[cfg(test)]
+pub(crate) fn test_binary(cpu_type: u32, uuid: [u8; 16]) -> Vec<u8> {
+    let mut data = Vec::new();
+    for value in [MH_MAGIC_64, cpu_type, 0, 2, 1, 24, 0, 0] {
+        data.extend(value.to_le_bytes());
+    }
+    data.extend(LC_UUID.to_le_bytes());
+    data.extend(24u32.to_le_bytes());
+    data.extend(uuid);
+    data
+}
+
+/// Build a little-endian 64-bit Mach-O header followed by LC_LOAD_DYLIB commands.
+#[cfg(test)]
+pub(crate) fn test_binary_linking(cpu_type: u32, dylibs: &[&str]) -> Vec<u8> {
+    let commands: Vec<Vec<u8>> = dylibs
+        .iter()
+        .map(|dylib| {
+            let size = (24 + dylib.len() + 1).next_multiple_of(8);
+            let mut command = Vec::new();
+            for value in [LC_LOAD_DYLIB, size as u32, 24, 0, 0, 0] {
+                command.extend(value.to_le_bytes());
+            }
+            command.extend(dylib.as_bytes());
+            command.resize(size, 0);
+            command
+        })
+        .collect();
+    let size: usize = commands.iter().map(Vec::len).sum();
+    let mut data = Vec::new();
+    for value in [
+        MH_MAGIC_64,
+        cpu_type,
+        0,
+        2,
+        commands.len() as u32,
+        size as u32,
+        0,
+        0,
+    ] {
+        data.extend(value.to_le_bytes());
+    }
+    data.extend(commands.concat());
+    data
+}
+
+/// Wrap thin Mach-O files into a universal file.
+#[cfg(test)]
+pub(crate) fn test_universal(slices: &[Vec<u8>]) -> Vec<u8> {
+    let mut data = Vec::new();
+    data.extend(FAT_MAGIC.to_be_bytes());
+    data.extend((slices.len() as u32).to_be_bytes());
+    let mut offset = 8 + 20 * slices.len() as u32;
+    for slice in slices {
+        let cpu_type = u32::from_le_bytes(slice[4..8].try_into().unwrap());
+        for value in [cpu_type, 0, offset, slice.len() as u32, 0] {
+            data.extend(value.to_be_bytes());
+        }
+        offset += slice.len() as u32;
+    }
+    for slice in slices {
+        data.extend(slice);
+    }
+    data
+}
+
+#[test]
+fn test_verify_dsym_match() {
+    let root = Path::new("target/verify_dsym_match");
+    let _ = fs::remove_dir_all(root);
+    let dwarf = root.join("App.dSYM/Contents/Resources/DWARF");
+    fs::create_dir_all(&dwarf).unwrap();
+
+    let arm64 = test_binary(CPU_TYPE_ARM64, [1; 16]);
+    let x86_64 = test_binary(CPU_TYPE_X86_64, [2; 16]);
+    fs::write(
+        root.join("App"),
+        test_universal(&[arm64.clone(), x86_64.clone()]),
+    )
+    .unwrap();
+    fs::write(dwarf.join("App"), test_universal(&[x86_64, arm64])).unwrap();
+
+    let slices = read(&root.join("App")).unwrap();
+    assert_eq!(slices[0].arch_name(), "arm64");
+    assert_eq!(
+        slices[0].uuid.unwrap().to_string(),
+        "01010101-0101-0101-0101-010101010101"
+    );
+    verify_dsym_match(&root.join("App"), &root.join("App.dSYM")).unwrap();
+
+    fs::write(dwarf.join("App"), test_binary(CPU_TYPE_ARM64, [3; 16])).unwrap();
+    let error = verify_dsym_match(&root.join("App"), &root.join("App.dSYM")).unwrap_err();
+    assert!(error.to_string().contains("arm64 03030303"));
+
+    fs::write(root.join("text"), "#!/bin/sh").unwrap();
+    assert!(read(&root.join("text")).is_err());
+
+    fs::remove_dir_all(root).unwrap();
+}
+
+#[test]
+fn test_parse_out_of_range() {
+    // a 64-bit universal file whose only architecture ends beyond u64::MAX
+    let mut data = Vec::new();
+    data.extend(FAT_MAGIC_64.to_be_bytes());
+    data.extend(1u32.to_be_bytes());
+    data.extend(CPU_TYPE_ARM64.to_be_bytes());
+    data.extend(0u32.to_be_bytes());
+    data.extend((u64::MAX - 1).to_be_bytes());
+    data.extend(4u64.to_be_bytes());
+    data.extend([0; 8]);
+    let error = parse(&data).unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::InvalidData);
+
+    // a load command string offset pointing past the command
+    let mut binary = test_binary_linking(CPU_TYPE_ARM64, &["/usr/lib/libSystem.B.dylib"]);
+    binary[32 + 8..32 + 12].copy_from_slice(&u32::MAX.to_le_bytes());
+    let error = parse(&binary).unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::InvalidData);
+}
+
+#[test]
+fn test_list_dylib_dependencies() {
+    let root = Path::new("target/list_dylib_dependencies");
+    let _ = fs::remove_dir_all(root);
+    fs::create_dir_all(root).unwrap();
+    let binary = root.join("tool");
+    fs::write(
+        &binary,
+        test_universal(&[
+            test_binary_linking(
+                CPU_TYPE_X86_64,
+                &["/usr/lib/libSystem.B.dylib", "@rpath/libfoo.dylib"],
+            ),
+            test_binary_linking(
+                CPU_TYPE_ARM64,
+                &[
+                    "/usr/lib/libSystem.B.dylib",
+                    "/opt/homebrew/lib/libbar.dylib",
+                ],
+            ),
+        ]),
+    )
+    .unwrap();
+
+    let dylibs = list_dylib_dependencies(&binary).unwrap();
+    assert_eq!(
+        dylibs,
+        [
+            "/usr/lib/libSystem.B.dylib",
+            "@rpath/libfoo.dylib",
+            "/opt/homebrew/lib/libbar.dylib"
+        ]
+    );
+    assert_eq!(dylibs.iter().filter(|d| !is_system_library(d)).count(), 2);
+
+    fs::remove_dir_all(root).unwrap();
+}
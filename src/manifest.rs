@@ -0,0 +1,264 @@
+//! JSON manifests listing files with their size and SHA-256 digest.
+//!
+//! A manifest is written next to copied files so later build steps can check the files'
+//! integrity. Paths are stored relative to the manifest's root directory using `/` as separator.
+
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+
+use walkdir::WalkDir;
+
+use crate::concurrency::parallel_map;
+use crate::hash::Sha256;
+use crate::json::{self, Value};
+
+/// Default file name used when writing a manifest into a destination directory.
+pub const MANIFEST_FILE_NAME: &str = "manifest.json";
+
+/// A single file recorded in a [`Manifest`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ManifestEntry {
+    /// Path relative to the manifest root, `/` separated
+    pub path: String,
+    pub size: u64,
+    pub sha256: Sha256,
+}
+
+/// A list of files with their size and SHA-256 digest.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Manifest {
+    pub entries: Vec<ManifestEntry>,
+}
+
+/// Result of checking a directory against a [`Manifest`].
+///
+/// All paths are relative to the checked directory, `/` separated.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ManifestVerification {
+    /// Files whose size or digest differs from the manifest
+    pub mismatched: Vec<String>,
+    /// Files listed in the manifest but not present
+    pub missing: Vec<String>,
+    /// Files present but not listed in the manifest
+    pub extra: Vec<String>,
+}
+
+impl ManifestVerification {
+    /// `true` if the directory matches the manifest exactly.
+    pub fn is_ok(&self) -> bool {
+        self.mismatched.is_empty() && self.missing.is_empty() && self.extra.is_empty()
+    }
+}
+
+/// Convert a relative path to the `/` separated form used in manifests.
+pub(crate) fn manifest_path(relative: &Path) -> String {
+    relative
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+impl Manifest {
+    /// Create a manifest for a list of files.
+    ///
+    /// # Arguments
+    ///
+    /// * `root` - directory the files are relative to
+    /// * `files` - relative paths of the files to record
+    pub fn from_files<I, P>(root: &Path, files: I) -> Result<Manifest, Error>
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<Path>,
+    {
+        let files: Vec<PathBuf> = files
+            .into_iter()
+            .map(|f| f.as_ref().to_path_buf())
+            .collect();
+        let mut entries = parallel_map(&files, |relative| {
+            let full_path = root.join(relative);
+            Ok(ManifestEntry {
+                path: manifest_path(relative),
+                size: std::fs::metadata(&full_path)?.len(),
+                sha256: Sha256::of_file(&full_path)?,
+            })
+        })
+        .into_iter()
+        .collect::<Result<Vec<_>, Error>>()?;
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(Manifest { entries })
+    }
+
+    /// Look up the entry for a relative path.
+    pub fn get(&self, path: &str) -> Option<&ManifestEntry> {
+        self.entries.iter().find(|entry| entry.path == path)
+    }
+
+    /// Serialize the manifest to JSON.
+    pub fn to_json(&self) -> String {
+        let files = self
+            .entries
+            .iter()
+            .map(|entry| {
+                Value::Object(vec![
+                    ("path".to_string(), entry.path.as_str().into()),
+                    ("size".to_string(), entry.size.into()),
+                    ("sha256".to_string(), entry.sha256.to_string().into()),
+                ])
+            })
+            .collect();
+        Value::Object(vec![("files".to_string(), Value::Array(files))]).to_pretty_string()
+    }
+
+    /// Parse a manifest from JSON.
+    pub fn from_json(input: &str) -> Result<Manifest, Error> {
+        let invalid = |what: &str| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("Invalid manifest: {}", what),
+            )
+        };
+
+        let document = json::parse(input)?;
+        let files = document
+            .get("files")
+            .and_then(Value::as_array)
+            .ok_or_else(|| invalid("missing files list"))?;
+
+        let mut entries = Vec::new();
+        for file in files {
+            entries.push(ManifestEntry {
+                path: file
+                    .get("path")
+                    .and_then(Value::as_str)
+                    .ok_or_else(|| invalid("missing path"))?
+                    .to_string(),
+                size: file
+                    .get("size")
+                    .and_then(Value::as_u64)
+                    .ok_or_else(|| invalid("missing size"))?,
+                sha256: file
+                    .get("sha256")
+                    .and_then(Value::as_str)
+                    .ok_or_else(|| invalid("missing sha256"))?
+                    .parse()?,
+            });
+        }
+        Ok(Manifest { entries })
+    }
+
+    /// Check the files below `dir` against this manifest.
+    pub fn verify(&self, dir: &Path) -> Result<ManifestVerification, Error> {
+        let mut verification = ManifestVerification::default();
+
+        for entry in &self.entries {
+            let path = dir.join(&entry.path);
+            match std::fs::metadata(&path) {
+                Ok(metadata) if metadata.is_file() => {
+                    if metadata.len() != entry.size || Sha256::of_file(&path)? != entry.sha256 {
+                        verification.mismatched.push(entry.path.clone());
+                    }
+                }
+                Ok(_) => verification.mismatched.push(entry.path.clone()),
+                Err(e) if e.kind() == ErrorKind::NotFound => {
+                    verification.missing.push(entry.path.clone())
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        for entry in WalkDir::new(dir).sort_by(|a, b| a.file_name().cmp(b.file_name())) {
+            let entry = entry?;
+            if entry.file_type().is_dir() {
+                continue;
+            }
+            let relative = manifest_path(entry.path().strip_prefix(dir).unwrap());
+            if self.get(&relative).is_none() {
+                verification.extra.push(relative);
+            }
+        }
+
+        Ok(verification)
+    }
+
+    /// Write the manifest as JSON to `path`.
+    pub fn write(&self, path: &Path) -> Result<(), Error> {
+        std::fs::write(path, self.to_json())
+    }
+
+    /// Read a manifest from a JSON file.
+    pub fn read(path: &Path) -> Result<Manifest, Error> {
+        Manifest::from_json(&std::fs::read_to_string(path)?)
+    }
+}
+
+/// Verify a directory against a manifest file, e.g. as a sanity check after packaging.
+///
+/// The manifest file itself is not reported as extra file when it's stored inside `dir`.
+///
+/// # Arguments
+///
+/// * `dir` - the directory to check
+/// * `manifest` - path of the JSON manifest
+///
+/// # Example
+///
+/// ```no_run
+/// use std::path::Path;
+/// use toolbelt::verify_manifest;
+///
+/// let dist = Path::new("target/dist");
+/// let result = verify_manifest(dist, &dist.join("manifest.json")).unwrap();
+/// assert!(result.is_ok(), "{:?}", result);
+/// ```
+pub fn verify_manifest(dir: &Path, manifest: &Path) -> Result<ManifestVerification, Error> {
+    let mut verification = Manifest::read(manifest)?.verify(dir)?;
+
+    if let Ok(relative) = manifest.strip_prefix(dir) {
+        let relative = manifest_path(relative);
+        verification.extra.retain(|path| *path != relative);
+    }
+    Ok(verification)
+}
+
+#[test]
+fn test_manifest_roundtrip() {
+    let root = Path::new("test/my_files");
+    let manifest = Manifest::from_files(root, ["more_files/file3.md", "file1.txt"]).unwrap();
+    assert_eq!(manifest.entries.len(), 2);
+    assert_eq!(manifest.entries[0].path, "file1.txt");
+    assert_eq!(manifest.entries[1].path, "more_files/file3.md");
+    assert_eq!(manifest.entries[0].sha256, Sha256::digest(b""));
+
+    assert_eq!(Manifest::from_json(&manifest.to_json()).unwrap(), manifest);
+}
+
+#[test]
+fn test_verify_manifest() {
+    let dir = Path::new("target/verify_manifest");
+    let _ = std::fs::remove_dir_all(dir);
+    std::fs::create_dir_all(dir.join("sub")).unwrap();
+    std::fs::write(dir.join("a.txt"), "a").unwrap();
+    std::fs::write(dir.join("sub/b.txt"), "b").unwrap();
+    std::fs::write(dir.join("c.txt"), "c").unwrap();
+
+    let manifest = Manifest::from_files(dir, ["a.txt", "sub/b.txt", "c.txt"]).unwrap();
+    manifest.write(&dir.join(MANIFEST_FILE_NAME)).unwrap();
+    assert!(verify_manifest(dir, &dir.join(MANIFEST_FILE_NAME))
+        .unwrap()
+        .is_ok());
+
+    std::fs::write(dir.join("a.txt"), "changed").unwrap();
+    std::fs::remove_file(dir.join("c.txt")).unwrap();
+    std::fs::write(dir.join("sub/d.txt"), "d").unwrap();
+    assert_eq!(
+        verify_manifest(dir, &dir.join(MANIFEST_FILE_NAME)).unwrap(),
+        ManifestVerification {
+            mismatched: vec!["a.txt".into()],
+            missing: vec!["c.txt".into()],
+            extra: vec!["sub/d.txt".into()],
+        }
+    );
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
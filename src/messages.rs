@@ -0,0 +1,339 @@
+//! Catalog of user-facing messages, selectable by locale.
+//!
+//! English and German texts are built in. Further languages or project specific wording can be
+//! added with [`register`]. The locale is taken from [`set_locale`] if called, otherwise from the
+//! first non-empty of `TOOLBELT_LOCALE`, `LC_ALL`, `LC_MESSAGES` and `LANG`. Texts missing for the
+//! locale's region, its language or the `C` locale fall back to English.
+
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::sync::{OnceLock, RwLock};
+
+/// Identifies a user-facing message.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Message {
+    /// The SDK directory doesn't exist. Placeholders: `{path}`
+    SdkMissing,
+    /// The SDK environment variable isn't usable. Placeholders: `{name}`
+    SdkEnvInvalid,
+    /// The SDK environment variable isn't set and no fallback location exists. Placeholders:
+    /// `{name}`, `{paths}`
+    SdkEnvNotSet,
+    /// The SDK version couldn't be determined. Placeholders: `{path}`, `{reason}`
+    SdkVersionUnknown,
+    /// The SDK is older than required. Placeholders: `{path}`, `{version}`, `{minimum}`
+    SdkTooOld,
+    /// A download failed. Placeholders: `{url}`, `{reason}`
+    DownloadFailed,
+    /// A file doesn't match its checksum. Placeholders: `{path}`, `{expected}`, `{actual}`
+    ChecksumMismatch,
+    /// An include directory pattern matched nothing. Placeholders: `{pattern}`, `{sdk}`
+    IncludePatternUnmatched,
+    /// A library directory pattern matched nothing. Placeholders: `{pattern}`, `{sdk}`
+    LibPatternUnmatched,
+    /// A framework directory pattern matched nothing. Placeholders: `{pattern}`, `{sdk}`
+    FrameworkPatternUnmatched,
+    /// Neither Xcode nor the Command Line Tools are installed
+    XcodeMissing,
+    /// `xcrun` isn't installed
+    XcrunMissing,
+    /// `xcode-select` doesn't report a developer directory
+    XcodeNotSelected,
+    /// An Apple SDK isn't installed. Placeholders: `{sdk}`
+    AppleSdkMissing,
+    /// The selected Xcode is older than required. Placeholders: `{minimum}`, `{version}`, `{path}`
+    XcodeTooOld,
+    /// Xcode is required, but only the Command Line Tools are selected. Placeholders:
+    /// `{minimum}`, `{path}`
+    XcodeRequired,
+    /// An Apple SDK is older than required. Placeholders: `{sdk}`, `{minimum}`, `{version}`
+    AppleSdkTooOld,
+    /// An Apple SDK is required, but not installed. Placeholders: `{sdk}`, `{minimum}`, `{path}`
+    AppleSdkRequired,
+}
+
+const FALLBACK_LOCALE: &str = "en";
+
+fn builtin(locale: &str, message: Message) -> Option<&'static str> {
+    let text = match (locale, message) {
+        ("en", Message::SdkMissing) => "Please download & unpack the SDK into {path}",
+        ("en", Message::SdkEnvInvalid) => "{name} env variable configuration error.",
+        ("en", Message::SdkEnvNotSet) => {
+            "Please set {name} to the SDK directory, it was not found in {paths}"
+        }
+        ("en", Message::SdkVersionUnknown) => "Cannot read the SDK version from {path}: {reason}",
+        ("en", Message::SdkTooOld) => {
+            "The SDK in {path} is version {version}, but at least {minimum} is required. Please \
+             download & unpack a newer SDK"
+        }
+        ("en", Message::DownloadFailed) => "Failed to download {url}: {reason}",
+        ("en", Message::ChecksumMismatch) => {
+            "Checksum mismatch for {path}: expected {expected}, got {actual}"
+        }
+        ("en", Message::IncludePatternUnmatched) => {
+            "Include pattern {pattern} matched no directories in {sdk}"
+        }
+        ("en", Message::LibPatternUnmatched) => {
+            "Library pattern {pattern} matched no directories in {sdk}"
+        }
+        ("en", Message::FrameworkPatternUnmatched) => {
+            "Framework pattern {pattern} matched no directories in {sdk}"
+        }
+        ("en", Message::XcodeMissing) => {
+            "Xcode or the Command Line Tools are missing, install Xcode or the Command Line Tools \
+             with `xcode-select --install`"
+        }
+        ("en", Message::XcrunMissing) => {
+            "xcrun was not found, install Xcode or the Command Line Tools with \
+             `xcode-select --install`"
+        }
+        ("en", Message::XcodeNotSelected) => {
+            "No developer directory is selected, install Xcode or the Command Line Tools with \
+             `xcode-select --install`"
+        }
+        ("en", Message::AppleSdkMissing) => "SDK {sdk} is not installed",
+        ("en", Message::XcodeTooOld) => {
+            "Xcode {minimum} or later is required, but Xcode {version} ({path}) is selected"
+        }
+        ("en", Message::XcodeRequired) => {
+            "Xcode {minimum} or later is required, but only the Command Line Tools ({path}) are \
+             selected"
+        }
+        ("en", Message::AppleSdkTooOld) => {
+            "SDK {sdk} {minimum} or later is required, but {version} is installed"
+        }
+        ("en", Message::AppleSdkRequired) => {
+            "SDK {sdk} {minimum} or later is required, but it isn't installed in {path}"
+        }
+        ("de", Message::SdkMissing) => "Bitte das SDK herunterladen und nach {path} entpacken",
+        ("de", Message::SdkEnvInvalid) => {
+            "Die Umgebungsvariable {name} ist fehlerhaft konfiguriert."
+        }
+        ("de", Message::SdkEnvNotSet) => {
+            "Bitte {name} auf das SDK-Verzeichnis setzen, es wurde nicht in {paths} gefunden"
+        }
+        ("de", Message::SdkVersionUnknown) => {
+            "Die SDK-Version kann nicht aus {path} gelesen werden: {reason}"
+        }
+        ("de", Message::SdkTooOld) => {
+            "Das SDK in {path} hat die Version {version}, benötigt wird mindestens {minimum}. \
+             Bitte ein neueres SDK herunterladen und entpacken"
+        }
+        ("de", Message::DownloadFailed) => "Download von {url} fehlgeschlagen: {reason}",
+        ("de", Message::ChecksumMismatch) => {
+            "Prüfsumme von {path} stimmt nicht: erwartet {expected}, erhalten {actual}"
+        }
+        ("de", Message::IncludePatternUnmatched) => {
+            "Das Include-Muster {pattern} passt auf kein Verzeichnis in {sdk}"
+        }
+        ("de", Message::LibPatternUnmatched) => {
+            "Das Bibliotheks-Muster {pattern} passt auf kein Verzeichnis in {sdk}"
+        }
+        ("de", Message::FrameworkPatternUnmatched) => {
+            "Das Framework-Muster {pattern} passt auf kein Verzeichnis in {sdk}"
+        }
+        ("de", Message::XcodeMissing) => {
+            "Xcode oder die Command Line Tools fehlen, bitte Xcode oder die Command Line Tools \
+             mit `xcode-select --install` installieren"
+        }
+        ("de", Message::XcrunMissing) => {
+            "xcrun wurde nicht gefunden, bitte Xcode oder die Command Line Tools mit \
+             `xcode-select --install` installieren"
+        }
+        ("de", Message::XcodeNotSelected) => {
+            "Es ist kein Entwicklerverzeichnis ausgewählt, bitte Xcode oder die Command Line \
+             Tools mit `xcode-select --install` installieren"
+        }
+        ("de", Message::AppleSdkMissing) => "Das SDK {sdk} ist nicht installiert",
+        ("de", Message::XcodeTooOld) => {
+            "Xcode {minimum} oder neuer wird benötigt, ausgewählt ist aber Xcode {version} \
+             ({path})"
+        }
+        ("de", Message::XcodeRequired) => {
+            "Xcode {minimum} oder neuer wird benötigt, ausgewählt sind aber nur die Command Line \
+             Tools ({path})"
+        }
+        ("de", Message::AppleSdkTooOld) => {
+            "Das SDK {sdk} wird in Version {minimum} oder neuer benötigt, installiert ist \
+             {version}"
+        }
+        ("de", Message::AppleSdkRequired) => {
+            "Das SDK {sdk} wird in Version {minimum} oder neuer benötigt, ist aber nicht in \
+             {path} installiert"
+        }
+        _ => return None,
+    };
+    Some(text)
+}
+
+#[derive(Default)]
+struct Catalog {
+    locale: Option<String>,
+    custom: HashMap<(String, Message), String>,
+}
+
+fn catalog() -> &'static RwLock<Catalog> {
+    static CATALOG: OnceLock<RwLock<Catalog>> = OnceLock::new();
+    CATALOG.get_or_init(Default::default)
+}
+
+/// Reduce locale strings like `de_DE.UTF-8` to language and region (`de_DE`).
+fn normalize(locale: &str) -> String {
+    let locale = locale.split(['.', '@']).next().unwrap_or_default();
+    let mut parts = locale.split(['_', '-']);
+    let language = parts.next().unwrap_or_default().to_lowercase();
+    match parts.next().filter(|region| !region.is_empty()) {
+        Some(region) => format!("{}_{}", language, region.to_uppercase()),
+        None => language,
+    }
+}
+
+/// Select the locale used for all further messages, e.g. `de` or `pt_BR`.
+///
+/// Texts registered for the region are preferred, otherwise those of the language are used.
+pub fn set_locale(locale: &str) {
+    catalog().write().unwrap().locale = Some(normalize(locale));
+}
+
+/// The currently active locale, language and region if known (e.g. `pt_BR`).
+pub fn locale() -> String {
+    if let Some(locale) = &catalog().read().unwrap().locale {
+        return locale.clone();
+    }
+    env_locale(|name| std::env::var(name).ok())
+}
+
+/// The locale of the first non-empty locale variable, like the C library does. `C` and `POSIX`
+/// select the fallback.
+fn env_locale(var: impl Fn(&str) -> Option<String>) -> String {
+    ["TOOLBELT_LOCALE", "LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|name| var(name))
+        .map(|value| normalize(&value))
+        .find(|value| !value.is_empty())
+        .filter(|value| value != "c" && value != "posix")
+        .unwrap_or_else(|| FALLBACK_LOCALE.to_string())
+}
+
+/// Add or replace the text of a message for a locale.
+///
+/// Placeholders in curly braces are replaced by the arguments passed to [`message`].
+pub fn register(locale: &str, message: Message, template: &str) {
+    catalog()
+        .write()
+        .unwrap()
+        .custom
+        .insert((normalize(locale), message), template.to_string());
+}
+
+/// Render a message in the active locale.
+///
+/// # Arguments
+///
+/// * `message` - the message to render
+/// * `args` - values for the message's placeholders as (name, value) pairs
+///
+/// # Example
+///
+/// ```
+/// use toolbelt::messages::{message, Message};
+///
+/// let text = message(Message::SdkMissing, &[("path", &"/opt/sdk")]);
+/// assert!(text.contains("/opt/sdk"));
+/// ```
+pub fn message(message: Message, args: &[(&str, &dyn Display)]) -> String {
+    render(&locale(), message, args)
+}
+
+/// Render a message in `locale`, falling back to the locale's language and English.
+fn render(locale: &str, message: Message, args: &[(&str, &dyn Display)]) -> String {
+    let language = locale.split('_').next().unwrap_or_default();
+    let template = {
+        let catalog = catalog().read().unwrap();
+        [locale, language, FALLBACK_LOCALE]
+            .iter()
+            .find_map(|locale| {
+                catalog
+                    .custom
+                    .get(&(locale.to_string(), message))
+                    .cloned()
+                    .or_else(|| builtin(locale, message).map(String::from))
+            })
+            .unwrap()
+    };
+
+    args.iter().fold(template, |text, (name, value)| {
+        text.replace(&format!("{{{}}}", name), &value.to_string())
+    })
+}
+
+#[test]
+fn test_message() {
+    assert_eq!(normalize("de_DE.UTF-8"), "de_DE");
+    assert_eq!(normalize("pt-br"), "pt_BR");
+    assert_eq!(normalize("sr_RS@latin"), "sr_RS");
+
+    // the first non-empty variable wins, C and POSIX mean the fallback
+    let env = |vars: &'static [(&'static str, &'static str)]| {
+        move |name: &str| {
+            vars.iter()
+                .find(|(n, _)| *n == name)
+                .map(|(_, v)| v.to_string())
+        }
+    };
+    assert_eq!(env_locale(env(&[("LANG", "de_DE.UTF-8")])), "de_DE");
+    assert_eq!(
+        env_locale(env(&[("LC_ALL", "C"), ("LANG", "de_DE.UTF-8")])),
+        "en"
+    );
+    assert_eq!(
+        env_locale(env(&[("LC_ALL", ""), ("LANG", "de_DE.UTF-8")])),
+        "de_DE"
+    );
+    assert_eq!(env_locale(env(&[])), "en");
+
+    // rendering with an explicit locale leaves the process-wide one to the other tests
+    register("pt_BR", Message::SdkMissing, "SDK em {path}");
+    register("pt", Message::DownloadFailed, "Falha ao baixar {url}");
+    // placeholders are substituted, custom texts of the region come first
+    assert_eq!(
+        render("pt_BR", Message::SdkMissing, &[("path", &"/opt/sdk")]),
+        "SDK em /opt/sdk"
+    );
+    // then custom texts of the language
+    assert_eq!(
+        render(
+            "pt_BR",
+            Message::DownloadFailed,
+            &[("url", &"https://example.com")]
+        ),
+        "Falha ao baixar https://example.com"
+    );
+    // then the English text
+    assert_eq!(
+        render("pt_BR", Message::ChecksumMismatch, &[("path", &"sdk.zip")]),
+        "Checksum mismatch for sdk.zip: expected {expected}, got {actual}"
+    );
+
+    assert_eq!(
+        render(
+            &normalize("de_AT"),
+            Message::SdkMissing,
+            &[("path", &"/opt/sdk")]
+        ),
+        "Bitte das SDK herunterladen und nach /opt/sdk entpacken"
+    );
+    assert_eq!(
+        render(
+            "de",
+            Message::XcodeTooOld,
+            &[
+                ("minimum", &"16.0"),
+                ("version", &"15.4"),
+                ("path", &"/Applications/Xcode.app")
+            ]
+        ),
+        "Xcode 16.0 oder neuer wird benötigt, ausgewählt ist aber Xcode 15.4 \
+         (/Applications/Xcode.app)"
+    );
+}
@@ -0,0 +1,448 @@
+//! Compiling Metal shaders into a `.metallib` the way Xcode does.
+//!
+//! Each `.metal` file is compiled to an `.air` file with `xcrun metal`, then all of them are
+//! linked into one library with `xcrun metallib`. Apps load `default.metallib` from their
+//! resources via `MTLDevice.makeDefaultLibrary()`.
+//!
+//! Like Xcode, shaders whose `.air` file is newer than the shader and all headers it includes are
+//! not compiled again, and the library is only linked if one of its `.air` files changed.
+
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::build_script::BuildScriptOutput;
+use crate::concurrency::parallel_map;
+use crate::fileset::{FileSet, PatternOptions};
+use crate::tool::{CompileError, Tool};
+
+/// Options for compiling and linking Metal shaders.
+#[derive(Clone, Debug)]
+pub struct MetalOptions {
+    /// SDK to use (`xcrun -sdk`, e.g. `macosx`, `iphoneos`)
+    pub sdk: String,
+    /// Metal language standard (`-std`, e.g. `metal3.0`)
+    pub std: Option<String>,
+    /// Additional header search paths (`-I`)
+    pub include_dirs: Vec<PathBuf>,
+    /// Keep debug information so shaders can be debugged in Xcode (`-gline-tables-only
+    /// -frecord-sources`)
+    pub debug: bool,
+    /// Name of the linked library without extension
+    pub library_name: String,
+    /// Skip shaders and the library if they are up to date
+    pub incremental: bool,
+    /// Matching of the shader pattern passed to [`build_metal_library`] and the walk
+    pub patterns: PatternOptions,
+    /// Report the shaders and include directories as inputs of a build script, also those
+    /// skipped as up to date
+    pub build_script: Option<BuildScriptOutput>,
+}
+
+impl Default for MetalOptions {
+    fn default() -> Self {
+        MetalOptions {
+            sdk: "macosx".to_string(),
+            std: None,
+            include_dirs: Vec::new(),
+            debug: false,
+            library_name: "default".to_string(),
+            incremental: true,
+            patterns: PatternOptions::default(),
+            build_script: None,
+        }
+    }
+}
+
+/// Severity of a compiler [`Diagnostic`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+}
+
+/// A message of the Metal compiler.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub file: PathBuf,
+    pub line: u32,
+    pub column: u32,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Note => "note",
+        };
+        write!(
+            f,
+            "{}:{}:{}: {}: {}",
+            self.file.display(),
+            self.line,
+            self.column,
+            severity,
+            self.message
+        )
+    }
+}
+
+/// Extract the diagnostics from the Metal compiler's output (`file:line:column: error: message`).
+///
+/// Use it on the stderr of a failed compilation carried by [`CompileError::Tool`].
+pub fn parse_diagnostics(output: &str) -> Vec<Diagnostic> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let (location, severity, message) = [
+                (": fatal error: ", Severity::Error),
+                (": error: ", Severity::Error),
+                (": warning: ", Severity::Warning),
+                (": note: ", Severity::Note),
+            ]
+            .iter()
+            .find_map(|(marker, severity)| {
+                line.split_once(marker)
+                    .map(|(location, message)| (location, *severity, message))
+            })?;
+            let mut location = location.rsplitn(3, ':');
+            let column = location.next()?.parse().ok()?;
+            let line = location.next()?.parse().ok()?;
+            let file = PathBuf::from(location.next()?);
+            Some(Diagnostic {
+                file,
+                line,
+                column,
+                severity,
+                message: message.to_string(),
+            })
+        })
+        .collect()
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    path.metadata().and_then(|m| m.modified()).ok()
+}
+
+/// The dependency file written next to an `.air` file.
+fn depfile(air: &Path) -> PathBuf {
+    let mut name = air.as_os_str().to_owned();
+    name.push(".d");
+    PathBuf::from(name)
+}
+
+/// Files listed in a Make style dependency file (`target: dep1 dep2 \`).
+fn parse_depfile(contents: &str) -> Vec<PathBuf> {
+    let joined = contents.replace("\\\n", " ").replace("\\\r\n", " ");
+    let dependencies = joined.split_once(": ").map_or("", |(_, deps)| deps);
+    let mut paths = Vec::new();
+    let mut current = String::new();
+    let mut chars = dependencies.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                if let Some(escaped) = chars.next() {
+                    current.push(escaped);
+                }
+            }
+            c if c.is_whitespace() => {
+                if !current.is_empty() {
+                    paths.push(PathBuf::from(std::mem::take(&mut current)));
+                }
+            }
+            c => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        paths.push(PathBuf::from(current));
+    }
+    paths
+}
+
+/// `true` if `air` is newer than `source` and every header recorded in its dependency file.
+fn is_up_to_date(source: &Path, air: &Path) -> bool {
+    let built = match modified(air) {
+        Some(built) => built,
+        None => return false,
+    };
+    let dependencies = match fs::read_to_string(depfile(air)) {
+        Ok(contents) => parse_depfile(&contents),
+        Err(_) => return false,
+    };
+    std::iter::once(source.to_path_buf())
+        .chain(dependencies)
+        .all(|dependency| modified(&dependency).is_some_and(|m| m <= built))
+}
+
+/// Report a shader or shader directory and the include directories to the build script.
+fn report_inputs(source: &Path, options: &MetalOptions) {
+    if let Some(output) = &options.build_script {
+        output.rerun_if_changed(source);
+        for dir in &options.include_dirs {
+            output.rerun_if_changed(dir);
+        }
+    }
+}
+
+/// Compile a single Metal shader to an `.air` file.
+///
+/// A dependency file (`<air>.d`) listing the included headers is written next to the `.air` file.
+/// Returns the compiler's warnings, which are logged as well.
+///
+/// # Arguments
+///
+/// * `source` - the `.metal` file
+/// * `air` - the `.air` file to create
+/// * `options` - SDK, language standard, include paths
+pub fn compile_shader(
+    source: &Path,
+    air: &Path,
+    options: &MetalOptions,
+) -> Result<Vec<Diagnostic>, CompileError> {
+    report_inputs(source, options);
+    if let Some(dir) = air.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut xcrun = Tool::new("xcrun");
+    xcrun.arg("-sdk").arg(&options.sdk).arg("metal").arg("-c");
+    if let Some(std) = &options.std {
+        xcrun.arg(format!("-std={}", std));
+    }
+    for dir in &options.include_dirs {
+        xcrun.arg("-I").arg(dir);
+    }
+    if options.debug {
+        xcrun.args(["-gline-tables-only", "-frecord-sources"]);
+    }
+    let output = xcrun
+        .arg("-MMD")
+        .arg("-MF")
+        .arg(depfile(air))
+        .arg(source)
+        .arg("-o")
+        .arg(air)
+        .run()
+        .map_err(|e| CompileError::Tool {
+            file: source.to_path_buf(),
+            source: e,
+        })?;
+
+    let warnings: Vec<Diagnostic> = parse_diagnostics(&output.stderr)
+        .into_iter()
+        .filter(|d| d.severity == Severity::Warning)
+        .collect();
+    for warning in &warnings {
+        warn!("{}", warning);
+    }
+    Ok(warnings)
+}
+
+/// Link `.air` files into a Metal library.
+///
+/// # Arguments
+///
+/// * `airs` - the compiled shaders
+/// * `metallib` - the library to create
+/// * `options` - the SDK to use
+pub fn link_metallib<P: AsRef<Path>>(
+    airs: &[P],
+    metallib: &Path,
+    options: &MetalOptions,
+) -> Result<(), CompileError> {
+    if let Some(dir) = metallib.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    Tool::new("xcrun")
+        .arg("-sdk")
+        .arg(&options.sdk)
+        .arg("metallib")
+        .args(airs.iter().map(|air| air.as_ref()))
+        .arg("-o")
+        .arg(metallib)
+        .run()
+        .map_err(|e| CompileError::Tool {
+            file: metallib.to_path_buf(),
+            source: e,
+        })?;
+    Ok(())
+}
+
+/// Compile all Metal shaders matching a pattern and link them into `<library_name>.metallib` in a
+/// bundle's resources. Returns the path of the library.
+///
+/// # Arguments
+///
+/// * `source` - directory containing the shaders
+/// * `pattern` - a standard glob pattern selecting the shaders (e.g. `**/*.metal`)
+/// * `intermediate_dir` - directory for the `.air` files, mirroring the structure below `source`
+/// * `resources_dir` - the bundle's resources directory
+/// * `options` - SDK, language standard, include paths and the library name
+///
+/// # Example
+///
+/// ```no_run
+/// use std::path::Path;
+/// use toolbelt::metal::{build_metal_library, MetalOptions};
+///
+/// let out_dir = std::env::var("OUT_DIR").unwrap();
+/// build_metal_library(
+///     Path::new("shaders"),
+///     "**/*.metal",
+///     &Path::new(&out_dir).join("air"),
+///     Path::new("target/MyApp.app/Contents/Resources"),
+///     &MetalOptions::default(),
+/// )
+/// .unwrap();
+/// ```
+pub fn build_metal_library(
+    source: &Path,
+    pattern: &str,
+    intermediate_dir: &Path,
+    resources_dir: &Path,
+    options: &MetalOptions,
+) -> Result<PathBuf, CompileError> {
+    report_inputs(source, options);
+    let mut shaders = Vec::new();
+    for entry in FileSet::new(source)
+        .pattern(pattern)
+        .options(options.patterns.clone())
+    {
+        let entry = entry?;
+        if entry.file_type.is_file() {
+            let air = intermediate_dir
+                .join(&entry.relative_path)
+                .with_extension("air");
+            shaders.push((entry.path, air));
+        }
+    }
+    shaders.sort();
+
+    let outdated: Vec<&(PathBuf, PathBuf)> = shaders
+        .iter()
+        .filter(|(shader, air)| !options.incremental || !is_up_to_date(shader, air))
+        .collect();
+    debug!(
+        "Compiling {} of {} Metal shaders",
+        outdated.len(),
+        shaders.len()
+    );
+    for result in parallel_map(&outdated, |(shader, air)| {
+        compile_shader(shader, air, options)
+    }) {
+        result?;
+    }
+
+    let metallib = resources_dir.join(format!("{}.metallib", options.library_name));
+    let linked = modified(&metallib);
+    let relink = !options.incremental
+        || linked.is_none()
+        || shaders
+            .iter()
+            .any(|(_, air)| modified(air).is_none_or(|m| Some(m) > linked));
+    if relink {
+        let airs: Vec<&PathBuf> = shaders.iter().map(|(_, air)| air).collect();
+        link_metallib(&airs, &metallib, options)?;
+    } else {
+        debug!("{} is up to date", metallib.display());
+    }
+    Ok(metallib)
+}
+
+#[test]
+fn test_build_metal_library_reports_failing_shader() {
+    let root = Path::new("target/build_metal_library");
+    let _ = fs::remove_dir_all(root);
+    fs::create_dir_all(root.join("shaders/effects")).unwrap();
+    fs::write(
+        root.join("shaders/effects/blur.metal"),
+        "kernel void blur() {}",
+    )
+    .unwrap();
+
+    // without Xcode xcrun is missing, the shader is named in the error either way
+    match build_metal_library(
+        &root.join("shaders"),
+        "**/*.metal",
+        &root.join("air"),
+        &root.join("Resources"),
+        &MetalOptions::default(),
+    ) {
+        Err(CompileError::Tool { file, .. }) => {
+            assert_eq!(file, root.join("shaders/effects/blur.metal"))
+        }
+        Ok(metallib) => assert_eq!(metallib, root.join("Resources/default.metallib")),
+        Err(e) => panic!("unexpected {}", e),
+    }
+    assert!(root.join("air/effects").is_dir());
+
+    fs::remove_dir_all(root).unwrap();
+}
+
+#[test]
+fn test_metal_diagnostics() {
+    let stderr = "shaders/blur.metal:12:5: warning: unused variable 'x': never read\n\
+                  \x20   int x = 0;\n\
+                  shaders/blur.metal:20:1: error: expected ';' after expression\n\
+                  1 warning and 1 error generated.\n";
+    let diagnostics = parse_diagnostics(stderr);
+    assert_eq!(diagnostics.len(), 2);
+    assert_eq!(diagnostics[0].severity, Severity::Warning);
+    assert_eq!(diagnostics[0].line, 12);
+    assert_eq!(diagnostics[0].message, "unused variable 'x': never read");
+    assert_eq!(diagnostics[1].file, Path::new("shaders/blur.metal"));
+    assert_eq!(
+        diagnostics[1].to_string(),
+        "shaders/blur.metal:20:1: error: expected ';' after expression"
+    );
+
+    assert_eq!(
+        parse_depfile("blur.air: shaders/blur.metal \\\n  shaders/common\\ types.h\n"),
+        [
+            PathBuf::from("shaders/blur.metal"),
+            PathBuf::from("shaders/common types.h")
+        ]
+    );
+}
+
+#[test]
+fn test_metal_up_to_date() {
+    let root = Path::new("target/metal_up_to_date");
+    let _ = fs::remove_dir_all(root);
+    fs::create_dir_all(root).unwrap();
+    fs::write(root.join("common.h"), "").unwrap();
+    fs::write(root.join("blur.metal"), "").unwrap();
+    assert!(!is_up_to_date(
+        &root.join("blur.metal"),
+        &root.join("blur.air")
+    ));
+
+    fs::write(root.join("blur.air"), "").unwrap();
+    fs::write(
+        depfile(&root.join("blur.air")),
+        format!("blur.air: {}\n", root.join("common.h").display()),
+    )
+    .unwrap();
+    assert!(is_up_to_date(
+        &root.join("blur.metal"),
+        &root.join("blur.air")
+    ));
+
+    let later = SystemTime::now() + std::time::Duration::from_secs(60);
+    fs::File::options()
+        .write(true)
+        .open(root.join("common.h"))
+        .unwrap()
+        .set_modified(later)
+        .unwrap();
+    assert!(!is_up_to_date(
+        &root.join("blur.metal"),
+        &root.join("blur.air")
+    ));
+
+    fs::remove_dir_all(root).unwrap();
+}
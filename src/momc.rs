@@ -0,0 +1,182 @@
+//! Compiling Core Data models with momc from Xcode.
+//!
+//! A versioned model (`.xcdatamodeld`) contains one `.xcdatamodel` per version and compiles to a
+//! `.momd` directory, a single `.xcdatamodel` compiles to a `.mom` file.
+
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+
+use crate::build_script::BuildScriptOutput;
+use crate::plist::Value;
+use crate::tool::{CompileError, Tool};
+
+/// Options for [`compile_data_model`].
+#[derive(Clone, Debug)]
+pub struct MomcOptions {
+    /// Platform the deployment target applies to (e.g. `macosx`, `iphoneos`)
+    pub platform: String,
+    /// Oldest OS version the model must support (`--<platform>-deployment-target`)
+    pub minimum_deployment_target: Option<String>,
+    /// Swift module of the generated entity classes (`--module`)
+    pub module: Option<String>,
+    /// Report the model as an input of a build script
+    pub build_script: Option<BuildScriptOutput>,
+}
+
+impl Default for MomcOptions {
+    fn default() -> Self {
+        MomcOptions {
+            platform: "macosx".to_string(),
+            minimum_deployment_target: None,
+            module: None,
+            build_script: None,
+        }
+    }
+}
+
+/// The current version of a versioned model, as selected in Xcode (e.g. `Model 2`).
+///
+/// Returns `None` if the model doesn't specify one, in which case momc uses the only version.
+pub fn current_model_version(xcdatamodeld: &Path) -> Result<Option<String>, Error> {
+    let path = xcdatamodeld.join(".xccurrentversion");
+    if !path.exists() {
+        return Ok(None);
+    }
+    let version = Value::read(&path)?
+        .as_dictionary()
+        .and_then(|d| d.get("_XCCurrentVersionName"))
+        .and_then(Value::as_str)
+        .map(|name| name.trim_end_matches(".xcdatamodel").to_string());
+    Ok(version)
+}
+
+/// Compile a Core Data model into a bundle's resources. Returns the compiled model, a `.momd`
+/// directory for versioned models or a `.mom` file otherwise.
+///
+/// For versioned models the current version is checked to exist before momc is run.
+///
+/// # Arguments
+///
+/// * `source` - the `.xcdatamodeld` or `.xcdatamodel` directory
+/// * `resources_dir` - the bundle's resources directory
+/// * `options` - deployment target and module
+///
+/// # Example
+///
+/// ```no_run
+/// use std::path::Path;
+/// use toolbelt::momc::{compile_data_model, MomcOptions};
+///
+/// let momd = compile_data_model(
+///     Path::new("Model.xcdatamodeld"),
+///     Path::new("target/MyApp.app/Contents/Resources"),
+///     &MomcOptions::default(),
+/// )
+/// .unwrap();
+/// ```
+pub fn compile_data_model(
+    source: &Path,
+    resources_dir: &Path,
+    options: &MomcOptions,
+) -> Result<PathBuf, CompileError> {
+    let versioned = match source.extension().and_then(|e| e.to_str()) {
+        Some("xcdatamodeld") => true,
+        Some("xcdatamodel") => false,
+        _ => {
+            return Err(CompileError::Io(Error::new(
+                ErrorKind::InvalidInput,
+                format!("{} is not a Core Data model", source.display()),
+            )))
+        }
+    };
+    if !source.is_dir() {
+        return Err(CompileError::Io(Error::new(
+            ErrorKind::NotFound,
+            format!("Core Data model {} not found", source.display()),
+        )));
+    }
+    if let Some(output) = &options.build_script {
+        output.rerun_if_changed(source);
+    }
+
+    if versioned {
+        if let Some(version) = current_model_version(source)? {
+            let model = source.join(format!("{}.xcdatamodel", version));
+            if !model.is_dir() {
+                return Err(CompileError::Io(Error::new(
+                    ErrorKind::NotFound,
+                    format!(
+                        "Current version {} of {} not found",
+                        version,
+                        source.display()
+                    ),
+                )));
+            }
+            debug!("Current version of {} is {}", source.display(), version);
+        }
+    }
+
+    fs::create_dir_all(resources_dir)?;
+    let stem = source.file_stem().unwrap_or_default().to_string_lossy();
+    let output = resources_dir.join(format!(
+        "{}.{}",
+        stem,
+        if versioned { "momd" } else { "mom" }
+    ));
+
+    let mut momc = Tool::new("xcrun");
+    momc.arg("momc");
+    if let Some(target) = &options.minimum_deployment_target {
+        momc.arg(format!("--{}-deployment-target", options.platform))
+            .arg(target);
+    }
+    if let Some(module) = &options.module {
+        momc.arg("--module").arg(module);
+    }
+    momc.arg(source)
+        .arg(&output)
+        .run()
+        .map_err(|e| CompileError::Tool {
+            file: source.to_path_buf(),
+            source: e,
+        })?;
+    Ok(output)
+}
+
+#[test]
+fn test_compile_data_model_versions() {
+    let root = Path::new("target/compile_data_model");
+    let _ = fs::remove_dir_all(root);
+    let model = root.join("Model.xcdatamodeld");
+    fs::create_dir_all(model.join("Model.xcdatamodel")).unwrap();
+
+    let mut current = crate::plist::Dictionary::new();
+    current.insert(
+        "_XCCurrentVersionName".to_string(),
+        "Model 2.xcdatamodel".into(),
+    );
+    Value::Dictionary(current)
+        .write(&model.join(".xccurrentversion"))
+        .unwrap();
+    assert_eq!(
+        current_model_version(&model).unwrap().as_deref(),
+        Some("Model 2")
+    );
+
+    // the current version is missing
+    assert!(matches!(
+        compile_data_model(&model, &root.join("Resources"), &MomcOptions::default()),
+        Err(CompileError::Io(_))
+    ));
+    assert!(matches!(
+        compile_data_model(
+            &root.join("Model"),
+            &root.join("Resources"),
+            &MomcOptions::default()
+        ),
+        Err(CompileError::Io(_))
+    ));
+
+    fs::remove_dir_all(root).unwrap();
+}
@@ -0,0 +1,315 @@
+//! Notarizing signed packages with Apple's notary service.
+//!
+//! The package is uploaded with `xcrun notarytool`, its status polled until Apple has checked it,
+//! and the ticket stapled to the package with `xcrun stapler` once it is accepted.
+
+use std::fmt;
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::json;
+use crate::tool::{Tool, ToolError};
+use crate::xcrun;
+
+/// Options for [`notarize_with_options`].
+#[derive(Clone, Debug)]
+pub struct NotarizeOptions {
+    /// How long to wait for Apple to check the submission before giving up with
+    /// [`NotarizeError::Timeout`]
+    pub timeout: Duration,
+    /// Time between two status requests while Apple checks the submission
+    pub poll_interval: Duration,
+}
+
+impl Default for NotarizeOptions {
+    /// Wait up to an hour, polling every 30 seconds.
+    fn default() -> Self {
+        NotarizeOptions {
+            timeout: Duration::from_secs(60 * 60),
+            poll_interval: Duration::from_secs(30),
+        }
+    }
+}
+
+/// How to authenticate with the notary service.
+#[derive(Clone, Debug)]
+pub enum NotaryCredentials {
+    /// Credentials stored with `xcrun notarytool store-credentials` (recommended)
+    KeychainProfile(String),
+    /// An Apple ID with an app-specific password
+    AppleId {
+        apple_id: String,
+        team_id: String,
+        password: String,
+    },
+    /// An App Store Connect API key
+    ApiKey {
+        key: PathBuf,
+        key_id: String,
+        issuer: String,
+    },
+}
+
+impl NotaryCredentials {
+    fn apply(&self, tool: &mut Tool) {
+        match self {
+            NotaryCredentials::KeychainProfile(profile) => {
+                tool.arg("--keychain-profile").arg(profile);
+            }
+            NotaryCredentials::AppleId {
+                apple_id,
+                team_id,
+                password,
+            } => {
+                tool.arg("--apple-id")
+                    .arg(apple_id)
+                    .arg("--team-id")
+                    .arg(team_id)
+                    .arg("--password")
+                    .secret_arg(password);
+            }
+            NotaryCredentials::ApiKey {
+                key,
+                key_id,
+                issuer,
+            } => {
+                tool.arg("--key")
+                    .arg(key)
+                    .arg("--key-id")
+                    .arg(key_id)
+                    .arg("--issuer")
+                    .arg(issuer);
+            }
+        }
+    }
+}
+
+/// Errors reported by [`notarize`].
+#[derive(Debug)]
+pub enum NotarizeError {
+    Io(Error),
+    /// notarytool, ditto or stapler failed
+    Tool(ToolError),
+    /// Apple didn't accept the package. `log` is the notary log listing the issues.
+    Rejected {
+        id: String,
+        status: String,
+        log: String,
+    },
+    /// Apple was still checking submission `id` when [`NotarizeOptions::timeout`] expired.
+    Timeout {
+        id: String,
+        waited: Duration,
+    },
+}
+
+impl fmt::Display for NotarizeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NotarizeError::Io(e) => write!(f, "{}", e),
+            NotarizeError::Tool(e) => write!(f, "{}", e),
+            NotarizeError::Rejected { id, status, log } => {
+                write!(f, "Notarization {} failed with status {}", id, status)?;
+                if !log.trim().is_empty() {
+                    write!(f, "\n{}", log.trim())?;
+                }
+                Ok(())
+            }
+            NotarizeError::Timeout { id, waited } => write!(
+                f,
+                "Notarization {} still in progress after {:?}, check it with \
+                 `xcrun notarytool info {}`",
+                id, waited, id
+            ),
+        }
+    }
+}
+
+impl std::error::Error for NotarizeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            NotarizeError::Io(e) => Some(e),
+            NotarizeError::Tool(e) => Some(e),
+            NotarizeError::Rejected { .. } | NotarizeError::Timeout { .. } => None,
+        }
+    }
+}
+
+impl From<Error> for NotarizeError {
+    fn from(e: Error) -> Self {
+        NotarizeError::Io(e)
+    }
+}
+
+impl From<ToolError> for NotarizeError {
+    fn from(e: ToolError) -> Self {
+        NotarizeError::Tool(e)
+    }
+}
+
+/// Run a notarytool command with JSON output and return the parsed result.
+fn notarytool(
+    args: &[&str],
+    credentials: &NotaryCredentials,
+) -> Result<json::Value, NotarizeError> {
+    let mut tool = xcrun::tool("notarytool", None)?;
+    tool.args(args);
+    credentials.apply(&mut tool);
+    let output = tool.args(["--output-format", "json"]).run()?;
+    json::parse(&output.stdout).map_err(NotarizeError::from)
+}
+
+fn field(value: &json::Value, name: &str) -> Result<String, NotarizeError> {
+    value
+        .get(name)
+        .and_then(json::Value::as_str)
+        .map(str::to_string)
+        .ok_or_else(|| {
+            NotarizeError::Io(Error::new(
+                ErrorKind::InvalidData,
+                format!("notarytool output lacks {}: {}", name, value),
+            ))
+        })
+}
+
+/// Notarize a signed app, disk image, installer package or zip archive.
+///
+/// App bundles are zipped for the upload. On success the notarization ticket is stapled to the
+/// package (zip archives can't be stapled) and the submission id is returned.
+///
+/// # Arguments
+///
+/// * `package` - the signed `.app`, `.dmg`, `.pkg` or `.zip`
+/// * `credentials` - how to authenticate with the notary service
+///
+/// # Example
+///
+/// ```no_run
+/// use std::path::Path;
+/// use toolbelt::notarize::{notarize, NotaryCredentials};
+///
+/// let credentials = NotaryCredentials::KeychainProfile("notary".to_string());
+/// notarize(Path::new("target/MyApp.dmg"), &credentials).unwrap();
+/// ```
+pub fn notarize(package: &Path, credentials: &NotaryCredentials) -> Result<String, NotarizeError> {
+    notarize_with_options(package, credentials, &NotarizeOptions::default())
+}
+
+/// Notarize a package like [`notarize`], with a custom timeout and poll interval.
+///
+/// # Arguments
+///
+/// * `package` - the signed `.app`, `.dmg`, `.pkg` or `.zip`
+/// * `credentials` - how to authenticate with the notary service
+/// * `options` - how long to wait for the result
+///
+/// # Example
+///
+/// ```no_run
+/// use std::path::Path;
+/// use std::time::Duration;
+/// use toolbelt::notarize::{notarize_with_options, NotarizeOptions, NotaryCredentials};
+///
+/// let credentials = NotaryCredentials::KeychainProfile("notary".to_string());
+/// let options = NotarizeOptions {
+///     timeout: Duration::from_secs(20 * 60),
+///     ..Default::default()
+/// };
+/// notarize_with_options(Path::new("target/MyApp.dmg"), &credentials, &options).unwrap();
+/// ```
+pub fn notarize_with_options(
+    package: &Path,
+    credentials: &NotaryCredentials,
+    options: &NotarizeOptions,
+) -> Result<String, NotarizeError> {
+    let archive = if package.is_dir() {
+        let archive = package.with_extension("notarize.zip");
+        Tool::new("ditto")
+            .args(["-c", "-k", "--keepParent"])
+            .arg(package)
+            .arg(&archive)
+            .run()?;
+        Some(archive)
+    } else {
+        None
+    };
+    let upload = archive.as_deref().unwrap_or(package);
+
+    info!("Submitting {} for notarization", package.display());
+    let upload_arg = upload.to_string_lossy();
+    let submission = notarytool(&["submit", &upload_arg], credentials);
+    if let Some(archive) = &archive {
+        let _ = fs::remove_file(archive);
+    }
+    let id = field(&submission?, "id")?;
+
+    let started = Instant::now();
+    let status = loop {
+        let status = field(&notarytool(&["info", &id], credentials)?, "status")?;
+        if status != "In Progress" {
+            break status;
+        }
+        let waited = started.elapsed();
+        if waited >= options.timeout {
+            return Err(NotarizeError::Timeout { id, waited });
+        }
+        debug!("Notarization {} in progress", id);
+        std::thread::sleep(options.poll_interval.min(options.timeout - waited));
+    };
+
+    if status != "Accepted" {
+        let log = match notarytool(&["log", &id], credentials) {
+            Ok(log) => log.to_pretty_string(),
+            Err(e) => format!("Failed to fetch the notary log: {}", e),
+        };
+        return Err(NotarizeError::Rejected { id, status, log });
+    }
+
+    if package.extension().is_some_and(|e| e == "zip") {
+        info!("Notarization {} accepted", id);
+    } else {
+        xcrun::tool("stapler", None)?
+            .arg("staple")
+            .arg(package)
+            .run()?;
+        info!("Notarization {} accepted and stapled", id);
+    }
+    Ok(id)
+}
+
+#[test]
+fn test_notary_credentials() {
+    let mut tool = Tool::new("notarytool");
+    NotaryCredentials::AppleId {
+        apple_id: "dev@example.com".to_string(),
+        team_id: "ABCDE12345".to_string(),
+        password: "abcd-efgh-ijkl-mnop".to_string(),
+    }
+    .apply(&mut tool);
+    assert_eq!(
+        tool.command_line(),
+        "notarytool --apple-id dev@example.com --team-id ABCDE12345 --password ***"
+    );
+
+    let error = NotarizeError::Rejected {
+        id: "2efe2717".to_string(),
+        status: "Invalid".to_string(),
+        log: "The binary is not signed.".to_string(),
+    };
+    assert_eq!(
+        error.to_string(),
+        "Notarization 2efe2717 failed with status Invalid\nThe binary is not signed."
+    );
+
+    let error = NotarizeError::Timeout {
+        id: "2efe2717".to_string(),
+        waited: Duration::from_secs(3600),
+    };
+    assert_eq!(
+        error.to_string(),
+        "Notarization 2efe2717 still in progress after 3600s, check it with \
+         `xcrun notarytool info 2efe2717`"
+    );
+}
@@ -0,0 +1,223 @@
+//! Audit file permissions of staged bundles and package trees.
+//!
+//! Only available on Unix platforms.
+
+use std::fs::{File, Permissions};
+use std::io::{Error, Read};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+use walkdir::WalkDir;
+
+const SETUID: u32 = 0o4000;
+const SETGID: u32 = 0o2000;
+const WORLD_WRITABLE: u32 = 0o002;
+const EXECUTABLE: u32 = 0o111;
+
+/// Kind of problem found by [`audit_permissions`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PermissionIssueKind {
+    /// Anyone may modify the file or directory
+    WorldWritable,
+    /// The setuid bit is set
+    SetUid,
+    /// The setgid bit is set
+    SetGid,
+    /// An executable (Mach-O, ELF or script) lacks execute permission
+    NotExecutable,
+}
+
+/// A problem found by [`audit_permissions`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PermissionIssue {
+    pub path: PathBuf,
+    pub kind: PermissionIssueKind,
+    /// The file mode at the time of the audit
+    pub mode: u32,
+}
+
+/// Which issues [`fix_permissions`] is allowed to correct.
+#[derive(Clone, Debug)]
+pub struct PermissionPolicy {
+    /// Remove write permission for others
+    pub clear_world_writable: bool,
+    /// Remove setuid and setgid bits
+    pub clear_setuid_setgid: bool,
+    /// Add execute permission wherever read permission is granted
+    pub make_binaries_executable: bool,
+}
+
+impl Default for PermissionPolicy {
+    fn default() -> Self {
+        PermissionPolicy {
+            clear_world_writable: true,
+            clear_setuid_setgid: true,
+            make_binaries_executable: true,
+        }
+    }
+}
+
+impl PermissionPolicy {
+    fn allows(&self, kind: PermissionIssueKind) -> bool {
+        match kind {
+            PermissionIssueKind::WorldWritable => self.clear_world_writable,
+            PermissionIssueKind::SetUid | PermissionIssueKind::SetGid => self.clear_setuid_setgid,
+            PermissionIssueKind::NotExecutable => self.make_binaries_executable,
+        }
+    }
+}
+
+/// Check whether a file starts with a Mach-O, ELF or script (`#!`) header.
+fn is_executable_format(path: &Path) -> Result<bool, Error> {
+    let mut magic = [0u8; 4];
+    let mut file = File::open(path)?;
+    let mut read = 0;
+    while read < magic.len() {
+        match file.read(&mut magic[read..])? {
+            0 => break,
+            n => read += n,
+        }
+    }
+    if read >= 2 && magic[..2] == *b"#!" {
+        return Ok(true);
+    }
+    if read < 4 {
+        return Ok(false);
+    }
+    Ok(matches!(
+        magic,
+        [0x7f, b'E', b'L', b'F']
+            | [0xfe, 0xed, 0xfa, 0xce]
+            | [0xfe, 0xed, 0xfa, 0xcf]
+            | [0xce, 0xfa, 0xed, 0xfe]
+            | [0xcf, 0xfa, 0xed, 0xfe]
+            | [0xca, 0xfe, 0xba, 0xbe]
+    ))
+}
+
+/// Report world-writable entries, setuid/setgid bits and executables without execute permission
+/// inside a directory tree.
+///
+/// Symbolic links are not followed.
+///
+/// # Arguments
+///
+/// * `root` - the bundle or package directory to audit
+pub fn audit_permissions(root: &Path) -> Result<Vec<PermissionIssue>, Error> {
+    let mut issues = Vec::new();
+
+    for entry in WalkDir::new(root) {
+        let entry = entry?;
+        if entry.file_type().is_symlink() {
+            continue;
+        }
+        let mode = entry.metadata()?.permissions().mode();
+        let mut report = |kind| {
+            issues.push(PermissionIssue {
+                path: entry.path().to_path_buf(),
+                kind,
+                mode,
+            })
+        };
+
+        if mode & WORLD_WRITABLE != 0 {
+            report(PermissionIssueKind::WorldWritable);
+        }
+        if mode & SETUID != 0 {
+            report(PermissionIssueKind::SetUid);
+        }
+        if mode & SETGID != 0 && !entry.file_type().is_dir() {
+            report(PermissionIssueKind::SetGid);
+        }
+        if entry.file_type().is_file()
+            && mode & EXECUTABLE == 0
+            && is_executable_format(entry.path())?
+        {
+            report(PermissionIssueKind::NotExecutable);
+        }
+    }
+
+    Ok(issues)
+}
+
+/// Audit a directory tree and correct all issues the policy allows to fix.
+///
+/// Returns the issues that were fixed. Issues the policy doesn't cover are left untouched and
+/// can be found with another call to [`audit_permissions`].
+///
+/// # Arguments
+///
+/// * `root` - the bundle or package directory to fix
+/// * `policy` - which kinds of issues to correct
+pub fn fix_permissions(
+    root: &Path,
+    policy: &PermissionPolicy,
+) -> Result<Vec<PermissionIssue>, Error> {
+    let mut fixed = Vec::new();
+
+    for issue in audit_permissions(root)? {
+        if !policy.allows(issue.kind) {
+            continue;
+        }
+        let mode = std::fs::symlink_metadata(&issue.path)?.permissions().mode();
+        let new_mode = match issue.kind {
+            PermissionIssueKind::WorldWritable => mode & !WORLD_WRITABLE,
+            PermissionIssueKind::SetUid => mode & !SETUID,
+            PermissionIssueKind::SetGid => mode & !SETGID,
+            // grant execute permission to everyone who may read the file
+            PermissionIssueKind::NotExecutable => mode | ((mode & 0o444) >> 2),
+        };
+        debug!(
+            "Change mode of {} from {:o} to {:o}",
+            issue.path.display(),
+            mode,
+            new_mode
+        );
+        std::fs::set_permissions(&issue.path, Permissions::from_mode(new_mode))?;
+        fixed.push(issue);
+    }
+
+    Ok(fixed)
+}
+
+#[test]
+fn test_audit_permissions() {
+    let root = Path::new("target/audit_permissions");
+    let _ = std::fs::remove_dir_all(root);
+    std::fs::create_dir_all(root).unwrap();
+
+    let script = root.join("run.sh");
+    std::fs::write(&script, "#!/bin/sh\n").unwrap();
+    std::fs::set_permissions(&script, Permissions::from_mode(0o644)).unwrap();
+    let data = root.join("data.txt");
+    std::fs::write(&data, "data").unwrap();
+    std::fs::set_permissions(&data, Permissions::from_mode(0o666)).unwrap();
+
+    let mut kinds: Vec<_> = audit_permissions(root)
+        .unwrap()
+        .into_iter()
+        .map(|issue| (issue.path, issue.kind))
+        .collect();
+    kinds.sort_by(|a, b| a.0.cmp(&b.0));
+    assert_eq!(
+        kinds,
+        [
+            (data.clone(), PermissionIssueKind::WorldWritable),
+            (script.clone(), PermissionIssueKind::NotExecutable)
+        ]
+    );
+
+    assert_eq!(
+        fix_permissions(root, &PermissionPolicy::default())
+            .unwrap()
+            .len(),
+        2
+    );
+    assert!(audit_permissions(root).unwrap().is_empty());
+    assert_eq!(
+        std::fs::metadata(&script).unwrap().permissions().mode() & 0o777,
+        0o755
+    );
+
+    std::fs::remove_dir_all(root).unwrap();
+}
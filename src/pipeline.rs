@@ -0,0 +1,870 @@
+//! Building several related products of a workspace in one run.
+//!
+//! A [`Pipeline`] builds applications, other bundles and command line tools into one staging
+//! directory, gives them all the same version and signs them with the same identity, and returns
+//! a combined [`PipelineReport`]. Custom [`Step`]s run in between, in the order they are added.
+
+use std::fmt;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::bundle::{install_executable, AppBundleBuilder, BundleBuilder};
+use crate::codesign::CodesignOptions;
+pub use crate::context::Context;
+use crate::context::{Secrets, TargetProfile};
+use crate::notarize::NotarizeError;
+use crate::plist::{Dictionary, Value};
+use crate::tool::{CompileError, ToolError};
+
+/// A product built by a [`Pipeline`].
+pub enum Product {
+    App(AppBundleBuilder),
+    Bundle(BundleBuilder),
+    /// A command line tool, installed into `bin` of the staging directory
+    Tool {
+        name: String,
+        executable: PathBuf,
+    },
+}
+
+impl Product {
+    fn name(&self) -> String {
+        match self {
+            Product::App(app) => bundle_name(&app.info_plist()),
+            Product::Bundle(bundle) => bundle_name(&bundle.info_plist()),
+            Product::Tool { name, .. } => name.clone(),
+        }
+    }
+
+    /// The version the product is built with, `None` for tools.
+    fn version(&self) -> Option<String> {
+        let info = match self {
+            Product::App(app) => app.info_plist(),
+            Product::Bundle(bundle) => bundle.info_plist(),
+            Product::Tool { .. } => return None,
+        };
+        info.get("CFBundleShortVersionString")
+            .and_then(Value::as_str)
+            .map(str::to_string)
+    }
+}
+
+fn bundle_name(info: &Dictionary) -> String {
+    info.get("CFBundleName")
+        .and_then(Value::as_str)
+        .unwrap_or_default()
+        .to_string()
+}
+
+/// A custom step of a [`Pipeline`], e.g. running a tool toolbelt doesn't wrap.
+///
+/// Steps declaring inputs and outputs are skipped while their outputs are newer than their
+/// inputs.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::io::Error;
+/// use std::path::PathBuf;
+/// use toolbelt::pipeline::{Context, Step};
+/// use toolbelt::tool::Tool;
+///
+/// struct CompileSchemes;
+///
+/// impl Step for CompileSchemes {
+///     fn name(&self) -> String {
+///         "compile schemes".to_string()
+///     }
+///     fn inputs(&self) -> Vec<PathBuf> {
+///         vec![PathBuf::from("schemes")]
+///     }
+///     fn outputs(&self) -> Vec<PathBuf> {
+///         vec![PathBuf::from("target/schemes.bin")]
+///     }
+///     fn run(&self, _context: &Context) -> Result<(), Error> {
+///         Tool::new("schemec").args(["schemes", "-o", "target/schemes.bin"]).run()
+///             .map(|_| ())
+///             .map_err(Error::other)
+///     }
+/// }
+/// ```
+pub trait Step {
+    /// Short name shown in logs and reports.
+    fn name(&self) -> String;
+
+    /// Files or directories the step reads.
+    fn inputs(&self) -> Vec<PathBuf> {
+        Vec::new()
+    }
+
+    /// Files or directories the step writes.
+    fn outputs(&self) -> Vec<PathBuf> {
+        Vec::new()
+    }
+
+    /// What the step would do, shown in dry runs. Defaults to the name.
+    fn describe(&self) -> String {
+        self.name()
+    }
+
+    fn run(&self, context: &Context) -> Result<(), Error>;
+}
+
+/// Modification time of a file, or of the newest file below a directory.
+fn newest_modification(path: &Path) -> Result<SystemTime, Error> {
+    let mut newest = std::fs::metadata(path)?.modified()?;
+    if path.is_dir() {
+        for entry in walkdir::WalkDir::new(path).min_depth(1) {
+            let modified = entry
+                .map_err(Error::from)?
+                .metadata()
+                .map_err(Error::from)?
+                .modified()?;
+            newest = newest.max(modified);
+        }
+    }
+    Ok(newest)
+}
+
+/// Modification time of a file, or of the oldest file below a directory.
+fn oldest_modification(path: &Path) -> Result<SystemTime, Error> {
+    let mut oldest: Option<SystemTime> = None;
+    if path.is_dir() {
+        for entry in walkdir::WalkDir::new(path).min_depth(1) {
+            let entry = entry.map_err(Error::from)?;
+            if entry.file_type().is_dir() {
+                continue;
+            }
+            let modified = entry.metadata().map_err(Error::from)?.modified()?;
+            oldest = Some(oldest.map_or(modified, |oldest| oldest.min(modified)));
+        }
+    }
+    match oldest {
+        Some(oldest) => Ok(oldest),
+        None => std::fs::metadata(path)?.modified(),
+    }
+}
+
+/// `true` if all outputs exist and every output file is newer than all inputs. Steps without
+/// declared inputs or outputs are never up to date.
+pub fn is_up_to_date(inputs: &[PathBuf], outputs: &[PathBuf]) -> Result<bool, Error> {
+    if inputs.is_empty() || outputs.is_empty() || !outputs.iter().all(|o| o.exists()) {
+        return Ok(false);
+    }
+    let mut newest_input = SystemTime::UNIX_EPOCH;
+    for input in inputs {
+        if !input.exists() {
+            return Ok(false);
+        }
+        newest_input = newest_input.max(newest_modification(input)?);
+    }
+    for output in outputs {
+        if oldest_modification(output)? < newest_input {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// How a step or product was handled.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StepOutcome {
+    Ran,
+    /// Skipped because the outputs are newer than the inputs
+    UpToDate,
+    /// Only rendered, because of a dry run
+    Planned,
+}
+
+impl fmt::Display for StepOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            StepOutcome::Ran => "ran",
+            StepOutcome::UpToDate => "up to date",
+            StepOutcome::Planned => "planned",
+        })
+    }
+}
+
+/// Outcome of one product or step.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StepReport {
+    pub name: String,
+    pub outcome: StepOutcome,
+    /// The built bundles or tools, or the outputs a step declares
+    pub outputs: Vec<PathBuf>,
+    /// Command lines of the tools the step ran via [`Context::run_tool`]
+    pub commands: Vec<String>,
+    pub duration: Duration,
+}
+
+/// Combined outcome of a [`Pipeline`] run.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PipelineReport {
+    /// The version all products were built with, if any product has one
+    pub version: Option<String>,
+    /// The signing identity, `None` if the products weren't signed
+    pub identity: Option<String>,
+    /// Products and steps in the order they were handled
+    pub steps: Vec<StepReport>,
+}
+
+impl PipelineReport {
+    /// Total time spent on the products and steps.
+    pub fn duration(&self) -> Duration {
+        self.steps.iter().map(|s| s.duration).sum()
+    }
+}
+
+impl fmt::Display for PipelineReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Pipeline of {} steps (version {}, {}) took {:.1?}",
+            self.steps.len(),
+            self.version.as_deref().unwrap_or("none"),
+            match &self.identity {
+                Some(identity) => format!("signed by {}", identity),
+                None => "unsigned".to_string(),
+            },
+            self.duration()
+        )?;
+        for step in &self.steps {
+            writeln!(
+                f,
+                "  {:<24} {:<10} {:>8.1?}",
+                step.name, step.outcome, step.duration
+            )?;
+            for command in &step.commands {
+                writeln!(f, "    $ {}", command)?;
+            }
+            for output in &step.outputs {
+                writeln!(f, "    {}", output.display())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Category of a pipeline failure, telling who has to act on it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FailureKind {
+    /// Invalid configuration or input, e.g. inconsistent versions, a missing or malformed file
+    Configuration,
+    /// A required tool isn't installed
+    MissingTool,
+    /// An external tool such as codesign or notarytool failed
+    ToolFailed,
+    /// A built artifact failed a check, e.g. a signature or dSYM mismatch or a rejected
+    /// notarization
+    Verification,
+    /// The product violates a policy, e.g. an App Store requirement
+    Policy,
+    /// The build machine got in the way, e.g. missing permissions or a full disk
+    Environment,
+}
+
+impl FailureKind {
+    /// Classify an error returned by a product build or step.
+    ///
+    /// Errors wrapping a [`PolicyViolation`], [`VerifyError`], [`NotarizeError`],
+    /// [`CompileError`] or [`ToolError`] are classified by their type, other errors by their
+    /// [`ErrorKind`].
+    pub fn of(error: &Error) -> FailureKind {
+        if let Some(inner) = error.get_ref() {
+            if inner.is::<PolicyViolation>() {
+                return FailureKind::Policy;
+            }
+            if inner.is::<VerifyError>() {
+                return FailureKind::Verification;
+            }
+            if let Some(e) = inner.downcast_ref::<NotarizeError>() {
+                return match e {
+                    NotarizeError::Io(e) => FailureKind::of(e),
+                    NotarizeError::Tool(e) => FailureKind::of_tool(e),
+                    NotarizeError::Rejected { .. } => FailureKind::Verification,
+                    NotarizeError::Timeout { .. } => FailureKind::ToolFailed,
+                };
+            }
+            if let Some(e) = inner.downcast_ref::<CompileError>() {
+                return match e {
+                    CompileError::Io(e) => FailureKind::of(e),
+                    CompileError::Tool { source, .. } => FailureKind::of_tool(source),
+                };
+            }
+            if let Some(e) = inner.downcast_ref::<ToolError>() {
+                return FailureKind::of_tool(e);
+            }
+        }
+        match error.kind() {
+            ErrorKind::InvalidInput | ErrorKind::InvalidData | ErrorKind::NotFound => {
+                FailureKind::Configuration
+            }
+            _ => FailureKind::Environment,
+        }
+    }
+
+    fn of_tool(error: &ToolError) -> FailureKind {
+        match error {
+            ToolError::Spawn { .. } => FailureKind::MissingTool,
+            ToolError::Failed { .. } | ToolError::Timeout { .. } => FailureKind::ToolFailed,
+        }
+    }
+
+    /// Exit code for command line tools, distinct per kind so CI can route failures.
+    pub fn exit_code(self) -> i32 {
+        match self {
+            FailureKind::Configuration => 2,
+            FailureKind::MissingTool => 3,
+            FailureKind::ToolFailed => 4,
+            FailureKind::Verification => 5,
+            FailureKind::Policy => 6,
+            FailureKind::Environment => 7,
+        }
+    }
+}
+
+impl fmt::Display for FailureKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            FailureKind::Configuration => "configuration error",
+            FailureKind::MissingTool => "missing tool",
+            FailureKind::ToolFailed => "tool failure",
+            FailureKind::Verification => "verification failure",
+            FailureKind::Policy => "policy violation",
+            FailureKind::Environment => "environment error",
+        })
+    }
+}
+
+/// A violated policy, wrapped in an [`Error`] by [`policy_violation`].
+#[derive(Debug)]
+pub struct PolicyViolation(pub String);
+
+impl fmt::Display for PolicyViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for PolicyViolation {}
+
+/// An error classified as [`FailureKind::Policy`].
+pub fn policy_violation(message: String) -> Error {
+    Error::other(PolicyViolation(message))
+}
+
+/// A built artifact that failed a check, wrapped in an [`Error`] by [`verification_failure`].
+#[derive(Debug)]
+pub struct VerifyError(pub String);
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for VerifyError {}
+
+/// An error classified as [`FailureKind::Verification`], of kind [`ErrorKind::InvalidData`].
+pub fn verification_failure(message: String) -> Error {
+    Error::new(ErrorKind::InvalidData, VerifyError(message))
+}
+
+/// Error returned by [`Pipeline::run`].
+#[derive(Debug)]
+pub struct PipelineError {
+    pub kind: FailureKind,
+    /// The product or step that failed, `None` if the pipeline failed before
+    pub step: Option<String>,
+    pub source: Error,
+}
+
+impl PipelineError {
+    fn new(step: Option<String>, source: Error) -> PipelineError {
+        PipelineError {
+            kind: FailureKind::of(&source),
+            step,
+            source,
+        }
+    }
+
+    /// Print the error and exit with the exit code of its kind.
+    pub fn exit(&self) -> ! {
+        eprintln!("{}", self);
+        std::process::exit(self.kind.exit_code());
+    }
+}
+
+impl fmt::Display for PipelineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.step {
+            Some(step) => write!(f, "{} failed ({}): {}", step, self.kind, self.source),
+            None => write!(f, "Pipeline failed ({}): {}", self.kind, self.source),
+        }
+    }
+}
+
+impl std::error::Error for PipelineError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+impl From<Error> for PipelineError {
+    fn from(e: Error) -> Self {
+        PipelineError::new(None, e)
+    }
+}
+
+enum Task {
+    Product(Box<Product>),
+    Step(Box<dyn Step>),
+}
+
+/// Builds related products (e.g. main app, helper app and command line tool) together.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::path::Path;
+/// use toolbelt::bundle::AppBundleBuilder;
+/// use toolbelt::pipeline::Pipeline;
+/// use toolbelt::CodesignOptions;
+///
+/// let report = Pipeline::new(Path::new("target/dist"))
+///     .version("1.2.0")
+///     .sign(CodesignOptions {
+///         identity: "Developer ID Application: Example Inc (ABCDE12345)".to_string(),
+///         hardened_runtime: true,
+///         ..Default::default()
+///     })
+///     .app(AppBundleBuilder::new("MyApp", "com.example.myapp")
+///         .executable(Path::new("target/release/myapp")))
+///     .app(AppBundleBuilder::new("MyApp Helper", "com.example.myapp.helper")
+///         .executable(Path::new("target/release/helper")))
+///     .tool("mytool", Path::new("target/release/mytool"))
+///     .run()
+///     .unwrap();
+/// println!("{}", report);
+/// ```
+pub struct Pipeline {
+    staging_dir: PathBuf,
+    version: Option<String>,
+    signing: Option<CodesignOptions>,
+    dry_run: bool,
+    target_profile: TargetProfile,
+    secrets: Secrets,
+    tasks: Vec<Task>,
+}
+
+impl Pipeline {
+    /// # Arguments
+    ///
+    /// * `staging_dir` - the directory all products are built into
+    pub fn new(staging_dir: &Path) -> Pipeline {
+        Pipeline {
+            staging_dir: staging_dir.to_path_buf(),
+            version: None,
+            signing: None,
+            dry_run: false,
+            target_profile: TargetProfile::default(),
+            secrets: Secrets::new(),
+            tasks: Vec::new(),
+        }
+    }
+
+    /// Build all products with this version. Without it, all bundles must already have the same
+    /// version.
+    pub fn version(mut self, version: &str) -> Pipeline {
+        self.version = Some(version.to_string());
+        self
+    }
+
+    /// Sign all products with these options. Each product keeps its own code identifier.
+    pub fn sign(mut self, options: CodesignOptions) -> Pipeline {
+        self.signing = Some(options);
+        self
+    }
+
+    pub fn app(self, app: AppBundleBuilder) -> Pipeline {
+        self.product(Product::App(app))
+    }
+
+    pub fn bundle(self, bundle: BundleBuilder) -> Pipeline {
+        self.product(Product::Bundle(bundle))
+    }
+
+    pub fn tool(self, name: &str, executable: &Path) -> Pipeline {
+        self.product(Product::Tool {
+            name: name.to_string(),
+            executable: executable.to_path_buf(),
+        })
+    }
+
+    pub fn product(mut self, product: Product) -> Pipeline {
+        self.tasks.push(Task::Product(Box::new(product)));
+        self
+    }
+
+    /// Add a custom step, run after the products and steps added before it.
+    pub fn step<S: Step + 'static>(mut self, step: S) -> Pipeline {
+        self.tasks.push(Task::Step(Box::new(step)));
+        self
+    }
+
+    /// Only report what would be built and run, without changing anything.
+    pub fn dry_run(mut self, dry_run: bool) -> Pipeline {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// The target and profile to build for, available to steps via [`Context::target_profile`].
+    /// Defaults to the target and profile cargo passes to build scripts.
+    pub fn target_profile(mut self, target_profile: TargetProfile) -> Pipeline {
+        self.target_profile = target_profile;
+        self
+    }
+
+    /// Secrets available to steps via [`Context::secret`].
+    pub fn secrets(mut self, secrets: Secrets) -> Pipeline {
+        self.secrets = secrets;
+        self
+    }
+
+    fn products(&self) -> impl Iterator<Item = &Product> {
+        self.tasks.iter().filter_map(|task| match task {
+            Task::Product(product) => Some(product.as_ref()),
+            Task::Step(_) => None,
+        })
+    }
+
+    /// Check that all products share one version, returning it.
+    fn consistent_version(&self) -> Result<Option<String>, Error> {
+        if let Some(version) = &self.version {
+            return Ok(Some(version.clone()));
+        }
+        let mut first: Option<(String, String)> = None;
+        for product in self.products() {
+            let Some(version) = product.version() else {
+                continue;
+            };
+            match &first {
+                None => first = Some((product.name(), version)),
+                Some((name, expected)) if *expected != version => {
+                    return Err(Error::new(
+                        ErrorKind::InvalidInput,
+                        format!(
+                            "Inconsistent versions: {} has {}, {} has {}",
+                            name,
+                            expected,
+                            product.name(),
+                            version
+                        ),
+                    ))
+                }
+                Some(_) => {}
+            }
+        }
+        Ok(first.map(|(_, version)| version))
+    }
+
+    /// Build all products and run all steps in the order they were added.
+    ///
+    /// Versions are checked before anything is built. The first failing product or step stops
+    /// the run, the error tells which one and classifies the failure.
+    pub fn run(self) -> Result<PipelineReport, PipelineError> {
+        let version = self.consistent_version()?;
+        let signing = self.signing.as_ref().map(|options| CodesignOptions {
+            identifier: None,
+            ..options.clone()
+        });
+        let context = Context::new(
+            &self.staging_dir,
+            self.dry_run,
+            self.target_profile,
+            self.secrets,
+        );
+        if !self.dry_run {
+            std::fs::create_dir_all(&self.staging_dir)?;
+        }
+
+        let mut report = PipelineReport {
+            version,
+            identity: signing.as_ref().map(|s| s.identity.clone()),
+            steps: Vec::new(),
+        };
+        for task in self.tasks {
+            let start = Instant::now();
+            let (name, outcome, outputs) = match task {
+                Task::Product(product) => {
+                    let name = product.name();
+                    if self.dry_run {
+                        info!("Would build {}", name);
+                        (name, StepOutcome::Planned, Vec::new())
+                    } else {
+                        info!("Building {}", name);
+                        let path = build_product(
+                            *product,
+                            &self.staging_dir,
+                            self.version.as_deref(),
+                            signing.as_ref(),
+                        )
+                        .map_err(|e| PipelineError::new(Some(name.clone()), e))?;
+                        (name, StepOutcome::Ran, vec![path])
+                    }
+                }
+                Task::Step(step) => {
+                    let outputs = step.outputs();
+                    let outcome = if self.dry_run {
+                        info!("Would run {}", step.describe());
+                        StepOutcome::Planned
+                    } else if is_up_to_date(&step.inputs(), &outputs)
+                        .map_err(|e| PipelineError::new(Some(step.name()), e))?
+                    {
+                        debug!("{} is up to date", step.name());
+                        StepOutcome::UpToDate
+                    } else {
+                        info!("Running {}", step.name());
+                        context.enter_step(&step.name());
+                        step.run(&context)
+                            .map_err(|e| PipelineError::new(Some(step.name()), e))?;
+                        StepOutcome::Ran
+                    };
+                    (step.name(), outcome, outputs)
+                }
+            };
+            report.steps.push(StepReport {
+                name,
+                outcome,
+                outputs,
+                commands: context.take_commands(),
+                duration: start.elapsed(),
+            });
+        }
+        Ok(report)
+    }
+}
+
+/// Build a product with the pipeline's version and signing options applied.
+fn build_product(
+    product: Product,
+    staging_dir: &Path,
+    version: Option<&str>,
+    signing: Option<&CodesignOptions>,
+) -> Result<PathBuf, Error> {
+    match product {
+        Product::App(mut app) => {
+            if let Some(version) = version {
+                app = app.version(version);
+            }
+            if let Some(signing) = signing {
+                app = app.sign(signing.clone());
+            }
+            app.build(staging_dir)
+        }
+        Product::Bundle(mut bundle) => {
+            if let Some(version) = version {
+                bundle = bundle.version(version);
+            }
+            let path = bundle.build(staging_dir)?;
+            if let Some(signing) = signing {
+                crate::codesign_with_options(&path, signing).map_err(Error::other)?;
+            }
+            Ok(path)
+        }
+        Product::Tool { name, executable } => {
+            let bin = staging_dir.join("bin");
+            std::fs::create_dir_all(&bin)?;
+            let path = bin.join(name);
+            install_executable(&executable, &path)?;
+            if let Some(signing) = signing {
+                crate::codesign_with_options(&path, signing).map_err(Error::other)?;
+            }
+            Ok(path)
+        }
+    }
+}
+
+#[test]
+fn test_pipeline() {
+    use crate::bundle::BundleKind;
+
+    let root = Path::new("target/pipeline");
+    let _ = std::fs::remove_dir_all(root);
+
+    let mismatch = Pipeline::new(root)
+        .app(AppBundleBuilder::new("Main", "com.example.main").version("1.0"))
+        .app(AppBundleBuilder::new("Helper", "com.example.main.helper").version("1.1"))
+        .run()
+        .unwrap_err();
+    assert_eq!(mismatch.kind, FailureKind::Configuration);
+    assert_eq!(
+        mismatch.to_string(),
+        "Pipeline failed (configuration error): Inconsistent versions: Main has 1.0, Helper has 1.1"
+    );
+    assert!(!root.exists());
+
+    let report = Pipeline::new(root)
+        .version("2.0")
+        .app(AppBundleBuilder::new("Main", "com.example.main").version("1.0"))
+        .bundle(BundleBuilder::new(
+            BundleKind::Plugin,
+            "Plugin",
+            "com.example.main.plugin",
+        ))
+        .tool("tool", Path::new("test/my_files/file1.txt"))
+        .run()
+        .unwrap();
+    assert_eq!(report.version.as_deref(), Some("2.0"));
+    let paths: Vec<_> = report
+        .steps
+        .iter()
+        .flat_map(|s| s.outputs.clone())
+        .collect();
+    assert_eq!(
+        paths,
+        [
+            root.join("Main.app"),
+            root.join("Plugin.plugin"),
+            root.join("bin/tool")
+        ]
+    );
+    let info = crate::bundle::read_info_plist(&root.join("Plugin.plugin")).unwrap();
+    assert_eq!(info["CFBundleShortVersionString"].as_str(), Some("2.0"));
+    assert!(report
+        .to_string()
+        .starts_with("Pipeline of 3 steps (version 2.0, unsigned)"));
+
+    std::fs::remove_dir_all(root).unwrap();
+}
+
+#[test]
+fn test_pipeline_steps() {
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    struct Stamp {
+        runs: Rc<Cell<u32>>,
+        input: PathBuf,
+        output: PathBuf,
+    }
+    impl Step for Stamp {
+        fn name(&self) -> String {
+            "stamp".to_string()
+        }
+        fn inputs(&self) -> Vec<PathBuf> {
+            vec![self.input.clone()]
+        }
+        fn outputs(&self) -> Vec<PathBuf> {
+            vec![self.output.clone()]
+        }
+        fn run(&self, context: &Context) -> Result<(), Error> {
+            assert!(!context.is_dry_run());
+            self.runs.set(self.runs.get() + 1);
+            std::fs::write(&self.output, "done")
+        }
+    }
+
+    let root = Path::new("target/pipeline_steps");
+    let _ = std::fs::remove_dir_all(root);
+    std::fs::create_dir_all(root).unwrap();
+    let runs = Rc::new(Cell::new(0));
+    let pipeline = || {
+        Pipeline::new(root).step(Stamp {
+            runs: runs.clone(),
+            input: PathBuf::from("test/my_files"),
+            output: root.join("stamp"),
+        })
+    };
+
+    let planned = pipeline().dry_run(true).run().unwrap();
+    assert_eq!(planned.steps[0].outcome, StepOutcome::Planned);
+    assert_eq!(runs.get(), 0);
+
+    assert_eq!(pipeline().run().unwrap().steps[0].outcome, StepOutcome::Ran);
+    // the output is newer than all input files now
+    assert_eq!(
+        pipeline().run().unwrap().steps[0].outcome,
+        StepOutcome::UpToDate
+    );
+    assert_eq!(runs.get(), 1);
+
+    std::fs::remove_dir_all(root).unwrap();
+}
+
+#[test]
+fn test_is_up_to_date() {
+    use std::fs::File;
+
+    let root = Path::new("target/is_up_to_date");
+    let _ = std::fs::remove_dir_all(root);
+    std::fs::create_dir_all(root.join("out")).unwrap();
+    let hour_ago = SystemTime::now() - Duration::from_secs(3600);
+    let touch = |path: &Path, modified: SystemTime| {
+        File::create(path).unwrap().set_modified(modified).unwrap();
+    };
+    touch(&root.join("input"), hour_ago);
+    touch(&root.join("out/fresh"), SystemTime::now());
+    touch(&root.join("out/stale"), hour_ago - Duration::from_secs(60));
+
+    let inputs = [root.join("input")];
+    // one output file is older than the input
+    assert!(!is_up_to_date(&inputs, &[root.join("out")]).unwrap());
+    touch(&root.join("out/stale"), SystemTime::now());
+    assert!(is_up_to_date(&inputs, &[root.join("out")]).unwrap());
+    assert!(!is_up_to_date(&inputs, &[root.join("missing")]).unwrap());
+
+    std::fs::remove_dir_all(root).unwrap();
+}
+
+#[test]
+fn test_failure_kind() {
+    let missing = Error::other(ToolError::Spawn {
+        program: "notarytool".to_string(),
+        source: Error::from(ErrorKind::NotFound),
+    });
+    assert_eq!(FailureKind::of(&missing), FailureKind::MissingTool);
+    assert_eq!(
+        FailureKind::of(&policy_violation("ATS disabled".to_string())),
+        FailureKind::Policy
+    );
+    assert_eq!(
+        FailureKind::of(&verification_failure("dSYM mismatch".to_string())),
+        FailureKind::Verification
+    );
+    assert_eq!(
+        FailureKind::of(&Error::other(NotarizeError::Rejected {
+            id: "2efe2717".to_string(),
+            status: "Invalid".to_string(),
+            log: String::new(),
+        })),
+        FailureKind::Verification
+    );
+    assert_eq!(
+        FailureKind::of(&Error::other(CompileError::Io(Error::from(
+            ErrorKind::PermissionDenied
+        )))),
+        FailureKind::Environment
+    );
+    // a malformed input file isn't a verification failure
+    assert_eq!(
+        FailureKind::of(&Error::new(ErrorKind::InvalidData, "invalid plist")),
+        FailureKind::Configuration
+    );
+    assert_eq!(
+        FailureKind::of(&Error::from(ErrorKind::NotFound)),
+        FailureKind::Configuration
+    );
+    assert_eq!(
+        FailureKind::of(&Error::from(ErrorKind::PermissionDenied)),
+        FailureKind::Environment
+    );
+
+    let error = PipelineError::new(Some("notarize".to_string()), missing);
+    assert_eq!(error.kind.exit_code(), 3);
+    assert!(error
+        .to_string()
+        .starts_with("notarize failed (missing tool): "));
+}
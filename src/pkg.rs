@@ -0,0 +1,178 @@
+//! Building macOS installer packages with pkgbuild and productbuild.
+//!
+//! A component package installs one payload, e.g. an app, to a fixed location. A product archive
+//! combines component packages into the installer users open, and is what gets signed with a
+//! "Developer ID Installer" certificate and notarized.
+
+use std::path::{Path, PathBuf};
+
+use crate::tool::{Tool, ToolError};
+
+/// Options for [`build_component_package`].
+#[derive(Clone, Debug)]
+pub struct PkgOptions {
+    /// Package identifier, e.g. `com.example.myapp.pkg`
+    pub identifier: String,
+    /// Package version, defaults to the version of the package being built
+    pub version: String,
+    /// Where the payload is installed (`--install-location`)
+    pub install_location: String,
+    /// Directory with `preinstall` and `postinstall` scripts (`--scripts`)
+    pub scripts: Option<PathBuf>,
+    /// "Developer ID Installer" identity to sign with (`--sign`)
+    pub signing_identity: Option<String>,
+}
+
+impl Default for PkgOptions {
+    fn default() -> Self {
+        PkgOptions {
+            identifier: String::new(),
+            version: std::env::var("CARGO_PKG_VERSION").unwrap_or_else(|_| "1.0.0".into()),
+            install_location: "/Applications".to_string(),
+            scripts: None,
+            signing_identity: None,
+        }
+    }
+}
+
+impl PkgOptions {
+    fn apply(&self, tool: &mut Tool) {
+        tool.arg("--identifier")
+            .arg(&self.identifier)
+            .arg("--version")
+            .arg(&self.version)
+            .arg("--install-location")
+            .arg(&self.install_location);
+        if let Some(scripts) = &self.scripts {
+            tool.arg("--scripts").arg(scripts);
+        }
+        if let Some(identity) = &self.signing_identity {
+            tool.arg("--sign").arg(identity);
+        }
+    }
+}
+
+/// Build a component package with pkgbuild.
+///
+/// An `.app` bundle is packaged as component, keeping its bundle layout; any other directory is
+/// packaged as root whose contents are installed into the install location.
+///
+/// # Arguments
+///
+/// * `payload` - the `.app` bundle or directory to install
+/// * `output` - the `.pkg` to write
+/// * `options` - identifier, version, install location, scripts and signing
+///
+/// # Example
+///
+/// ```no_run
+/// use std::path::Path;
+/// use toolbelt::pkg::{build_component_package, PkgOptions};
+///
+/// build_component_package(
+///     Path::new("target/MyApp.app"),
+///     Path::new("target/MyApp-component.pkg"),
+///     &PkgOptions {
+///         identifier: "com.example.myapp.pkg".to_string(),
+///         ..Default::default()
+///     },
+/// )
+/// .unwrap();
+/// ```
+pub fn build_component_package(
+    payload: &Path,
+    output: &Path,
+    options: &PkgOptions,
+) -> Result<(), ToolError> {
+    let mut pkgbuild = Tool::new("pkgbuild");
+    if payload.extension().is_some_and(|e| e == "app") {
+        pkgbuild.arg("--component").arg(payload);
+    } else {
+        pkgbuild.arg("--root").arg(payload);
+    }
+    options.apply(&mut pkgbuild);
+    pkgbuild.arg(output).run()?;
+    Ok(())
+}
+
+/// Build a product archive from component packages with productbuild.
+///
+/// # Arguments
+///
+/// * `packages` - the component packages built with [`build_component_package`]
+/// * `distribution` - a distribution file customizing the installer, `None` uses the default
+/// * `signing_identity` - "Developer ID Installer" identity to sign with
+/// * `output` - the `.pkg` to write
+pub fn build_product_archive<P: AsRef<Path>>(
+    packages: &[P],
+    distribution: Option<&Path>,
+    signing_identity: Option<&str>,
+    output: &Path,
+) -> Result<(), ToolError> {
+    let mut productbuild = Tool::new("productbuild");
+    product_archive_args(&mut productbuild, packages, distribution, signing_identity);
+    productbuild.arg(output).run()?;
+    Ok(())
+}
+
+fn product_archive_args<P: AsRef<Path>>(
+    tool: &mut Tool,
+    packages: &[P],
+    distribution: Option<&Path>,
+    signing_identity: Option<&str>,
+) {
+    match distribution {
+        Some(distribution) => {
+            tool.arg("--distribution").arg(distribution);
+            // the distribution file references the packages by name
+            let mut dirs: Vec<&Path> = Vec::new();
+            for package in packages {
+                let dir = package.as_ref().parent().unwrap_or(Path::new("."));
+                if !dirs.contains(&dir) {
+                    dirs.push(dir);
+                }
+            }
+            for dir in dirs {
+                tool.arg("--package-path").arg(dir);
+            }
+        }
+        None => {
+            for package in packages {
+                tool.arg("--package").arg(package.as_ref());
+            }
+        }
+    }
+    if let Some(identity) = signing_identity {
+        tool.arg("--sign").arg(identity);
+    }
+}
+
+#[test]
+fn test_pkg_arguments() {
+    let mut pkgbuild = Tool::new("pkgbuild");
+    PkgOptions {
+        identifier: "com.example.myapp.pkg".to_string(),
+        version: "1.2.0".to_string(),
+        scripts: Some("scripts".into()),
+        signing_identity: Some("Developer ID Installer: Example".to_string()),
+        ..Default::default()
+    }
+    .apply(&mut pkgbuild);
+    assert_eq!(
+        pkgbuild.command_line(),
+        "pkgbuild --identifier com.example.myapp.pkg --version 1.2.0 --install-location \
+         /Applications --scripts scripts --sign \"Developer ID Installer: Example\""
+    );
+
+    let mut productbuild = Tool::new("productbuild");
+    product_archive_args(
+        &mut productbuild,
+        &["target/a.pkg", "target/b.pkg"],
+        Some(Path::new("distribution.xml")),
+        None,
+    );
+    assert_eq!(
+        productbuild.command_line(),
+        "productbuild --distribution distribution.xml --package-path target"
+    );
+}
@@ -0,0 +1,251 @@
+//! Compiler and linker flags of system libraries from pkg-config.
+//!
+//! The flags are split into the same pieces the SDK helpers work with: include directories,
+//! library directories and defines, plus the libraries to link. So a system library can be added
+//! to an [`crate::SdkSet`] like a vendored SDK.
+
+use std::env;
+use std::io::{Error, ErrorKind};
+use std::path::PathBuf;
+
+use crate::tool::Tool;
+use crate::xcrun::version_at_least;
+use crate::{DefineFormat, IncludeDir, IncludeDirFormat, LibDirFormat};
+
+/// Split pkg-config output into flags. pkg-config escapes spaces within a flag with a backslash.
+fn split_flags(output: &str) -> Vec<String> {
+    let mut flags = Vec::new();
+    let mut flag = String::new();
+    let mut chars = output.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => flag.extend(chars.next()),
+            c if c.is_whitespace() => {
+                if !flag.is_empty() {
+                    flags.push(std::mem::take(&mut flag));
+                }
+            }
+            c => flag.push(c),
+        }
+    }
+    if !flag.is_empty() {
+        flags.push(flag);
+    }
+    flags
+}
+
+/// The value of a flag given either joined (`-I/usr/include`) or as the following argument
+/// (`-I /usr/include`).
+fn flag_value<'a>(
+    flag: &'a str,
+    prefix: &str,
+    flags: &mut impl Iterator<Item = &'a String>,
+) -> Option<&'a str> {
+    match flag.strip_prefix(prefix)? {
+        "" => flags.next().map(String::as_str),
+        value => Some(value),
+    }
+}
+
+/// The flags pkg-config reports for a package.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PkgConfig {
+    pub package: String,
+    /// The installed version, from `pkg-config --modversion`
+    pub version: String,
+    /// The installation prefix, from the package's `prefix` variable
+    pub prefix: Option<PathBuf>,
+    /// Directories of `-I` flags
+    pub include_dirs: Vec<PathBuf>,
+    /// Directories of `-L` flags
+    pub lib_dirs: Vec<PathBuf>,
+    /// Libraries of `-l` flags
+    pub libs: Vec<String>,
+    /// Defines of `-D` flags
+    pub defines: Vec<(String, Option<String>)>,
+    /// All other compiler flags
+    pub other_cflags: Vec<String>,
+    /// All other linker flags, e.g. `-pthread` or `-framework Foo`
+    pub other_libs: Vec<String>,
+}
+
+impl PkgConfig {
+    /// Sort the output of `pkg-config --cflags` and `pkg-config --libs` into the fields.
+    fn parse(package: &str, version: &str, cflags: &str, libs: &str) -> PkgConfig {
+        let mut config = PkgConfig {
+            package: package.to_string(),
+            version: version.to_string(),
+            ..PkgConfig::default()
+        };
+        let cflags = split_flags(cflags);
+        let mut cflags = cflags.iter();
+        while let Some(flag) = cflags.next() {
+            if let Some(dir) = flag_value(flag, "-I", &mut cflags) {
+                config.include_dirs.push(PathBuf::from(dir));
+            } else if let Some(define) = flag_value(flag, "-D", &mut cflags) {
+                config.defines.push(match define.split_once('=') {
+                    Some((name, value)) => (name.to_string(), Some(value.to_string())),
+                    None => (define.to_string(), None),
+                });
+            } else {
+                config.other_cflags.push(flag.clone());
+            }
+        }
+        let libs = split_flags(libs);
+        let mut libs = libs.iter();
+        while let Some(flag) = libs.next() {
+            if let Some(dir) = flag_value(flag, "-L", &mut libs) {
+                config.lib_dirs.push(PathBuf::from(dir));
+            } else if let Some(lib) = flag_value(flag, "-l", &mut libs) {
+                config.libs.push(lib.to_string());
+            } else {
+                config.other_libs.push(flag.clone());
+            }
+        }
+        config
+    }
+
+    /// The include directories, formatted like [`crate::get_sdk_include_paths`].
+    pub fn include_dir_flags(&self, format: IncludeDirFormat) -> Vec<IncludeDir> {
+        self.include_dirs
+            .iter()
+            .map(|path| IncludeDir {
+                path: path.clone(),
+                flag: format.format(path),
+            })
+            .collect()
+    }
+
+    /// The library directories, formatted like [`crate::get_sdk_lib_dirs`].
+    pub fn lib_dir_flags(&self, format: LibDirFormat) -> Vec<String> {
+        self.lib_dirs.iter().map(|dir| format.format(dir)).collect()
+    }
+
+    /// The defines, formatted like [`crate::get_sdk_defines`].
+    pub fn define_flags(&self, format: DefineFormat) -> Vec<String> {
+        crate::get_sdk_defines(&self.defines, format)
+    }
+
+    /// Print the `cargo:rustc-link-search` and `cargo:rustc-link-lib` lines linking the package.
+    pub fn emit_cargo_metadata(&self) {
+        for dir in &self.lib_dirs {
+            println!("cargo:rustc-link-search=native={}", dir.display());
+        }
+        for lib in &self.libs {
+            println!("cargo:rustc-link-lib={}", lib);
+        }
+    }
+}
+
+/// Query pkg-config for the compiler and linker flags of a package. The `PKG_CONFIG`
+/// environment variable selects another pkg-config executable, `PKG_CONFIG_PATH` and the other
+/// pkg-config variables are honored as usual.
+///
+/// # Arguments
+///
+/// * `package` - The package name, e.g. `zlib` or `gtk4`
+/// * `min_version` - The oldest supported version, compared numerically by dotted components
+///
+/// # Example
+///
+/// ```no_run
+/// use toolbelt::{pkg_config_flags, IncludeDirFormat};
+///
+/// let zlib = pkg_config_flags("zlib", Some("1.2.11")).unwrap();
+/// let include_dirs = zlib.include_dir_flags(IncludeDirFormat::CLANG);
+/// zlib.emit_cargo_metadata();
+/// ```
+pub fn pkg_config_flags(package: &str, min_version: Option<&str>) -> Result<PkgConfig, Error> {
+    let program = env::var("PKG_CONFIG").unwrap_or_else(|_| "pkg-config".to_string());
+    let query = |args: &[&str]| -> Result<String, Error> {
+        Tool::new(&program)
+            .args(args)
+            .arg(package)
+            .run()
+            .map(|output| output.stdout.trim().to_string())
+            .map_err(Error::other)
+    };
+    let version = query(&["--modversion"])?;
+    if let Some(min_version) = min_version {
+        if !version_at_least(&version, min_version) {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "{} {} is installed, but at least {} is required",
+                    package, version, min_version
+                ),
+            ));
+        }
+    }
+    let mut config = PkgConfig::parse(
+        package,
+        &version,
+        &query(&["--cflags"])?,
+        &query(&["--libs"])?,
+    );
+    config.prefix = Some(query(&["--variable=prefix"])?)
+        .filter(|prefix| !prefix.is_empty())
+        .map(PathBuf::from);
+    debug!("pkg-config found {} {}", package, version);
+    Ok(config)
+}
+
+#[test]
+fn test_pkg_config_flags() {
+    let config = PkgConfig::parse(
+        "foo",
+        "1.2",
+        "-I/opt/foo/include -I /opt/foo/include/My\\ Dir -DFOO_STATIC -DFOO_API=3 -pthread",
+        "-L/opt/foo/lib -lfoo -l bar -Wl,--as-needed",
+    );
+    assert_eq!(
+        config.include_dirs,
+        [
+            PathBuf::from("/opt/foo/include"),
+            PathBuf::from("/opt/foo/include/My Dir")
+        ]
+    );
+    assert_eq!(
+        config.define_flags(DefineFormat::MSVC),
+        ["/DFOO_STATIC", "/DFOO_API=3"]
+    );
+    assert_eq!(config.other_cflags, ["-pthread"]);
+    assert_eq!(
+        config.lib_dir_flags(LibDirFormat::CLANG),
+        ["-L/opt/foo/lib"]
+    );
+    assert_eq!(config.libs, ["foo", "bar"]);
+    assert_eq!(config.other_libs, ["-Wl,--as-needed"]);
+    assert_eq!(
+        config.include_dir_flags(IncludeDirFormat::ISYSTEM)[0].flag,
+        "-isystem/opt/foo/include"
+    );
+
+    // with pkg-config installed, query a generated package
+    let root = std::env::current_dir()
+        .unwrap()
+        .join("target/pkg_config_flags");
+    let _ = std::fs::remove_dir_all(&root);
+    std::fs::create_dir_all(&root).unwrap();
+    std::fs::write(
+        root.join("toolbelt-test.pc"),
+        format!(
+            "prefix={}\nName: toolbelt-test\nDescription: test\nVersion: 2.4.1\n\
+             Cflags: -I${{prefix}}/include -DTOOLBELT_TEST\nLibs: -L${{prefix}}/lib -ltoolbelt\n",
+            root.display()
+        ),
+    )
+    .unwrap();
+    std::env::set_var("PKG_CONFIG_PATH", &root);
+    if let Ok(config) = pkg_config_flags("toolbelt-test", Some("2.4")) {
+        assert_eq!(config.version, "2.4.1");
+        assert_eq!(config.prefix.as_deref(), Some(root.as_path()));
+        assert_eq!(config.include_dirs, [root.join("include")]);
+        assert_eq!(config.libs, ["toolbelt"]);
+        let e = pkg_config_flags("toolbelt-test", Some("2.10")).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidData);
+    }
+    assert!(pkg_config_flags("toolbelt-missing", None).is_err());
+
+    std::fs::remove_dir_all(&root).unwrap();
+}
@@ -0,0 +1,725 @@
+//! Property lists as used for Info.plist and entitlements files.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+
+use crate::tool::{Tool, ToolError};
+
+/// A property list dictionary. Keys are kept sorted like Xcode does.
+pub type Dictionary = BTreeMap<String, Value>;
+
+/// A property list value.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    String(String),
+    Integer(i64),
+    Real(f64),
+    Boolean(bool),
+    Data(Vec<u8>),
+    /// ISO 8601 date as written in the property list, e.g. `2024-01-31T12:00:00Z`
+    Date(String),
+    Array(Vec<Value>),
+    Dictionary(Dictionary),
+}
+
+impl Value {
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_dictionary(&self) -> Option<&Dictionary> {
+        match self {
+            Value::Dictionary(d) => Some(d),
+            _ => None,
+        }
+    }
+
+    pub fn as_dictionary_mut(&mut self) -> Option<&mut Dictionary> {
+        match self {
+            Value::Dictionary(d) => Some(d),
+            _ => None,
+        }
+    }
+
+    /// Parse an XML property list document.
+    ///
+    /// Binary property lists are not supported.
+    pub fn from_xml(input: &str) -> Result<Value, Error> {
+        let mut parser = XmlParser { input, pos: 0 };
+        loop {
+            match parser.next_tag()? {
+                Some(Tag::Open(name)) if name == "plist" => break,
+                Some(_) => continue,
+                None => return Err(invalid("missing <plist> element")),
+            }
+        }
+        let value = parser.value()?;
+        match parser.next_tag()? {
+            Some(Tag::Close(name)) if name == "plist" => Ok(value),
+            _ => Err(invalid("expected </plist>")),
+        }
+    }
+
+    /// Read a property list file.
+    ///
+    /// Binary property lists are converted with plutil, which is only available on macOS.
+    pub fn read(path: &Path) -> Result<Value, Error> {
+        let mut data = std::fs::read(path)?;
+        if data.starts_with(b"bplist") {
+            if let Ok(output) = Tool::new("plutil")
+                .args(["-convert", "xml1", "-o", "-"])
+                .arg(path)
+                .run()
+            {
+                data = output.stdout.into_bytes();
+            }
+        }
+        if data.starts_with(b"bplist") {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "{} is a binary property list and plutil is not available",
+                    path.display()
+                ),
+            ));
+        }
+        let text = String::from_utf8(data).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+        Value::from_xml(&text)
+            .map_err(|e| Error::new(ErrorKind::InvalidData, format!("{}: {}", path.display(), e)))
+    }
+
+    /// Serialize as XML property list document.
+    pub fn to_xml(&self) -> String {
+        let mut out = String::from(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+             <!DOCTYPE plist PUBLIC \"-//Apple//DTD PLIST 1.0//EN\" \
+             \"http://www.apple.com/DTDs/PropertyList-1.0.dtd\">\n\
+             <plist version=\"1.0\">\n",
+        );
+        self.write_xml(&mut out, 0);
+        out.push_str("</plist>\n");
+        out
+    }
+
+    /// Write as XML property list file.
+    pub fn write(&self, path: &Path) -> Result<(), Error> {
+        std::fs::write(path, self.to_xml())
+    }
+
+    fn write_xml(&self, out: &mut String, indent: usize) {
+        let pad = "\t".repeat(indent);
+        match self {
+            Value::String(s) => {
+                let _ = writeln!(out, "{}<string>{}</string>", pad, escape(s));
+            }
+            Value::Integer(i) => {
+                let _ = writeln!(out, "{}<integer>{}</integer>", pad, i);
+            }
+            Value::Real(r) => {
+                let _ = writeln!(out, "{}<real>{}</real>", pad, r);
+            }
+            Value::Boolean(b) => {
+                let _ = writeln!(out, "{}<{}/>", pad, b);
+            }
+            Value::Data(data) => {
+                let _ = writeln!(out, "{}<data>{}</data>", pad, base64_encode(data));
+            }
+            Value::Date(date) => {
+                let _ = writeln!(out, "{}<date>{}</date>", pad, escape(date));
+            }
+            Value::Array(items) if items.is_empty() => {
+                let _ = writeln!(out, "{}<array/>", pad);
+            }
+            Value::Array(items) => {
+                let _ = writeln!(out, "{}<array>", pad);
+                for item in items {
+                    item.write_xml(out, indent + 1);
+                }
+                let _ = writeln!(out, "{}</array>", pad);
+            }
+            Value::Dictionary(dict) if dict.is_empty() => {
+                let _ = writeln!(out, "{}<dict/>", pad);
+            }
+            Value::Dictionary(dict) => {
+                let _ = writeln!(out, "{}<dict>", pad);
+                for (key, value) in dict {
+                    let _ = writeln!(out, "{}\t<key>{}</key>", pad, escape(key));
+                    value.write_xml(out, indent + 1);
+                }
+                let _ = writeln!(out, "{}</dict>", pad);
+            }
+        }
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Self {
+        Value::String(s.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Self {
+        Value::String(s)
+    }
+}
+
+impl From<i64> for Value {
+    fn from(i: i64) -> Self {
+        Value::Integer(i)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
+        Value::Boolean(b)
+    }
+}
+
+impl From<Dictionary> for Value {
+    fn from(d: Dictionary) -> Self {
+        Value::Dictionary(d)
+    }
+}
+
+impl<T: Into<Value>> From<Vec<T>> for Value {
+    fn from(items: Vec<T>) -> Self {
+        Value::Array(items.into_iter().map(Into::into).collect())
+    }
+}
+
+/// Merge `other` into `base`. Nested dictionaries are merged recursively, other values in
+/// `other` replace those in `base`.
+pub fn merge(base: &mut Dictionary, other: &Dictionary) {
+    for (key, value) in other {
+        match (base.get_mut(key), value) {
+            (Some(Value::Dictionary(existing)), Value::Dictionary(value)) => merge(existing, value),
+            _ => {
+                base.insert(key.clone(), value.clone());
+            }
+        }
+    }
+}
+
+/// An Info.plist being created or patched.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::path::Path;
+/// use toolbelt::plist::{InfoPlist, Value};
+///
+/// let partial = Value::read(Path::new("target/assetcatalog_generated_info.plist")).unwrap();
+/// InfoPlist::read(Path::new("Info.plist"))
+///     .unwrap()
+///     .bundle_identifier("com.example.myapp")
+///     .version_from_cargo()
+///     .minimum_system_version("11.0")
+///     .usage_description("NSCameraUsageDescription", "Scans documents")
+///     .merge(partial.as_dictionary().unwrap())
+///     .write(Path::new("target/MyApp.app/Contents/Info.plist"))
+///     .unwrap();
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct InfoPlist {
+    values: Dictionary,
+}
+
+impl InfoPlist {
+    /// An empty Info.plist.
+    pub fn new() -> InfoPlist {
+        InfoPlist::default()
+    }
+
+    /// Read an existing Info.plist to patch it.
+    pub fn read(path: &Path) -> Result<InfoPlist, Error> {
+        match Value::read(path)? {
+            Value::Dictionary(values) => Ok(InfoPlist { values }),
+            _ => Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("{} is not a dictionary", path.display()),
+            )),
+        }
+    }
+
+    /// Set a value, replacing an existing one.
+    pub fn set<V: Into<Value>>(mut self, key: &str, value: V) -> InfoPlist {
+        self.values.insert(key.to_string(), value.into());
+        self
+    }
+
+    /// Set `CFBundleIdentifier`.
+    pub fn bundle_identifier(self, identifier: &str) -> InfoPlist {
+        self.set("CFBundleIdentifier", identifier)
+    }
+
+    /// Set `CFBundleShortVersionString` to the version and `CFBundleVersion` to its numeric part,
+    /// i.e. without pre-release or build metadata (`1.2.0-beta.1` becomes `1.2.0`).
+    pub fn version(self, version: &str) -> InfoPlist {
+        let build = version.split(['-', '+']).next().unwrap_or(version);
+        self.set("CFBundleShortVersionString", version)
+            .set("CFBundleVersion", build)
+    }
+
+    /// Set the version from the `CARGO_PKG_VERSION` environment variable cargo passes to build
+    /// scripts and tests. The version is left unchanged if the variable is not set.
+    pub fn version_from_cargo(self) -> InfoPlist {
+        match std::env::var("CARGO_PKG_VERSION") {
+            Ok(version) => self.version(&version),
+            Err(_) => {
+                warn!("CARGO_PKG_VERSION is not set, keeping the Info.plist version");
+                self
+            }
+        }
+    }
+
+    /// Set `LSMinimumSystemVersion`, the oldest macOS version the bundle runs on.
+    pub fn minimum_system_version(self, version: &str) -> InfoPlist {
+        self.set("LSMinimumSystemVersion", version)
+    }
+
+    /// Set a privacy usage description shown when the app asks for access, e.g.
+    /// `NSCameraUsageDescription` or `NSMicrophoneUsageDescription`. Other keys are set as well,
+    /// with a warning.
+    pub fn usage_description(self, key: &str, description: &str) -> InfoPlist {
+        if !key.ends_with("UsageDescription") {
+            warn!("{} is no usage description key", key);
+        }
+        self.set(key, description)
+    }
+
+    /// Merge a partial Info.plist, e.g. the one returned by [`crate::compile_asset_catalog`] or
+    /// written by ibtool. Nested dictionaries are merged, other values replaced.
+    pub fn merge(mut self, partial: &Dictionary) -> InfoPlist {
+        merge(&mut self.values, partial);
+        self
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.values.get(key)
+    }
+
+    pub fn as_dictionary(&self) -> &Dictionary {
+        &self.values
+    }
+
+    pub fn into_dictionary(self) -> Dictionary {
+        self.values
+    }
+
+    /// Write as XML property list file. The bundle identifier is validated first.
+    pub fn write(&self, path: &Path) -> Result<(), Error> {
+        if let Some(identifier) = self.get("CFBundleIdentifier").and_then(Value::as_str) {
+            crate::bundle::validate_bundle_identifier(identifier)?;
+        }
+        Value::Dictionary(self.values.clone()).write(path)
+    }
+}
+
+impl From<Dictionary> for InfoPlist {
+    fn from(values: Dictionary) -> Self {
+        InfoPlist { values }
+    }
+}
+
+/// Property list file formats understood by plutil.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Xml,
+    /// The compact binary format Xcode uses for plists in built bundles
+    Binary,
+    Json,
+}
+
+impl Format {
+    /// Name of the format for `plutil -convert`.
+    pub fn plutil_name(self) -> &'static str {
+        match self {
+            Format::Xml => "xml1",
+            Format::Binary => "binary1",
+            Format::Json => "json",
+        }
+    }
+}
+
+/// Convert a property list file to another format with plutil from macOS.
+///
+/// # Arguments
+///
+/// * `source` - the property list to convert
+/// * `format` - the format to convert to
+/// * `destination` - where to write the converted file, `None` converts in place
+///
+/// # Example
+///
+/// ```no_run
+/// use std::path::Path;
+/// use toolbelt::plist::{convert, Format};
+///
+/// convert(Path::new("target/MyApp.app/Contents/Info.plist"), Format::Binary, None).unwrap();
+/// ```
+pub fn convert(source: &Path, format: Format, destination: Option<&Path>) -> Result<(), ToolError> {
+    let mut plutil = Tool::new("plutil");
+    plutil.arg("-convert").arg(format.plutil_name());
+    if let Some(destination) = destination {
+        plutil.arg("-o").arg(destination);
+    }
+    plutil.arg(source).run()?;
+    Ok(())
+}
+
+/// Check property list files for syntax errors with `plutil -lint`.
+///
+/// The error names the invalid files in its output.
+pub fn lint<P: AsRef<Path>>(paths: &[P]) -> Result<(), ToolError> {
+    let mut plutil = Tool::new("plutil");
+    plutil
+        .args(["-lint", "-s"])
+        .args(paths.iter().map(AsRef::as_ref))
+        .run()?;
+    Ok(())
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64_encode(data: &[u8]) -> String {
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let b = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let n = (u32::from(b[0]) << 16) | (u32::from(b[1]) << 8) | u32::from(b[2]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+fn base64_decode(text: &str) -> Result<Vec<u8>, Error> {
+    let mut out = Vec::new();
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for c in text
+        .bytes()
+        .filter(|c| !c.is_ascii_whitespace() && *c != b'=')
+    {
+        let value = BASE64
+            .iter()
+            .position(|b| *b == c)
+            .ok_or_else(|| invalid("invalid base64 data"))?;
+        buffer = (buffer << 6) | value as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((buffer >> bits) as u8);
+        }
+    }
+    Ok(out)
+}
+
+fn invalid(what: &str) -> Error {
+    Error::new(
+        ErrorKind::InvalidData,
+        format!("Invalid property list: {}", what),
+    )
+}
+
+fn unescape(text: &str) -> Result<String, Error> {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        out.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find(';')
+            .ok_or_else(|| invalid("unterminated entity"))?;
+        let entity = &rest[start + 1..start + end];
+        let c = match entity {
+            "amp" => '&',
+            "lt" => '<',
+            "gt" => '>',
+            "quot" => '"',
+            "apos" => '\'',
+            _ => {
+                let code = if let Some(hex) = entity.strip_prefix("#x") {
+                    u32::from_str_radix(hex, 16).ok()
+                } else if let Some(decimal) = entity.strip_prefix('#') {
+                    decimal.parse().ok()
+                } else {
+                    None
+                };
+                code.and_then(char::from_u32)
+                    .ok_or_else(|| invalid("unknown entity"))?
+            }
+        };
+        out.push(c);
+        rest = &rest[start + end + 1..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+enum Tag {
+    Open(String),
+    Close(String),
+    Empty(String),
+}
+
+struct XmlParser<'a> {
+    input: &'a str,
+    pos: usize,
+}
+
+impl XmlParser<'_> {
+    /// Advance to the next element tag, skipping text, comments and declarations.
+    fn next_tag(&mut self) -> Result<Option<Tag>, Error> {
+        loop {
+            let start = match self.input[self.pos..].find('<') {
+                Some(offset) => self.pos + offset,
+                None => return Ok(None),
+            };
+            let rest = &self.input[start..];
+            if rest.starts_with("<!--") {
+                let end = rest
+                    .find("-->")
+                    .ok_or_else(|| invalid("unterminated comment"))?;
+                self.pos = start + end + 3;
+                continue;
+            }
+            let end = rest.find('>').ok_or_else(|| invalid("unterminated tag"))?;
+            self.pos = start + end + 1;
+            let tag = &rest[1..end];
+            if tag.starts_with('?') || tag.starts_with('!') {
+                continue;
+            }
+            if let Some(name) = tag.strip_prefix('/') {
+                return Ok(Some(Tag::Close(name.trim().to_string())));
+            }
+            let (tag, empty) = match tag.strip_suffix('/') {
+                Some(tag) => (tag, true),
+                None => (tag, false),
+            };
+            let name = tag
+                .split_whitespace()
+                .next()
+                .unwrap_or_default()
+                .to_string();
+            return Ok(Some(if empty {
+                Tag::Empty(name)
+            } else {
+                Tag::Open(name)
+            }));
+        }
+    }
+
+    /// Read the text up to the closing tag of `name`.
+    fn text(&mut self, name: &str) -> Result<String, Error> {
+        let close = format!("</{}>", name);
+        let end = self.input[self.pos..]
+            .find(&close)
+            .ok_or_else(|| invalid(&format!("missing {}", close)))?;
+        let text = unescape(&self.input[self.pos..self.pos + end])?;
+        self.pos += end + close.len();
+        Ok(text)
+    }
+
+    fn value(&mut self) -> Result<Value, Error> {
+        match self.next_tag()? {
+            Some(Tag::Open(name)) => self.element(&name),
+            Some(Tag::Empty(name)) => match name.as_str() {
+                "true" => Ok(Value::Boolean(true)),
+                "false" => Ok(Value::Boolean(false)),
+                "string" => Ok(Value::String(String::new())),
+                "data" => Ok(Value::Data(Vec::new())),
+                "array" => Ok(Value::Array(Vec::new())),
+                "dict" => Ok(Value::Dictionary(Dictionary::new())),
+                other => Err(invalid(&format!("unexpected <{}/>", other))),
+            },
+            _ => Err(invalid("expected a value")),
+        }
+    }
+
+    fn element(&mut self, name: &str) -> Result<Value, Error> {
+        match name {
+            "string" => Ok(Value::String(self.text(name)?)),
+            "integer" => {
+                let text = self.text(name)?;
+                let text = text.trim();
+                let parsed = match text.strip_prefix("0x") {
+                    Some(hex) => i64::from_str_radix(hex, 16).ok(),
+                    None => text.parse().ok(),
+                };
+                parsed
+                    .map(Value::Integer)
+                    .ok_or_else(|| invalid("invalid integer"))
+            }
+            "real" => self
+                .text(name)?
+                .trim()
+                .parse()
+                .map(Value::Real)
+                .map_err(|_| invalid("invalid real")),
+            "data" => Ok(Value::Data(base64_decode(&self.text(name)?)?)),
+            "date" => Ok(Value::Date(self.text(name)?.trim().to_string())),
+            "true" | "false" => {
+                self.text(name)?;
+                Ok(Value::Boolean(name == "true"))
+            }
+            "array" => {
+                let mut items = Vec::new();
+                loop {
+                    let pos = self.pos;
+                    if let Some(Tag::Close(close)) = self.next_tag()? {
+                        if close == "array" {
+                            return Ok(Value::Array(items));
+                        }
+                        return Err(invalid("mismatched </array>"));
+                    }
+                    self.pos = pos;
+                    items.push(self.value()?);
+                }
+            }
+            "dict" => {
+                let mut dict = Dictionary::new();
+                loop {
+                    match self.next_tag()? {
+                        Some(Tag::Close(close)) if close == "dict" => {
+                            return Ok(Value::Dictionary(dict))
+                        }
+                        Some(Tag::Open(key)) if key == "key" => {
+                            let key = self.text("key")?;
+                            dict.insert(key, self.value()?);
+                        }
+                        _ => return Err(invalid("expected <key> in <dict>")),
+                    }
+                }
+            }
+            other => Err(invalid(&format!("unexpected <{}>", other))),
+        }
+    }
+}
+
+#[test]
+fn test_plist_xml() {
+    let mut dict = Dictionary::new();
+    dict.insert("CFBundleName".into(), "A & B".into());
+    dict.insert("Count".into(), 3i64.into());
+    dict.insert("Flags".into(), vec![true, false].into());
+    dict.insert("Blob".into(), Value::Data(b"Man".to_vec()));
+    let xml = Value::Dictionary(dict).to_xml();
+
+    assert!(xml.contains("\t<key>CFBundleName</key>\n\t<string>A &amp; B</string>\n"));
+    assert!(xml.contains("<integer>3</integer>"));
+    assert!(xml.contains("\t<array>\n\t\t<true/>\n\t\t<false/>\n\t</array>\n"));
+    assert!(xml.contains("<data>TWFu</data>"));
+    assert_eq!(base64_encode(b"Ma"), "TWE=");
+    assert_eq!(base64_encode(b"M"), "TQ==");
+}
+
+#[test]
+fn test_plist_parse() {
+    let mut dict = Dictionary::new();
+    dict.insert("Name".into(), "<A & 'B'>".into());
+    dict.insert("Count".into(), (-3i64).into());
+    dict.insert("Ratio".into(), Value::Real(0.5));
+    dict.insert("Blob".into(), Value::Data(vec![0, 1, 2, 3, 255]));
+    dict.insert("Empty".into(), Value::Array(Vec::new()));
+    dict.insert("Date".into(), Value::Date("2024-01-31T12:00:00Z".into()));
+    let mut nested = Dictionary::new();
+    nested.insert("Flags".into(), vec![true, false].into());
+    dict.insert("Nested".into(), nested.into());
+    let value = Value::Dictionary(dict);
+
+    assert_eq!(Value::from_xml(&value.to_xml()).unwrap(), value);
+
+    let handwritten = "<?xml version=\"1.0\"?>\n<!-- comment -->\n<plist version=\"1.0\"><dict>\
+        <key>A</key><string>x &#x41;</string><key>B</key><string/></dict></plist>";
+    let parsed = Value::from_xml(handwritten).unwrap();
+    let parsed = parsed.as_dictionary().unwrap();
+    assert_eq!(parsed["A"].as_str(), Some("x A"));
+    assert_eq!(parsed["B"].as_str(), Some(""));
+    assert!(Value::from_xml("<plist><dict><string>x</string></dict></plist>").is_err());
+}
+
+#[test]
+fn test_info_plist() {
+    let mut icons = Dictionary::new();
+    icons.insert("CFBundlePrimaryIcon".into(), "Old".into());
+    icons.insert("Keep".into(), true.into());
+    let info = InfoPlist::new()
+        .bundle_identifier("com.example.app")
+        .version("1.2.0-beta.1")
+        .minimum_system_version("11.0")
+        .usage_description("NSCameraUsageDescription", "Scans documents")
+        .set("CFBundleIcons", icons);
+
+    let mut partial_icons = Dictionary::new();
+    partial_icons.insert("CFBundlePrimaryIcon".into(), "AppIcon".into());
+    let mut partial = Dictionary::new();
+    partial.insert("CFBundleIcons".into(), partial_icons.into());
+    partial.insert("CFBundleIconName".into(), "AppIcon".into());
+    let info = info.merge(&partial);
+
+    assert_eq!(
+        info.get("CFBundleShortVersionString")
+            .and_then(Value::as_str),
+        Some("1.2.0-beta.1")
+    );
+    assert_eq!(
+        info.get("CFBundleVersion").and_then(Value::as_str),
+        Some("1.2.0")
+    );
+    assert_eq!(
+        info.get("CFBundleIconName").and_then(Value::as_str),
+        Some("AppIcon")
+    );
+    let icons = info
+        .get("CFBundleIcons")
+        .and_then(Value::as_dictionary)
+        .unwrap();
+    assert_eq!(icons["CFBundlePrimaryIcon"].as_str(), Some("AppIcon"));
+    assert_eq!(icons["Keep"], Value::Boolean(true));
+
+    // a misspelled key is only warned about
+    let info = info.usage_description("NSCameraUsage", "Scans documents");
+    assert_eq!(
+        info.get("NSCameraUsage").and_then(Value::as_str),
+        Some("Scans documents")
+    );
+
+    let path = Path::new("target/test_info_plist.plist");
+    info.write(path).unwrap();
+    assert_eq!(InfoPlist::read(path).unwrap(), info);
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_plist_binary() {
+    assert_eq!(Format::Binary.plutil_name(), "binary1");
+    let path = Path::new("target/test_plutil_binary.plist");
+    std::fs::write(path, b"bplist00\x08").unwrap();
+    // only macOS can convert binary property lists
+    if cfg!(not(target_os = "macos")) {
+        let error = Value::read(path).unwrap_err();
+        assert!(error.to_string().contains("binary property list"));
+        assert!(matches!(lint(&[path]), Err(ToolError::Spawn { .. })));
+    }
+    std::fs::remove_file(path).unwrap();
+}
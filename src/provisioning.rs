@@ -0,0 +1,309 @@
+//! Inspecting provisioning profiles and embedding them in app bundles.
+//!
+//! A profile (`.mobileprovision` for iOS, `.provisionprofile` for macOS) is a property list
+//! wrapped in a CMS signature. The property list is stored uncompressed inside it, so it is read
+//! without verifying the signature; the signature is checked by the system on installation.
+
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::bundle::validate_bundle_identifier;
+use crate::plist::{Dictionary, Value};
+
+/// The contents of a provisioning profile.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProvisioningProfile {
+    pub name: String,
+    pub uuid: String,
+    /// Teams the profile belongs to, usually one
+    pub team_ids: Vec<String>,
+    /// Platforms the profile is valid for, e.g. `iOS` or `OSX`
+    pub platforms: Vec<String>,
+    /// Entitlements apps signed with the profile may claim
+    pub entitlements: Dictionary,
+    /// ISO 8601 date, e.g. `2025-01-31T12:00:00Z`
+    pub expiration_date: String,
+    /// UDIDs of the devices a development or ad hoc profile is limited to
+    pub devices: Vec<String>,
+    /// `true` for enterprise profiles valid on any device
+    pub provisions_all_devices: bool,
+}
+
+impl ProvisioningProfile {
+    /// Read a profile from a file.
+    pub fn read(path: &Path) -> Result<ProvisioningProfile, Error> {
+        ProvisioningProfile::parse(&fs::read(path)?)
+            .map_err(|e| Error::new(e.kind(), format!("{}: {}", path.display(), e)))
+    }
+
+    /// Parse a profile, signed or as bare property list.
+    pub fn parse(data: &[u8]) -> Result<ProvisioningProfile, Error> {
+        let invalid = |message: &str| Error::new(ErrorKind::InvalidData, message.to_string());
+        let start = find(data, b"<?xml").ok_or_else(|| invalid("no property list found"))?;
+        let end = find(&data[start..], b"</plist>")
+            .ok_or_else(|| invalid("property list is truncated"))?
+            + start
+            + b"</plist>".len();
+        let xml = std::str::from_utf8(&data[start..end])
+            .map_err(|_| invalid("property list is not UTF-8"))?;
+        let Value::Dictionary(profile) = Value::from_xml(xml)? else {
+            return Err(invalid("property list is not a dictionary"));
+        };
+
+        let string = |key: &str| {
+            profile
+                .get(key)
+                .and_then(Value::as_str)
+                .map(String::from)
+                .ok_or_else(|| invalid(&format!("{} is missing", key)))
+        };
+        let strings = |key: &str| -> Vec<String> {
+            match profile.get(key) {
+                Some(Value::Array(values)) => values
+                    .iter()
+                    .filter_map(Value::as_str)
+                    .map(String::from)
+                    .collect(),
+                _ => Vec::new(),
+            }
+        };
+        let expiration_date = match profile.get("ExpirationDate") {
+            Some(Value::Date(date)) => date.clone(),
+            _ => return Err(invalid("ExpirationDate is missing")),
+        };
+
+        Ok(ProvisioningProfile {
+            name: string("Name")?,
+            uuid: string("UUID")?,
+            team_ids: strings("TeamIdentifier"),
+            platforms: strings("Platform"),
+            entitlements: profile
+                .get("Entitlements")
+                .and_then(Value::as_dictionary)
+                .cloned()
+                .unwrap_or_default(),
+            expiration_date,
+            devices: strings("ProvisionedDevices"),
+            provisions_all_devices: profile.get("ProvisionsAllDevices")
+                == Some(&Value::Boolean(true)),
+        })
+    }
+
+    /// The application identifier, `<team id>.<bundle id>`, where the bundle id may end in `*`.
+    pub fn application_identifier(&self) -> Option<&str> {
+        ["application-identifier", "com.apple.application-identifier"]
+            .iter()
+            .find_map(|key| self.entitlements.get(*key))
+            .and_then(Value::as_str)
+    }
+
+    /// `true` if apps with this bundle identifier may be signed with the profile.
+    pub fn matches_bundle_identifier(&self, identifier: &str) -> bool {
+        let Some(pattern) = self
+            .application_identifier()
+            .and_then(|id| id.split_once('.'))
+            .map(|(_, pattern)| pattern)
+        else {
+            return false;
+        };
+        match pattern.strip_suffix('*') {
+            Some(prefix) => identifier.starts_with(prefix),
+            None => identifier == pattern,
+        }
+    }
+
+    /// `true` if the profile has expired.
+    pub fn is_expired(&self) -> bool {
+        // ISO 8601 dates in UTC compare like strings
+        self.expiration_date.as_str() <= iso8601(SystemTime::now()).as_str()
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+/// Format a time as ISO 8601 date in UTC, e.g. `2024-01-31T12:00:00Z`.
+fn iso8601(time: SystemTime) -> String {
+    let seconds = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let (days, rest) = (seconds / 86400, seconds % 86400);
+    // civil date from days since 1970-01-01, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        rest / 3600,
+        rest % 3600 / 60,
+        rest % 60
+    )
+}
+
+/// Embed a provisioning profile in an app bundle, after checking that it hasn't expired and
+/// covers the bundle identifier.
+///
+/// macOS bundles get it as `Contents/embedded.provisionprofile`, iOS bundles as
+/// `embedded.mobileprovision`. Embed the profile before signing the bundle. Returns the path of
+/// the embedded profile.
+///
+/// # Arguments
+///
+/// * `bundle` - the `.app` bundle
+/// * `profile` - the `.mobileprovision` or `.provisionprofile` file
+///
+/// # Example
+///
+/// ```no_run
+/// use std::path::Path;
+/// use toolbelt::provisioning::embed_provisioning_profile;
+///
+/// embed_provisioning_profile(
+///     Path::new("target/MyApp.app"),
+///     Path::new("profiles/MyApp_AppStore.provisionprofile"),
+/// )
+/// .unwrap();
+/// ```
+pub fn embed_provisioning_profile(bundle: &Path, profile: &Path) -> Result<PathBuf, Error> {
+    let parsed = ProvisioningProfile::read(profile)?;
+    let contents = bundle.join("Contents");
+    let (info_plist, embedded) = if contents.is_dir() {
+        (
+            contents.join("Info.plist"),
+            contents.join("embedded.provisionprofile"),
+        )
+    } else {
+        (
+            bundle.join("Info.plist"),
+            bundle.join("embedded.mobileprovision"),
+        )
+    };
+
+    let identifier = match Value::read(&info_plist)? {
+        Value::Dictionary(info) => info
+            .get("CFBundleIdentifier")
+            .and_then(Value::as_str)
+            .map(String::from),
+        _ => None,
+    }
+    .ok_or_else(|| {
+        Error::new(
+            ErrorKind::InvalidData,
+            format!("{}: CFBundleIdentifier is missing", info_plist.display()),
+        )
+    })?;
+    validate_bundle_identifier(&identifier)?;
+    if !parsed.matches_bundle_identifier(&identifier) {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "Provisioning profile {} ({}) doesn't cover {}",
+                parsed.name,
+                parsed
+                    .application_identifier()
+                    .unwrap_or("no application identifier"),
+                identifier
+            ),
+        ));
+    }
+    if parsed.is_expired() {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "Provisioning profile {} expired on {}",
+                parsed.name, parsed.expiration_date
+            ),
+        ));
+    }
+
+    fs::copy(profile, &embedded)?;
+    debug!("Embedded {} in {}", parsed.name, bundle.display());
+    Ok(embedded)
+}
+
+#[test]
+fn test_provisioning_profile() {
+    let root = Path::new("target/provisioning_profile");
+    let _ = fs::remove_dir_all(root);
+    let app = root.join("MyApp.app");
+    fs::create_dir_all(app.join("Contents")).unwrap();
+    let mut info = Dictionary::new();
+    info.insert("CFBundleIdentifier".into(), "com.example.myapp".into());
+    Value::Dictionary(info)
+        .write(&app.join("Contents/Info.plist"))
+        .unwrap();
+
+    let profile = |expiration: &str, app_id: &str| {
+        let mut entitlements = Dictionary::new();
+        entitlements.insert("com.apple.application-identifier".into(), app_id.into());
+        let mut profile = Dictionary::new();
+        profile.insert("Name".into(), "MyApp Development".into());
+        profile.insert("UUID".into(), "0f1e2d3c-4b5a-6978-8796-a5b4c3d2e1f0".into());
+        profile.insert("TeamIdentifier".into(), vec!["ABCDE12345"].into());
+        profile.insert("Platform".into(), vec!["OSX"].into());
+        profile.insert("Entitlements".into(), entitlements.into());
+        profile.insert("ExpirationDate".into(), Value::Date(expiration.into()));
+        profile.insert("ProvisionedDevices".into(), vec!["00008103-0001"].into());
+        // the CMS envelope around the property list
+        let mut data = vec![0x30, 0x80, 0x06, 0x09];
+        data.extend(Value::Dictionary(profile).to_xml().into_bytes());
+        data.extend([0xa0, 0x82, 0x00]);
+        data
+    };
+
+    let path = root.join("dev.provisionprofile");
+    fs::write(
+        &path,
+        profile("2999-12-31T00:00:00Z", "ABCDE12345.com.example.*"),
+    )
+    .unwrap();
+    let parsed = ProvisioningProfile::read(&path).unwrap();
+    assert_eq!(parsed.team_ids, ["ABCDE12345"]);
+    assert_eq!(parsed.devices, ["00008103-0001"]);
+    assert!(!parsed.is_expired());
+    assert!(parsed.matches_bundle_identifier("com.example.myapp"));
+    assert!(!parsed.matches_bundle_identifier("org.example.myapp"));
+    assert_eq!(
+        embed_provisioning_profile(&app, &path).unwrap(),
+        app.join("Contents/embedded.provisionprofile")
+    );
+
+    fs::write(
+        &path,
+        profile("2020-01-01T00:00:00Z", "ABCDE12345.com.example.myapp"),
+    )
+    .unwrap();
+    assert_eq!(
+        embed_provisioning_profile(&app, &path)
+            .unwrap_err()
+            .to_string(),
+        "Provisioning profile MyApp Development expired on 2020-01-01T00:00:00Z"
+    );
+    fs::write(
+        &path,
+        profile("2999-12-31T00:00:00Z", "ABCDE12345.com.example.other"),
+    )
+    .unwrap();
+    assert_eq!(
+        embed_provisioning_profile(&app, &path).unwrap_err().kind(),
+        ErrorKind::InvalidInput
+    );
+
+    assert_eq!(iso8601(UNIX_EPOCH), "1970-01-01T00:00:00Z");
+    assert_eq!(
+        iso8601(UNIX_EPOCH + std::time::Duration::from_secs(1_709_210_096)),
+        "2024-02-29T12:34:56Z"
+    );
+
+    fs::remove_dir_all(root).unwrap();
+}
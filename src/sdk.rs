@@ -0,0 +1,407 @@
+//! Locating vendor SDKs unpacked by the developer.
+//!
+//! An SDK is found through an environment variable holding its path, falling back to a list of
+//! conventional locations. Errors are returned as [`SdkError`] so build scripts can report them
+//! through cargo, or exit with the localized message using [`get_sdk_path_or_exit`]. With the
+//! `http` feature, [`installer::SdkInstaller`] downloads and unpacks a missing SDK instead. Builds
+//! depending on several SDKs describe them with an [`SdkSet`].
+
+use std::env;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use regex::Regex;
+
+use crate::build_script::BuildScriptOutput;
+use crate::messages::{message, Message};
+use crate::xcrun::version_at_least;
+
+#[cfg(feature = "http")]
+pub mod installer;
+pub mod set;
+
+pub use set::{Sdk, SdkSet, SdkSpec};
+
+/// Errors reported by [`get_sdk_path`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SdkError {
+    /// The name isn't a valid environment variable name
+    EnvInvalid { name: String },
+    /// The environment variable isn't set and none of the fallback locations exists
+    EnvNotSet {
+        name: String,
+        fallbacks: Vec<PathBuf>,
+    },
+    /// The SDK directory doesn't exist, it has to be downloaded and unpacked first
+    NotFound { path: PathBuf },
+    /// The version marker is missing or doesn't contain a version
+    VersionUnknown { path: PathBuf, reason: String },
+    /// The SDK is older than required
+    TooOld {
+        path: PathBuf,
+        version: String,
+        minimum: String,
+    },
+}
+
+impl fmt::Display for SdkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SdkError::EnvInvalid { name } => {
+                write!(f, "{}", message(Message::SdkEnvInvalid, &[("name", name)]))
+            }
+            SdkError::EnvNotSet { name, fallbacks } => {
+                let paths = fallbacks
+                    .iter()
+                    .map(|path| path.display().to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                write!(
+                    f,
+                    "{}",
+                    message(Message::SdkEnvNotSet, &[("name", name), ("paths", &paths)])
+                )
+            }
+            SdkError::NotFound { path } => write!(
+                f,
+                "{}",
+                message(Message::SdkMissing, &[("path", &path.display())])
+            ),
+            SdkError::VersionUnknown { path, reason } => write!(
+                f,
+                "{}",
+                message(
+                    Message::SdkVersionUnknown,
+                    &[("path", &path.display()), ("reason", reason)]
+                )
+            ),
+            SdkError::TooOld {
+                path,
+                version,
+                minimum,
+            } => write!(
+                f,
+                "{}",
+                message(
+                    Message::SdkTooOld,
+                    &[
+                        ("path", &path.display()),
+                        ("version", version),
+                        ("minimum", minimum)
+                    ]
+                )
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SdkError {}
+
+impl From<SdkError> for std::io::Error {
+    fn from(e: SdkError) -> Self {
+        let kind = match e {
+            SdkError::EnvInvalid { .. } => std::io::ErrorKind::InvalidInput,
+            SdkError::EnvNotSet { .. } | SdkError::NotFound { .. } => std::io::ErrorKind::NotFound,
+            SdkError::VersionUnknown { .. } | SdkError::TooOld { .. } => {
+                std::io::ErrorKind::InvalidData
+            }
+        };
+        std::io::Error::new(kind, e)
+    }
+}
+
+/// Expand a leading `~` to the home directory.
+fn expand_home(path: &Path) -> PathBuf {
+    match path.strip_prefix("~") {
+        Ok(rest) => env::var_os("HOME")
+            .or_else(|| env::var_os("USERPROFILE"))
+            .map(|home| PathBuf::from(home).join(rest))
+            .unwrap_or_else(|| path.to_path_buf()),
+        Err(_) => path.to_path_buf(),
+    }
+}
+
+/// Reads a SDK path from an environment variable and returns a PathBuf pointing to it.
+///
+/// # Arguments
+///
+/// * `sdk_name` – The name of the environment variable that shall contain the SDK path
+///
+/// # Example
+///
+/// ```no_run
+/// use toolbelt::get_sdk_path;
+/// let sdk_path = get_sdk_path("THE_SDK").unwrap();
+/// ```
+pub fn get_sdk_path(sdk_name: &str) -> Result<PathBuf, SdkError> {
+    get_sdk_path_with_fallbacks::<&str>(sdk_name, &[])
+}
+
+/// Like [`get_sdk_path`], but if the environment variable isn't set, the first existing of the
+/// `fallbacks` is used. A leading `~` in a fallback stands for the home directory.
+///
+/// # Arguments
+///
+/// * `sdk_name` – The name of the environment variable that shall contain the SDK path
+/// * `fallbacks` - Locations to probe in order if the variable isn't set
+///
+/// # Example
+///
+/// ```no_run
+/// use toolbelt::get_sdk_path_with_fallbacks;
+/// let fallbacks = ["~/SDKs/Foo", "/opt/foo-sdk"];
+/// let sdk_path = get_sdk_path_with_fallbacks("FOO_SDK", &fallbacks).unwrap();
+/// ```
+pub fn get_sdk_path_with_fallbacks<P: AsRef<Path>>(
+    sdk_name: &str,
+    fallbacks: &[P],
+) -> Result<PathBuf, SdkError> {
+    if sdk_name.is_empty() || sdk_name.contains(['=', '\0']) {
+        return Err(SdkError::EnvInvalid {
+            name: sdk_name.to_string(),
+        });
+    }
+    match env::var_os(sdk_name).filter(|value| !value.is_empty()) {
+        Some(value) => {
+            let sdk_path = PathBuf::from(value);
+            if !sdk_path.exists() {
+                return Err(SdkError::NotFound { path: sdk_path });
+            }
+            Ok(sdk_path)
+        }
+        None => {
+            let fallbacks: Vec<PathBuf> = fallbacks
+                .iter()
+                .map(|fallback| expand_home(fallback.as_ref()))
+                .collect();
+            match fallbacks.iter().find(|path| path.is_dir()) {
+                Some(path) => {
+                    debug!("{} is not set, using {}", sdk_name, path.display());
+                    Ok(path.clone())
+                }
+                None => Err(SdkError::EnvNotSet {
+                    name: sdk_name.to_string(),
+                    fallbacks,
+                }),
+            }
+        }
+    }
+}
+
+/// Like [`get_sdk_path_with_fallbacks`], but also tells cargo through `output` to rerun the build
+/// script when the environment variable changes.
+///
+/// # Arguments
+///
+/// * `sdk_name` – The name of the environment variable that shall contain the SDK path
+/// * `fallbacks` - Locations to probe in order if the variable isn't set
+/// * `output` - Where to report the variable to cargo
+///
+/// # Example
+///
+/// ```no_run
+/// use toolbelt::build_script::BuildScriptOutput;
+/// use toolbelt::get_sdk_path_with_output;
+/// let output = BuildScriptOutput::new();
+/// let sdk_path = get_sdk_path_with_output("FOO_SDK", &["~/SDKs/Foo"], &output).unwrap();
+/// ```
+pub fn get_sdk_path_with_output<P: AsRef<Path>>(
+    sdk_name: &str,
+    fallbacks: &[P],
+    output: &BuildScriptOutput,
+) -> Result<PathBuf, SdkError> {
+    let sdk_path = get_sdk_path_with_fallbacks(sdk_name, fallbacks);
+    if !matches!(sdk_path, Err(SdkError::EnvInvalid { .. })) {
+        output.rerun_if_env_changed(sdk_name);
+    }
+    sdk_path
+}
+
+/// Like [`get_sdk_path`], but prints the error and exits the process if the SDK isn't found, as
+/// `get_sdk_path` used to.
+pub fn get_sdk_path_or_exit(sdk_name: &str) -> PathBuf {
+    get_sdk_path(sdk_name).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    })
+}
+
+/// Where an SDK records its version, relative to the SDK directory.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum VersionMarker {
+    /// A file containing nothing but the version, e.g. `VERSION`
+    File(PathBuf),
+    /// A file, typically a header, and a regular expression whose first group matches the
+    /// version, e.g. `#define FOO_VERSION "([0-9.]+)"` in `include/foo_version.h`
+    Pattern { file: PathBuf, regex: String },
+}
+
+/// Read the version of the SDK in `sdk_path` from its version marker.
+pub fn get_sdk_version(sdk_path: &Path, marker: &VersionMarker) -> Result<String, SdkError> {
+    let (file, regex) = match marker {
+        VersionMarker::File(file) => (file, None),
+        VersionMarker::Pattern { file, regex } => (file, Some(regex)),
+    };
+    let path = sdk_path.join(file);
+    let unknown = |reason: String| SdkError::VersionUnknown {
+        path: path.clone(),
+        reason,
+    };
+    let text = std::fs::read_to_string(&path).map_err(|e| unknown(e.to_string()))?;
+    let version = match regex {
+        None => text.trim().to_string(),
+        Some(regex) => {
+            let regex = Regex::new(regex).map_err(|e| unknown(e.to_string()))?;
+            regex
+                .captures(&text)
+                .and_then(|captures| captures.get(1))
+                .map(|version| version.as_str().trim().to_string())
+                .ok_or_else(|| unknown(format!("{} doesn't match", regex)))?
+        }
+    };
+    if version.is_empty() {
+        return Err(unknown("the version is empty".to_string()));
+    }
+    Ok(version)
+}
+
+/// Fail with a clear error if the SDK in `sdk_path` is older than `min_version`, instead of
+/// letting the build fail later on missing declarations. Returns the SDK version.
+///
+/// # Arguments
+///
+/// * `sdk_path` - The SDK directory, e.g. from [`get_sdk_path`]
+/// * `marker` - Where the SDK records its version
+/// * `min_version` - The oldest supported version, compared numerically by dotted components
+///
+/// # Example
+///
+/// ```no_run
+/// use toolbelt::{get_sdk_path, require_sdk_version, VersionMarker};
+///
+/// let sdk_path = get_sdk_path("FOO_SDK").unwrap();
+/// let marker = VersionMarker::Pattern {
+///     file: "include/foo_version.h".into(),
+///     regex: r#"#define FOO_VERSION "([0-9.]+)""#.to_string(),
+/// };
+/// require_sdk_version(&sdk_path, &marker, "2.4").unwrap();
+/// ```
+pub fn require_sdk_version(
+    sdk_path: &Path,
+    marker: &VersionMarker,
+    min_version: &str,
+) -> Result<String, SdkError> {
+    let version = get_sdk_version(sdk_path, marker)?;
+    if !version_at_least(&version, min_version) {
+        return Err(SdkError::TooOld {
+            path: sdk_path.to_path_buf(),
+            version,
+            minimum: min_version.to_string(),
+        });
+    }
+    debug!("SDK {} is version {}", sdk_path.display(), version);
+    Ok(version)
+}
+
+#[test]
+fn test_require_sdk_version() {
+    let root = Path::new("target/require_sdk_version");
+    let _ = std::fs::remove_dir_all(root);
+    std::fs::create_dir_all(root.join("include")).unwrap();
+    std::fs::write(root.join("VERSION"), "2.10.1\n").unwrap();
+    std::fs::write(
+        root.join("include/foo_version.h"),
+        "#pragma once\n#define FOO_VERSION \"2.3.0\"\n",
+    )
+    .unwrap();
+
+    let file = VersionMarker::File("VERSION".into());
+    assert_eq!(require_sdk_version(root, &file, "2.4").unwrap(), "2.10.1");
+    let header = VersionMarker::Pattern {
+        file: "include/foo_version.h".into(),
+        regex: r#"#define FOO_VERSION "([0-9.]+)""#.to_string(),
+    };
+    assert_eq!(get_sdk_version(root, &header).unwrap(), "2.3.0");
+    match require_sdk_version(root, &header, "2.4") {
+        Err(SdkError::TooOld {
+            version, minimum, ..
+        }) => assert_eq!((version.as_str(), minimum.as_str()), ("2.3.0", "2.4")),
+        other => panic!("unexpected result {:?}", other),
+    }
+    let unmatched = VersionMarker::Pattern {
+        file: "include/foo_version.h".into(),
+        regex: "BAR_VERSION (.*)".to_string(),
+    };
+    assert!(matches!(
+        get_sdk_version(root, &unmatched),
+        Err(SdkError::VersionUnknown { .. })
+    ));
+    assert!(matches!(
+        get_sdk_version(root, &VersionMarker::File("missing".into())),
+        Err(SdkError::VersionUnknown { .. })
+    ));
+
+    std::fs::remove_dir_all(root).unwrap();
+}
+
+#[test]
+fn test_get_sdk_path() {
+    env::set_var("TOOLBELT_TEST_SDK", "test/my_files");
+    assert_eq!(
+        get_sdk_path("TOOLBELT_TEST_SDK").unwrap(),
+        PathBuf::from("test/my_files")
+    );
+    env::set_var("TOOLBELT_TEST_SDK", "target/no-such-sdk");
+    let e = get_sdk_path_with_fallbacks("TOOLBELT_TEST_SDK", &["test/my_files"]).unwrap_err();
+    assert_eq!(
+        e,
+        SdkError::NotFound {
+            path: PathBuf::from("target/no-such-sdk")
+        }
+    );
+    assert!(e.to_string().contains("target/no-such-sdk"));
+    assert_eq!(std::io::Error::from(e).kind(), std::io::ErrorKind::NotFound);
+
+    env::remove_var("TOOLBELT_TEST_SDK");
+    assert_eq!(
+        get_sdk_path_with_fallbacks(
+            "TOOLBELT_TEST_SDK",
+            &["target/no-such-sdk", "test/my_files"]
+        )
+        .unwrap(),
+        PathBuf::from("test/my_files")
+    );
+    match get_sdk_path_with_fallbacks("TOOLBELT_TEST_SDK", &["target/no-such-sdk"]) {
+        Err(SdkError::EnvNotSet { name, fallbacks }) => {
+            assert_eq!(name, "TOOLBELT_TEST_SDK");
+            assert_eq!(fallbacks, [PathBuf::from("target/no-such-sdk")]);
+        }
+        other => panic!("unexpected result {:?}", other),
+    }
+    assert!(matches!(get_sdk_path(""), Err(SdkError::EnvInvalid { .. })));
+
+    if let Some(home) = env::var_os("HOME") {
+        assert_eq!(
+            expand_home(Path::new("~/SDKs/Foo")),
+            Path::new(&home).join("SDKs/Foo")
+        );
+    }
+    assert_eq!(expand_home(Path::new("/opt/foo")), Path::new("/opt/foo"));
+}
+
+#[test]
+fn test_get_sdk_path_with_output() {
+    let output = BuildScriptOutput::capture();
+    env::set_var("TOOLBELT_TEST_OUTPUT_SDK", "test/my_files");
+    assert_eq!(
+        get_sdk_path_with_output::<&str>("TOOLBELT_TEST_OUTPUT_SDK", &[], &output).unwrap(),
+        PathBuf::from("test/my_files")
+    );
+    env::remove_var("TOOLBELT_TEST_OUTPUT_SDK");
+    assert!(get_sdk_path_with_output::<&str>("TOOLBELT_TEST_OUTPUT_SDK", &[], &output).is_err());
+    assert!(get_sdk_path_with_output::<&str>("", &[], &output).is_err());
+    assert_eq!(
+        output.lines(),
+        ["cargo:rerun-if-env-changed=TOOLBELT_TEST_OUTPUT_SDK"]
+    );
+}
@@ -0,0 +1,291 @@
+//! Downloading and unpacking SDKs, so a missing SDK is installed instead of reported.
+//!
+//! Only available with the `http` feature enabled.
+
+use std::fmt;
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+
+use super::{get_sdk_path, SdkError};
+use crate::download::{download, DownloadError};
+use crate::hash::Sha256;
+use crate::tool::{Tool, ToolError};
+
+/// File inside an installed SDK recording the checksum of the archive it came from.
+const MARKER: &str = ".toolbelt-sdk";
+
+/// Errors reported by [`SdkInstaller`].
+#[derive(Debug)]
+pub enum SdkInstallError {
+    /// Downloading the archive failed or its checksum didn't match
+    Download(DownloadError),
+    /// The archive couldn't be unpacked
+    Unpack(ToolError),
+    /// Reading or writing the SDK directory failed, or the archive format isn't supported
+    Io(Error),
+}
+
+impl fmt::Display for SdkInstallError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SdkInstallError::Download(e) => write!(f, "{}", e),
+            SdkInstallError::Unpack(e) => write!(f, "{}", e),
+            SdkInstallError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for SdkInstallError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SdkInstallError::Download(e) => Some(e),
+            SdkInstallError::Unpack(e) => Some(e),
+            SdkInstallError::Io(e) => Some(e),
+        }
+    }
+}
+
+impl From<DownloadError> for SdkInstallError {
+    fn from(e: DownloadError) -> Self {
+        SdkInstallError::Download(e)
+    }
+}
+
+impl From<ToolError> for SdkInstallError {
+    fn from(e: ToolError) -> Self {
+        SdkInstallError::Unpack(e)
+    }
+}
+
+impl From<Error> for SdkInstallError {
+    fn from(e: Error) -> Self {
+        SdkInstallError::Io(e)
+    }
+}
+
+/// Archive formats an SDK can be shipped in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ArchiveFormat {
+    Zip,
+    /// Plain or compressed tar archives, which tar detects by itself
+    Tar,
+}
+
+impl ArchiveFormat {
+    fn from_file_name(name: &str) -> Option<ArchiveFormat> {
+        let name = name.to_lowercase();
+        if name.ends_with(".zip") {
+            Some(ArchiveFormat::Zip)
+        } else if [".tar", ".tar.gz", ".tgz", ".tar.bz2", ".tar.xz"]
+            .iter()
+            .any(|extension| name.ends_with(extension))
+        {
+            Some(ArchiveFormat::Tar)
+        } else {
+            None
+        }
+    }
+
+    fn unpack_command(self, archive: &Path, dir: &Path) -> Tool {
+        let mut tool;
+        if self == ArchiveFormat::Zip && !cfg!(windows) {
+            tool = Tool::new("unzip");
+            tool.args(["-q", "-o"]).arg(archive).arg("-d").arg(dir);
+        } else {
+            // tar on Windows is bsdtar, which unpacks zip archives as well
+            tool = Tool::new("tar");
+            tool.arg("-xf").arg(archive).arg("-C").arg(dir);
+        }
+        tool
+    }
+}
+
+/// Downloads an SDK archive, verifies its checksum and unpacks it into a directory.
+///
+/// Installing again is a no-op while the directory holds the SDK from the same archive.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::path::Path;
+/// use toolbelt::sdk::installer::SdkInstaller;
+///
+/// let checksum = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+///     .parse()
+///     .unwrap();
+/// let sdk_path = SdkInstaller::new(
+///     "https://example.com/foo-sdk-1.2.tar.gz",
+///     checksum,
+///     Path::new("target/sdks/foo"),
+/// )
+/// .get_or_install("FOO_SDK")
+/// .unwrap();
+/// ```
+#[derive(Clone, Debug)]
+pub struct SdkInstaller {
+    url: String,
+    checksum: Sha256,
+    target_dir: PathBuf,
+    strip_top_level: bool,
+}
+
+impl SdkInstaller {
+    /// Install the archive at `url` with the SHA-256 digest `checksum` into `target_dir`.
+    pub fn new(url: &str, checksum: Sha256, target_dir: &Path) -> SdkInstaller {
+        SdkInstaller {
+            url: url.to_string(),
+            checksum,
+            target_dir: target_dir.to_path_buf(),
+            strip_top_level: true,
+        }
+    }
+
+    /// Whether an archive containing a single directory is unpacked without it, so
+    /// `foo-sdk-1.2/include` ends up as `<target_dir>/include`. Enabled by default.
+    pub fn strip_top_level(mut self, strip: bool) -> SdkInstaller {
+        self.strip_top_level = strip;
+        self
+    }
+
+    /// The file name of the archive, from the URL without query or fragment.
+    fn file_name(&self) -> &str {
+        let path = self.url.split(['?', '#']).next().unwrap_or(&self.url);
+        path.rsplit('/').next().unwrap_or(path)
+    }
+
+    /// `true` if the target directory holds the SDK from this installer's archive.
+    pub fn is_installed(&self) -> bool {
+        fs::read_to_string(self.target_dir.join(MARKER))
+            .is_ok_and(|checksum| checksum.trim() == self.checksum.to_string())
+    }
+
+    /// Download, verify and unpack the SDK unless it is installed already, and return the SDK
+    /// directory.
+    pub fn install(&self) -> Result<PathBuf, SdkInstallError> {
+        if self.is_installed() {
+            debug!("{} is already installed", self.target_dir.display());
+            return Ok(self.target_dir.clone());
+        }
+        let format = ArchiveFormat::from_file_name(self.file_name()).ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                format!("{} is neither a zip nor a tar archive", self.file_name()),
+            )
+        })?;
+        let name = self
+            .target_dir
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "sdk".to_string());
+        let parent = self.target_dir.parent().unwrap_or(Path::new("."));
+        let archive = parent
+            .join(format!("{}.download", name))
+            .join(self.file_name());
+        download(&self.url, &archive, Some(self.checksum))?;
+
+        info!(
+            "Unpack {} into {}",
+            archive.display(),
+            self.target_dir.display()
+        );
+        let partial = parent.join(format!("{}.partial", name));
+        if partial.exists() {
+            fs::remove_dir_all(&partial)?;
+        }
+        fs::create_dir_all(&partial)?;
+        format.unpack_command(&archive, &partial).run()?;
+
+        let mut unpacked = partial.clone();
+        if self.strip_top_level {
+            let entries: Vec<PathBuf> = fs::read_dir(&partial)?
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect::<Result<_, Error>>()?;
+            if let [single] = entries.as_slice() {
+                if single.is_dir() {
+                    unpacked = single.clone();
+                }
+            }
+        }
+        if self.target_dir.exists() {
+            fs::remove_dir_all(&self.target_dir)?;
+        }
+        fs::rename(&unpacked, &self.target_dir)?;
+        if partial.exists() {
+            fs::remove_dir_all(&partial)?;
+        }
+        fs::write(self.target_dir.join(MARKER), self.checksum.to_string())?;
+        fs::remove_dir_all(archive.parent().unwrap())?;
+        Ok(self.target_dir.clone())
+    }
+
+    /// The SDK named by the environment variable `sdk_name` if it exists, otherwise install it
+    /// into the target directory, also if the variable names a missing directory.
+    pub fn get_or_install(&self, sdk_name: &str) -> Result<PathBuf, SdkInstallError> {
+        match get_sdk_path(sdk_name) {
+            Ok(path) => Ok(path),
+            Err(e @ SdkError::EnvInvalid { .. }) => Err(SdkInstallError::Io(e.into())),
+            Err(e) => {
+                info!("{}, installing it from {}", e, self.url);
+                self.install()
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn test_sdk_installer() {
+    let root = std::env::current_dir()
+        .unwrap()
+        .join("target/sdk_installer");
+    let _ = fs::remove_dir_all(&root);
+    fs::create_dir_all(root.join("foo-sdk-1.2/include")).unwrap();
+    fs::write(root.join("foo-sdk-1.2/include/foo.h"), "int foo();").unwrap();
+    let archive = root.join("foo-sdk-1.2.tar.gz");
+    Tool::new("tar")
+        .arg("-czf")
+        .arg(&archive)
+        .arg("-C")
+        .arg(&root)
+        .arg("foo-sdk-1.2")
+        .run()
+        .unwrap();
+    let checksum = Sha256::of_file(&archive).unwrap();
+    let url = format!("file://{}", archive.display());
+
+    let target = root.join("sdks/foo");
+    let installer = SdkInstaller::new(&url, checksum, &target);
+    assert!(!installer.is_installed());
+    std::env::remove_var("TOOLBELT_TEST_INSTALLED_SDK");
+    let sdk = installer
+        .get_or_install("TOOLBELT_TEST_INSTALLED_SDK")
+        .unwrap();
+    assert_eq!(sdk, target);
+    assert!(target.join("include/foo.h").is_file());
+    assert!(installer.is_installed());
+    assert!(!root.join("sdks/foo.download").exists());
+    assert!(!root.join("sdks/foo.partial").exists());
+    // installed already, so the archive isn't needed anymore
+    fs::remove_file(&archive).unwrap();
+    assert_eq!(installer.install().unwrap(), target);
+
+    let wrong = SdkInstaller::new(&url, Sha256::digest(b"other"), &root.join("sdks/bar"));
+    assert!(matches!(wrong.install(), Err(SdkInstallError::Download(_))));
+    let e = SdkInstaller::new(
+        "https://example.com/sdk.rar",
+        checksum,
+        &root.join("sdks/baz"),
+    )
+    .strip_top_level(false)
+    .install()
+    .unwrap_err();
+    assert!(matches!(e, SdkInstallError::Io(e) if e.kind() == ErrorKind::InvalidInput));
+    assert_eq!(
+        ArchiveFormat::from_file_name("sdk.TGZ"),
+        Some(ArchiveFormat::Tar)
+    );
+    assert_eq!(ArchiveFormat::from_file_name("sdk.rar"), None);
+
+    fs::remove_dir_all(&root).unwrap();
+}
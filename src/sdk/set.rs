@@ -0,0 +1,343 @@
+//! Several vendor SDKs used by one build, each with its include and library directories and
+//! preprocessor defines.
+//!
+//! The SDKs are described in code with [`SdkSpec`] or in `toolbelt.toml`:
+//!
+//! ```toml
+//! [sdk.foo]
+//! env = "FOO_SDK"
+//! fallbacks = ["~/SDKs/Foo"]
+//! include_dirs = ["include", "include/foo/**"]
+//! lib_dirs = ["lib/x64"]
+//! defines = ["FOO_STATIC", "FOO_API_VERSION=3"]
+//! ```
+//!
+//! `env` defaults to the upper-cased name followed by `_SDK`, all other settings are optional.
+//! System libraries are added through pkg-config with [`SdkSet::add_pkg_config`].
+
+use std::io::Error;
+use std::path::{Path, PathBuf};
+
+use super::{get_sdk_path_with_fallbacks, get_sdk_path_with_output, SdkError};
+use crate::build_script::BuildScriptOutput;
+use crate::config::Config;
+use crate::copy::absolute_path;
+use crate::pkg_config::pkg_config_flags;
+use crate::{
+    get_sdk_include_paths, get_sdk_lib_paths, DefineFormat, IncludeDirFormat, LibDirFormat,
+};
+
+/// Description of an SDK: where to find it and which of its directories and defines to use.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SdkSpec {
+    /// Name to look the SDK up with [`SdkSet::get`]
+    pub name: String,
+    /// The environment variable holding the SDK path
+    pub env: String,
+    /// Locations to probe in order if the environment variable isn't set
+    pub fallbacks: Vec<PathBuf>,
+    /// Glob patterns of header directories, relative to the SDK
+    pub include_dirs: Vec<String>,
+    /// Glob patterns of library directories, relative to the SDK
+    pub lib_dirs: Vec<String>,
+    /// Preprocessor defines with optional values
+    pub defines: Vec<(String, Option<String>)>,
+}
+
+impl SdkSpec {
+    /// An SDK named `name` whose path is held by the environment variable `env`.
+    pub fn new(name: &str, env: &str) -> SdkSpec {
+        SdkSpec {
+            name: name.to_string(),
+            env: env.to_string(),
+            fallbacks: Vec::new(),
+            include_dirs: Vec::new(),
+            lib_dirs: Vec::new(),
+            defines: Vec::new(),
+        }
+    }
+
+    /// Add a location to probe if the environment variable isn't set.
+    pub fn fallback<P: AsRef<Path>>(mut self, path: P) -> SdkSpec {
+        self.fallbacks.push(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Add glob patterns of header directories.
+    pub fn include_dirs<I>(mut self, patterns: I) -> SdkSpec
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        self.include_dirs
+            .extend(patterns.into_iter().map(Into::into));
+        self
+    }
+
+    /// Add glob patterns of library directories.
+    pub fn lib_dirs<I>(mut self, patterns: I) -> SdkSpec
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        self.lib_dirs.extend(patterns.into_iter().map(Into::into));
+        self
+    }
+
+    /// Add a preprocessor define, `-DNAME` without or `-DNAME=VALUE` with a value. String values
+    /// are best written with [`crate::c_string_literal`].
+    pub fn define(mut self, name: &str, value: Option<&str>) -> SdkSpec {
+        self.defines
+            .push((name.to_string(), value.map(str::to_string)));
+        self
+    }
+
+    /// The spec of the `[sdk.<name>]` table in a configuration.
+    fn from_config(config: &Config, name: &str) -> SdkSpec {
+        let key = |setting: &str| format!("sdk.{}.{}", name, setting);
+        let strings = |setting: &str| config.get_strings(&key(setting)).unwrap_or_default();
+        let env = config
+            .get_str(&key("env"))
+            .map(str::to_string)
+            .unwrap_or_else(|| format!("{}_SDK", name.to_uppercase()));
+        let mut spec = SdkSpec::new(name, &env)
+            .include_dirs(strings("include_dirs"))
+            .lib_dirs(strings("lib_dirs"));
+        spec.fallbacks = strings("fallbacks")
+            .into_iter()
+            .map(PathBuf::from)
+            .collect();
+        for define in strings("defines") {
+            spec = match define.split_once('=') {
+                Some((name, value)) => spec.define(name, Some(value)),
+                None => spec.define(&define, None),
+            };
+        }
+        spec
+    }
+}
+
+/// An SDK of an [`SdkSet`] with its patterns expanded.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Sdk {
+    pub name: String,
+    /// The absolute SDK path
+    pub path: PathBuf,
+    pub include_dirs: Vec<PathBuf>,
+    pub lib_dirs: Vec<PathBuf>,
+    pub defines: Vec<(String, Option<String>)>,
+}
+
+/// The SDKs a build uses, in the order they were added.
+///
+/// # Example
+///
+/// ```no_run
+/// use toolbelt::{SdkSet, SdkSpec};
+///
+/// let mut sdks = SdkSet::new();
+/// sdks.add(
+///     &SdkSpec::new("foo", "FOO_SDK")
+///         .include_dirs(["include"])
+///         .lib_dirs(["lib"])
+///         .define("FOO_STATIC", None),
+/// )
+/// .unwrap();
+/// sdks.add(&SdkSpec::new("bar", "BAR_SDK").include_dirs(["headers/**"]))
+///     .unwrap();
+/// let flags = sdks.clang_flags();
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SdkSet {
+    sdks: Vec<Sdk>,
+    build_script: Option<BuildScriptOutput>,
+}
+
+impl SdkSet {
+    pub fn new() -> SdkSet {
+        SdkSet::default()
+    }
+
+    /// All SDKs of the `[sdk.<name>]` tables in a configuration, added in the order of their
+    /// names.
+    pub fn from_config(config: &Config) -> Result<SdkSet, SdkError> {
+        let mut sdks = SdkSet::new();
+        sdks.add_config(config)?;
+        Ok(sdks)
+    }
+
+    /// Report the environment variables the SDKs are located with to a build script, so it
+    /// reruns when an SDK is moved or switched. Pkg-config's variables are reported for
+    /// [`SdkSet::add_pkg_config`].
+    pub fn build_script(mut self, output: BuildScriptOutput) -> SdkSet {
+        self.build_script = Some(output);
+        self
+    }
+
+    /// Add the SDKs of the `[sdk.<name>]` tables in a configuration, like
+    /// [`SdkSet::from_config`].
+    pub fn add_config(&mut self, config: &Config) -> Result<(), SdkError> {
+        for name in config.keys("sdk") {
+            self.add(&SdkSpec::from_config(config, name))?;
+        }
+        Ok(())
+    }
+
+    /// Locate the SDK and expand its directory patterns. An SDK with the same name is replaced.
+    pub fn add(&mut self, spec: &SdkSpec) -> Result<&Sdk, SdkError> {
+        let path = match &self.build_script {
+            Some(output) => get_sdk_path_with_output(&spec.env, &spec.fallbacks, output)?,
+            None => get_sdk_path_with_fallbacks(&spec.env, &spec.fallbacks)?,
+        };
+        let path = absolute_path(&path).unwrap_or(path);
+        let sdk_path = format!("{}/", path.display());
+        let sdk = Sdk {
+            name: spec.name.clone(),
+            include_dirs: get_sdk_include_paths(
+                &spec.include_dirs,
+                &sdk_path,
+                IncludeDirFormat::PLAIN,
+            )
+            .into_iter()
+            .map(|dir| dir.path)
+            .collect(),
+            lib_dirs: get_sdk_lib_paths(&spec.lib_dirs, &sdk_path),
+            defines: spec.defines.clone(),
+            path,
+        };
+        debug!("SDK {} in {}", sdk.name, sdk.path.display());
+        Ok(self.insert(sdk))
+    }
+
+    /// Add a system library found by pkg-config like an SDK named after the package. Its path is
+    /// the package's installation prefix, the libraries to link are left to
+    /// [`PkgConfig::emit_cargo_metadata`](crate::PkgConfig::emit_cargo_metadata).
+    pub fn add_pkg_config(
+        &mut self,
+        package: &str,
+        min_version: Option<&str>,
+    ) -> Result<&Sdk, Error> {
+        if let Some(output) = &self.build_script {
+            for name in [
+                "PKG_CONFIG",
+                "PKG_CONFIG_PATH",
+                "PKG_CONFIG_LIBDIR",
+                "PKG_CONFIG_SYSROOT_DIR",
+            ] {
+                output.rerun_if_env_changed(name);
+            }
+        }
+        let config = pkg_config_flags(package, min_version)?;
+        Ok(self.insert(Sdk {
+            name: config.package,
+            path: config.prefix.unwrap_or_default(),
+            include_dirs: config.include_dirs,
+            lib_dirs: config.lib_dirs,
+            defines: config.defines,
+        }))
+    }
+
+    fn insert(&mut self, sdk: Sdk) -> &Sdk {
+        self.sdks.retain(|existing| existing.name != sdk.name);
+        self.sdks.push(sdk);
+        self.sdks.last().unwrap()
+    }
+
+    /// The SDK added with `name`.
+    pub fn get(&self, name: &str) -> Option<&Sdk> {
+        self.sdks.iter().find(|sdk| sdk.name == name)
+    }
+
+    /// All SDKs in the order they were added.
+    pub fn sdks(&self) -> &[Sdk] {
+        &self.sdks
+    }
+
+    /// The clang flags for all SDKs: first the `-I` flags, then the `-D` flags, then the `-L`
+    /// flags, each in the order the SDKs were added.
+    pub fn clang_flags(&self) -> Vec<String> {
+        let includes = self.sdks.iter().flat_map(|sdk| &sdk.include_dirs);
+        let defines = self.sdks.iter().flat_map(|sdk| &sdk.defines);
+        let libs = self.sdks.iter().flat_map(|sdk| &sdk.lib_dirs);
+        includes
+            .map(|dir| IncludeDirFormat::CLANG.format(dir))
+            .chain(defines.map(|(name, value)| DefineFormat::CLANG.format(name, value.as_deref())))
+            .chain(libs.map(|dir| LibDirFormat::CLANG.format(dir)))
+            .collect()
+    }
+}
+
+#[test]
+fn test_sdk_set() {
+    let root = Path::new("target/sdk_set");
+    let _ = std::fs::remove_dir_all(root);
+    std::fs::create_dir_all(root.join("foo/include/foo")).unwrap();
+    std::fs::create_dir_all(root.join("foo/lib/x64")).unwrap();
+    std::fs::create_dir_all(root.join("bar/headers")).unwrap();
+    let foo = absolute_path(&root.join("foo")).unwrap();
+    let bar = absolute_path(&root.join("bar")).unwrap();
+    std::env::set_var("TOOLBELT_TEST_FOO_SDK", &foo);
+    std::env::remove_var("BAR_SDK");
+
+    let config = Config::parse(
+        &format!(
+            r#"
+[sdk.foo]
+env = "TOOLBELT_TEST_FOO_SDK"
+include_dirs = ["include/**"]
+lib_dirs = ["lib/x64"]
+defines = ["FOO_STATIC", "FOO_API_VERSION=3"]
+
+[sdk.bar]
+fallbacks = ['{}']
+include_dirs = ["headers"]
+"#,
+            bar.display()
+        ),
+        None,
+        None,
+    )
+    .unwrap();
+    let output = BuildScriptOutput::capture();
+    let mut sdks = SdkSet::new().build_script(output.clone());
+    sdks.add_config(&config).unwrap();
+    assert_eq!(
+        output.lines(),
+        [
+            "cargo:rerun-if-env-changed=BAR_SDK",
+            "cargo:rerun-if-env-changed=TOOLBELT_TEST_FOO_SDK"
+        ]
+    );
+    assert_eq!(
+        sdks,
+        SdkSet::from_config(&config).unwrap().build_script(output)
+    );
+    assert_eq!(sdks.sdks().len(), 2);
+    assert_eq!(sdks.get("foo").unwrap().path, foo);
+    assert_eq!(sdks.get("bar").unwrap().path, bar);
+    assert_eq!(
+        sdks.clang_flags(),
+        [
+            format!("-I{}", bar.join("headers").display()),
+            format!("-I{}", foo.join("include").display()),
+            format!("-I{}", foo.join("include/foo").display()),
+            "-DFOO_STATIC".to_string(),
+            "-DFOO_API_VERSION=3".to_string(),
+            format!("-L{}", foo.join("lib/x64").display()),
+        ]
+    );
+
+    let mut sdks = SdkSet::new();
+    sdks.add(&SdkSpec::new("foo", "TOOLBELT_TEST_FOO_SDK").define("A", None))
+        .unwrap();
+    sdks.add(&SdkSpec::new("foo", "TOOLBELT_TEST_FOO_SDK").define("B", None))
+        .unwrap();
+    assert_eq!(sdks.clang_flags(), ["-DB"]);
+    assert!(matches!(
+        sdks.add(&SdkSpec::new("baz", "TOOLBELT_TEST_BAZ_SDK")),
+        Err(SdkError::EnvNotSet { .. })
+    ));
+
+    std::env::remove_var("TOOLBELT_TEST_FOO_SDK");
+    std::fs::remove_dir_all(root).unwrap();
+}
@@ -0,0 +1,243 @@
+//! Driving the iOS Simulator with `xcrun simctl`, e.g. for integration tests of iOS builds.
+//!
+//! A [`Simulator`] is looked up by name, booted, gets the app installed and launched, and its
+//! log is collected once the test is done.
+
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+use std::time::Duration;
+
+use crate::json::{self, Value};
+use crate::tool::ToolOutput;
+use crate::xcrun::{self, version_at_least};
+
+/// A simulated device as listed by `xcrun simctl list devices`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Simulator {
+    pub udid: String,
+    /// Device name, e.g. `iPhone 15`
+    pub name: String,
+    /// Runtime identifier, e.g. `com.apple.CoreSimulator.SimRuntime.iOS-17-2`
+    pub runtime: String,
+    /// `Booted`, `Shutdown`, `Booting`, …
+    pub state: String,
+    /// `false` if the runtime of the device isn't installed
+    pub available: bool,
+}
+
+impl Simulator {
+    /// List all simulated devices.
+    pub fn list() -> Result<Vec<Simulator>, Error> {
+        let output = simctl(&["list", "devices", "--json"])?;
+        parse_devices(&json::parse(&output.stdout)?)
+    }
+
+    /// Find an available device by name or UDID. If several runtimes provide a device with that
+    /// name, the one with the newest runtime is used.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use std::path::Path;
+    /// use std::time::Duration;
+    /// use toolbelt::simctl::Simulator;
+    ///
+    /// let simulator = Simulator::find("iPhone 15").unwrap();
+    /// simulator.boot().unwrap();
+    /// simulator.install(Path::new("target/MyApp.app")).unwrap();
+    /// simulator.launch("com.example.myapp", &["--run-tests"]).unwrap();
+    /// std::thread::sleep(Duration::from_secs(10));
+    /// let log = simulator.logs(Some("process == \"MyApp\""), Duration::from_secs(30));
+    /// println!("{}", log.unwrap());
+    /// simulator.shutdown().unwrap();
+    /// ```
+    pub fn find(name: &str) -> Result<Simulator, Error> {
+        select(Simulator::list()?, name).ok_or_else(|| {
+            Error::new(
+                ErrorKind::NotFound,
+                format!(
+                    "No available simulator named {}, see `xcrun simctl list devices`",
+                    name
+                ),
+            )
+        })
+    }
+
+    /// Version of the runtime, e.g. `17.2`.
+    pub fn runtime_version(&self) -> Option<String> {
+        let (_, version) = self.runtime.rsplit_once('.')?.1.split_once('-')?;
+        Some(version.replace('-', "."))
+    }
+
+    /// `true` if the device was booted when it was listed.
+    pub fn is_booted(&self) -> bool {
+        self.state == "Booted"
+    }
+
+    /// Boot the device and wait until it has finished booting. A booted device is left alone.
+    pub fn boot(&self) -> Result<(), Error> {
+        debug!("Boot simulator {} ({})", self.name, self.udid);
+        simctl(&["bootstatus", &self.udid, "-b"])?;
+        Ok(())
+    }
+
+    /// Shut the device down. A device that is already shut down is left alone.
+    pub fn shutdown(&self) -> Result<(), Error> {
+        if let Err(e) = simctl(&["shutdown", &self.udid]) {
+            if self.is_running()? {
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Install an app bundle built for the simulator, replacing an installed version.
+    pub fn install(&self, app: &Path) -> Result<(), Error> {
+        let mut tool = xcrun::tool("simctl", None).map_err(Error::other)?;
+        tool.args(["install", &self.udid])
+            .arg(app)
+            .run()
+            .map_err(Error::other)?;
+        Ok(())
+    }
+
+    /// Remove an installed app.
+    pub fn uninstall(&self, bundle_identifier: &str) -> Result<(), Error> {
+        simctl(&["uninstall", &self.udid, bundle_identifier])?;
+        Ok(())
+    }
+
+    /// Launch an installed app with arguments. Returns the process id of the app.
+    pub fn launch(&self, bundle_identifier: &str, args: &[&str]) -> Result<u32, Error> {
+        let mut simctl_args = vec!["launch", &self.udid, bundle_identifier];
+        simctl_args.extend(args);
+        let output = simctl(&simctl_args)?;
+        parse_launch(&output.stdout).ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "Unexpected output of simctl launch: {}",
+                    output.stdout.trim()
+                ),
+            )
+        })
+    }
+
+    /// Terminate a running app.
+    pub fn terminate(&self, bundle_identifier: &str) -> Result<(), Error> {
+        simctl(&["terminate", &self.udid, bundle_identifier])?;
+        Ok(())
+    }
+
+    /// Collect the device's log entries of the last `last` time.
+    ///
+    /// # Arguments
+    ///
+    /// * `predicate` - a `log show` predicate filtering the entries, e.g. `process == "MyApp"`
+    /// * `last` - how far back to collect
+    pub fn logs(&self, predicate: Option<&str>, last: Duration) -> Result<String, Error> {
+        let last = format!("{}s", last.as_secs().max(1));
+        let mut args = vec![
+            "spawn", &self.udid, "log", "show", "--style", "compact", "--last", &last,
+        ];
+        if let Some(predicate) = predicate {
+            args.extend(["--predicate", predicate]);
+        }
+        Ok(simctl(&args)?.stdout)
+    }
+
+    /// Whether the device is currently booted, according to a fresh listing.
+    fn is_running(&self) -> Result<bool, Error> {
+        Ok(Simulator::list()?
+            .iter()
+            .any(|device| device.udid == self.udid && device.state != "Shutdown"))
+    }
+}
+
+fn simctl(args: &[&str]) -> Result<ToolOutput, Error> {
+    xcrun::tool("simctl", None)
+        .and_then(|mut tool| tool.args(args).run())
+        .map_err(Error::other)
+}
+
+/// Parse the output of `simctl list devices --json`.
+fn parse_devices(list: &Value) -> Result<Vec<Simulator>, Error> {
+    let Some(Value::Object(runtimes)) = list.get("devices") else {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            "simctl list lacks devices",
+        ));
+    };
+    let mut devices = Vec::new();
+    for (runtime, runtime_devices) in runtimes {
+        for device in runtime_devices.as_array().unwrap_or_default() {
+            let field = |name: &str| device.get(name).and_then(Value::as_str).map(String::from);
+            if let (Some(udid), Some(name)) = (field("udid"), field("name")) {
+                devices.push(Simulator {
+                    udid,
+                    name,
+                    runtime: runtime.clone(),
+                    state: field("state").unwrap_or_default(),
+                    available: device.get("isAvailable") != Some(&Value::Bool(false)),
+                });
+            }
+        }
+    }
+    Ok(devices)
+}
+
+/// Pick the available device named `name` with the newest runtime, or the one with that UDID.
+fn select(devices: Vec<Simulator>, name: &str) -> Option<Simulator> {
+    devices
+        .into_iter()
+        .filter(|device| device.available && (device.name == name || device.udid == name))
+        .reduce(|best, device| {
+            let newer = match (device.runtime_version(), best.runtime_version()) {
+                (Some(version), Some(best_version)) => {
+                    version != best_version && version_at_least(&version, &best_version)
+                }
+                (Some(_), None) => true,
+                _ => false,
+            };
+            if newer {
+                device
+            } else {
+                best
+            }
+        })
+}
+
+/// Parse the process id from the output of `simctl launch`, e.g. `com.example.myapp: 4711`.
+fn parse_launch(stdout: &str) -> Option<u32> {
+    stdout.trim().rsplit_once(": ")?.1.parse().ok()
+}
+
+#[test]
+fn test_simulator_list() {
+    let list = json::parse(
+        r#"{"devices": {
+            "com.apple.CoreSimulator.SimRuntime.iOS-16-4": [
+                {"udid": "A1", "name": "iPhone 14", "state": "Shutdown", "isAvailable": true}
+            ],
+            "com.apple.CoreSimulator.SimRuntime.iOS-17-2": [
+                {"udid": "B1", "name": "iPhone 14", "state": "Booted", "isAvailable": true},
+                {"udid": "B2", "name": "iPhone 15", "state": "Shutdown", "isAvailable": true}
+            ],
+            "com.apple.CoreSimulator.SimRuntime.iOS-18-0": [
+                {"udid": "C1", "name": "iPhone 14", "state": "Shutdown", "isAvailable": false}
+            ]
+        }}"#,
+    )
+    .unwrap();
+    let devices = parse_devices(&list).unwrap();
+    assert_eq!(devices.len(), 4);
+    assert_eq!(devices[1].runtime_version().as_deref(), Some("17.2"));
+    assert!(devices[1].is_booted());
+
+    assert_eq!(select(devices.clone(), "iPhone 14").unwrap().udid, "B1");
+    assert_eq!(select(devices.clone(), "A1").unwrap().name, "iPhone 14");
+    assert!(select(devices, "iPad").is_none());
+
+    assert_eq!(parse_launch("com.example.myapp: 4711\n"), Some(4711));
+    assert_eq!(parse_launch("An error was encountered"), None);
+}
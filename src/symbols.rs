@@ -0,0 +1,154 @@
+//! Debug symbols of release binaries: archiving them in dSYM bundles and stripping them.
+
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+
+use crate::macho::verify_dsym_match;
+use crate::tool::Tool;
+use crate::xcrun;
+
+/// Extract the debug symbols of a binary into a `.dSYM` bundle using dsymutil from Xcode.
+///
+/// The dSYM is checked to match the binary, so crash reports can be symbolicated with it. If
+/// `strip` is set, debug symbols are stripped from the binary afterwards.
+///
+/// Returns the path of the dSYM bundle, `<output_dir>/<binary name>.dSYM`.
+///
+/// # Arguments
+///
+/// * `binary` - the executable or library built with debug info
+/// * `output_dir` - the directory to write the dSYM to, e.g. an archive folder
+/// * `strip` - strip the debug symbols from the binary once they are archived
+///
+/// # Example
+///
+/// ```no_run
+/// use std::path::Path;
+/// use toolbelt::symbols::generate_dsym;
+///
+/// let dsym = generate_dsym(
+///     Path::new("target/release/mytool"),
+///     Path::new("target/archive"),
+///     true,
+/// )
+/// .unwrap();
+/// ```
+pub fn generate_dsym(binary: &Path, output_dir: &Path, strip: bool) -> Result<PathBuf, Error> {
+    let name = binary.file_name().unwrap_or_default().to_string_lossy();
+    let dsym = output_dir.join(format!("{}.dSYM", name));
+    if dsym.exists() {
+        std::fs::remove_dir_all(&dsym)?;
+    }
+    std::fs::create_dir_all(output_dir)?;
+
+    xcrun::tool("dsymutil", None)
+        .map_err(Error::other)?
+        .arg(binary)
+        .arg("-o")
+        .arg(&dsym)
+        .run()
+        .map_err(Error::other)?;
+    verify_dsym_match(binary, &dsym)?;
+    debug!("Generated {}", dsym.display());
+
+    if strip {
+        strip_binary(binary, StripLevel::Debug)?;
+    }
+    Ok(dsym)
+}
+
+/// What [`strip_binary`] removes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StripLevel {
+    /// Debug symbols only
+    Debug,
+    /// Debug and local symbols, keeping the global symbols dynamic libraries export
+    Local,
+    /// All symbols not needed to run the binary
+    All,
+}
+
+impl StripLevel {
+    /// strip's flags for the level on the host platform.
+    fn flags(self) -> &'static [&'static str] {
+        if cfg!(target_vendor = "apple") {
+            match self {
+                StripLevel::Debug => &["-S"],
+                StripLevel::Local => &["-x"],
+                StripLevel::All => &[],
+            }
+        } else {
+            match self {
+                StripLevel::Debug => &["--strip-debug"],
+                StripLevel::Local => &["--discard-all"],
+                StripLevel::All => &["--strip-all"],
+            }
+        }
+    }
+}
+
+/// Strip symbols from a release binary to reduce its size. Strip before signing, as stripping
+/// invalidates the signature.
+///
+/// Uses Apple's strip on macOS and GNU strip elsewhere. Windows keeps debug info in separate PDB
+/// files, so binaries are left unchanged there.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::path::Path;
+/// use toolbelt::symbols::{strip_binary, StripLevel};
+///
+/// strip_binary(Path::new("target/release/mytool"), StripLevel::All).unwrap();
+/// ```
+pub fn strip_binary(path: &Path, level: StripLevel) -> Result<(), Error> {
+    if cfg!(windows) {
+        info!("Not stripping {} on Windows", path.display());
+        return Ok(());
+    }
+    if !path.is_file() {
+        return Err(Error::new(
+            ErrorKind::NotFound,
+            format!("{} not found", path.display()),
+        ));
+    }
+    let mut strip = if cfg!(target_vendor = "apple") {
+        xcrun::tool("strip", None).map_err(Error::other)?
+    } else {
+        Tool::new("strip")
+    };
+    strip
+        .args(level.flags())
+        .arg(path)
+        .run()
+        .map_err(Error::other)?;
+    Ok(())
+}
+
+#[test]
+fn test_generate_dsym_missing_binary() {
+    let root = Path::new("target/generate_dsym");
+    let _ = std::fs::remove_dir_all(root);
+    // fails whether or not dsymutil is installed
+    assert!(generate_dsym(&root.join("missing"), root, false).is_err());
+    assert!(root.is_dir());
+    std::fs::remove_dir_all(root).unwrap();
+}
+
+#[test]
+fn test_strip_binary() {
+    if cfg!(windows) {
+        return;
+    }
+    assert_eq!(
+        strip_binary(Path::new("target/strip_binary/missing"), StripLevel::All)
+            .unwrap_err()
+            .kind(),
+        ErrorKind::NotFound
+    );
+    if cfg!(target_vendor = "apple") {
+        assert_eq!(StripLevel::Local.flags(), ["-x"]);
+    } else {
+        assert_eq!(StripLevel::Local.flags(), ["--discard-all"]);
+    }
+}
@@ -0,0 +1,142 @@
+//! Assembling a sysroot for cross-compilation from the headers and libraries of one or more SDKs.
+//!
+//! clang's `--sysroot` expects headers in `usr/include` and libraries in `usr/lib`. Vendor SDKs
+//! rarely ship in that layout, so the files are selected with glob patterns and copied into it.
+
+use std::fs;
+use std::io::Error;
+use std::path::{Path, PathBuf};
+
+use crate::build_script::BuildScriptOutput;
+use crate::copy::{copy_dir_with_options, CopyOptions};
+
+/// Subdirectory of the sysroot for headers.
+pub const INCLUDE_DIR: &str = "usr/include";
+
+/// Subdirectory of the sysroot for libraries.
+pub const LIB_DIR: &str = "usr/lib";
+
+/// The files to copy into a sysroot.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SysrootSpec {
+    /// Source directory, glob pattern relative to it, and sysroot subdirectory
+    entries: Vec<(PathBuf, String, &'static str)>,
+    build_script: Option<BuildScriptOutput>,
+}
+
+impl SysrootSpec {
+    pub fn new() -> SysrootSpec {
+        SysrootSpec::default()
+    }
+
+    /// Copy the headers below `source` matching `pattern` into `usr/include`, keeping their path
+    /// relative to `source`.
+    pub fn headers<P: AsRef<Path>>(mut self, source: P, pattern: &str) -> SysrootSpec {
+        self.entries.push((
+            source.as_ref().to_path_buf(),
+            pattern.to_string(),
+            INCLUDE_DIR,
+        ));
+        self
+    }
+
+    /// Copy the libraries below `source` matching `pattern` into `usr/lib`, keeping their path
+    /// relative to `source`. Symbolic links like `libfoo.so -> libfoo.so.1` are preserved.
+    pub fn libraries<P: AsRef<Path>>(mut self, source: P, pattern: &str) -> SysrootSpec {
+        self.entries
+            .push((source.as_ref().to_path_buf(), pattern.to_string(), LIB_DIR));
+        self
+    }
+
+    /// Report the copied headers and libraries to a build script, see
+    /// [`CopyOptions::build_script`].
+    pub fn build_script(mut self, output: BuildScriptOutput) -> SysrootSpec {
+        self.build_script = Some(output);
+        self
+    }
+}
+
+/// Copy the headers and libraries of `spec` into a sysroot in `out_dir` and return the sysroot
+/// path. `usr/include` and `usr/lib` are emptied first, so files of a previous run that are no
+/// longer selected don't linger.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::path::Path;
+/// use toolbelt::{assemble_sysroot, get_sdk_path, SysrootSpec};
+///
+/// let foo = get_sdk_path("FOO_SDK").unwrap();
+/// let bar = get_sdk_path("BAR_SDK").unwrap();
+/// let spec = SysrootSpec::new()
+///     .headers(foo.join("include"), "**/*.h")
+///     .libraries(foo.join("lib/aarch64"), "*.{a,so*}")
+///     .headers(bar.join("api"), "bar/**/*.h");
+/// let sysroot = assemble_sysroot(&spec, Path::new("target/sysroot")).unwrap();
+/// let flag = format!("--sysroot={}", sysroot.display());
+/// ```
+pub fn assemble_sysroot(spec: &SysrootSpec, out_dir: &Path) -> Result<PathBuf, Error> {
+    for dir in [INCLUDE_DIR, LIB_DIR] {
+        let dir = out_dir.join(dir);
+        if dir.exists() {
+            fs::remove_dir_all(&dir)?;
+        }
+        fs::create_dir_all(&dir)?;
+    }
+    for (source, pattern, subdir) in &spec.entries {
+        // linked headers are copied, they may point anywhere in the SDK
+        let options = CopyOptions {
+            preserve_symlinks: *subdir == LIB_DIR,
+            build_script: spec.build_script.clone(),
+            ..CopyOptions::default()
+        };
+        let report = copy_dir_with_options(source, &out_dir.join(subdir), pattern, &options)?;
+        if report.files == 0 {
+            warn!("{} matched no files in {}", pattern, source.display());
+        }
+        debug!(
+            "Copied {} files from {} into {}",
+            report.files,
+            source.display(),
+            subdir
+        );
+    }
+    Ok(out_dir.to_path_buf())
+}
+
+#[test]
+fn test_assemble_sysroot() {
+    let root = Path::new("target/assemble_sysroot");
+    let _ = fs::remove_dir_all(root);
+    fs::create_dir_all(root.join("foo/include/foo")).unwrap();
+    fs::create_dir_all(root.join("foo/lib/aarch64")).unwrap();
+    fs::create_dir_all(root.join("bar/api/bar")).unwrap();
+    fs::write(root.join("foo/include/foo/foo.h"), "int foo();").unwrap();
+    fs::write(root.join("foo/include/foo/README"), "").unwrap();
+    fs::write(root.join("foo/lib/aarch64/libfoo.so.1"), "").unwrap();
+    fs::write(root.join("bar/api/bar/bar.h"), "int bar();").unwrap();
+    #[cfg(unix)]
+    std::os::unix::fs::symlink("libfoo.so.1", root.join("foo/lib/aarch64/libfoo.so")).unwrap();
+
+    let out = root.join("sysroot");
+    fs::create_dir_all(out.join("usr/include")).unwrap();
+    fs::write(out.join("usr/include/stale.h"), "").unwrap();
+    let spec = SysrootSpec::new()
+        .headers(root.join("foo/include"), "**/*.h")
+        .libraries(root.join("foo/lib/aarch64"), "*.so*")
+        .headers(root.join("bar/api"), "bar/**/*.h");
+    assert_eq!(assemble_sysroot(&spec, &out).unwrap(), out);
+
+    assert!(out.join("usr/include/foo/foo.h").is_file());
+    assert!(out.join("usr/include/bar/bar.h").is_file());
+    assert!(!out.join("usr/include/foo/README").exists());
+    assert!(!out.join("usr/include/stale.h").exists());
+    assert!(out.join("usr/lib/libfoo.so.1").is_file());
+    #[cfg(unix)]
+    assert!(fs::symlink_metadata(out.join("usr/lib/libfoo.so"))
+        .unwrap()
+        .file_type()
+        .is_symlink());
+
+    fs::remove_dir_all(root).unwrap();
+}
@@ -0,0 +1,428 @@
+//! Reader for the subset of TOML used by toolbelt's configuration and data files.
+//!
+//! Supported are tables (`[a.b]`, quoted keys like `[target.'aarch64-apple-darwin']`), arrays of
+//! tables (`[[a]]`), dotted keys, basic and literal strings (including multi-line), integers,
+//! floats, booleans, arrays and inline tables.
+
+use std::collections::BTreeMap;
+use std::io::{Error, ErrorKind};
+
+pub type Table = BTreeMap<String, Value>;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    String(String),
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+    Array(Vec<Value>),
+    Table(Table),
+}
+
+impl Value {
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_table(&self) -> Option<&Table> {
+        match self {
+            Value::Table(t) => Some(t),
+            _ => None,
+        }
+    }
+}
+
+/// Parse a TOML document into its root table.
+pub fn parse(input: &str) -> Result<Table, Error> {
+    let mut parser = Parser {
+        chars: input.chars().collect(),
+        pos: 0,
+        line: 1,
+    };
+    parser.document()
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    line: usize,
+}
+
+impl Parser {
+    fn error(&self, what: &str) -> Error {
+        Error::new(
+            ErrorKind::InvalidData,
+            format!("Invalid TOML in line {}: {}", self.line, what),
+        )
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.chars.get(self.pos).copied();
+        if c == Some('\n') {
+            self.line += 1;
+        }
+        self.pos += 1;
+        c
+    }
+
+    fn starts_with(&self, s: &str) -> bool {
+        s.chars()
+            .enumerate()
+            .all(|(i, c)| self.chars.get(self.pos + i) == Some(&c))
+    }
+
+    /// Skip spaces and tabs, plus newlines and comments if `newlines` is set.
+    fn skip_whitespace(&mut self, newlines: bool) {
+        while let Some(c) = self.peek() {
+            match c {
+                ' ' | '\t' | '\r' => {
+                    self.next();
+                }
+                '\n' if newlines => {
+                    self.next();
+                }
+                '#' => {
+                    while !matches!(self.peek(), None | Some('\n')) {
+                        self.next();
+                    }
+                }
+                _ => break,
+            }
+        }
+    }
+
+    fn end_of_line(&mut self) -> Result<(), Error> {
+        self.skip_whitespace(false);
+        match self.peek() {
+            None | Some('\n') => Ok(()),
+            Some(c) => Err(self.error(&format!("unexpected '{}'", c))),
+        }
+    }
+
+    fn document(&mut self) -> Result<Table, Error> {
+        let mut root = Table::new();
+        let mut current: Vec<String> = Vec::new();
+
+        loop {
+            self.skip_whitespace(true);
+            match self.peek() {
+                None => return Ok(root),
+                Some('[') => {
+                    self.next();
+                    let array = self.peek() == Some('[');
+                    if array {
+                        self.next();
+                    }
+                    let path = self.key_path()?;
+                    for _ in 0..if array { 2 } else { 1 } {
+                        self.skip_whitespace(false);
+                        if self.next() != Some(']') {
+                            return Err(self.error("expected ']'"));
+                        }
+                    }
+                    self.end_of_line()?;
+
+                    if array {
+                        let (last, parents) = path.split_last().unwrap();
+                        let parent = self.table_at(&mut root, parents)?;
+                        let entry = parent
+                            .entry(last.clone())
+                            .or_insert_with(|| Value::Array(Vec::new()));
+                        match entry {
+                            Value::Array(items) => items.push(Value::Table(Table::new())),
+                            _ => return Err(self.error(&format!("{} is not an array", last))),
+                        }
+                    } else {
+                        self.table_at(&mut root, &path)?;
+                    }
+                    current = path;
+                }
+                Some(_) => {
+                    let path = self.key_path()?;
+                    self.skip_whitespace(false);
+                    if self.next() != Some('=') {
+                        return Err(self.error("expected '='"));
+                    }
+                    let value = self.value()?;
+                    self.end_of_line()?;
+
+                    let mut full_path = current.clone();
+                    full_path.extend(path);
+                    let (last, parents) = full_path.split_last().unwrap();
+                    let table = self.table_at(&mut root, parents)?;
+                    if table.insert(last.clone(), value).is_some() {
+                        return Err(self.error(&format!("duplicate key {}", last)));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Get or create the table at `path`, descending into the last element of table arrays.
+    fn table_at<'t>(&self, root: &'t mut Table, path: &[String]) -> Result<&'t mut Table, Error> {
+        let mut table = root;
+        for key in path {
+            let entry = table
+                .entry(key.clone())
+                .or_insert_with(|| Value::Table(Table::new()));
+            table = match entry {
+                Value::Table(t) => t,
+                Value::Array(items) => match items.last_mut() {
+                    Some(Value::Table(t)) => t,
+                    _ => return Err(self.error(&format!("{} is not a table", key))),
+                },
+                _ => return Err(self.error(&format!("{} is not a table", key))),
+            };
+        }
+        Ok(table)
+    }
+
+    fn key_path(&mut self) -> Result<Vec<String>, Error> {
+        let mut path = Vec::new();
+        loop {
+            self.skip_whitespace(false);
+            let key = match self.peek() {
+                Some('"') => self.basic_string()?,
+                Some('\'') => self.literal_string()?,
+                _ => {
+                    let mut key = String::new();
+                    while let Some(c) = self.peek() {
+                        if c.is_ascii_alphanumeric() || c == '_' || c == '-' {
+                            key.push(c);
+                            self.next();
+                        } else {
+                            break;
+                        }
+                    }
+                    if key.is_empty() {
+                        return Err(self.error("expected a key"));
+                    }
+                    key
+                }
+            };
+            path.push(key);
+            self.skip_whitespace(false);
+            if self.peek() == Some('.') {
+                self.next();
+            } else {
+                return Ok(path);
+            }
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, Error> {
+        self.skip_whitespace(false);
+        match self.peek() {
+            Some('"') => Ok(Value::String(self.basic_string()?)),
+            Some('\'') => Ok(Value::String(self.literal_string()?)),
+            Some('[') => {
+                self.next();
+                let mut items = Vec::new();
+                loop {
+                    self.skip_whitespace(true);
+                    if self.peek() == Some(']') {
+                        self.next();
+                        return Ok(Value::Array(items));
+                    }
+                    items.push(self.value()?);
+                    self.skip_whitespace(true);
+                    match self.next() {
+                        Some(',') => continue,
+                        Some(']') => return Ok(Value::Array(items)),
+                        _ => return Err(self.error("expected ',' or ']'")),
+                    }
+                }
+            }
+            Some('{') => {
+                self.next();
+                let mut table = Table::new();
+                self.skip_whitespace(false);
+                if self.peek() == Some('}') {
+                    self.next();
+                    return Ok(Value::Table(table));
+                }
+                loop {
+                    let path = self.key_path()?;
+                    self.skip_whitespace(false);
+                    if self.next() != Some('=') {
+                        return Err(self.error("expected '='"));
+                    }
+                    let value = self.value()?;
+                    let (last, parents) = path.split_last().unwrap();
+                    self.table_at(&mut table, parents)?
+                        .insert(last.clone(), value);
+                    self.skip_whitespace(false);
+                    match self.next() {
+                        Some(',') => continue,
+                        Some('}') => return Ok(Value::Table(table)),
+                        _ => return Err(self.error("expected ',' or '}'")),
+                    }
+                }
+            }
+            Some(_) => {
+                let mut word = String::new();
+                while let Some(c) = self.peek() {
+                    if c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '_' | '.' | ':') {
+                        word.push(c);
+                        self.next();
+                    } else {
+                        break;
+                    }
+                }
+                match word.as_str() {
+                    "true" => Ok(Value::Boolean(true)),
+                    "false" => Ok(Value::Boolean(false)),
+                    _ => {
+                        let number = word.replace('_', "");
+                        if let Ok(i) = number.parse::<i64>() {
+                            Ok(Value::Integer(i))
+                        } else if let Some(hex) = number.strip_prefix("0x") {
+                            i64::from_str_radix(hex, 16)
+                                .map(Value::Integer)
+                                .map_err(|_| self.error("invalid number"))
+                        } else {
+                            number
+                                .parse::<f64>()
+                                .map(Value::Float)
+                                .map_err(|_| self.error(&format!("invalid value '{}'", word)))
+                        }
+                    }
+                }
+            }
+            None => Err(self.error("expected a value")),
+        }
+    }
+
+    fn basic_string(&mut self) -> Result<String, Error> {
+        let multiline = self.starts_with("\"\"\"");
+        self.pos += if multiline { 3 } else { 1 };
+        if multiline && self.peek() == Some('\n') {
+            self.next();
+        }
+
+        let mut s = String::new();
+        loop {
+            if multiline && self.starts_with("\"\"\"") {
+                self.pos += 3;
+                return Ok(s);
+            }
+            match self.next() {
+                Some('"') if !multiline => return Ok(s),
+                Some('\\') => match self.next() {
+                    Some('n') => s.push('\n'),
+                    Some('t') => s.push('\t'),
+                    Some('r') => s.push('\r'),
+                    Some('"') => s.push('"'),
+                    Some('\\') => s.push('\\'),
+                    Some(c @ ('u' | 'U')) => {
+                        let digits = if c == 'u' { 4 } else { 8 };
+                        let code: String = (0..digits).filter_map(|_| self.next()).collect();
+                        let c = u32::from_str_radix(&code, 16)
+                            .ok()
+                            .and_then(char::from_u32)
+                            .ok_or_else(|| self.error("invalid unicode escape"))?;
+                        s.push(c);
+                    }
+                    Some('\n') if multiline => self.skip_whitespace(true),
+                    _ => return Err(self.error("invalid escape")),
+                },
+                Some('\n') if !multiline => return Err(self.error("unterminated string")),
+                Some(c) => s.push(c),
+                None => return Err(self.error("unterminated string")),
+            }
+        }
+    }
+
+    fn literal_string(&mut self) -> Result<String, Error> {
+        let multiline = self.starts_with("'''");
+        self.pos += if multiline { 3 } else { 1 };
+        if multiline && self.peek() == Some('\n') {
+            self.next();
+        }
+
+        let mut s = String::new();
+        loop {
+            if multiline && self.starts_with("'''") {
+                self.pos += 3;
+                return Ok(s);
+            }
+            match self.next() {
+                Some('\'') if !multiline => return Ok(s),
+                Some('\n') if !multiline => return Err(self.error("unterminated string")),
+                Some(c) => s.push(c),
+                None => return Err(self.error("unterminated string")),
+            }
+        }
+    }
+}
+
+#[test]
+fn test_toml_parse() {
+    let doc = r#"
+# comment
+name = "toolbelt" # trailing comment
+count = 1_000
+ratio = 0.5
+enabled = true
+list = [1, 2,
+  3, ]
+inline = { a = "x", b.c = 'y' }
+
+[profile.ci]
+jobs = 2
+
+[target.'aarch64-apple-darwin']
+"sdk.path" = "/opt/sdk"
+
+[[sdk]]
+name = "one"
+[[sdk]]
+name = "two"
+
+[text]
+multi = """
+line 1
+line 2"""
+"#;
+    let root = parse(doc).unwrap();
+    assert_eq!(root["name"].as_str(), Some("toolbelt"));
+    assert_eq!(root["count"], Value::Integer(1000));
+    assert_eq!(root["ratio"], Value::Float(0.5));
+    assert_eq!(root["enabled"], Value::Boolean(true));
+    assert_eq!(
+        root["list"],
+        Value::Array(vec![
+            Value::Integer(1),
+            Value::Integer(2),
+            Value::Integer(3)
+        ])
+    );
+    let inline = root["inline"].as_table().unwrap();
+    assert_eq!(inline["b"].as_table().unwrap()["c"].as_str(), Some("y"));
+    let ci = root["profile"].as_table().unwrap()["ci"]
+        .as_table()
+        .unwrap();
+    assert_eq!(ci["jobs"], Value::Integer(2));
+    let target = root["target"].as_table().unwrap()["aarch64-apple-darwin"]
+        .as_table()
+        .unwrap();
+    assert_eq!(target["sdk.path"].as_str(), Some("/opt/sdk"));
+    match &root["sdk"] {
+        Value::Array(items) => assert_eq!(items.len(), 2),
+        other => panic!("unexpected {:?}", other),
+    }
+    assert_eq!(
+        root["text"].as_table().unwrap()["multi"].as_str(),
+        Some("line 1\nline 2")
+    );
+
+    assert!(parse("a = 1\na = 2").is_err());
+    assert!(parse("a = \"open").is_err());
+}
@@ -0,0 +1,570 @@
+//! Runner for the external tools toolbelt drives (ibtool, codesign, …).
+//!
+//! Every tool is started under a watchdog, so it doesn't outlive the build script when that is
+//! killed by Ctrl-C or a CI timeout:
+//!
+//! * On Unix the tool runs in its own process group. A small companion process in a separate group
+//!   watches the build script and terminates the tool's group once the build script is gone,
+//!   escalating to SIGKILL if the tool ignores SIGTERM. Linux' `PR_SET_PDEATHSIG` isn't used, as
+//!   it fires when the spawning *thread* exits, which would kill tools started from worker
+//!   threads while the build script is still running.
+//! * On Windows all tools are assigned to a job object that kills its processes when the build
+//!   script exits.
+//!
+//! A tool given a [`Tool::timeout`] is killed together with the processes it started once the
+//! timeout expires.
+
+use std::ffi::OsStr;
+use std::fmt;
+use std::io::{Error, Read};
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus, Stdio};
+use std::time::{Duration, Instant};
+
+/// Output of a finished tool.
+#[derive(Clone, Debug)]
+pub struct ToolOutput {
+    pub status: ExitStatus,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+/// Errors reported by [`Tool::run`].
+#[derive(Debug)]
+pub enum ToolError {
+    /// The tool could not be started, e.g. because it isn't installed.
+    Spawn { program: String, source: Error },
+    /// The tool exited with a non-zero status.
+    Failed {
+        command: String,
+        status: ExitStatus,
+        stdout: String,
+        stderr: String,
+    },
+    /// The tool didn't finish within its [`Tool::timeout`] and was killed.
+    Timeout { command: String, timeout: Duration },
+}
+
+impl fmt::Display for ToolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ToolError::Spawn { program, source } => {
+                write!(f, "Failed to run {}: {}", program, source)
+            }
+            ToolError::Failed {
+                command,
+                status,
+                stdout,
+                stderr,
+            } => {
+                write!(f, "{} failed ({})", command, status)?;
+                for output in [stderr.trim(), stdout.trim()] {
+                    if !output.is_empty() {
+                        write!(f, "\n{}", output)?;
+                    }
+                }
+                Ok(())
+            }
+            ToolError::Timeout { command, timeout } => {
+                write!(f, "{} timed out after {:?}", command, timeout)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ToolError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ToolError::Spawn { source, .. } => Some(source),
+            ToolError::Failed { .. } | ToolError::Timeout { .. } => None,
+        }
+    }
+}
+
+/// Errors reported by functions compiling resource files with an external tool (ibtool, …).
+#[derive(Debug)]
+pub enum CompileError {
+    /// Finding the input files or creating the output directories failed.
+    Io(Error),
+    /// The tool failed for `file`.
+    Tool { file: PathBuf, source: ToolError },
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CompileError::Io(e) => write!(f, "{}", e),
+            CompileError::Tool { file, source } => {
+                write!(f, "Compiling {} failed: {}", file.display(), source)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CompileError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CompileError::Io(e) => Some(e),
+            CompileError::Tool { source, .. } => Some(source),
+        }
+    }
+}
+
+impl From<Error> for CompileError {
+    fn from(e: Error) -> Self {
+        CompileError::Io(e)
+    }
+}
+
+/// Builder for running an external tool, modeled after [`std::process::Command`].
+///
+/// # Example
+///
+/// ```no_run
+/// use toolbelt::tool::Tool;
+///
+/// let output = Tool::new("xcrun").arg("--show-sdk-path").run().unwrap();
+/// println!("{}", output.stdout.trim());
+/// ```
+pub struct Tool {
+    command: Command,
+    /// Indices of arguments hidden in [`Tool::command_line`]
+    secrets: Vec<usize>,
+    timeout: Option<Duration>,
+}
+
+impl Tool {
+    pub fn new<S: AsRef<OsStr>>(program: S) -> Tool {
+        Tool {
+            command: Command::new(program),
+            secrets: Vec::new(),
+            timeout: None,
+        }
+    }
+
+    pub fn arg<S: AsRef<OsStr>>(&mut self, arg: S) -> &mut Tool {
+        self.command.arg(arg);
+        self
+    }
+
+    pub fn args<I, S>(&mut self, args: I) -> &mut Tool
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        self.command.args(args);
+        self
+    }
+
+    /// Add an argument such as a password that is masked in logs and error messages.
+    pub fn secret_arg<S: AsRef<OsStr>>(&mut self, arg: S) -> &mut Tool {
+        self.secrets.push(self.command.get_args().count());
+        self.command.arg(arg);
+        self
+    }
+
+    pub fn env<K: AsRef<OsStr>, V: AsRef<OsStr>>(&mut self, key: K, value: V) -> &mut Tool {
+        self.command.env(key, value);
+        self
+    }
+
+    pub fn current_dir<P: AsRef<Path>>(&mut self, dir: P) -> &mut Tool {
+        self.command.current_dir(dir);
+        self
+    }
+
+    /// Kill the tool and the processes it started if it runs longer than `timeout`, failing
+    /// with [`ToolError::Timeout`].
+    pub fn timeout(&mut self, timeout: Duration) -> &mut Tool {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// The command line as it would be typed into a shell, for logging and error messages.
+    pub fn command_line(&self) -> String {
+        let args = self.command.get_args().enumerate().map(|(index, arg)| {
+            if self.secrets.contains(&index) {
+                OsStr::new("***")
+            } else {
+                arg
+            }
+        });
+        std::iter::once(self.command.get_program())
+            .chain(args)
+            .map(|part| {
+                let part = part.to_string_lossy();
+                if part.contains(char::is_whitespace) {
+                    format!("\"{}\"", part)
+                } else {
+                    part.into_owned()
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// Run the tool to completion and capture its output, regardless of the exit status.
+    pub fn output(&mut self) -> Result<ToolOutput, ToolError> {
+        debug!("Run {}", self.command_line());
+
+        self.command
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        watchdog::prepare(&mut self.command);
+
+        let program = self.command.get_program().to_string_lossy().into_owned();
+        let spawn_error = |source| ToolError::Spawn {
+            program: program.clone(),
+            source,
+        };
+        let started = Instant::now();
+        let mut child = self.command.spawn().map_err(spawn_error)?;
+        let _watchdog = watchdog::start(&mut child).map_err(spawn_error)?;
+
+        // read both pipes while waiting, so a chatty tool can't block on a full pipe
+        let read = |pipe: Option<Box<dyn Read + Send>>| {
+            std::thread::spawn(move || {
+                let mut buffer = Vec::new();
+                if let Some(mut pipe) = pipe {
+                    let _ = pipe.read_to_end(&mut buffer);
+                }
+                String::from_utf8_lossy(&buffer).into_owned()
+            })
+        };
+        let stdout = read(child.stdout.take().map(|p| Box::new(p) as _));
+        let stderr = read(child.stderr.take().map(|p| Box::new(p) as _));
+
+        let status = match self.timeout {
+            None => child.wait().map_err(spawn_error)?,
+            Some(timeout) => loop {
+                if let Some(status) = child.try_wait().map_err(spawn_error)? {
+                    break status;
+                }
+                if started.elapsed() >= timeout {
+                    watchdog::kill(&mut child);
+                    let _ = child.wait();
+                    // the reader threads finish once the killed processes closed the pipes
+                    return Err(ToolError::Timeout {
+                        command: self.command_line(),
+                        timeout,
+                    });
+                }
+                std::thread::sleep(Duration::from_millis(20));
+            },
+        };
+
+        Ok(ToolOutput {
+            status,
+            stdout: stdout.join().unwrap_or_default(),
+            stderr: stderr.join().unwrap_or_default(),
+        })
+    }
+
+    /// Run the tool to completion, failing if it exits with a non-zero status.
+    pub fn run(&mut self) -> Result<ToolOutput, ToolError> {
+        let output = self.output()?;
+        if output.status.success() {
+            Ok(output)
+        } else {
+            Err(ToolError::Failed {
+                command: self.command_line(),
+                status: output.status,
+                stdout: output.stdout,
+                stderr: output.stderr,
+            })
+        }
+    }
+}
+
+#[cfg(unix)]
+mod watchdog {
+    use std::io::Error;
+    use std::os::unix::process::CommandExt;
+    use std::process::{Child, Command, Stdio};
+
+    /// Waits for the build script (`$0`) to disappear, then terminates the tool's process
+    /// group (`$1`).
+    const REAPER: &str = "while kill -0 $0 2>/dev/null; do sleep 1; done; \
+                          kill -TERM -$1 2>/dev/null; sleep 2; kill -KILL -$1 2>/dev/null";
+
+    pub(super) struct Watchdog {
+        reaper: Option<Child>,
+    }
+
+    impl Drop for Watchdog {
+        fn drop(&mut self) {
+            if let Some(reaper) = &mut self.reaper {
+                let _ = reaper.kill();
+                let _ = reaper.wait();
+            }
+        }
+    }
+
+    pub(super) fn prepare(command: &mut Command) {
+        command.process_group(0);
+    }
+
+    pub(super) fn start(child: &mut Child) -> Result<Watchdog, Error> {
+        Ok(start_reaper(std::process::id(), child))
+    }
+
+    /// Start the reaper terminating `child` once process `parent` is gone.
+    ///
+    /// The reaper runs in a process group of its own, so it survives the SIGTERM it sends to the
+    /// tool's group and can follow up with SIGKILL.
+    pub(super) fn start_reaper(parent: u32, child: &Child) -> Watchdog {
+        let reaper = Command::new("/bin/sh")
+            .arg("-c")
+            .arg(REAPER)
+            .arg(parent.to_string())
+            .arg(child.id().to_string())
+            .process_group(0)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn();
+        if let Err(e) = &reaper {
+            trace!("Could not start watchdog for process {}: {}", child.id(), e);
+        }
+        Watchdog {
+            reaper: reaper.ok(),
+        }
+    }
+
+    pub(super) fn kill(child: &mut Child) {
+        const SIGKILL: i32 = 9;
+        extern "C" {
+            fn kill(pid: i32, signal: i32) -> i32;
+        }
+        // SAFETY: kill only sends a signal, the tool is the leader of its own process group
+        unsafe {
+            kill(-(child.id() as i32), SIGKILL);
+        }
+    }
+}
+
+#[cfg(windows)]
+mod watchdog {
+    use std::ffi::c_void;
+    use std::io::Error;
+    use std::os::windows::io::AsRawHandle;
+    use std::process::{Child, Command};
+    use std::sync::OnceLock;
+
+    type Handle = *mut c_void;
+
+    const JOB_OBJECT_EXTENDED_LIMIT_INFORMATION: i32 = 9;
+    const JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE: u32 = 0x2000;
+
+    #[repr(C)]
+    #[derive(Default)]
+    struct BasicLimitInformation {
+        per_process_user_time_limit: i64,
+        per_job_user_time_limit: i64,
+        limit_flags: u32,
+        minimum_working_set_size: usize,
+        maximum_working_set_size: usize,
+        active_process_limit: u32,
+        affinity: usize,
+        priority_class: u32,
+        scheduling_class: u32,
+    }
+
+    #[repr(C)]
+    #[derive(Default)]
+    struct ExtendedLimitInformation {
+        basic_limit_information: BasicLimitInformation,
+        io_info: [u64; 6],
+        process_memory_limit: usize,
+        job_memory_limit: usize,
+        peak_process_memory_used: usize,
+        peak_job_memory_used: usize,
+    }
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn CreateJobObjectW(attributes: *mut c_void, name: *const u16) -> Handle;
+        fn SetInformationJobObject(job: Handle, class: i32, info: *mut c_void, length: u32) -> i32;
+        fn AssignProcessToJobObject(job: Handle, process: Handle) -> i32;
+        fn CloseHandle(handle: Handle) -> i32;
+    }
+
+    struct Job(Handle);
+
+    // SAFETY: the job handle is only used for kernel calls, which are thread safe
+    unsafe impl Send for Job {}
+    unsafe impl Sync for Job {}
+
+    /// The job object shared by all tools. Its handle is closed by the system when the build
+    /// script exits, which kills all processes assigned to it.
+    fn job() -> Result<&'static Job, Error> {
+        static JOB: OnceLock<Result<Job, i32>> = OnceLock::new();
+        JOB.get_or_init(|| unsafe {
+            let handle = CreateJobObjectW(std::ptr::null_mut(), std::ptr::null());
+            if handle.is_null() {
+                return Err(last_error());
+            }
+            let mut info = ExtendedLimitInformation::default();
+            info.basic_limit_information.limit_flags = JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE;
+            let set = SetInformationJobObject(
+                handle,
+                JOB_OBJECT_EXTENDED_LIMIT_INFORMATION,
+                &mut info as *mut _ as *mut c_void,
+                std::mem::size_of::<ExtendedLimitInformation>() as u32,
+            );
+            if set == 0 {
+                let error = last_error();
+                CloseHandle(handle);
+                return Err(error);
+            }
+            Ok(Job(handle))
+        })
+        .as_ref()
+        .map_err(|code| Error::from_raw_os_error(*code))
+    }
+
+    fn last_error() -> i32 {
+        Error::last_os_error().raw_os_error().unwrap_or_default()
+    }
+
+    pub(super) struct Watchdog;
+
+    pub(super) fn prepare(_command: &mut Command) {}
+
+    /// Assign the tool to the job object. If that fails, the tool is killed: it would outlive
+    /// the build script.
+    pub(super) fn start(child: &mut Child) -> Result<Watchdog, Error> {
+        // SAFETY: both handles are valid for the duration of the call
+        let assigned = job().and_then(|job| unsafe {
+            if AssignProcessToJobObject(job.0, child.as_raw_handle() as Handle) == 0 {
+                Err(Error::last_os_error())
+            } else {
+                Ok(())
+            }
+        });
+        if let Err(e) = assigned {
+            kill(child);
+            let _ = child.wait();
+            return Err(e);
+        }
+        Ok(Watchdog)
+    }
+
+    /// Kill the tool. Processes it started are only killed with the job object, as that is
+    /// shared by all tools.
+    pub(super) fn kill(child: &mut Child) {
+        let _ = child.kill();
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+mod watchdog {
+    use std::io::Error;
+    use std::process::{Child, Command};
+
+    pub(super) struct Watchdog;
+
+    pub(super) fn prepare(_command: &mut Command) {}
+
+    pub(super) fn start(_child: &mut Child) -> Result<Watchdog, Error> {
+        Ok(Watchdog)
+    }
+
+    pub(super) fn kill(child: &mut Child) {
+        let _ = child.kill();
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn test_tool_run() {
+    let output = Tool::new("sh").arg("-c").arg("echo hello").run().unwrap();
+    assert_eq!(output.stdout.trim(), "hello");
+
+    match Tool::new("sh").args(["-c", "echo oops >&2; exit 3"]).run() {
+        Err(ToolError::Failed { stderr, .. }) => assert_eq!(stderr.trim(), "oops"),
+        other => panic!("unexpected result {:?}", other),
+    }
+    assert!(matches!(
+        Tool::new("toolbelt-does-not-exist").run(),
+        Err(ToolError::Spawn { .. })
+    ));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_tool_timeout() {
+    // the tool starts a grandchild that would outlive it
+    let root = Path::new("target/tool_timeout");
+    let _ = std::fs::remove_dir_all(root);
+    std::fs::create_dir_all(root).unwrap();
+    let pid_file = root.join("pid");
+    let started = Instant::now();
+    let result = Tool::new("sh")
+        .arg("-c")
+        .arg("sleep 30 & echo $! > \"$0\"; wait")
+        .arg(&pid_file)
+        .timeout(Duration::from_millis(500))
+        .output();
+    assert!(started.elapsed() < Duration::from_secs(10));
+    match result {
+        Err(ToolError::Timeout { command, timeout }) => {
+            assert!(command.starts_with("sh -c"));
+            assert_eq!(timeout, Duration::from_millis(500));
+        }
+        other => panic!("unexpected result {:?}", other),
+    }
+
+    let pid = std::fs::read_to_string(&pid_file).unwrap();
+    // the killed grandchild is gone, once its parent reaped it
+    let alive = || {
+        Command::new("kill")
+            .args(["-0", pid.trim()])
+            .stderr(Stdio::null())
+            .status()
+            .unwrap()
+            .success()
+    };
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while alive() && Instant::now() < deadline {
+        std::thread::sleep(Duration::from_millis(50));
+    }
+    assert!(!alive());
+
+    std::fs::remove_dir_all(root).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn test_watchdog_reaper() {
+    use std::os::unix::process::CommandExt;
+
+    // a stand-in for the build script, and a tool in its own group that ignores SIGTERM
+    let mut parent = Command::new("sleep").arg("1").spawn().unwrap();
+    let mut child = Command::new("sh")
+        .args(["-c", "trap '' TERM; sleep 30"])
+        .process_group(0)
+        .spawn()
+        .unwrap();
+    let _watchdog = watchdog::start_reaper(parent.id(), &child);
+    parent.wait().unwrap();
+
+    // the reaper sends SIGTERM, then SIGKILL two seconds later
+    let deadline = Instant::now() + Duration::from_secs(10);
+    let status = loop {
+        if let Some(status) = child.try_wait().unwrap() {
+            break Some(status);
+        }
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            break None;
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    };
+    let status = status.expect("the tool survived the build script");
+    assert_eq!(
+        std::os::unix::process::ExitStatusExt::signal(&status),
+        Some(9)
+    );
+}
@@ -0,0 +1,223 @@
+//! Locating packages installed with vcpkg.
+//!
+//! vcpkg installs the headers and libraries of all packages of a triplet, e.g. `x64-windows`,
+//! into one directory: `vcpkg_installed/<triplet>` next to a `vcpkg.json` manifest, or
+//! `installed/<triplet>` in the vcpkg root for the classic mode. Builds using it don't need an
+//! environment variable per library.
+
+use std::env;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+
+use crate::{IncludeDirFormat, LibDirFormat};
+
+/// The installed packages of a vcpkg triplet.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Vcpkg {
+    /// The triplet, e.g. `x64-windows-static`
+    pub triplet: String,
+    /// The triplet's directory, e.g. `vcpkg_installed/x64-windows`
+    pub dir: PathBuf,
+}
+
+/// The vcpkg triplet matching a Rust target, e.g. `x64-windows` for `x86_64-pc-windows-msvc`.
+/// Windows targets linking the C runtime statically use the `-static` triplets.
+///
+/// # Arguments
+///
+/// * `target` - The Rust target triple, e.g. the value of `TARGET` in a build script
+/// * `crt_static` - Whether the C runtime is linked statically, see `CARGO_CFG_TARGET_FEATURE`
+pub fn vcpkg_triplet(target: &str, crt_static: bool) -> String {
+    let arch = match target.split('-').next().unwrap_or_default() {
+        "x86_64" => "x64",
+        "i586" | "i686" => "x86",
+        "aarch64" => "arm64",
+        arch if arch.starts_with("arm") || arch.starts_with("thumb") => "arm",
+        arch => arch,
+    };
+    if target.contains("-windows-") {
+        if target.ends_with("-gnu") {
+            format!("{}-mingw-dynamic", arch)
+        } else if crt_static {
+            format!("{}-windows-static", arch)
+        } else {
+            format!("{}-windows", arch)
+        }
+    } else if target.contains("-apple-darwin") {
+        format!("{}-osx", arch)
+    } else if target.contains("-apple-ios") {
+        format!("{}-ios", arch)
+    } else if target.contains("-linux-android") {
+        format!("{}-android", arch)
+    } else {
+        format!("{}-linux", arch)
+    }
+}
+
+impl Vcpkg {
+    /// The triplet `triplet` in an installed directory, e.g. `vcpkg_installed` or
+    /// `<vcpkg root>/installed`.
+    pub fn at(installed_dir: &Path, triplet: &str) -> Result<Vcpkg, Error> {
+        let dir = installed_dir.join(triplet);
+        if !dir.join("include").is_dir() {
+            return Err(Error::new(
+                ErrorKind::NotFound,
+                format!(
+                    "No packages are installed for the vcpkg triplet {} in {}",
+                    triplet,
+                    installed_dir.display()
+                ),
+            ));
+        }
+        Ok(Vcpkg {
+            triplet: triplet.to_string(),
+            dir,
+        })
+    }
+
+    /// The header directory, formatted like [`crate::get_sdk_include_dirs`].
+    pub fn include_dirs(&self, format: IncludeDirFormat) -> Vec<String> {
+        vec![format.format(&self.dir.join("include"))]
+    }
+
+    /// The library directories, `debug/lib` instead of `lib` for debug builds.
+    pub fn lib_dirs(&self, debug: bool) -> Vec<PathBuf> {
+        let prefix = if debug {
+            self.dir.join("debug")
+        } else {
+            self.dir.clone()
+        };
+        [prefix.join("lib")]
+            .into_iter()
+            .filter(|dir| dir.is_dir())
+            .collect()
+    }
+
+    /// The library directories, formatted like [`crate::get_sdk_lib_dirs`].
+    pub fn lib_dir_flags(&self, debug: bool, format: LibDirFormat) -> Vec<String> {
+        self.lib_dirs(debug)
+            .iter()
+            .map(|dir| format.format(dir))
+            .collect()
+    }
+}
+
+/// The directories vcpkg may have installed packages into, in order of precedence.
+fn installed_dirs() -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    if let Some(dir) = env::var_os("VCPKG_INSTALLED_DIR") {
+        dirs.push(PathBuf::from(dir));
+    }
+    if let Some(dir) = env::var_os("CARGO_MANIFEST_DIR") {
+        dirs.push(PathBuf::from(dir).join("vcpkg_installed"));
+    }
+    // VCPKG_INSTALLATION_ROOT is set on GitHub's and Azure's hosted runners
+    for var in ["VCPKG_ROOT", "VCPKG_INSTALLATION_ROOT"] {
+        if let Some(root) = env::var_os(var).filter(|root| !root.is_empty()) {
+            dirs.push(PathBuf::from(root).join("installed"));
+        }
+    }
+    dirs
+}
+
+/// Detect the vcpkg packages for the target of a build script.
+///
+/// The triplet is taken from `VCPKG_DEFAULT_TRIPLET` or derived from `TARGET` with
+/// [`vcpkg_triplet`]. It is looked up in `VCPKG_INSTALLED_DIR`, `vcpkg_installed` in the crate
+/// directory (manifest mode), and the `installed` directory of `VCPKG_ROOT` (classic mode).
+///
+/// # Example
+///
+/// ```no_run
+/// use toolbelt::{detect_vcpkg, IncludeDirFormat};
+///
+/// let vcpkg = detect_vcpkg().unwrap();
+/// let include_dirs = vcpkg.include_dirs(IncludeDirFormat::MSVC);
+/// let debug = std::env::var("PROFILE").unwrap() == "debug";
+/// for dir in vcpkg.lib_dirs(debug) {
+///     println!("cargo:rustc-link-search=native={}", dir.display());
+/// }
+/// ```
+pub fn detect_vcpkg() -> Result<Vcpkg, Error> {
+    let triplet = match env::var("VCPKG_DEFAULT_TRIPLET") {
+        Ok(triplet) => triplet,
+        Err(_) => {
+            let target = env::var("TARGET").map_err(|_| {
+                Error::new(
+                    ErrorKind::NotFound,
+                    "Set VCPKG_DEFAULT_TRIPLET or run from a build script",
+                )
+            })?;
+            let crt_static = env::var("CARGO_CFG_TARGET_FEATURE")
+                .is_ok_and(|features| features.split(',').any(|f| f == "crt-static"));
+            vcpkg_triplet(&target, crt_static)
+        }
+    };
+    let dirs = installed_dirs();
+    let vcpkg = dirs
+        .iter()
+        .find_map(|dir| Vcpkg::at(dir, &triplet).ok())
+        .ok_or_else(|| {
+            Error::new(
+                ErrorKind::NotFound,
+                format!(
+                    "No vcpkg packages found for {}, install them with vcpkg install \
+                     --triplet {} or set VCPKG_ROOT",
+                    triplet, triplet
+                ),
+            )
+        })?;
+    debug!("vcpkg {} in {}", vcpkg.triplet, vcpkg.dir.display());
+    Ok(vcpkg)
+}
+
+#[test]
+fn test_vcpkg() {
+    assert_eq!(
+        vcpkg_triplet("x86_64-pc-windows-msvc", false),
+        "x64-windows"
+    );
+    assert_eq!(
+        vcpkg_triplet("x86_64-pc-windows-msvc", true),
+        "x64-windows-static"
+    );
+    assert_eq!(
+        vcpkg_triplet("x86_64-pc-windows-gnu", false),
+        "x64-mingw-dynamic"
+    );
+    assert_eq!(vcpkg_triplet("aarch64-apple-darwin", false), "arm64-osx");
+    assert_eq!(vcpkg_triplet("i686-unknown-linux-gnu", false), "x86-linux");
+
+    let root = Path::new("target/vcpkg");
+    let _ = std::fs::remove_dir_all(root);
+    let installed = root.join("vcpkg_installed");
+    std::fs::create_dir_all(installed.join("x64-windows/include")).unwrap();
+    std::fs::create_dir_all(installed.join("x64-windows/lib")).unwrap();
+    std::fs::create_dir_all(installed.join("x64-windows/debug/lib")).unwrap();
+
+    let vcpkg = Vcpkg::at(&installed, "x64-windows").unwrap();
+    assert_eq!(
+        vcpkg.include_dirs(IncludeDirFormat::MSVC),
+        [format!(
+            "/I{}",
+            installed.join("x64-windows/include").display()
+        )]
+    );
+    assert_eq!(
+        vcpkg.lib_dirs(true),
+        [installed.join("x64-windows/debug/lib")]
+    );
+    assert_eq!(
+        vcpkg.lib_dir_flags(false, LibDirFormat::MSVC),
+        [format!(
+            "/LIBPATH:{}",
+            installed.join("x64-windows/lib").display()
+        )]
+    );
+    assert_eq!(
+        Vcpkg::at(&installed, "x64-linux").unwrap_err().kind(),
+        ErrorKind::NotFound
+    );
+
+    std::fs::remove_dir_all(root).unwrap();
+}
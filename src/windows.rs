@@ -0,0 +1,11 @@
+//! Windows counterparts of the Apple tooling: locating the Windows SDK, code signing with
+//! signtool, compiling resource scripts, version information, application manifests, icon files
+//! and installer packages.
+
+pub mod app_manifest;
+pub mod ico;
+pub mod msi;
+pub mod resources;
+pub mod sdk;
+pub mod signtool;
+pub mod version_info;
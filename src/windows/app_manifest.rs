@@ -0,0 +1,238 @@
+//! Application manifests for Windows executables.
+//!
+//! The manifest tells Windows how to treat a program: whether it handles high DPI itself, which
+//! privileges it needs when started and whether its dialogs use the themed common controls
+//! (version 6) instead of the Windows 95 look. It is either linked into the executable from
+//! `build.rs` ([`link_app_manifest`]) or embedded into a built executable with mt.exe
+//! ([`embed_app_manifest`]).
+
+use std::fmt::Write;
+use std::fs;
+use std::io::Error;
+use std::path::{Path, PathBuf};
+
+use super::resources::{compile_resource, ResourceCompiler};
+use super::sdk::detect_windows_sdk;
+use crate::tool::{CompileError, Tool};
+
+/// How the program handles high DPI displays.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DpiAwareness {
+    /// Windows scales the program's bitmap, making it blurry
+    Unaware,
+    /// The program scales to the DPI of the primary display when it starts
+    System,
+    /// The program rescales when moved between displays, with scaled non-client areas and
+    /// dialogs (Windows 10 1703 and later)
+    PerMonitorV2,
+}
+
+/// The privileges the program asks for when it is started.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExecutionLevel {
+    /// Run with the privileges of the parent process
+    AsInvoker,
+    /// Run with the highest privileges the user has, asking administrators for elevation
+    HighestAvailable,
+    /// Always ask for administrator privileges
+    RequireAdministrator,
+}
+
+impl ExecutionLevel {
+    fn as_str(self) -> &'static str {
+        match self {
+            ExecutionLevel::AsInvoker => "asInvoker",
+            ExecutionLevel::HighestAvailable => "highestAvailable",
+            ExecutionLevel::RequireAdministrator => "requireAdministrator",
+        }
+    }
+}
+
+/// Contents of an application manifest.
+#[derive(Clone, Debug)]
+pub struct AppManifestOptions {
+    pub dpi_awareness: DpiAwareness,
+    pub execution_level: ExecutionLevel,
+    /// Allow bypassing UI restrictions (`uiAccess`), only for signed accessibility tools
+    pub ui_access: bool,
+    /// Use version 6 of the common controls for themed dialogs and controls
+    pub common_controls: bool,
+    /// Allow paths longer than `MAX_PATH` if enabled in the system
+    pub long_path_aware: bool,
+}
+
+impl Default for AppManifestOptions {
+    fn default() -> Self {
+        AppManifestOptions {
+            dpi_awareness: DpiAwareness::PerMonitorV2,
+            execution_level: ExecutionLevel::AsInvoker,
+            ui_access: false,
+            common_controls: true,
+            long_path_aware: false,
+        }
+    }
+}
+
+impl AppManifestOptions {
+    /// The manifest as XML.
+    pub fn to_xml(&self) -> String {
+        let mut xml = String::from(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n\
+             <assembly xmlns=\"urn:schemas-microsoft-com:asm.v1\" manifestVersion=\"1.0\">\n",
+        );
+        if self.common_controls {
+            xml.push_str(
+                "  <dependency>\n    <dependentAssembly>\n      <assemblyIdentity \
+                 type=\"win32\" name=\"Microsoft.Windows.Common-Controls\" version=\"6.0.0.0\" \
+                 processorArchitecture=\"*\" publicKeyToken=\"6595b64144ccf1df\" \
+                 language=\"*\"/>\n    </dependentAssembly>\n  </dependency>\n",
+            );
+        }
+        let _ = write!(
+            xml,
+            "  <trustInfo xmlns=\"urn:schemas-microsoft-com:asm.v3\">\n    <security>\n      \
+             <requestedPrivileges>\n        <requestedExecutionLevel level=\"{}\" \
+             uiAccess=\"{}\"/>\n      </requestedPrivileges>\n    </security>\n  </trustInfo>\n",
+            self.execution_level.as_str(),
+            self.ui_access
+        );
+        let (dpi_aware, dpi_awareness) = match self.dpi_awareness {
+            DpiAwareness::Unaware => ("false", "unaware"),
+            DpiAwareness::System => ("true", "system"),
+            DpiAwareness::PerMonitorV2 => ("true/pm", "PerMonitorV2, PerMonitor"),
+        };
+        xml.push_str(
+            "  <application xmlns=\"urn:schemas-microsoft-com:asm.v3\">\n    <windowsSettings>\n",
+        );
+        let _ = writeln!(
+            xml,
+            "      <dpiAware xmlns=\"http://schemas.microsoft.com/SMI/2005/WindowsSettings\">{}</dpiAware>",
+            dpi_aware
+        );
+        let _ = writeln!(
+            xml,
+            "      <dpiAwareness xmlns=\"http://schemas.microsoft.com/SMI/2016/WindowsSettings\">{}</dpiAwareness>",
+            dpi_awareness
+        );
+        if self.long_path_aware {
+            xml.push_str(
+                "      <longPathAware xmlns=\"http://schemas.microsoft.com/SMI/2016/WindowsSettings\">true</longPathAware>\n",
+            );
+        }
+        xml.push_str("    </windowsSettings>\n  </application>\n</assembly>\n");
+        xml
+    }
+}
+
+/// Write the manifest to a file, e.g. `app.exe.manifest`.
+pub fn write_app_manifest(path: &Path, options: &AppManifestOptions) -> Result<(), Error> {
+    fs::write(path, options.to_xml())
+}
+
+/// Resource script embedding `manifest` as resource 1 of type `RT_MANIFEST` (24).
+fn manifest_rc(manifest: &Path) -> String {
+    format!(
+        "1 24 \"{}\"\n",
+        manifest.display().to_string().replace('\\', "\\\\")
+    )
+}
+
+/// Link the manifest into the crate's executables from `build.rs`.
+///
+/// For MSVC targets the manifest is passed to link.exe (`/MANIFEST:EMBED /MANIFESTINPUT:`), for
+/// GNU targets it is compiled into a resource with windres. Returns the written manifest.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::path::Path;
+/// use toolbelt::windows::app_manifest::{link_app_manifest, AppManifestOptions, ExecutionLevel};
+///
+/// if std::env::var("CARGO_CFG_TARGET_OS").as_deref() == Ok("windows") {
+///     let out_dir = std::env::var("OUT_DIR").unwrap();
+///     let options = AppManifestOptions {
+///         execution_level: ExecutionLevel::RequireAdministrator,
+///         ..Default::default()
+///     };
+///     link_app_manifest(&options, Path::new(&out_dir)).unwrap();
+/// }
+/// ```
+pub fn link_app_manifest(
+    options: &AppManifestOptions,
+    out_dir: &Path,
+) -> Result<PathBuf, CompileError> {
+    fs::create_dir_all(out_dir)?;
+    let manifest = out_dir.join("app.manifest");
+    write_app_manifest(&manifest, options)?;
+    match ResourceCompiler::for_target() {
+        ResourceCompiler::Rc => {
+            println!("cargo:rustc-link-arg=/MANIFEST:EMBED");
+            println!("cargo:rustc-link-arg=/MANIFESTINPUT:{}", manifest.display());
+        }
+        compiler => {
+            let rc_file = out_dir.join("app_manifest.rc");
+            fs::write(&rc_file, manifest_rc(&manifest))?;
+            let resource = compile_resource(&rc_file, out_dir, compiler)?;
+            println!("cargo:rustc-link-arg={}", resource.display());
+        }
+    }
+    Ok(manifest)
+}
+
+/// Embed the manifest into a built executable with mt.exe from the Windows SDK, replacing an
+/// existing one. The manifest is written next to the executable as `<name>.manifest`.
+pub fn embed_app_manifest(
+    executable: &Path,
+    options: &AppManifestOptions,
+) -> Result<(), CompileError> {
+    let mut manifest = executable.as_os_str().to_owned();
+    manifest.push(".manifest");
+    let manifest = PathBuf::from(manifest);
+    write_app_manifest(&manifest, options)?;
+    let mut output_resource = std::ffi::OsString::from("-outputresource:");
+    output_resource.push(executable);
+    output_resource.push(";#1");
+    let mt = match detect_windows_sdk() {
+        Ok(sdk) if sdk.mt.is_file() => sdk.mt,
+        _ => PathBuf::from("mt"),
+    };
+    Tool::new(mt)
+        .arg("-nologo")
+        .arg("-manifest")
+        .arg(&manifest)
+        .arg(output_resource)
+        .run()
+        .map_err(|source| CompileError::Tool {
+            file: executable.to_path_buf(),
+            source,
+        })?;
+    Ok(())
+}
+
+#[test]
+fn test_app_manifest() {
+    let xml = AppManifestOptions::default().to_xml();
+    assert!(xml.contains("name=\"Microsoft.Windows.Common-Controls\" version=\"6.0.0.0\""));
+    assert!(xml.contains("<requestedExecutionLevel level=\"asInvoker\" uiAccess=\"false\"/>"));
+    assert!(xml.contains(">PerMonitorV2, PerMonitor</dpiAwareness>"));
+    assert!(!xml.contains("longPathAware"));
+
+    let xml = AppManifestOptions {
+        dpi_awareness: DpiAwareness::Unaware,
+        execution_level: ExecutionLevel::RequireAdministrator,
+        common_controls: false,
+        long_path_aware: true,
+        ..Default::default()
+    }
+    .to_xml();
+    assert!(!xml.contains("Common-Controls"));
+    assert!(xml.contains("level=\"requireAdministrator\""));
+    assert!(xml.contains(">false</dpiAware>"));
+    assert!(xml.contains(">true</longPathAware>"));
+    assert_eq!(xml.matches("<assembly").count(), 1);
+
+    assert_eq!(
+        manifest_rc(Path::new("C:\\out\\app.manifest")),
+        "1 24 \"C:\\\\out\\\\app.manifest\"\n"
+    );
+}
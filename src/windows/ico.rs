@@ -0,0 +1,202 @@
+//! Assembling Windows `.ico` files from PNG images.
+//!
+//! Icon files may contain PNG compressed images since Windows Vista, so the master PNGs used for
+//! the macOS app icon are stored as they are, without decoding them.
+
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+
+use crate::fileset::{glob_paths, PatternOptions};
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+/// Smallest and largest image size an icon file can hold.
+pub const ICO_SIZES: std::ops::RangeInclusive<u32> = 16..=256;
+
+/// An image to be stored in the icon file.
+struct IcoImage {
+    size: u32,
+    bits_per_pixel: u16,
+    data: Vec<u8>,
+}
+
+/// Read a PNG file and check it is square and of a size an icon can hold.
+fn read_png(path: &Path) -> Result<IcoImage, Error> {
+    let data = fs::read(path)?;
+    let invalid = |message: &str| {
+        Error::new(
+            ErrorKind::InvalidData,
+            format!("{}: {}", path.display(), message),
+        )
+    };
+    // The IHDR chunk follows the signature: length, type, width, height, bit depth, color type
+    if data.len() < 26 || !data.starts_with(PNG_SIGNATURE) || &data[12..16] != b"IHDR" {
+        return Err(invalid("not a PNG image"));
+    }
+    let width = u32::from_be_bytes([data[16], data[17], data[18], data[19]]);
+    let height = u32::from_be_bytes([data[20], data[21], data[22], data[23]]);
+    if width != height {
+        return Err(invalid(&format!("{}x{} is not square", width, height)));
+    }
+    if !ICO_SIZES.contains(&width) {
+        return Err(invalid(&format!(
+            "{}x{} is not between 16x16 and 256x256",
+            width, height
+        )));
+    }
+    let channels = match data[25] {
+        0 | 3 => 1,
+        4 => 2,
+        2 => 3,
+        _ => 4,
+    };
+    Ok(IcoImage {
+        size: width,
+        bits_per_pixel: (u16::from(data[24]) * channels).min(32),
+        data,
+    })
+}
+
+/// Write an icon file containing the given PNG images and return the sizes it holds.
+///
+/// The images must be square, between 16 and 256 pixels and of different sizes.
+pub fn write_ico<P: AsRef<Path>>(pngs: &[P], output: &Path) -> Result<Vec<u32>, Error> {
+    let mut images = pngs
+        .iter()
+        .map(|png| read_png(png.as_ref()))
+        .collect::<Result<Vec<IcoImage>, Error>>()?;
+    if images.is_empty() {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("No images for {}", output.display()),
+        ));
+    }
+    images.sort_by_key(|image| image.size);
+    if let Some(pair) = images.windows(2).find(|pair| pair[0].size == pair[1].size) {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "Several {0}x{0} images for {1}",
+                pair[0].size,
+                output.display()
+            ),
+        ));
+    }
+
+    let mut ico = Vec::new();
+    // ICONDIR: reserved, type 1 (icon), number of images
+    ico.extend_from_slice(&0u16.to_le_bytes());
+    ico.extend_from_slice(&1u16.to_le_bytes());
+    ico.extend_from_slice(&(images.len() as u16).to_le_bytes());
+    let mut offset = 6 + 16 * images.len();
+    for image in &images {
+        // ICONDIRENTRY: width and height (0 means 256), colors, reserved, planes, bit count,
+        // size and offset of the image data
+        let size = if image.size == 256 {
+            0
+        } else {
+            image.size as u8
+        };
+        ico.extend_from_slice(&[size, size, 0, 0]);
+        ico.extend_from_slice(&1u16.to_le_bytes());
+        ico.extend_from_slice(&image.bits_per_pixel.to_le_bytes());
+        ico.extend_from_slice(&(image.data.len() as u32).to_le_bytes());
+        ico.extend_from_slice(&(offset as u32).to_le_bytes());
+        offset += image.data.len();
+    }
+    for image in &images {
+        ico.extend_from_slice(&image.data);
+    }
+    if let Some(parent) = output.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(output, ico)?;
+    Ok(images.iter().map(|image| image.size).collect())
+}
+
+/// Assemble a multi-resolution icon file from PNG images, e.g. the same master images the macOS
+/// app icon is made of. Returns the sizes the icon holds.
+///
+/// # Arguments
+///
+/// * `pngs_or_glob` - a PNG file or a glob pattern selecting several (e.g. `icons/icon_*.png`)
+/// * `output` - the `.ico` file to write
+///
+/// # Example
+///
+/// ```no_run
+/// use std::path::Path;
+/// use toolbelt::windows::ico::generate_ico;
+///
+/// let sizes = generate_ico("icons/icon_*.png", Path::new("target/app.ico")).unwrap();
+/// println!("app.ico holds sizes {:?}", sizes);
+/// ```
+pub fn generate_ico(pngs_or_glob: &str, output: &Path) -> Result<Vec<u32>, Error> {
+    generate_ico_with_options(pngs_or_glob, output, &PatternOptions::default())
+}
+
+/// Like [`generate_ico`], with the matching of the pattern configured, e.g. case-insensitive.
+pub fn generate_ico_with_options(
+    pngs_or_glob: &str,
+    output: &Path,
+    options: &PatternOptions,
+) -> Result<Vec<u32>, Error> {
+    let pngs: Vec<PathBuf> = glob_paths(pngs_or_glob, options)?;
+    if pngs.is_empty() {
+        return Err(Error::new(
+            ErrorKind::NotFound,
+            format!("No images match {}", pngs_or_glob),
+        ));
+    }
+    write_ico(&pngs, output)
+}
+
+#[test]
+fn test_generate_ico() {
+    let root = Path::new("target/generate_ico");
+    let _ = fs::remove_dir_all(root);
+    fs::create_dir_all(root).unwrap();
+    // Only the header is read, so a signature and an IHDR chunk make a PNG good enough
+    let png = |name: &str, width: u32, height: u32| {
+        let mut data = PNG_SIGNATURE.to_vec();
+        data.extend_from_slice(&13u32.to_be_bytes());
+        data.extend_from_slice(b"IHDR");
+        data.extend_from_slice(&width.to_be_bytes());
+        data.extend_from_slice(&height.to_be_bytes());
+        data.extend_from_slice(&[8, 6, 0, 0, 0, 0, 0, 0, 0]);
+        fs::write(root.join(name), data).unwrap();
+    };
+    png("icon_256.png", 256, 256);
+    png("icon_16.png", 16, 16);
+    png("icon_32.png", 32, 32);
+
+    let output = root.join("app.ico");
+    let sizes = generate_ico("target/generate_ico/icon_*.png", &output).unwrap();
+    assert_eq!(sizes, [16, 32, 256]);
+    let ico = fs::read(&output).unwrap();
+    assert_eq!(&ico[..6], &[0, 0, 1, 0, 3, 0]);
+    assert_eq!(&ico[6..8], &[16, 16]);
+    assert_eq!(&ico[38..40], &[0, 0]);
+    assert_eq!(u16::from_le_bytes([ico[12], ico[13]]), 32);
+    let offset = u32::from_le_bytes([ico[18], ico[19], ico[20], ico[21]]) as usize;
+    assert_eq!(offset, 6 + 3 * 16);
+    assert!(ico[offset..].starts_with(PNG_SIGNATURE));
+
+    let options = PatternOptions {
+        case_sensitive: false,
+        ..Default::default()
+    };
+    let sizes =
+        generate_ico_with_options("target/generate_ico/ICON_*.PNG", &output, &options).unwrap();
+    assert_eq!(sizes, [16, 32, 256]);
+
+    png("icon_512.png", 512, 512);
+    let e = generate_ico("target/generate_ico/icon_*.png", &output).unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::InvalidData);
+    png("icon_512.png", 32, 32);
+    let e = generate_ico("target/generate_ico/icon_*.png", &output).unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::InvalidInput);
+
+    fs::remove_dir_all(root).unwrap();
+}
@@ -0,0 +1,376 @@
+//! Building Windows installer packages (`.msi`) with the WiX toolset.
+//!
+//! A minimal WiX source is generated from the files to install, either listed in a
+//! [`Manifest`] or selected with a glob pattern, and compiled with `wix build` (WiX 4 and later)
+//! or `candle` and `light` (WiX 3). The installer copies the files into
+//! `Program Files\<install_dir>` and replaces older versions of the same product.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+
+use crate::fileset::{FileSet, PatternOptions};
+use crate::manifest::Manifest;
+use crate::tool::Tool;
+
+/// The WiX toolset generation to generate the source for and run.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WixToolset {
+    /// `candle` and `light`
+    Wix3,
+    /// The `wix` command line tool of WiX 4 and later
+    Wix4,
+}
+
+impl WixToolset {
+    /// `Wix4` if `wix` can be run, `Wix3` otherwise.
+    pub fn detect() -> WixToolset {
+        match Tool::new("wix").arg("--version").output() {
+            Ok(output) if output.status.success() => WixToolset::Wix4,
+            _ => WixToolset::Wix3,
+        }
+    }
+}
+
+/// Options for [`build_msi`].
+#[derive(Clone, Debug)]
+pub struct MsiOptions {
+    /// Product name shown in "Apps & features", defaults to the package name
+    pub product_name: String,
+    pub manufacturer: String,
+    /// Product version, defaults to the version of the package being built. Only the numeric
+    /// `major.minor.patch` part is used, as Windows Installer requires.
+    pub version: String,
+    /// GUID identifying the product across versions, needed for upgrades; keep it stable
+    pub upgrade_code: String,
+    /// Directory below `Program Files`, defaults to the product name
+    pub install_dir: Option<String>,
+    /// Defaults to WiX 4, use [`WixToolset::detect`] to fall back to WiX 3
+    pub toolset: WixToolset,
+    /// Matching of the pattern passed to [`build_msi`] and the walk, e.g. to leave out hidden
+    /// files
+    pub patterns: PatternOptions,
+}
+
+impl Default for MsiOptions {
+    fn default() -> Self {
+        MsiOptions {
+            product_name: std::env::var("CARGO_PKG_NAME").unwrap_or_default(),
+            manufacturer: String::new(),
+            version: std::env::var("CARGO_PKG_VERSION").unwrap_or_else(|_| "1.0.0".into()),
+            upgrade_code: String::new(),
+            install_dir: None,
+            toolset: WixToolset::Wix4,
+            patterns: PatternOptions::default(),
+        }
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// A directory of the installation with its subdirectories and files.
+#[derive(Default)]
+struct DirNode {
+    dirs: BTreeMap<String, DirNode>,
+    files: Vec<PathBuf>,
+}
+
+impl DirNode {
+    fn write(&self, xml: &mut String, depth: usize, ids: &mut Ids, toolset: WixToolset) {
+        let indent = "  ".repeat(depth);
+        for file in &self.files {
+            let component = ids.component();
+            let guid = match toolset {
+                WixToolset::Wix3 => " Guid=\"*\"",
+                WixToolset::Wix4 => "",
+            };
+            let _ = writeln!(
+                xml,
+                "{0}<Component Id=\"{1}\"{2}>\n{0}  <File Id=\"file{3}\" Source=\"{4}\" \
+                 KeyPath=\"yes\"/>\n{0}</Component>",
+                indent,
+                component,
+                guid,
+                ids.components.len(),
+                escape(&file.to_string_lossy())
+            );
+        }
+        for (name, dir) in &self.dirs {
+            ids.dirs += 1;
+            let _ = writeln!(
+                xml,
+                "{}<Directory Id=\"dir{}\" Name=\"{}\">",
+                indent,
+                ids.dirs,
+                escape(name)
+            );
+            dir.write(xml, depth + 1, ids, toolset);
+            let _ = writeln!(xml, "{}</Directory>", indent);
+        }
+    }
+}
+
+#[derive(Default)]
+struct Ids {
+    dirs: usize,
+    components: Vec<String>,
+}
+
+impl Ids {
+    fn component(&mut self) -> String {
+        let id = format!("component{}", self.components.len() + 1);
+        self.components.push(id.clone());
+        id
+    }
+}
+
+/// The `major.minor.patch` part of a version, e.g. `1.2.3` of `1.2.3-beta.1`.
+fn msi_version(version: &str) -> &str {
+    version.split(['-', '+']).next().unwrap_or(version)
+}
+
+/// Generate the WiX source installing `files`, given relative to `root`.
+pub fn generate_wxs<S: AsRef<str>>(root: &Path, files: &[S], options: &MsiOptions) -> String {
+    let mut tree = DirNode::default();
+    for file in files {
+        let file = file.as_ref();
+        let mut node = &mut tree;
+        let mut components: Vec<&str> = file.split(['/', '\\']).collect();
+        components.pop();
+        for component in components {
+            node = node.dirs.entry(component.to_string()).or_default();
+        }
+        node.files.push(root.join(file));
+    }
+
+    let install_dir = options
+        .install_dir
+        .as_deref()
+        .unwrap_or(&options.product_name);
+    let attributes = format!(
+        "Name=\"{}\" Manufacturer=\"{}\" Version=\"{}\" UpgradeCode=\"{}\"",
+        escape(&options.product_name),
+        escape(&options.manufacturer),
+        escape(msi_version(&options.version)),
+        escape(&options.upgrade_code)
+    );
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    let (root_open, root_close) = match options.toolset {
+        WixToolset::Wix3 => {
+            xml.push_str("<Wix xmlns=\"http://schemas.microsoft.com/wix/2006/wi\">\n");
+            let _ = writeln!(
+                xml,
+                "  <Product Id=\"*\" Language=\"1033\" {}>\n    <Package InstallerVersion=\"500\" \
+                 Compressed=\"yes\" InstallScope=\"perMachine\"/>",
+                attributes
+            );
+            (
+                "    <Directory Id=\"TARGETDIR\" Name=\"SourceDir\">\n      \
+                 <Directory Id=\"ProgramFiles64Folder\">\n",
+                "      </Directory>\n    </Directory>\n",
+            )
+        }
+        WixToolset::Wix4 => {
+            xml.push_str("<Wix xmlns=\"http://wixtoolset.org/schemas/v4/wxs\">\n");
+            let _ = writeln!(xml, "  <Package {} Scope=\"perMachine\">", attributes);
+            (
+                "    <StandardDirectory Id=\"ProgramFiles64Folder\">\n",
+                "    </StandardDirectory>\n",
+            )
+        }
+    };
+    xml.push_str(
+        "    <MajorUpgrade DowngradeErrorMessage=\"A newer version of [ProductName] is already \
+         installed.\"/>\n    <MediaTemplate EmbedCab=\"yes\"/>\n",
+    );
+    xml.push_str(root_open);
+    let depth = match options.toolset {
+        WixToolset::Wix3 => 4,
+        WixToolset::Wix4 => 3,
+    };
+    let indent = "  ".repeat(depth);
+    let _ = writeln!(
+        xml,
+        "{}<Directory Id=\"INSTALLFOLDER\" Name=\"{}\">",
+        indent,
+        escape(install_dir)
+    );
+    let mut ids = Ids::default();
+    tree.write(&mut xml, depth + 1, &mut ids, options.toolset);
+    let _ = writeln!(xml, "{}</Directory>", indent);
+    xml.push_str(root_close);
+    xml.push_str("    <Feature Id=\"Main\" Level=\"1\">\n");
+    for component in &ids.components {
+        let _ = writeln!(xml, "      <ComponentRef Id=\"{}\"/>", component);
+    }
+    xml.push_str("    </Feature>\n");
+    match options.toolset {
+        WixToolset::Wix3 => xml.push_str("  </Product>\n</Wix>\n"),
+        WixToolset::Wix4 => xml.push_str("  </Package>\n</Wix>\n"),
+    }
+    xml
+}
+
+fn build(root: &Path, files: &[String], output: &Path, options: &MsiOptions) -> Result<(), Error> {
+    if options.upgrade_code.is_empty() {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "MsiOptions::upgrade_code is required",
+        ));
+    }
+    if files.is_empty() {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("No files to install from {}", root.display()),
+        ));
+    }
+    let root = crate::copy::absolute_path(root)?;
+    let wxs = output.with_extension("wxs");
+    if let Some(parent) = output.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(&wxs, generate_wxs(&root, files, options))?;
+    debug!("Building {} with {} files", output.display(), files.len());
+    match options.toolset {
+        WixToolset::Wix4 => {
+            Tool::new("wix")
+                .arg("build")
+                .arg(&wxs)
+                .args(["-arch", "x64", "-o"])
+                .arg(output)
+                .run()
+                .map_err(Error::other)?;
+        }
+        WixToolset::Wix3 => {
+            let wixobj = output.with_extension("wixobj");
+            Tool::new("candle")
+                .args(["-nologo", "-arch", "x64", "-out"])
+                .arg(&wixobj)
+                .arg(&wxs)
+                .run()
+                .map_err(Error::other)?;
+            Tool::new("light")
+                .args(["-nologo", "-out"])
+                .arg(output)
+                .arg(&wixobj)
+                .run()
+                .map_err(Error::other)?;
+        }
+    }
+    Ok(())
+}
+
+/// Build an installer for the files below `root` matching a glob pattern.
+///
+/// The WiX source is written next to the installer (`<name>.wxs`).
+///
+/// # Arguments
+///
+/// * `root` - the staged installation, e.g. `target/release/dist`
+/// * `pattern` - a glob pattern selecting the files to install (e.g. `**/*`)
+/// * `output` - the `.msi` to write
+/// * `options` - product name, manufacturer, version, upgrade code and toolset
+///
+/// # Example
+///
+/// ```no_run
+/// use std::path::Path;
+/// use toolbelt::windows::msi::{build_msi, MsiOptions};
+///
+/// build_msi(
+///     Path::new("target/release/dist"),
+///     "**/*",
+///     Path::new("target/MyApp.msi"),
+///     &MsiOptions {
+///         product_name: "My App".to_string(),
+///         manufacturer: "Example Inc.".to_string(),
+///         upgrade_code: "6F1B5B0C-8E7A-4F6E-9C43-2B8D2A0E5D11".to_string(),
+///         ..Default::default()
+///     },
+/// )
+/// .unwrap();
+/// ```
+pub fn build_msi(
+    root: &Path,
+    pattern: &str,
+    output: &Path,
+    options: &MsiOptions,
+) -> Result<(), Error> {
+    let mut files = Vec::new();
+    for entry in FileSet::new(root)
+        .pattern(pattern)
+        .options(options.patterns.clone())
+    {
+        let entry = entry?;
+        if entry.file_type.is_file() {
+            files.push(entry.relative_path.to_string_lossy().into_owned());
+        }
+    }
+    files.sort();
+    build(root, &files, output, options)
+}
+
+/// Build an installer for the files listed in a manifest, e.g. the one written by
+/// [`crate::copy_dir_with_options`] when staging the installation.
+pub fn build_msi_from_manifest(
+    root: &Path,
+    manifest: &Manifest,
+    output: &Path,
+    options: &MsiOptions,
+) -> Result<(), Error> {
+    let files: Vec<String> = manifest.entries.iter().map(|e| e.path.clone()).collect();
+    build(root, &files, output, options)
+}
+
+#[test]
+fn test_generate_wxs() {
+    let mut options = MsiOptions {
+        product_name: "My App".to_string(),
+        manufacturer: "Smith & Sons".to_string(),
+        version: "1.2.3-beta.1".to_string(),
+        upgrade_code: "6F1B5B0C-8E7A-4F6E-9C43-2B8D2A0E5D11".to_string(),
+        install_dir: None,
+        toolset: WixToolset::Wix4,
+        patterns: PatternOptions::default(),
+    };
+    let files = ["myapp.exe", "data/en/strings.txt", "data/logo.png"];
+    let wxs = generate_wxs(Path::new("/dist"), &files, &options);
+    assert!(wxs.contains("<Wix xmlns=\"http://wixtoolset.org/schemas/v4/wxs\">"));
+    assert!(wxs.contains(
+        "<Package Name=\"My App\" Manufacturer=\"Smith &amp; Sons\" Version=\"1.2.3\" \
+         UpgradeCode=\"6F1B5B0C-8E7A-4F6E-9C43-2B8D2A0E5D11\" Scope=\"perMachine\">"
+    ));
+    assert!(wxs.contains("<Directory Id=\"INSTALLFOLDER\" Name=\"My App\">"));
+    assert!(wxs.contains("<Directory Id=\"dir1\" Name=\"data\">"));
+    assert!(wxs.contains("<Directory Id=\"dir2\" Name=\"en\">"));
+    assert!(wxs.contains("Source=\"/dist/data/en/strings.txt\""));
+    assert_eq!(wxs.matches("<ComponentRef ").count(), 3);
+    assert!(!wxs.contains("Guid="));
+
+    options.toolset = WixToolset::Wix3;
+    options.install_dir = Some("MyApp".to_string());
+    let wxs = generate_wxs(Path::new("/dist"), &files, &options);
+    assert!(wxs.contains("<Product Id=\"*\""));
+    assert!(wxs.contains("<Directory Id=\"ProgramFiles64Folder\">"));
+    assert!(wxs.contains("<Directory Id=\"INSTALLFOLDER\" Name=\"MyApp\">"));
+    assert_eq!(wxs.matches("Guid=\"*\"").count(), 3);
+    assert_eq!(
+        wxs.matches("<Directory ").count(),
+        wxs.matches("</Directory>").count()
+    );
+
+    let e = build_msi_from_manifest(
+        Path::new("/dist"),
+        &Manifest::default(),
+        Path::new("target/msi/MyApp.msi"),
+        &options,
+    )
+    .unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::InvalidInput);
+}
@@ -0,0 +1,232 @@
+//! Compiling Windows resource scripts (`.rc`) and linking them into the executable.
+//!
+//! Resource scripts carry the icon, version information and application manifest of a Windows
+//! program. They are compiled with `rc.exe` for the MSVC toolchain or `windres` for the GNU
+//! toolchain, and the result is handed to the linker with `cargo:rustc-link-arg`.
+
+use std::env;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+
+use super::sdk::detect_windows_sdk;
+use crate::build_script::BuildScriptOutput;
+use crate::fileset::{glob_paths, PatternOptions};
+use crate::tool::{CompileError, Tool};
+
+/// The resource compiler matching the target's toolchain.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ResourceCompiler {
+    /// `rc.exe` from the Windows SDK, producing `.res` files for link.exe
+    Rc,
+    /// `windres` from binutils, producing COFF objects for the GNU linker
+    Windres,
+}
+
+impl ResourceCompiler {
+    /// `Rc` when building for an MSVC target (`CARGO_CFG_TARGET_ENV=msvc`), `Windres` otherwise.
+    pub fn for_target() -> ResourceCompiler {
+        match env::var("CARGO_CFG_TARGET_ENV").as_deref() {
+            Ok("msvc") => ResourceCompiler::Rc,
+            Ok(_) => ResourceCompiler::Windres,
+            Err(_) if cfg!(target_env = "msvc") => ResourceCompiler::Rc,
+            Err(_) => ResourceCompiler::Windres,
+        }
+    }
+
+    /// The program to run, overridable with the `RC` or `WINDRES` environment variable, e.g. for
+    /// `x86_64-w64-mingw32-windres` when cross compiling. rc.exe is taken from the Windows SDK
+    /// if it isn't overridden.
+    pub fn program(self) -> String {
+        let (variable, default) = match self {
+            ResourceCompiler::Rc => ("RC", "rc"),
+            ResourceCompiler::Windres => ("WINDRES", "windres"),
+        };
+        if let Ok(program) = env::var(variable) {
+            return program;
+        }
+        if self == ResourceCompiler::Rc {
+            if let Ok(sdk) = detect_windows_sdk() {
+                if sdk.rc.is_file() {
+                    return sdk.rc.to_string_lossy().into_owned();
+                }
+            }
+        }
+        default.to_string()
+    }
+
+    /// Extension of the compiled resource file.
+    pub fn extension(self) -> &'static str {
+        match self {
+            ResourceCompiler::Rc => "res",
+            ResourceCompiler::Windres => "o",
+        }
+    }
+
+    /// The command compiling `rc_file` to `output`. Files included by the script are looked up
+    /// next to it.
+    fn command(self, program: &str, rc_file: &Path, output: &Path) -> Tool {
+        let mut tool = Tool::new(program);
+        let include_dir = rc_file.parent().unwrap_or_else(|| Path::new("."));
+        match self {
+            ResourceCompiler::Rc => {
+                tool.arg("/nologo")
+                    .arg("/I")
+                    .arg(include_dir)
+                    .arg("/fo")
+                    .arg(output)
+                    .arg(rc_file);
+            }
+            ResourceCompiler::Windres => {
+                tool.arg("--include-dir")
+                    .arg(include_dir)
+                    .args(["--input-format=rc", "--output-format=coff", "-i"])
+                    .arg(rc_file)
+                    .arg("-o")
+                    .arg(output);
+            }
+        }
+        tool
+    }
+}
+
+/// Options for [`compile_windows_resources_with_options`].
+#[derive(Clone, Debug, Default)]
+pub struct ResourceOptions {
+    /// Matching of the resource script pattern
+    pub patterns: PatternOptions,
+    /// Report the resource scripts as inputs of a build script
+    pub build_script: Option<BuildScriptOutput>,
+}
+
+/// Compile one resource script with the given compiler and return the compiled file in `out_dir`.
+pub fn compile_resource(
+    rc_file: &Path,
+    out_dir: &Path,
+    compiler: ResourceCompiler,
+) -> Result<PathBuf, CompileError> {
+    let stem = rc_file.file_stem().ok_or_else(|| {
+        Error::new(
+            ErrorKind::InvalidInput,
+            format!("{} is not a file", rc_file.display()),
+        )
+    })?;
+    std::fs::create_dir_all(out_dir)?;
+    let output = out_dir.join(stem).with_extension(compiler.extension());
+    compiler
+        .command(&compiler.program(), rc_file, &output)
+        .run()
+        .map_err(|source| CompileError::Tool {
+            file: rc_file.to_path_buf(),
+            source,
+        })?;
+    Ok(output)
+}
+
+/// Compile resource scripts and link them into the crate's executables.
+///
+/// Prints `cargo:rerun-if-changed` for every script and `cargo:rustc-link-arg` for every compiled
+/// file, so this is meant to be called from `build.rs`. The compiler is chosen by
+/// [`ResourceCompiler::for_target`]. Use [`compile_windows_resources_with_options`] to report the
+/// scripts to a shared [`BuildScriptOutput`] instead.
+///
+/// # Arguments
+///
+/// * `rc_file_or_glob` - a resource script or a glob pattern selecting several (e.g. `res/*.rc`)
+/// * `out_dir` - directory for the compiled resources, usually `OUT_DIR`
+///
+/// # Example
+///
+/// ```no_run
+/// use std::path::Path;
+/// use toolbelt::windows::resources::compile_windows_resources;
+///
+/// if std::env::var("CARGO_CFG_TARGET_OS").as_deref() == Ok("windows") {
+///     let out_dir = std::env::var("OUT_DIR").unwrap();
+///     compile_windows_resources("res/app.rc", Path::new(&out_dir)).unwrap();
+/// }
+/// ```
+pub fn compile_windows_resources(
+    rc_file_or_glob: &str,
+    out_dir: &Path,
+) -> Result<Vec<PathBuf>, CompileError> {
+    let options = ResourceOptions {
+        build_script: Some(BuildScriptOutput::new()),
+        ..Default::default()
+    };
+    compile_windows_resources_with_options(rc_file_or_glob, out_dir, &options)
+}
+
+/// Like [`compile_windows_resources`], with additional options. The scripts are only reported
+/// to [`ResourceOptions::build_script`].
+pub fn compile_windows_resources_with_options(
+    rc_file_or_glob: &str,
+    out_dir: &Path,
+    options: &ResourceOptions,
+) -> Result<Vec<PathBuf>, CompileError> {
+    let rc_files = glob_paths(rc_file_or_glob, &options.patterns)?;
+    if rc_files.is_empty() {
+        return Err(CompileError::Io(Error::new(
+            ErrorKind::NotFound,
+            format!("No resource scripts match {}", rc_file_or_glob),
+        )));
+    }
+
+    let compiler = ResourceCompiler::for_target();
+    let mut compiled = Vec::new();
+    for rc_file in rc_files {
+        if let Some(build_script) = &options.build_script {
+            build_script.rerun_if_changed(&rc_file);
+        }
+        let output = compile_resource(&rc_file, out_dir, compiler)?;
+        debug!("Compiled {} to {}", rc_file.display(), output.display());
+        println!("cargo:rustc-link-arg={}", output.display());
+        compiled.push(output);
+    }
+    Ok(compiled)
+}
+
+#[test]
+fn test_resource_compiler_command() {
+    let rc_file = Path::new("res/app.rc");
+    let output = Path::new("out/app.res");
+    assert_eq!(
+        ResourceCompiler::Rc
+            .command("rc", rc_file, output)
+            .command_line(),
+        "rc /nologo /I res /fo out/app.res res/app.rc"
+    );
+    assert_eq!(
+        ResourceCompiler::Windres
+            .command("windres", rc_file, Path::new("out/app.o"))
+            .command_line(),
+        "windres --include-dir res --input-format=rc --output-format=coff -i res/app.rc -o out/app.o"
+    );
+
+    match compile_windows_resources("target/no_such_dir/*.rc", Path::new("target/resources")) {
+        Err(CompileError::Io(e)) => assert_eq!(e.kind(), ErrorKind::NotFound),
+        _ => panic!("expected NotFound"),
+    }
+
+    // scripts are reported even if compiling them fails
+    let root = Path::new("target/windows_resources");
+    let _ = std::fs::remove_dir_all(root);
+    std::fs::create_dir_all(root).unwrap();
+    std::fs::write(root.join("app.rc"), "no resource script {").unwrap();
+    let output = BuildScriptOutput::capture();
+    let options = ResourceOptions {
+        build_script: Some(output.clone()),
+        ..Default::default()
+    };
+    let pattern = root.join("*.rc");
+    assert!(
+        compile_windows_resources_with_options(&pattern.to_string_lossy(), root, &options).is_err()
+    );
+    assert_eq!(
+        output.lines(),
+        [format!(
+            "cargo:rerun-if-changed={}",
+            root.join("app.rc").display()
+        )]
+    );
+    std::fs::remove_dir_all(root).unwrap();
+}
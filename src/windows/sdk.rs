@@ -0,0 +1,246 @@
+//! Locating the Windows SDK and Visual Studio.
+//!
+//! The SDK provides signtool, rc.exe and mt.exe as well as the headers and import libraries for
+//! the Windows API. It is found through the environment of a Visual Studio developer prompt
+//! (`WindowsSdkDir`, `WindowsSDKVersion`), the registry or its default location, and Visual
+//! Studio through vswhere.
+
+use std::env;
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+
+use crate::tool::Tool;
+use crate::xcrun::version_at_least;
+use crate::IncludeDirFormat;
+
+const KITS_ROOT_KEYS: [&str; 2] = [
+    r"HKLM\SOFTWARE\Microsoft\Windows Kits\Installed Roots",
+    r"HKLM\SOFTWARE\WOW6432Node\Microsoft\Windows Kits\Installed Roots",
+];
+
+const DEFAULT_KITS_ROOT: &str = r"C:\Program Files (x86)\Windows Kits\10";
+
+/// Header directories of the SDK below `Include\<version>`.
+const INCLUDE_SUBDIRS: [&str; 5] = ["ucrt", "um", "shared", "winrt", "cppwinrt"];
+
+/// An installed Windows SDK and the tools of the matching host architecture.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WindowsSdk {
+    /// The kits root, e.g. `C:\Program Files (x86)\Windows Kits\10`
+    pub root: PathBuf,
+    /// The SDK version, e.g. `10.0.22621.0`
+    pub version: String,
+    /// Directory of the SDK tools, e.g. `bin\10.0.22621.0\x64`
+    pub bin_dir: PathBuf,
+    pub signtool: PathBuf,
+    pub rc: PathBuf,
+    pub mt: PathBuf,
+    /// Installation directory of the latest Visual Studio with the C++ tools, if any
+    pub visual_studio: Option<PathBuf>,
+}
+
+/// The SDK's name for an architecture, `x64` for `x86_64`.
+fn sdk_arch(arch: &str) -> &str {
+    match arch {
+        "x86_64" => "x64",
+        "x86" | "i586" | "i686" => "x86",
+        "aarch64" => "arm64",
+        "arm" => "arm",
+        other => other,
+    }
+}
+
+impl WindowsSdk {
+    /// The latest SDK version installed in a kits root.
+    pub fn at(root: &Path) -> Result<WindowsSdk, Error> {
+        let mut versions: Vec<String> = fs::read_dir(root.join("Include"))?
+            .filter_map(Result::ok)
+            .filter(|entry| entry.path().join("um").join("windows.h").is_file())
+            .map(|entry| entry.file_name().to_string_lossy().into_owned())
+            .collect();
+        versions.sort_by(|a, b| {
+            if version_at_least(a, b) && version_at_least(b, a) {
+                std::cmp::Ordering::Equal
+            } else if version_at_least(a, b) {
+                std::cmp::Ordering::Greater
+            } else {
+                std::cmp::Ordering::Less
+            }
+        });
+        let version = versions.pop().ok_or_else(|| {
+            Error::new(
+                ErrorKind::NotFound,
+                format!("No Windows SDK is installed in {}", root.display()),
+            )
+        })?;
+        WindowsSdk::with_version(root, &version)
+    }
+
+    /// A specific SDK version installed in a kits root.
+    pub fn with_version(root: &Path, version: &str) -> Result<WindowsSdk, Error> {
+        let version = version.trim_end_matches(['\\', '/']);
+        if !root.join("Include").join(version).is_dir() {
+            return Err(Error::new(
+                ErrorKind::NotFound,
+                format!(
+                    "Windows SDK {} is not installed in {}",
+                    version,
+                    root.display()
+                ),
+            ));
+        }
+        let bin_dir = root
+            .join("bin")
+            .join(version)
+            .join(sdk_arch(env::consts::ARCH));
+        Ok(WindowsSdk {
+            root: root.to_path_buf(),
+            version: version.to_string(),
+            signtool: bin_dir.join("signtool.exe"),
+            rc: bin_dir.join("rc.exe"),
+            mt: bin_dir.join("mt.exe"),
+            bin_dir,
+            visual_studio: None,
+        })
+    }
+
+    /// The SDK's header directories, formatted like [`crate::get_sdk_include_dirs`].
+    pub fn include_dirs(&self, format: IncludeDirFormat) -> Vec<String> {
+        let include = self.root.join("Include").join(&self.version);
+        INCLUDE_SUBDIRS
+            .iter()
+            .map(|subdir| include.join(subdir))
+            .filter(|dir| dir.is_dir())
+            .map(|dir| format.format(&dir))
+            .collect()
+    }
+
+    /// The import library directories for a target architecture, e.g. `x86_64` or the value of
+    /// `CARGO_CFG_TARGET_ARCH`.
+    pub fn lib_dirs(&self, target_arch: &str) -> Vec<PathBuf> {
+        let lib = self.root.join("Lib").join(&self.version);
+        ["ucrt", "um"]
+            .iter()
+            .map(|subdir| lib.join(subdir).join(sdk_arch(target_arch)))
+            .filter(|dir| dir.is_dir())
+            .collect()
+    }
+}
+
+/// The kits root from the output of `reg query <key> /v KitsRoot10`.
+fn parse_kits_root(stdout: &str) -> Option<PathBuf> {
+    stdout.lines().find_map(|line| {
+        let (_, value) = line.trim().split_once("REG_SZ")?;
+        Some(PathBuf::from(value.trim()))
+    })
+}
+
+fn kits_root() -> Option<PathBuf> {
+    if let Some(dir) = env::var_os("WindowsSdkDir") {
+        return Some(PathBuf::from(dir));
+    }
+    for key in KITS_ROOT_KEYS {
+        if let Ok(output) = Tool::new("reg")
+            .args(["query", key, "/v", "KitsRoot10"])
+            .run()
+        {
+            if let Some(root) = parse_kits_root(&output.stdout) {
+                return Some(root);
+            }
+        }
+    }
+    let default = PathBuf::from(DEFAULT_KITS_ROOT);
+    default.is_dir().then_some(default)
+}
+
+/// Installation directory of the latest Visual Studio with the C++ tools, from vswhere.
+fn visual_studio() -> Option<PathBuf> {
+    let program_files = env::var_os("ProgramFiles(x86)")?;
+    let vswhere = Path::new(&program_files).join(r"Microsoft Visual Studio\Installer\vswhere.exe");
+    let output = Tool::new(vswhere)
+        .args([
+            "-latest",
+            "-products",
+            "*",
+            "-requires",
+            "Microsoft.VisualStudio.Component.VC.Tools.x86.x64",
+            "-property",
+            "installationPath",
+        ])
+        .run()
+        .ok()?;
+    let path = output.stdout.lines().next()?.trim();
+    (!path.is_empty()).then(|| PathBuf::from(path))
+}
+
+/// Detect the Windows SDK and Visual Studio.
+///
+/// The SDK version of a developer prompt (`WindowsSDKVersion`) is used if set, otherwise the
+/// latest installed one.
+///
+/// # Example
+///
+/// ```no_run
+/// use toolbelt::{detect_windows_sdk, IncludeDirFormat};
+///
+/// let sdk = detect_windows_sdk().unwrap();
+/// let include_dirs = sdk.include_dirs(IncludeDirFormat::CLANG);
+/// for dir in sdk.lib_dirs(&std::env::var("CARGO_CFG_TARGET_ARCH").unwrap()) {
+///     println!("cargo:rustc-link-search=native={}", dir.display());
+/// }
+/// ```
+pub fn detect_windows_sdk() -> Result<WindowsSdk, Error> {
+    let root = kits_root().ok_or_else(|| {
+        Error::new(
+            ErrorKind::NotFound,
+            "No Windows SDK found, install it with the Visual Studio Installer",
+        )
+    })?;
+    let mut sdk = match env::var("WindowsSDKVersion") {
+        Ok(version) => WindowsSdk::with_version(&root, &version)?,
+        Err(_) => WindowsSdk::at(&root)?,
+    };
+    sdk.visual_studio = visual_studio();
+    debug!("Windows SDK {} in {}", sdk.version, sdk.root.display());
+    Ok(sdk)
+}
+
+#[test]
+fn test_windows_sdk() {
+    assert_eq!(
+        parse_kits_root(
+            "\r\nHKEY_LOCAL_MACHINE\\SOFTWARE\\Microsoft\\Windows Kits\\Installed Roots\r\n    \
+             KitsRoot10    REG_SZ    C:\\Program Files (x86)\\Windows Kits\\10\\\r\n\r\n"
+        ),
+        Some(PathBuf::from("C:\\Program Files (x86)\\Windows Kits\\10\\"))
+    );
+    assert_eq!(parse_kits_root(""), None);
+
+    let root = Path::new("target/windows_sdk");
+    let _ = fs::remove_dir_all(root);
+    for version in ["10.0.19041.0", "10.0.22621.0", "10.0.9999.0"] {
+        let include = root.join("Include").join(version);
+        for subdir in ["ucrt", "um", "shared"] {
+            fs::create_dir_all(include.join(subdir)).unwrap();
+        }
+        fs::write(include.join("um/windows.h"), "").unwrap();
+    }
+    fs::create_dir_all(root.join("Include/10.0.26100.0/um")).unwrap();
+    fs::create_dir_all(root.join("Lib/10.0.22621.0/um/x64")).unwrap();
+
+    let sdk = WindowsSdk::at(root).unwrap();
+    assert_eq!(sdk.version, "10.0.22621.0");
+    assert!(sdk.bin_dir.starts_with(root.join("bin/10.0.22621.0")));
+    assert_eq!(sdk.signtool, sdk.bin_dir.join("signtool.exe"));
+    let include_dirs = sdk.include_dirs(IncludeDirFormat::CLANG);
+    assert_eq!(include_dirs.len(), 3);
+    assert!(include_dirs[0].starts_with("-Itarget/windows_sdk/Include/10.0.22621.0"));
+    assert_eq!(
+        sdk.lib_dirs("x86_64"),
+        [root.join("Lib/10.0.22621.0/um/x64")]
+    );
+    assert!(WindowsSdk::with_version(root, "10.0.1.0").is_err());
+
+    fs::remove_dir_all(root).unwrap();
+}
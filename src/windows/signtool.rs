@@ -0,0 +1,257 @@
+//! Signing executables, libraries and installers with signtool from the Windows SDK.
+//!
+//! Like [`crate::codesign`] on macOS: files are signed with a certificate from a `.pfx` file or
+//! the certificate store, hashed with SHA-256 and timestamped by an RFC 3161 server so the
+//! signature stays valid after the certificate expires.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use super::sdk::detect_windows_sdk;
+use crate::tool::{Tool, ToolError, ToolOutput};
+
+/// Timestamp server used unless [`SigntoolOptions::timestamp_url`] is changed.
+pub const DEFAULT_TIMESTAMP_URL: &str = "http://timestamp.digicert.com";
+
+/// The certificate to sign with.
+#[derive(Clone, Debug)]
+pub enum Certificate {
+    /// A `.pfx` file (`/f`) and its password (`/p`)
+    File { path: PathBuf, password: String },
+    /// A certificate in the store, selected by the SHA-1 thumbprint (`/sha1`)
+    Thumbprint(String),
+}
+
+/// Options for [`sign`].
+#[derive(Clone, Debug)]
+pub struct SigntoolOptions {
+    pub certificate: Certificate,
+    /// File digest algorithm (`/fd`), also used for the timestamp (`/td`)
+    pub digest: String,
+    /// RFC 3161 timestamp server (`/tr`), `None` to sign without a timestamp
+    pub timestamp_url: Option<String>,
+    /// Description shown in the UAC prompt (`/d`)
+    pub description: Option<String>,
+    /// URL with more information about the program (`/du`)
+    pub description_url: Option<String>,
+}
+
+impl SigntoolOptions {
+    /// SHA-256 signatures timestamped by [`DEFAULT_TIMESTAMP_URL`].
+    pub fn new(certificate: Certificate) -> SigntoolOptions {
+        SigntoolOptions {
+            certificate,
+            digest: "SHA256".to_string(),
+            timestamp_url: Some(DEFAULT_TIMESTAMP_URL.to_string()),
+            description: None,
+            description_url: None,
+        }
+    }
+
+    fn apply(&self, tool: &mut Tool) {
+        tool.args(["sign", "/fd"]).arg(&self.digest);
+        match &self.certificate {
+            Certificate::File { path, password } => {
+                tool.arg("/f").arg(path).arg("/p").secret_arg(password);
+            }
+            Certificate::Thumbprint(thumbprint) => {
+                tool.arg("/sha1").arg(thumbprint);
+            }
+        }
+        if let Some(url) = &self.timestamp_url {
+            tool.arg("/tr").arg(url).arg("/td").arg(&self.digest);
+        }
+        if let Some(description) = &self.description {
+            tool.arg("/d").arg(description);
+        }
+        if let Some(url) = &self.description_url {
+            tool.arg("/du").arg(url);
+        }
+    }
+}
+
+/// Why signtool failed, judging by its error messages.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SigntoolFailure {
+    /// No certificate in the store matched the thumbprint
+    CertificateNotFound,
+    /// The `.pfx` password is wrong
+    InvalidPassword,
+    /// The timestamp server couldn't be reached or didn't answer
+    Timestamp,
+    /// The file isn't a format signtool can sign, or is missing
+    UnsupportedFile,
+    /// Verification failed: no signature, or an untrusted or broken one
+    Verification,
+    Other,
+}
+
+impl SigntoolFailure {
+    fn classify(errors: &[String]) -> SigntoolFailure {
+        let text = errors.join("\n");
+        if text.contains("No certificates were found") {
+            SigntoolFailure::CertificateNotFound
+        } else if text.contains("password is not correct") {
+            SigntoolFailure::InvalidPassword
+        } else if text.contains("timestamp server") {
+            SigntoolFailure::Timestamp
+        } else if text.contains("format of the specified file") || text.contains("File not found") {
+            SigntoolFailure::UnsupportedFile
+        } else if text.contains("verify") || text.contains("No signature found") {
+            SigntoolFailure::Verification
+        } else {
+            SigntoolFailure::Other
+        }
+    }
+}
+
+/// Errors reported by [`sign`] and [`verify`].
+#[derive(Debug)]
+pub enum SigntoolError {
+    /// signtool couldn't be started, e.g. because the Windows SDK isn't installed
+    Tool(ToolError),
+    /// signtool ran and failed
+    Failed {
+        failure: SigntoolFailure,
+        /// signtool's `SignTool Error:` messages
+        errors: Vec<String>,
+    },
+}
+
+impl fmt::Display for SigntoolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SigntoolError::Tool(e) => write!(f, "{}", e),
+            SigntoolError::Failed { errors, .. } => {
+                f.write_str("signtool failed")?;
+                for error in errors {
+                    write!(f, "\n{}", error)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for SigntoolError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SigntoolError::Tool(e) => Some(e),
+            SigntoolError::Failed { .. } => None,
+        }
+    }
+}
+
+impl From<ToolError> for SigntoolError {
+    fn from(e: ToolError) -> Self {
+        match e {
+            ToolError::Failed { stdout, stderr, .. } => {
+                let mut errors: Vec<String> = stderr
+                    .lines()
+                    .chain(stdout.lines())
+                    .map(str::trim)
+                    .filter(|line| line.starts_with("SignTool Error:"))
+                    .map(String::from)
+                    .collect();
+                if errors.is_empty() {
+                    errors.extend(
+                        stderr
+                            .lines()
+                            .map(str::trim)
+                            .filter(|line| !line.is_empty())
+                            .map(String::from),
+                    );
+                }
+                SigntoolError::Failed {
+                    failure: SigntoolFailure::classify(&errors),
+                    errors,
+                }
+            }
+            e => SigntoolError::Tool(e),
+        }
+    }
+}
+
+/// signtool from the Windows SDK, or from `PATH` if no SDK is found.
+fn signtool() -> Tool {
+    match detect_windows_sdk() {
+        Ok(sdk) if sdk.signtool.is_file() => Tool::new(sdk.signtool),
+        _ => Tool::new("signtool"),
+    }
+}
+
+/// Sign executables, libraries or installers (`.exe`, `.dll`, `.msi`, …) in one signtool run.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::path::Path;
+/// use toolbelt::windows::signtool::{sign, Certificate, SigntoolOptions};
+///
+/// let mut options = SigntoolOptions::new(Certificate::File {
+///     path: "certs/codesign.pfx".into(),
+///     password: std::env::var("PFX_PASSWORD").unwrap(),
+/// });
+/// options.description = Some("My App".to_string());
+/// sign(&[Path::new("target/release/myapp.exe")], &options).unwrap();
+/// ```
+pub fn sign<P: AsRef<Path>>(
+    files: &[P],
+    options: &SigntoolOptions,
+) -> Result<ToolOutput, SigntoolError> {
+    let mut tool = signtool();
+    options.apply(&mut tool);
+    for file in files {
+        tool.arg(file.as_ref());
+    }
+    Ok(tool.run()?)
+}
+
+/// Verify the Authenticode signature of a file against the default policy (`signtool verify
+/// /pa`). An invalid signature fails with [`SigntoolFailure::Verification`].
+pub fn verify(file: &Path) -> Result<ToolOutput, SigntoolError> {
+    Ok(signtool().args(["verify", "/pa", "/v"]).arg(file).run()?)
+}
+
+#[test]
+fn test_signtool_options() {
+    let mut tool = Tool::new("signtool");
+    let mut options = SigntoolOptions::new(Certificate::File {
+        path: "codesign.pfx".into(),
+        password: "hunter2".to_string(),
+    });
+    options.description = Some("My App".to_string());
+    options.apply(&mut tool);
+    assert_eq!(
+        tool.command_line(),
+        "signtool sign /fd SHA256 /f codesign.pfx /p *** /tr http://timestamp.digicert.com \
+         /td SHA256 /d \"My App\""
+    );
+
+    let mut tool = Tool::new("signtool");
+    let options = SigntoolOptions {
+        timestamp_url: None,
+        ..SigntoolOptions::new(Certificate::Thumbprint("0123abcd".to_string()))
+    };
+    options.apply(&mut tool);
+    assert_eq!(
+        tool.command_line(),
+        "signtool sign /fd SHA256 /sha1 0123abcd"
+    );
+
+    assert_eq!(
+        SigntoolFailure::classify(&[
+            "SignTool Error: No certificates were found that met all the given criteria."
+                .to_string()
+        ]),
+        SigntoolFailure::CertificateNotFound
+    );
+    assert_eq!(
+        SigntoolFailure::classify(&[
+            "SignTool Error: The specified timestamp server either could not be reached or \
+             returned an invalid response."
+                .to_string()
+        ]),
+        SigntoolFailure::Timestamp
+    );
+}
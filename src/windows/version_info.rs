@@ -0,0 +1,205 @@
+//! `VERSIONINFO` resources, the version and product details Explorer shows in a file's
+//! properties.
+//!
+//! [`VersionInfo::from_cargo_env`] takes them from the environment cargo sets for build scripts,
+//! and [`VersionInfo::link`] compiles them with [`super::resources`] and links them in.
+
+use std::env;
+use std::fmt::Write;
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+
+use inflector::cases::titlecase::to_title_case;
+
+use super::resources::{compile_resource, ResourceCompiler};
+use crate::tool::CompileError;
+
+/// The kind of file the resource describes (`FILETYPE`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VersionFileType {
+    App,
+    Dll,
+}
+
+/// Contents of a `VERSIONINFO` resource.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VersionInfo {
+    /// Version of the file, e.g. `1.2.3`; a pre-release suffix marks the file as pre-release
+    pub file_version: String,
+    pub product_version: String,
+    pub product_name: String,
+    pub company_name: String,
+    pub file_description: String,
+    pub legal_copyright: String,
+    /// The file's name, e.g. `myapp.exe`
+    pub original_filename: String,
+    pub file_type: VersionFileType,
+}
+
+impl VersionInfo {
+    /// Version information of the package being built, from `CARGO_PKG_VERSION`, `CARGO_PKG_NAME`
+    /// (title cased like [`crate::get_name_from_cargo`]), `CARGO_PKG_AUTHORS` (the first author
+    /// is the company) and `CARGO_PKG_DESCRIPTION`. The file is described as executable; change
+    /// [`VersionInfo::file_type`] and [`VersionInfo::original_filename`] for a DLL.
+    pub fn from_cargo_env() -> Result<VersionInfo, Error> {
+        let var = |name: &str| {
+            env::var(name).map_err(|_| {
+                Error::new(
+                    ErrorKind::NotFound,
+                    format!("{} is not set, call this from a build script", name),
+                )
+            })
+        };
+        let version = var("CARGO_PKG_VERSION")?;
+        let package = var("CARGO_PKG_NAME")?;
+        let company_name = env::var("CARGO_PKG_AUTHORS")
+            .unwrap_or_default()
+            .split(':')
+            .next()
+            .map(|author| match author.split_once('<') {
+                Some((name, _)) => name.trim().to_string(),
+                None => author.trim().to_string(),
+            })
+            .unwrap_or_default();
+        let product_name = to_title_case(&package);
+        Ok(VersionInfo {
+            file_version: version.clone(),
+            product_version: version,
+            file_description: env::var("CARGO_PKG_DESCRIPTION")
+                .ok()
+                .filter(|d| !d.is_empty())
+                .unwrap_or_else(|| product_name.clone()),
+            legal_copyright: if company_name.is_empty() {
+                String::new()
+            } else {
+                format!("Copyright © {}", company_name)
+            },
+            original_filename: format!("{}.exe", package),
+            product_name,
+            company_name,
+            file_type: VersionFileType::App,
+        })
+    }
+
+    /// The resource script, encoded as UTF-8.
+    pub fn to_rc(&self) -> String {
+        let prerelease = self.file_version.contains('-') || self.product_version.contains('-');
+        // Without the pragma rc.exe and windres read scripts in the ANSI code page
+        let mut rc = String::from("#pragma code_page(65001)\n");
+        let _ = write!(
+            rc,
+            "1 VERSIONINFO\nFILEVERSION {}\nPRODUCTVERSION {}\nFILEFLAGSMASK 0x3f\n\
+             FILEFLAGS {}\nFILEOS 0x40004\nFILETYPE {}\nFILESUBTYPE 0x0\n\
+             BEGIN\n  BLOCK \"StringFileInfo\"\n  BEGIN\n    BLOCK \"040904b0\"\n    BEGIN\n",
+            numeric_version(&self.file_version),
+            numeric_version(&self.product_version),
+            if prerelease { "0x2" } else { "0x0" },
+            match self.file_type {
+                VersionFileType::App => "0x1",
+                VersionFileType::Dll => "0x2",
+            }
+        );
+        for (key, value) in [
+            ("CompanyName", &self.company_name),
+            ("FileDescription", &self.file_description),
+            ("FileVersion", &self.file_version),
+            ("InternalName", &self.product_name),
+            ("LegalCopyright", &self.legal_copyright),
+            ("OriginalFilename", &self.original_filename),
+            ("ProductName", &self.product_name),
+            ("ProductVersion", &self.product_version),
+        ] {
+            if !value.is_empty() {
+                let _ = writeln!(rc, "      VALUE \"{}\", \"{}\"", key, escape(value));
+            }
+        }
+        rc.push_str(
+            "    END\n  END\n  BLOCK \"VarFileInfo\"\n  BEGIN\n    \
+             VALUE \"Translation\", 0x409, 1200\n  END\nEND\n",
+        );
+        rc
+    }
+
+    /// Write the resource script, e.g. to include it from another `.rc` file.
+    pub fn write_rc(&self, path: &Path) -> Result<(), Error> {
+        fs::write(path, self.to_rc())
+    }
+
+    /// Compile the resource into `out_dir` and link it into the crate's executables from
+    /// `build.rs`.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use std::path::Path;
+    /// use toolbelt::windows::version_info::VersionInfo;
+    ///
+    /// if std::env::var("CARGO_CFG_TARGET_OS").as_deref() == Ok("windows") {
+    ///     let out_dir = std::env::var("OUT_DIR").unwrap();
+    ///     let mut info = VersionInfo::from_cargo_env().unwrap();
+    ///     info.company_name = "Example Inc.".to_string();
+    ///     info.link(Path::new(&out_dir)).unwrap();
+    /// }
+    /// ```
+    pub fn link(&self, out_dir: &Path) -> Result<PathBuf, CompileError> {
+        fs::create_dir_all(out_dir)?;
+        let rc_file = out_dir.join("version_info.rc");
+        self.write_rc(&rc_file)?;
+        let resource = compile_resource(&rc_file, out_dir, ResourceCompiler::for_target())?;
+        println!("cargo:rustc-link-arg={}", resource.display());
+        Ok(resource)
+    }
+}
+
+/// `1,2,3,0` for `1.2.3-beta.1`; missing or non-numeric parts are 0.
+fn numeric_version(version: &str) -> String {
+    let version = version.split(['-', '+']).next().unwrap_or_default();
+    let mut parts: Vec<u16> = version
+        .split('.')
+        .take(4)
+        .map(|part| part.parse().unwrap_or(0))
+        .collect();
+    parts.resize(4, 0);
+    parts
+        .iter()
+        .map(u16::to_string)
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Escape a string literal for a resource script.
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\"\"")
+}
+
+#[test]
+fn test_version_info() {
+    assert_eq!(numeric_version("1.2.3-beta.1"), "1,2,3,0");
+    assert_eq!(numeric_version("2.0"), "2,0,0,0");
+
+    let info = VersionInfo {
+        file_version: "1.2.3-beta.1".to_string(),
+        product_version: "1.2.3-beta.1".to_string(),
+        product_name: "My App".to_string(),
+        company_name: "\"Quoted\" Inc.".to_string(),
+        file_description: "My App".to_string(),
+        legal_copyright: String::new(),
+        original_filename: "my-app.exe".to_string(),
+        file_type: VersionFileType::App,
+    };
+    let rc = info.to_rc();
+    assert!(rc.contains("FILEVERSION 1,2,3,0\n"));
+    assert!(rc.contains("FILEFLAGS 0x2\n"));
+    assert!(rc.contains("FILETYPE 0x1\n"));
+    assert!(rc.contains("VALUE \"CompanyName\", \"\"\"Quoted\"\" Inc.\"\n"));
+    assert!(rc.contains("VALUE \"ProductVersion\", \"1.2.3-beta.1\"\n"));
+    assert!(!rc.contains("LegalCopyright"));
+    assert_eq!(rc.matches("BEGIN").count(), rc.matches("END\n").count());
+
+    if env::var("CARGO_PKG_NAME").is_ok() {
+        let info = VersionInfo::from_cargo_env().unwrap();
+        assert_eq!(info.product_name, "Toolbelt");
+        assert_eq!(info.original_filename, "toolbelt.exe");
+    }
+}
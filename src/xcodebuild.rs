@@ -0,0 +1,335 @@
+//! Building a companion Xcode target from a build script with `xcodebuild`.
+//!
+//! The scheme is built with `xcodebuild build`, then the location of the product is read from
+//! `xcodebuild -showBuildSettings` with the same arguments. Failures are reported with the kind
+//! of failure taken from the exit status and the compiler errors extracted from the log.
+
+use std::fmt;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::process::ExitStatus;
+
+use crate::metal::{parse_diagnostics, Diagnostic, Severity};
+use crate::tool::{Tool, ToolError};
+use crate::xcrun;
+
+/// Number of log lines kept in [`XcodebuildError::Failed`] when no errors could be extracted.
+const LOG_TAIL_LINES: usize = 20;
+
+/// What the exit status of a failed `xcodebuild` means, following `sysexits.h`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum XcodebuildFailure {
+    /// Invalid arguments, e.g. an unknown scheme or destination (exit status 64)
+    Usage,
+    /// The build itself failed, e.g. a compiler error (exit status 65)
+    BuildFailed,
+    /// xcodebuild crashed or hit an internal error (exit status 70)
+    Internal,
+    /// Any other exit status
+    Other(i32),
+    /// xcodebuild was killed by a signal
+    Signal,
+}
+
+impl XcodebuildFailure {
+    fn from_status(status: ExitStatus) -> XcodebuildFailure {
+        match status.code() {
+            Some(64) => XcodebuildFailure::Usage,
+            Some(65) => XcodebuildFailure::BuildFailed,
+            Some(70) => XcodebuildFailure::Internal,
+            Some(code) => XcodebuildFailure::Other(code),
+            None => XcodebuildFailure::Signal,
+        }
+    }
+}
+
+impl fmt::Display for XcodebuildFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            XcodebuildFailure::Usage => f.write_str("invalid arguments"),
+            XcodebuildFailure::BuildFailed => f.write_str("build failed"),
+            XcodebuildFailure::Internal => f.write_str("internal error"),
+            XcodebuildFailure::Other(code) => write!(f, "exit status {}", code),
+            XcodebuildFailure::Signal => f.write_str("killed by a signal"),
+        }
+    }
+}
+
+/// Errors reported by [`xcodebuild`].
+#[derive(Debug)]
+pub enum XcodebuildError {
+    /// The project is missing or the build settings couldn't be read
+    Io(Error),
+    /// xcodebuild couldn't be started
+    Tool(ToolError),
+    /// xcodebuild failed
+    Failed {
+        failure: XcodebuildFailure,
+        /// Compiler errors with their location
+        diagnostics: Vec<Diagnostic>,
+        /// Other `error:` lines, e.g. for a missing scheme or signing problems
+        errors: Vec<String>,
+        /// The last lines of the log if neither diagnostics nor errors were found
+        log_tail: String,
+    },
+}
+
+impl fmt::Display for XcodebuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            XcodebuildError::Io(e) => write!(f, "{}", e),
+            XcodebuildError::Tool(e) => write!(f, "{}", e),
+            XcodebuildError::Failed {
+                failure,
+                diagnostics,
+                errors,
+                log_tail,
+            } => {
+                write!(f, "xcodebuild failed: {}", failure)?;
+                for diagnostic in diagnostics {
+                    write!(f, "\n{}", diagnostic)?;
+                }
+                for error in errors {
+                    write!(f, "\n{}", error)?;
+                }
+                if !log_tail.is_empty() {
+                    write!(f, "\n{}", log_tail)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for XcodebuildError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            XcodebuildError::Io(e) => Some(e),
+            XcodebuildError::Tool(e) => Some(e),
+            XcodebuildError::Failed { .. } => None,
+        }
+    }
+}
+
+impl From<Error> for XcodebuildError {
+    fn from(e: Error) -> Self {
+        XcodebuildError::Io(e)
+    }
+}
+
+impl From<ToolError> for XcodebuildError {
+    fn from(e: ToolError) -> Self {
+        match e {
+            ToolError::Failed {
+                status,
+                stdout,
+                stderr,
+                ..
+            } => failed(status, &format!("{}\n{}", stdout, stderr)),
+            e => XcodebuildError::Tool(e),
+        }
+    }
+}
+
+/// Result of a successful [`xcodebuild`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct XcodebuildOutput {
+    /// The built product, e.g. `…/Build/Products/Release/Helper.app`
+    pub product: PathBuf,
+    /// The directory the products of the scheme were built into
+    pub built_products_dir: PathBuf,
+    /// Compiler warnings from the log
+    pub warnings: Vec<Diagnostic>,
+}
+
+/// Build a scheme of an Xcode project or workspace and return the path of its product.
+///
+/// Warnings are logged and returned. Add the project's sources to `cargo:rerun-if-changed` to
+/// rebuild the target only when it changed.
+///
+/// # Arguments
+///
+/// * `project_or_workspace` - an `.xcodeproj` or `.xcworkspace`
+/// * `scheme` - the scheme to build
+/// * `configuration` - the build configuration, e.g. `Release`
+/// * `destination` - an xcodebuild destination, e.g. `generic/platform=iOS`, or `None` for the
+///   scheme's default
+///
+/// # Example
+///
+/// ```no_run
+/// use std::path::Path;
+/// use toolbelt::xcodebuild::xcodebuild;
+///
+/// let output = xcodebuild(
+///     Path::new("helper/Helper.xcodeproj"),
+///     "Helper",
+///     "Release",
+///     Some("generic/platform=macOS"),
+/// )
+/// .unwrap();
+/// println!("cargo:rustc-env=HELPER_APP={}", output.product.display());
+/// ```
+pub fn xcodebuild(
+    project_or_workspace: &Path,
+    scheme: &str,
+    configuration: &str,
+    destination: Option<&str>,
+) -> Result<XcodebuildOutput, XcodebuildError> {
+    let project_flag = match project_or_workspace.extension().and_then(|e| e.to_str()) {
+        Some("xcodeproj") => "-project",
+        Some("xcworkspace") => "-workspace",
+        _ => {
+            return Err(XcodebuildError::Io(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "{} is neither an .xcodeproj nor an .xcworkspace",
+                    project_or_workspace.display()
+                ),
+            )))
+        }
+    };
+    if !project_or_workspace.exists() {
+        return Err(XcodebuildError::Io(Error::new(
+            ErrorKind::NotFound,
+            format!("{} not found", project_or_workspace.display()),
+        )));
+    }
+    let command = || -> Result<Tool, XcodebuildError> {
+        let mut tool = xcrun::tool("xcodebuild", None)?;
+        tool.arg(project_flag).arg(project_or_workspace).args([
+            "-scheme",
+            scheme,
+            "-configuration",
+            configuration,
+        ]);
+        if let Some(destination) = destination {
+            tool.args(["-destination", destination]);
+        }
+        Ok(tool)
+    };
+
+    let output = command()?.arg("build").run()?;
+    let warnings: Vec<Diagnostic> = parse_diagnostics(&output.stdout)
+        .into_iter()
+        .filter(|d| d.severity == Severity::Warning)
+        .collect();
+    for warning in &warnings {
+        warn!("{}", warning);
+    }
+
+    let settings = command()?.arg("-showBuildSettings").run()?;
+    let (built_products_dir, product_name) = parse_build_settings(&settings.stdout)?;
+    Ok(XcodebuildOutput {
+        product: built_products_dir.join(product_name),
+        built_products_dir,
+        warnings,
+    })
+}
+
+fn failed(status: ExitStatus, log: &str) -> XcodebuildError {
+    let diagnostics: Vec<Diagnostic> = parse_diagnostics(log)
+        .into_iter()
+        .filter(|d| d.severity == Severity::Error)
+        .collect();
+    let mut errors: Vec<String> = Vec::new();
+    for line in log.lines().map(str::trim) {
+        if (line.starts_with("error: ") || line.starts_with("xcodebuild: error: "))
+            && !errors.iter().any(|e| e == line)
+        {
+            errors.push(line.to_string());
+        }
+    }
+    let log_tail = if diagnostics.is_empty() && errors.is_empty() {
+        let lines: Vec<&str> = log.trim_end().lines().collect();
+        lines[lines.len().saturating_sub(LOG_TAIL_LINES)..].join("\n")
+    } else {
+        String::new()
+    };
+    XcodebuildError::Failed {
+        failure: XcodebuildFailure::from_status(status),
+        diagnostics,
+        errors,
+        log_tail,
+    }
+}
+
+/// `BUILT_PRODUCTS_DIR` and `FULL_PRODUCT_NAME` of the first target in the output of
+/// `xcodebuild -showBuildSettings`, which is the target the scheme builds.
+fn parse_build_settings(output: &str) -> Result<(PathBuf, String), Error> {
+    let mut built_products_dir = None;
+    let mut product_name = None;
+    for line in output.lines() {
+        if line.starts_with("Build settings for ") && built_products_dir.is_some() {
+            break;
+        }
+        match line.trim().split_once(" = ") {
+            Some(("BUILT_PRODUCTS_DIR", value)) => built_products_dir = Some(PathBuf::from(value)),
+            Some(("FULL_PRODUCT_NAME", value)) => product_name = Some(value.to_string()),
+            _ => (),
+        }
+    }
+    built_products_dir.zip(product_name).ok_or_else(|| {
+        Error::new(
+            ErrorKind::InvalidData,
+            "xcodebuild -showBuildSettings lacks BUILT_PRODUCTS_DIR or FULL_PRODUCT_NAME",
+        )
+    })
+}
+
+#[test]
+fn test_xcodebuild_output() {
+    let settings = "Command line invocation:\n    xcodebuild -showBuildSettings\n\n\
+        Build settings for action build and target Helper:\n    \
+        BUILT_PRODUCTS_DIR = /tmp/Build/Products/Release\n    \
+        FULL_PRODUCT_NAME = Helper.app\n\n\
+        Build settings for action build and target HelperKit:\n    \
+        BUILT_PRODUCTS_DIR = /tmp/Build/Products/Release\n    \
+        FULL_PRODUCT_NAME = HelperKit.framework\n";
+    assert_eq!(
+        parse_build_settings(settings).unwrap(),
+        (
+            PathBuf::from("/tmp/Build/Products/Release"),
+            "Helper.app".to_string()
+        )
+    );
+    assert!(parse_build_settings("").is_err());
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+
+        let log = "CompileSwift normal arm64 /src/App.swift\n\
+            /src/App.swift:3:5: error: cannot find 'foo' in scope\n\
+            /src/App.swift:9:1: warning: unused variable\n\
+            error: Signing for \"Helper\" requires a development team.\n\
+            ** BUILD FAILED **\n";
+        match failed(ExitStatus::from_raw(65 << 8), log) {
+            XcodebuildError::Failed {
+                failure,
+                diagnostics,
+                errors,
+                log_tail,
+            } => {
+                assert_eq!(failure, XcodebuildFailure::BuildFailed);
+                assert_eq!(diagnostics.len(), 1);
+                assert_eq!(diagnostics[0].line, 3);
+                assert_eq!(
+                    errors,
+                    ["error: Signing for \"Helper\" requires a development team."]
+                );
+                assert!(log_tail.is_empty());
+            }
+            e => panic!("unexpected error {}", e),
+        }
+        match failed(ExitStatus::from_raw(64 << 8), "something went wrong\n") {
+            XcodebuildError::Failed {
+                failure, log_tail, ..
+            } => {
+                assert_eq!(failure, XcodebuildFailure::Usage);
+                assert_eq!(log_tail, "something went wrong");
+            }
+            e => panic!("unexpected error {}", e),
+        }
+    }
+}
@@ -0,0 +1,471 @@
+//! Locating Xcode's developer tools with `xcrun --find`.
+//!
+//! Tools such as ibtool, actool or codesign are resolved in the active developer directory, the
+//! one selected with `xcode-select` or overridden by the `DEVELOPER_DIR` environment variable, so
+//! builds use the same toolchain as Xcode. Resolved paths are cached for the lifetime of the
+//! process.
+//!
+//! [`detect_xcode`] reports the versions of the installation and its SDKs, so build scripts can
+//! require a minimum toolchain. [`apple_sdk_path`] locates the SDKs themselves.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+
+use crate::messages::{message, Message};
+use crate::tool::{Tool, ToolError};
+
+/// The path of a developer tool in the active Xcode or Command Line Tools installation.
+///
+/// Fails with [`ToolError::Spawn`] explaining what is missing: Xcode as a whole, the tool or the
+/// SDK.
+///
+/// # Arguments
+///
+/// * `name` - the tool, e.g. `ibtool`
+/// * `sdk` - the SDK to look the tool up for (`xcrun --sdk`, e.g. `iphoneos`), `None` for the
+///   default one
+///
+/// # Example
+///
+/// ```no_run
+/// use toolbelt::xcrun::find_tool;
+///
+/// let ibtool = find_tool("ibtool", None).unwrap();
+/// println!("Using {}", ibtool.display());
+/// ```
+pub fn find_tool(name: &str, sdk: Option<&str>) -> Result<PathBuf, ToolError> {
+    let developer_dir = std::env::var_os("DEVELOPER_DIR").map(PathBuf::from);
+    find_tool_in(name, sdk, developer_dir.as_deref())
+}
+
+/// A [`Tool`] for the developer tool `name`, resolved with [`find_tool`].
+pub fn tool(name: &str, sdk: Option<&str>) -> Result<Tool, ToolError> {
+    find_tool(name, sdk).map(Tool::new)
+}
+
+type CacheKey = (String, Option<String>, Option<PathBuf>);
+
+fn find_tool_in(
+    name: &str,
+    sdk: Option<&str>,
+    developer_dir: Option<&Path>,
+) -> Result<PathBuf, ToolError> {
+    static CACHE: OnceLock<Mutex<HashMap<CacheKey, PathBuf>>> = OnceLock::new();
+    let key = (
+        name.to_string(),
+        sdk.map(String::from),
+        developer_dir.map(Path::to_path_buf),
+    );
+    let cache = CACHE.get_or_init(Default::default);
+    if let Some(path) = cache.lock().unwrap().get(&key) {
+        return Ok(path.clone());
+    }
+
+    let missing = |message: String| ToolError::Spawn {
+        program: name.to_string(),
+        source: Error::new(ErrorKind::NotFound, message),
+    };
+    if let Some(dir) = developer_dir {
+        if !dir.is_dir() {
+            return Err(missing(format!(
+                "DEVELOPER_DIR {} is not a directory",
+                dir.display()
+            )));
+        }
+    }
+
+    let mut xcrun = Tool::new("xcrun");
+    if let Some(dir) = developer_dir {
+        xcrun.env("DEVELOPER_DIR", dir);
+    }
+    if let Some(sdk) = sdk {
+        xcrun.args(["--sdk", sdk]);
+    }
+    let output = match xcrun.args(["--find", name]).output() {
+        Ok(output) => output,
+        Err(_) => return Err(missing(message(Message::XcrunMissing, &[]))),
+    };
+    let path = PathBuf::from(output.stdout.trim());
+    if !output.status.success() || path.as_os_str().is_empty() {
+        return Err(missing(failure_message(name, sdk, &output.stderr)));
+    }
+
+    debug!("Found {} at {}", name, path.display());
+    cache.lock().unwrap().insert(key, path.clone());
+    Ok(path)
+}
+
+/// Explain why `xcrun --find` failed, judging by its error output.
+fn failure_message(name: &str, sdk: Option<&str>, stderr: &str) -> String {
+    if stderr.contains("invalid active developer path") || stderr.contains("xcode-select") {
+        message(Message::XcodeMissing, &[])
+    } else if let Some(sdk) = sdk.filter(|_| stderr.contains("cannot be located")) {
+        message(Message::AppleSdkMissing, &[("sdk", &sdk)])
+    } else {
+        let detail = stderr.trim();
+        let mut message = format!("xcrun can't find {}", name);
+        if let Some(sdk) = sdk {
+            message += &format!(" for SDK {}", sdk);
+        }
+        if !detail.is_empty() {
+            message += &format!(": {}", detail);
+        }
+        message
+    }
+}
+
+/// The SDKs of Apple platforms, as passed to `xcrun --sdk`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum AppleSdk {
+    MacOsx,
+    IPhoneOs,
+    IPhoneSimulator,
+    AppleTvOs,
+    AppleTvSimulator,
+    WatchOs,
+    WatchSimulator,
+    XrOs,
+    XrSimulator,
+}
+
+impl AppleSdk {
+    /// All SDKs, the ones [`detect_xcode`] looks for.
+    pub const ALL: [AppleSdk; 9] = [
+        AppleSdk::MacOsx,
+        AppleSdk::IPhoneOs,
+        AppleSdk::IPhoneSimulator,
+        AppleSdk::AppleTvOs,
+        AppleSdk::AppleTvSimulator,
+        AppleSdk::WatchOs,
+        AppleSdk::WatchSimulator,
+        AppleSdk::XrOs,
+        AppleSdk::XrSimulator,
+    ];
+
+    /// The name used by `xcrun --sdk`, e.g. `iphonesimulator`.
+    pub fn name(self) -> &'static str {
+        match self {
+            AppleSdk::MacOsx => "macosx",
+            AppleSdk::IPhoneOs => "iphoneos",
+            AppleSdk::IPhoneSimulator => "iphonesimulator",
+            AppleSdk::AppleTvOs => "appletvos",
+            AppleSdk::AppleTvSimulator => "appletvsimulator",
+            AppleSdk::WatchOs => "watchos",
+            AppleSdk::WatchSimulator => "watchsimulator",
+            AppleSdk::XrOs => "xros",
+            AppleSdk::XrSimulator => "xrsimulator",
+        }
+    }
+
+    /// The SDK with the given `xcrun --sdk` name.
+    pub fn from_name(name: &str) -> Option<AppleSdk> {
+        AppleSdk::ALL.into_iter().find(|sdk| sdk.name() == name)
+    }
+}
+
+impl fmt::Display for AppleSdk {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// The path of an Apple platform SDK in the active developer directory
+/// (`xcrun --sdk <sdk> --show-sdk-path`), e.g. for `--sysroot` or bindgen's `-isysroot`.
+///
+/// Unlike [`crate::get_sdk_path`], which reads the path of any SDK from an environment variable,
+/// this asks Xcode. Fails with [`ToolError::Spawn`] if Xcode or the SDK is missing.
+///
+/// # Example
+///
+/// ```no_run
+/// use toolbelt::xcrun::{apple_sdk_path, AppleSdk};
+///
+/// let sdk = apple_sdk_path(AppleSdk::IPhoneSimulator).unwrap();
+/// println!("cargo:rustc-link-search=framework={}/System/Library/Frameworks", sdk.display());
+/// ```
+pub fn apple_sdk_path(sdk: AppleSdk) -> Result<PathBuf, ToolError> {
+    let output = match Tool::new("xcrun")
+        .args(["--sdk", sdk.name(), "--show-sdk-path"])
+        .output()
+    {
+        Ok(output) => output,
+        Err(_) => {
+            return Err(ToolError::Spawn {
+                program: "xcrun".to_string(),
+                source: Error::new(ErrorKind::NotFound, message(Message::XcrunMissing, &[])),
+            })
+        }
+    };
+    let path = PathBuf::from(output.stdout.trim());
+    if !output.status.success() || path.as_os_str().is_empty() {
+        return Err(ToolError::Spawn {
+            program: "xcrun".to_string(),
+            source: Error::new(
+                ErrorKind::NotFound,
+                failure_message("the SDK path", Some(sdk.name()), &output.stderr),
+            ),
+        });
+    }
+    debug!("Found SDK {} at {}", sdk, path.display());
+    Ok(path)
+}
+
+/// An SDK installed with Xcode or the Command Line Tools.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SdkVersion {
+    /// Name as passed to `xcrun --sdk`, e.g. `iphoneos`
+    pub sdk: String,
+    /// e.g. `17.5`
+    pub version: String,
+}
+
+/// The active Xcode or Command Line Tools installation, see [`detect_xcode`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct XcodeInstallation {
+    /// Xcode's version, e.g. `15.4`. `None` if only the Command Line Tools are installed.
+    pub version: Option<String>,
+    /// Xcode's build number, e.g. `15F31d`
+    pub build: Option<String>,
+    /// The active developer directory
+    pub developer_dir: PathBuf,
+    pub sdks: Vec<SdkVersion>,
+}
+
+impl XcodeInstallation {
+    /// The version of an installed SDK.
+    pub fn sdk_version(&self, sdk: &str) -> Option<&str> {
+        self.sdks
+            .iter()
+            .find(|s| s.sdk == sdk)
+            .map(|s| s.version.as_str())
+    }
+
+    /// Fail unless Xcode `minimum` or later is installed.
+    pub fn require_version(&self, minimum: &str) -> Result<(), Error> {
+        match &self.version {
+            Some(version) if version_at_least(version, minimum) => Ok(()),
+            Some(version) => Err(Error::new(
+                ErrorKind::InvalidInput,
+                message(
+                    Message::XcodeTooOld,
+                    &[
+                        ("minimum", &minimum),
+                        ("version", version),
+                        ("path", &self.developer_dir.display()),
+                    ],
+                ),
+            )),
+            None => Err(Error::new(
+                ErrorKind::InvalidInput,
+                message(
+                    Message::XcodeRequired,
+                    &[
+                        ("minimum", &minimum),
+                        ("path", &self.developer_dir.display()),
+                    ],
+                ),
+            )),
+        }
+    }
+
+    /// Fail unless the SDK is installed in version `minimum` or later.
+    pub fn require_sdk(&self, sdk: &str, minimum: &str) -> Result<(), Error> {
+        match self.sdk_version(sdk) {
+            Some(version) if version_at_least(version, minimum) => Ok(()),
+            Some(version) => Err(Error::new(
+                ErrorKind::InvalidInput,
+                message(
+                    Message::AppleSdkTooOld,
+                    &[("sdk", &sdk), ("minimum", &minimum), ("version", &version)],
+                ),
+            )),
+            None => Err(Error::new(
+                ErrorKind::InvalidInput,
+                message(
+                    Message::AppleSdkRequired,
+                    &[
+                        ("sdk", &sdk),
+                        ("minimum", &minimum),
+                        ("path", &self.developer_dir.display()),
+                    ],
+                ),
+            )),
+        }
+    }
+}
+
+/// Detect the active Xcode installation: its version and build number (`xcodebuild -version`),
+/// the developer directory and the versions of the installed SDKs (`xcrun --show-sdk-version`).
+///
+/// # Example
+///
+/// ```no_run
+/// use toolbelt::xcrun::detect_xcode;
+///
+/// let xcode = detect_xcode().unwrap();
+/// xcode.require_version("15.0").unwrap();
+/// xcode.require_sdk("iphoneos", "17.0").unwrap();
+/// ```
+pub fn detect_xcode() -> Result<XcodeInstallation, ToolError> {
+    let developer_dir = match std::env::var_os("DEVELOPER_DIR") {
+        Some(dir) => PathBuf::from(dir),
+        None => {
+            let output = Tool::new("xcode-select")
+                .arg("--print-path")
+                .run()
+                .map_err(|_| ToolError::Spawn {
+                    program: "xcode-select".to_string(),
+                    source: Error::new(
+                        ErrorKind::NotFound,
+                        message(Message::XcodeNotSelected, &[]),
+                    ),
+                })?;
+            PathBuf::from(output.stdout.trim())
+        }
+    };
+
+    // xcodebuild only exists in Xcode, not in the Command Line Tools
+    let (version, build) = match Tool::new("xcodebuild").arg("-version").output() {
+        Ok(output) if output.status.success() => parse_xcodebuild_version(&output.stdout),
+        _ => (None, None),
+    };
+
+    let mut sdks = Vec::new();
+    for sdk in AppleSdk::ALL {
+        if let Ok(output) = Tool::new("xcrun")
+            .args(["--sdk", sdk.name(), "--show-sdk-version"])
+            .run()
+        {
+            sdks.push(SdkVersion {
+                sdk: sdk.to_string(),
+                version: output.stdout.trim().to_string(),
+            });
+        }
+    }
+
+    Ok(XcodeInstallation {
+        version,
+        build,
+        developer_dir,
+        sdks,
+    })
+}
+
+/// Version and build number from the output of `xcodebuild -version`.
+fn parse_xcodebuild_version(stdout: &str) -> (Option<String>, Option<String>) {
+    let field = |prefix: &str| {
+        stdout
+            .lines()
+            .find_map(|line| line.strip_prefix(prefix))
+            .map(|value| value.trim().to_string())
+    };
+    (field("Xcode "), field("Build version "))
+}
+
+/// `true` if the dotted version is `minimum` or later, e.g. `15.0.1` is at least `15`.
+pub(crate) fn version_at_least(version: &str, minimum: &str) -> bool {
+    let parse = |v: &str| -> Vec<u64> {
+        v.split('.')
+            .map(|part| {
+                let digits: String = part.chars().take_while(char::is_ascii_digit).collect();
+                digits.parse().unwrap_or(0)
+            })
+            .collect()
+    };
+    let (mut version, mut minimum) = (parse(version), parse(minimum));
+    let len = version.len().max(minimum.len());
+    version.resize(len, 0);
+    minimum.resize(len, 0);
+    version >= minimum
+}
+
+#[test]
+fn test_find_tool_errors() {
+    match find_tool_in("ibtool", None, Some(Path::new("target/no-such-xcode"))) {
+        Err(ToolError::Spawn { program, source }) => {
+            assert_eq!(program, "ibtool");
+            assert_eq!(source.kind(), ErrorKind::NotFound);
+            assert_eq!(
+                source.to_string(),
+                "DEVELOPER_DIR target/no-such-xcode is not a directory"
+            );
+        }
+        other => panic!("unexpected {:?}", other),
+    }
+
+    assert_eq!(
+        failure_message(
+            "ibtool",
+            None,
+            "xcrun: error: invalid active developer path (/Library/Developer/CommandLineTools), \
+             missing xcrun at: /Library/Developer/CommandLineTools/usr/bin/xcrun\n"
+        ),
+        "Xcode or the Command Line Tools are missing, install Xcode or the Command Line Tools \
+         with `xcode-select --install`"
+    );
+    assert_eq!(
+        failure_message(
+            "metal",
+            Some("xros"),
+            "xcrun: error: SDK \"xros\" cannot be located\n"
+        ),
+        "SDK xros is not installed"
+    );
+    assert_eq!(
+        failure_message(
+            "metal",
+            Some("macosx"),
+            "xcrun: error: unable to find utility \"metal\", not a developer tool or in PATH\n"
+        ),
+        "xcrun can't find metal for SDK macosx: xcrun: error: unable to find utility \"metal\", \
+         not a developer tool or in PATH"
+    );
+}
+
+#[test]
+fn test_xcode_installation() {
+    assert_eq!(
+        parse_xcodebuild_version("Xcode 15.4\nBuild version 15F31d\n"),
+        (Some("15.4".to_string()), Some("15F31d".to_string()))
+    );
+    assert!(version_at_least("15.0.1", "15"));
+    assert!(version_at_least("15.10", "15.4"));
+    assert!(!version_at_least("14.3.1", "15.0"));
+
+    let xcode = XcodeInstallation {
+        version: Some("15.4".to_string()),
+        build: Some("15F31d".to_string()),
+        developer_dir: PathBuf::from("/Applications/Xcode.app/Contents/Developer"),
+        sdks: vec![SdkVersion {
+            sdk: "macosx".to_string(),
+            version: "14.5".to_string(),
+        }],
+    };
+    assert!(xcode.require_version("15.0").is_ok());
+    assert_eq!(
+        xcode.require_version("16.0").unwrap_err().to_string(),
+        "Xcode 16.0 or later is required, but Xcode 15.4 \
+         (/Applications/Xcode.app/Contents/Developer) is selected"
+    );
+    assert!(xcode.require_sdk("macosx", "14.0").is_ok());
+    assert_eq!(
+        xcode
+            .require_sdk("iphoneos", "17.0")
+            .unwrap_err()
+            .to_string(),
+        "SDK iphoneos 17.0 or later is required, but it isn't installed in \
+         /Applications/Xcode.app/Contents/Developer"
+    );
+
+    assert_eq!(
+        AppleSdk::from_name("iphonesimulator"),
+        Some(AppleSdk::IPhoneSimulator)
+    );
+    assert!(AppleSdk::ALL
+        .iter()
+        .all(|sdk| AppleSdk::from_name(sdk.name()) == Some(*sdk)));
+    assert_eq!(AppleSdk::from_name("android"), None);
+}